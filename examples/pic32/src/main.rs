@@ -6,11 +6,9 @@
 //!
 #![no_std]
 #![no_main]
-#![feature(alloc_error_handler)]
 
 use core::fmt::Write;
 use embedded_hal::{delay::DelayNs, digital::OutputPin};
-use mips_mcu_alloc::MipsMcuHeap;
 use mips_rt::entry;
 use panic_halt as _;
 use pic32_config_sector::pic32mx2xx::*;
@@ -52,14 +50,8 @@ pub static CONFIGSFRS: ConfigSector = ConfigSector::default()
     .JTAGEN(JTAGEN::OFF)
     .build();
 
-#[global_allocator]
-static ALLOCATOR: MipsMcuHeap = MipsMcuHeap::empty();
-
 #[entry]
 fn main() -> ! {
-    // Initialize the allocator BEFORE you use it
-    ALLOCATOR.init();
-
     let p = pac::Peripherals::take().unwrap();
     let parts = p.PORTB.split();
     let vpins = p.PPS.split();
@@ -116,11 +108,8 @@ fn main() -> ! {
         -12539, -16383, -19947, -23169, -25995, -28377, -30272, -31650, -32486, -32767, -32486,
         -31650, -30272, -28377, -25995, -23169, -19947, -16383, -12539, -8480, -4276,
     ];
-    let sinetab_le = unsafe { &*(&sinetab as *const _ as *const [u8; 96]) };
 
     let mut ctr = 0;
-    let mut input_alt_setting = 0;
-    let mut output_alt_setting = 0;
     loop {
         if usb_dev.poll(&mut [&mut usb_audio]) {
             let mut buf = [0u8; 1024];
@@ -132,18 +121,10 @@ fn main() -> ! {
                 }
             }
         }
-        if input_alt_setting != usb_audio.input_alt_setting().unwrap()
-            || output_alt_setting != usb_audio.output_alt_setting().unwrap()
-        {
-            input_alt_setting = usb_audio.input_alt_setting().unwrap();
-            output_alt_setting = usb_audio.output_alt_setting().unwrap();
-            writeln!(tx, "Alt. set. {} {}", input_alt_setting, output_alt_setting).unwrap();
+        usb_audio.update_device_state(usb_dev.state());
+        while let Some(event) = usb_audio.poll_event() {
+            writeln!(tx, "{:?}", event).unwrap();
         }
-        usb_audio.write(sinetab_le).ok();
+        usb_audio.write_samples(&sinetab).ok();
     }
 }
-
-#[alloc_error_handler]
-fn alloc_error(layout: core::alloc::Layout) -> ! {
-    panic!("Cannot allocate heap memory: {:?}", layout);
-}
@@ -119,11 +119,8 @@ fn main() -> ! {
         -12539, -16383, -19947, -23169, -25995, -28377, -30272, -31650, -32486, -32767, -32486,
         -31650, -30272, -28377, -25995, -23169, -19947, -16383, -12539, -8480, -4276,
     ];
-    let sinetab_le = unsafe { &*(&sinetab as *const _ as *const [u8; 96]) };
 
     let mut ctr = 0;
-    let mut input_alt_setting = 0;
-    let mut output_alt_setting = 0;
     loop {
         if usb_dev.poll(&mut [&mut usb_audio]) {
             let mut buf = [0u8; 1024];
@@ -135,18 +132,10 @@ fn main() -> ! {
                 }
             }
         }
-        if input_alt_setting != usb_audio.input_alt_setting().unwrap()
-            || output_alt_setting != usb_audio.output_alt_setting().unwrap()
-        {
-            input_alt_setting = usb_audio.input_alt_setting().unwrap();
-            output_alt_setting = usb_audio.output_alt_setting().unwrap();
-            writeln!(
-                uart,
-                "Alt. set. {} {}",
-                input_alt_setting, output_alt_setting
-            )
-            .unwrap();
+        usb_audio.update_device_state(usb_dev.state());
+        while let Some(event) = usb_audio.poll_event() {
+            writeln!(uart, "{:?}", event).unwrap();
         }
-        usb_audio.write(sinetab_le).ok();
+        usb_audio.write_samples(&sinetab).ok();
     }
 }
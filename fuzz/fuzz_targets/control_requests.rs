@@ -0,0 +1,192 @@
+//! Feeds arbitrary SETUP packets and data stages through the control request
+//! handling of the class, interleaved with isochronous packets and bus resets
+//!
+//! The first byte of the input selects the function, the rest is a sequence
+//! of operations, each starting with an opcode byte:
+//!
+//! - `0`: control transfer, followed by the 8 bytes of the SETUP packet and,
+//!   for a transfer from the host to the device, by up to wLength bytes of
+//!   data
+//! - `1`: packet for the data endpoint of the output stream, preceded by its
+//!   length
+//! - `2`: packet read from the data endpoint of the input stream
+//! - `3`: bus reset
+//!
+//! Run with `cargo fuzz run control_requests`.
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use usb_device::bus::UsbBusAllocator;
+use usb_device::class::UsbClass;
+use usb_device::control::{Recipient, RequestType};
+use usb_device::prelude::*;
+use usbd_audio::test_util::{MockBus, MockHost};
+use usbd_audio::*;
+
+/// Function with most of the optional entities and controls, so that the
+/// requests reach as much of the request handling as possible
+fn builder<'a>(selector: u8) -> Option<AudioClassBuilder<'a>> {
+    let version = match selector % 3 {
+        0 => AudioVersion::Uac1,
+        1 => AudioVersion::Uac2,
+        _ => AudioVersion::Uac3Badd,
+    };
+    let mut input =
+        StreamConfig::new_discrete(Format::S16le, 2, &[48000], TerminalType::InMicrophone)
+            .ok()?
+            .with_mute_control()
+            .with_volume_control(-40 * 256, 20 * 256, 256)
+            .ok()?;
+    let mut output =
+        StreamConfig::new_discrete(Format::S16le, 2, &[48000], TerminalType::OutSpeaker)
+            .ok()?
+            .with_mute_control()
+            .with_volume_control(-60 * 256, 0, 256)
+            .ok()?;
+    let mut builder = AudioClassBuilder::new().version(version);
+    if version != AudioVersion::Uac3Badd {
+        input = input
+            .with_channel_mute_control()
+            .ok()?
+            .with_agc_control()
+            .with_connector_control()
+            .with_selector(&[TerminalType::ExtLineConnector])
+            .ok()?
+            .with_extension_unit(ExtensionUnit::new(0x1234).with_enable_control());
+        output = output
+            .with_channel_volume_control(-60 * 256, 0, 256)
+            .ok()?
+            .with_tone_controls()
+            .with_graphic_equalizer(EQUALIZER_OCTAVE_BANDS)
+            .ok()?
+            .with_copy_protect_control()
+            .with_latency(2000)
+            .with_alt_formats(&[Format::S24le])
+            .with_processing_unit(ProcessingUnit::DynamicRangeCompressor)
+            .with_feedback_endpoint();
+        if version == AudioVersion::Uac2 {
+            output = output.with_external_clock().with_clock_selector();
+        }
+        builder = builder
+            .monitor(0, 0)
+            .interrupt_endpoint()
+            .midi()
+            .consumer_control();
+    }
+    if selector & 0x80 != 0 {
+        builder = builder.high_speed();
+    }
+    Some(builder.input(input).output(output))
+}
+
+fn request_type(bm_request_type: u8) -> RequestType {
+    match (bm_request_type >> 5) & 0x03 {
+        0 => RequestType::Standard,
+        1 => RequestType::Class,
+        2 => RequestType::Vendor,
+        _ => RequestType::Reserved,
+    }
+}
+
+fn recipient(bm_request_type: u8) -> Recipient {
+    match bm_request_type & 0x1f {
+        0 => Recipient::Device,
+        1 => Recipient::Interface,
+        2 => Recipient::Endpoint,
+        3 => Recipient::Other,
+        _ => Recipient::Reserved,
+    }
+}
+
+fuzz_target!(|data: &[u8]| {
+    let Some((&selector, mut data)) = data.split_first() else {
+        return;
+    };
+    let (host, bus) = MockHost::new();
+    let alloc = UsbBusAllocator::new(bus);
+    let Some(builder) = builder(selector) else {
+        return;
+    };
+    let Ok(mut audio) = builder.build(&alloc) else {
+        return;
+    };
+    let mut dev = UsbDeviceBuilder::new(&alloc, UsbVidPid(0x16c0, 0x27dd))
+        .composite_with_iads()
+        .build();
+    let ep_out = audio.data_endpoint(StreamId::Output(0)).ok();
+    let ep_in = audio.data_endpoint(StreamId::Input(0)).ok();
+    host.configure(&mut dev, &mut [&mut audio]).ok();
+
+    while let Some((&opcode, rest)) = data.split_first() {
+        data = rest;
+        let classes: &mut [&mut dyn UsbClass<MockBus>] = &mut [&mut audio];
+        match opcode {
+            0 => {
+                let Some((setup, rest)) = data.split_first_chunk::<8>() else {
+                    return;
+                };
+                data = rest;
+                let bm_request_type = setup[0];
+                let value = u16::from_le_bytes([setup[2], setup[3]]);
+                let index = u16::from_le_bytes([setup[4], setup[5]]);
+                let length = u16::from_le_bytes([setup[6], setup[7]]);
+                let (request_type, recipient) =
+                    (request_type(bm_request_type), recipient(bm_request_type));
+                if bm_request_type & 0x80 != 0 {
+                    let _ = host.control_in(
+                        &mut dev,
+                        classes,
+                        request_type,
+                        recipient,
+                        setup[1],
+                        value,
+                        index,
+                        length,
+                    );
+                } else {
+                    let (payload, rest) = data.split_at((length as usize).min(data.len()));
+                    data = rest;
+                    let _ = host.control_out(
+                        &mut dev,
+                        classes,
+                        request_type,
+                        recipient,
+                        setup[1],
+                        value,
+                        index,
+                        payload,
+                    );
+                }
+            }
+            1 => {
+                let Some((&len, rest)) = data.split_first() else {
+                    return;
+                };
+                let (packet, rest) = rest.split_at((len as usize).min(rest.len()));
+                data = rest;
+                if let Some(ep) = ep_out {
+                    let _ = host.send_packet(&mut dev, classes, ep, packet);
+                }
+                let mut buf = [0u8; DEFAULT_PACKET_SIZE];
+                let _ = audio.read(&mut buf);
+            }
+            2 => {
+                let _ = audio.write(&[0; 192]);
+                let classes: &mut [&mut dyn UsbClass<MockBus>] = &mut [&mut audio];
+                if let Some(ep) = ep_in {
+                    let _ = host.receive_packet(&mut dev, classes, ep);
+                }
+            }
+            3 => host.reset(&mut dev, classes),
+            _ => {}
+        }
+        // the state changed by the requests is consistent enough for the
+        // application to query it
+        while audio.poll_event().is_some() {}
+        for stream in [StreamId::Input(0), StreamId::Output(0)] {
+            let _ = audio.stream_format(stream);
+            let _ = audio.volume(stream);
+            let _ = audio.mute(stream);
+        }
+    }
+});
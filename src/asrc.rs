@@ -0,0 +1,118 @@
+//! Lightweight asynchronous sample rate conversion, enabled by the feature
+//! `asrc`
+//!
+//! Devices whose codec runs from a fixed clock and that cannot use a feedback
+//! endpoint, e.g. because the host does not support it or the stream is
+//! synchronous, receive slightly more or fewer samples than the codec
+//! consumes. A `Resampler` bridges such small mismatches by linear
+//! interpolation in fixed-point arithmetic. The ratio of the input to the
+//! output rate typically comes from `rate::RateEstimator::ratio()`, possibly
+//! corrected by the fill level of the buffer in between.
+//!
+//! Linear interpolation attenuates high frequencies slightly and adds some
+//! aliasing, which is acceptable for voice and monitoring; high-fidelity
+//! designs use feedback endpoints instead.
+//!
+//! ```
+//! use usbd_audio::asrc::Resampler;
+//!
+//! let mut resampler = Resampler::<1>::new();
+//! // two input frames per output frame
+//! resampler.set_ratio(2 << 16);
+//! let input = [[0], [100], [200], [300], [400], [500]];
+//! let mut output = [[0i32]; 4];
+//! assert_eq!(resampler.process(&input, &mut output), (6, 3));
+//! // the output is delayed by one input frame
+//! assert_eq!(output[..3], [[0], [100], [300]]);
+//! ```
+
+/// 1.0 in 16.16 format
+const ONE: u32 = 1 << 16;
+/// Smallest and largest ratio of the input to the output rate
+const MIN_RATIO: u32 = ONE / 2;
+const MAX_RATIO: u32 = ONE * 2;
+
+/// Fractional resampler of audio frames of `C` channels by linear
+/// interpolation
+#[derive(Clone, Debug)]
+pub struct Resampler<const C: usize> {
+    /// input frames per output frame in 16.16 format
+    ratio: u32,
+    /// position of the next output frame after `previous` in 16.16 format
+    position: u32,
+    /// input frames between which the next output frame is interpolated
+    previous: [i32; C],
+    current: [i32; C],
+}
+
+impl<const C: usize> Resampler<C> {
+    /// Create a resampler passing the frames through at the ratio 1.0,
+    /// usable as initializer of a static
+    pub const fn new() -> Self {
+        Resampler {
+            ratio: ONE,
+            position: ONE,
+            previous: [0; C],
+            current: [0; C],
+        }
+    }
+
+    /// Set the number of input frames per output frame in 16.16 format, e.g.
+    /// the ratio of the rate of the host to the rate of the codec. The ratio
+    /// is limited to the range 0.5 to 2.0 and takes effect at the next output
+    /// frame, so that it can be adjusted continuously.
+    pub fn set_ratio(&mut self, ratio: u32) {
+        self.ratio = ratio.clamp(MIN_RATIO, MAX_RATIO);
+    }
+
+    /// Get the number of input frames per output frame in 16.16 format
+    pub fn ratio(&self) -> u32 {
+        self.ratio
+    }
+
+    /// Discard the interpolation state, e.g. when the stream restarts, keeping
+    /// the ratio
+    pub fn reset(&mut self) {
+        *self = Resampler {
+            ratio: self.ratio,
+            ..Resampler::new()
+        };
+    }
+
+    /// Resample the frames of `input` into `output` until either is exhausted
+    /// and return the number of input frames consumed and output frames
+    /// produced. The remaining input frames are passed again on the next call.
+    /// The output is delayed by one input frame.
+    pub fn process(&mut self, input: &[[i32; C]], output: &mut [[i32; C]]) -> (usize, usize) {
+        let mut consumed = 0;
+        for (produced, frame) in output.iter_mut().enumerate() {
+            while self.position >= ONE {
+                let Some(next) = input.get(consumed) else {
+                    return (consumed, produced);
+                };
+                self.previous = self.current;
+                self.current = *next;
+                self.position -= ONE;
+                consumed += 1;
+            }
+            *frame = core::array::from_fn(|ch| {
+                interpolate(self.previous[ch], self.current[ch], self.position)
+            });
+            self.position += self.ratio;
+        }
+        (consumed, output.len())
+    }
+}
+
+impl<const C: usize> Default for Resampler<C> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Interpolate linearly between `a` and `b` at the position `fraction` in
+/// 0.16 format
+fn interpolate(a: i32, b: i32, fraction: u32) -> i32 {
+    let delta = (b as i64 - a as i64) * fraction as i64;
+    (a as i64 + (delta >> 16)) as i32
+}
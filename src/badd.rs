@@ -0,0 +1,267 @@
+//! Descriptor generation and handling of class-specific requests for the
+//! Generic I/O profile of the Universal Serial Bus Device Class Definition for
+//! Basic Audio Devices (BADD), Release 1.0, which is part of USB Audio Class 3.0
+//!
+//! A BADD function is described by its profile and the standard descriptors
+//! only. The topology, the formats and the sampling rate (48 kHz) are implied
+//! by the profile. Each stream carries one or two channels of 16 or 24 bit PCM
+//! audio data.
+//!
+
+use crate::class_codes::{AUDIO, AUDIOCONTROL, AUDIOSTREAMING, MUTE_CONTROL, VOLUME_CONTROL};
+use crate::class_codes_v2::{CS_CLOCK_VALID_CONTROL, CS_SAM_FREQ_CONTROL, CUR, RANGE};
+use crate::class_codes_v3::*;
+use crate::descriptor::DescriptorSink;
+use crate::{
+    accept_param, AudioClass, AudioControlHandler, AudioStream, Format, Rates, StreamConfig,
+    VolumeRange,
+};
+use core::convert::TryInto;
+use usb_device::class_prelude::*;
+use usb_device::endpoint::EndpointDirection;
+
+/// Range of the volume controls of the Feature Units
+const VOLUME_RANGE: VolumeRange = VolumeRange {
+    min: -60 * 256,
+    max: 0,
+    res: 256,
+};
+
+impl StreamConfig<'_> {
+    /// Endpoint size of a stream with a nominal packet size of `ep_size` that
+    /// is compatible with the BADD profiles or `None` if the configuration
+    /// cannot be expressed by a BADD profile, which also does not provide for
+    /// feedback endpoints, service intervals other than 1, additional
+    /// alternate settings, channel configurations, names, Selector Units,
+    /// Extension Units or automatic gain controls. The endpoint must hold one
+    /// additional audio frame since the endpoints are not synchronous.
+    pub(crate) fn badd_ep_size(&self, ep_size: u16) -> Option<u16> {
+        let supported_format = matches!(self.format, Format::S16le | Format::S24le);
+        let supported_rate = matches!(self.rates, Rates::Discrete([BADD_SAMPLING_RATE]));
+        let supported_channels = (1..=2).contains(&self.channels);
+        let supported_endpoint =
+            !self.feedback && self.interval == 1 && self.num_alt_settings() == 1;
+        let supported_cluster = self.channel_config.is_none() && self.channel_names.is_empty();
+        let supported_names = self.terminal_name.is_none();
+        let supported_topology = self.selector_sources.is_empty()
+            && self.extension_unit.is_none()
+            && !self.agc_control
+            && !self.tone_controls
+            && self.equalizer_bands == 0
+            && !self.copy_protect_control
+            && !self.connector_control;
+        if !supported_format
+            || !supported_rate
+            || !supported_channels
+            || !supported_endpoint
+            || !supported_cluster
+            || !supported_names
+            || !supported_topology
+        {
+            return None;
+        }
+        let frame_size = self.channels as u16 * self.format.subframe_size() as u16;
+        Some(ep_size + frame_size)
+    }
+}
+
+impl<B: UsbBus, D: EndpointDirection, const P: usize> AudioStream<'_, B, D, P> {
+    /// ID of the Feature Unit that is part of the signal path of the stream
+    fn badd_feature_unit_id(&self) -> u8 {
+        if self.is_input() {
+            BADD_FU_ID5
+        } else {
+            BADD_FU_ID2
+        }
+    }
+
+    fn write_as_and_ep_descriptors_badd<W: DescriptorSink>(
+        &self,
+        writer: &mut W,
+    ) -> usb_device::Result<()> {
+        // Standard AS Interface Descriptor (Alt. Set. 0)
+        writer.interface_alt(
+            self.interface,
+            0x00,
+            AUDIO,
+            AUDIOSTREAMING,
+            IP_VERSION_03_00,
+            None,
+        )?;
+
+        // Standard AS Interface Descriptor (Alt. Set. 1)
+        self.write_alt_interface_descriptor(writer, 0x01, IP_VERSION_03_00)?;
+
+        // Standard AS Isochronous Audio Data Endpoint Descriptor
+        self.ep_descriptor.write(writer, &[])
+    }
+
+    /// Handle a GET request addressed to the Feature Unit of the stream
+    fn feature_unit_in_badd(&self, xfer: ControlIn<B>) {
+        let req = *xfer.request();
+        let cs = (req.value >> 8) as u8;
+        let channel = req.value as u8;
+        match (req.request, cs) {
+            (CUR, MUTE_CONTROL) if channel == 0 => accept_param(xfer, &[self.mute[0] as u8]),
+            (CUR, VOLUME_CONTROL) if (1..=self.stream_config.channels).contains(&channel) => {
+                accept_param(xfer, &self.volume[channel as usize].to_le_bytes())
+            }
+            (RANGE, VOLUME_CONTROL) if (1..=self.stream_config.channels).contains(&channel) => {
+                let mut buf = [0u8; 8];
+                buf[0..2].copy_from_slice(&1u16.to_le_bytes()); // wNumSubRanges
+                buf[2..4].copy_from_slice(&VOLUME_RANGE.min.to_le_bytes());
+                buf[4..6].copy_from_slice(&VOLUME_RANGE.max.to_le_bytes());
+                buf[6..8].copy_from_slice(&VOLUME_RANGE.res.to_le_bytes());
+                xfer.accept_with(&buf)
+            }
+            _ => xfer.reject(),
+        }
+        .ok();
+    }
+
+    /// Handle a SET request addressed to the Feature Unit of the stream
+    fn feature_unit_out_badd(
+        &mut self,
+        xfer: ControlOut<B>,
+        handler: Option<&mut (dyn AudioControlHandler + Send + '_)>,
+    ) {
+        let req = *xfer.request();
+        let cs = (req.value >> 8) as u8;
+        let channel = req.value as u8;
+        match (req.request, cs, xfer.data()) {
+            (CUR, MUTE_CONTROL, &[mute]) if channel == 0 => {
+                if self.set_mute(0, mute != 0, handler) {
+                    xfer.accept()
+                } else {
+                    xfer.reject()
+                }
+            }
+            (CUR, VOLUME_CONTROL, &[lsb, msb])
+                if (1..=self.stream_config.channels).contains(&channel) =>
+            {
+                let volume = i16::from_le_bytes([lsb, msb]);
+                if self.set_volume(channel, volume, VOLUME_RANGE, handler) {
+                    xfer.accept()
+                } else {
+                    xfer.reject()
+                }
+            }
+            _ => xfer.reject(),
+        }
+        .ok();
+    }
+}
+
+impl<B: UsbBus, const P: usize> AudioClass<'_, B, P> {
+    pub(crate) fn write_descriptors_badd<W: DescriptorSink>(
+        &self,
+        writer: &mut W,
+    ) -> usb_device::Result<()> {
+        let in_collection =
+            self.inputs.iter().flatten().count() + self.outputs.iter().flatten().count();
+
+        // Interface Association Descriptor
+        writer.iad(
+            self.control_iface,
+            1 + in_collection as u8,
+            AUDIO,
+            FUNCTION_SUBCLASS_GENERIC_IO,
+            AF_VERSION_03_00,
+            self.function_string(),
+        )?;
+
+        // Standard AC Interface Descriptor
+        writer.interface_alt(
+            self.control_iface,
+            0x00,
+            AUDIO,
+            AUDIOCONTROL,
+            IP_VERSION_03_00,
+            self.function_string(),
+        )?;
+
+        // write Audio Streaming (AS) and endpoint (EP) descriptors
+        for a in self.inputs.iter().flatten() {
+            a.write_as_and_ep_descriptors_badd(writer)?;
+        }
+        for a in self.outputs.iter().flatten() {
+            a.write_as_and_ep_descriptors_badd(writer)?;
+        }
+        Ok(())
+    }
+
+    /// Handle a class-specific GET request addressed to an entity of the
+    /// AudioControl interface
+    pub(crate) fn entity_control_in_badd(&mut self, xfer: ControlIn<B>) {
+        let req = *xfer.request();
+        let entity = (req.index >> 8) as u8;
+        let cs = (req.value >> 8) as u8;
+        let channel = req.value as u8;
+        if entity == BADD_CS_ID9 {
+            match (req.request, cs, channel) {
+                (CUR, CS_SAM_FREQ_CONTROL, 0) => {
+                    accept_param(xfer, &BADD_SAMPLING_RATE.to_le_bytes())
+                }
+                (RANGE, CS_SAM_FREQ_CONTROL, 0) => {
+                    let mut buf = [0u8; 14];
+                    buf[0..2].copy_from_slice(&1u16.to_le_bytes()); // wNumSubRanges
+                    buf[2..6].copy_from_slice(&BADD_SAMPLING_RATE.to_le_bytes());
+                    buf[6..10].copy_from_slice(&BADD_SAMPLING_RATE.to_le_bytes());
+                    xfer.accept_with(&buf)
+                }
+                (CUR, CS_CLOCK_VALID_CONTROL, 0) => accept_param(xfer, &[0x01]),
+                _ => xfer.reject(),
+            }
+            .ok();
+            return;
+        }
+        for info in self.inputs.iter().flatten() {
+            if entity == info.badd_feature_unit_id() {
+                info.feature_unit_in_badd(xfer);
+                return;
+            }
+        }
+        for info in self.outputs.iter().flatten() {
+            if entity == info.badd_feature_unit_id() {
+                info.feature_unit_in_badd(xfer);
+                return;
+            }
+        }
+        xfer.reject().ok();
+    }
+
+    /// Handle a class-specific SET request addressed to an entity of the
+    /// AudioControl interface
+    pub(crate) fn entity_control_out_badd(&mut self, xfer: ControlOut<B>) {
+        let req = *xfer.request();
+        let entity = (req.index >> 8) as u8;
+        let cs = (req.value >> 8) as u8;
+        let channel = req.value as u8;
+        if entity == BADD_CS_ID9 {
+            let rate = xfer.data().try_into().ok().map(u32::from_le_bytes);
+            if req.request == CUR
+                && cs == CS_SAM_FREQ_CONTROL
+                && channel == 0
+                && rate == Some(BADD_SAMPLING_RATE)
+            {
+                xfer.accept().ok();
+            } else {
+                xfer.reject().ok();
+            }
+            return;
+        }
+        for info in self.inputs.iter_mut().flatten() {
+            if entity == info.badd_feature_unit_id() {
+                info.feature_unit_out_badd(xfer, self.control_handler.as_deref_mut());
+                return;
+            }
+        }
+        for info in self.outputs.iter_mut().flatten() {
+            if entity == info.badd_feature_unit_id() {
+                info.feature_unit_out_badd(xfer, self.control_handler.as_deref_mut());
+                return;
+            }
+        }
+        xfer.reject().ok();
+    }
+}
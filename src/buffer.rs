@@ -0,0 +1,309 @@
+//! Sample buffers decoupling the audio data of streams from `UsbDevice::poll()`
+//!
+//! A `SampleBuffer` is a lock-free single-producer single-consumer ring buffer.
+//! `AudioClass::split_buffered()` attaches buffers to the first input and the
+//! first output stream and returns their application ends: a `Producer` into
+//! which e.g. the codec interrupt pushes the samples to be sent to the host and
+//! a `Consumer` from which it pulls the samples received from the host. The
+//! class services the data endpoints from the other ends whenever the device
+//! is polled, so neither side needs a critical section around the class.
+//!
+//! `AudioClass::set_buffer_depth()` turns a buffer into an elastic buffer that
+//! absorbs scheduling jitter: its consumer holds the data back until the
+//! buffer is filled to the configured depth and again after it ran empty.
+//!
+//! The buffers only use atomic loads and stores and are therefore also
+//! available on cores without compare-and-swap instructions.
+
+use core::cell::UnsafeCell;
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+/// Depth of an elastic buffer set by `AudioClass::set_buffer_depth()`
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum BufferDepth {
+    /// Number of audio frames
+    Frames(u32),
+    /// Duration in milliseconds at the current sampling rate
+    Millis(u32),
+}
+
+impl BufferDepth {
+    /// Depth in bytes for the indicated frame size and sampling rate
+    pub(crate) fn bytes(self, frame_size: usize, sampling_rate: u32) -> usize {
+        let frames = match self {
+            BufferDepth::Frames(frames) => frames as u64,
+            BufferDepth::Millis(ms) => ms as u64 * sampling_rate as u64 / 1000,
+        };
+        (frames as usize).saturating_mul(frame_size)
+    }
+}
+
+/// Ring buffer with a capacity of `N - 1` bytes
+pub struct SampleBuffer<const N: usize> {
+    data: [UnsafeCell<u8>; N],
+    /// index of the next byte to be written by the producer
+    head: AtomicUsize,
+    /// index of the next byte to be read by the consumer
+    tail: AtomicUsize,
+    /// number of bytes the buffer must hold before the consumer reads them
+    threshold: AtomicUsize,
+    /// whether the threshold has been reached since the buffer ran empty
+    primed: AtomicBool,
+}
+
+impl<const N: usize> SampleBuffer<N> {
+    /// Create an empty buffer, usable as initializer of a static. `N` must be
+    /// at least 2.
+    pub const fn new() -> Self {
+        const { assert!(N > 1) };
+        SampleBuffer {
+            data: [const { UnsafeCell::new(0) }; N],
+            head: AtomicUsize::new(0),
+            tail: AtomicUsize::new(0),
+            threshold: AtomicUsize::new(0),
+            primed: AtomicBool::new(false),
+        }
+    }
+
+    /// Split the buffer into its producer and consumer end
+    pub fn split(&mut self) -> (Producer<'_>, Consumer<'_>) {
+        let ring = Ring {
+            data: &self.data,
+            head: &self.head,
+            tail: &self.tail,
+            threshold: &self.threshold,
+            primed: &self.primed,
+        };
+        (Producer { ring }, Consumer { ring })
+    }
+}
+
+impl<const N: usize> Default for SampleBuffer<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Storage and indices of a `SampleBuffer` shared by its ends
+#[derive(Clone, Copy)]
+struct Ring<'a> {
+    data: &'a [UnsafeCell<u8>],
+    head: &'a AtomicUsize,
+    tail: &'a AtomicUsize,
+    threshold: &'a AtomicUsize,
+    primed: &'a AtomicBool,
+}
+
+impl Ring<'_> {
+    /// Number of bytes between the indices `from` and `to`
+    fn distance(&self, from: usize, to: usize) -> usize {
+        (to + self.data.len() - from) % self.data.len()
+    }
+
+    fn len(&self) -> usize {
+        let head = self.head.load(Ordering::Acquire);
+        let tail = self.tail.load(Ordering::Acquire);
+        self.distance(tail, head)
+    }
+
+    fn capacity(&self) -> usize {
+        self.data.len().saturating_sub(1)
+    }
+
+    /// Set the number of bytes held back until the buffer has been filled,
+    /// limited to the capacity
+    fn set_threshold(&self, threshold: usize) {
+        self.threshold
+            .store(threshold.min(self.capacity()), Ordering::Relaxed);
+    }
+}
+
+/// End of a `SampleBuffer` that writes data
+pub struct Producer<'a> {
+    ring: Ring<'a>,
+}
+
+// SAFETY: only the producer writes the bytes between head and tail and it
+// publishes them by a release store of head.
+unsafe impl Send for Producer<'_> {}
+
+impl Producer<'_> {
+    /// Number of bytes waiting in the buffer
+    pub fn len(&self) -> usize {
+        self.ring.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Number of bytes that can be pushed without overflowing the buffer
+    pub fn free(&self) -> usize {
+        self.ring.capacity() - self.ring.len()
+    }
+
+    /// Append as many bytes of `data` as fit into the buffer and return their
+    /// number
+    pub fn push(&mut self, data: &[u8]) -> usize {
+        let ring = &self.ring;
+        let len = data.len().min(self.free());
+        let mut head = ring.head.load(Ordering::Relaxed);
+        for &byte in &data[..len] {
+            // SAFETY: the consumer does not access the free bytes
+            unsafe { *ring.data[head].get() = byte };
+            head = (head + 1) % ring.data.len();
+        }
+        ring.head.store(head, Ordering::Release);
+        len
+    }
+
+    pub(crate) fn set_threshold(&self, threshold: usize) {
+        self.ring.set_threshold(threshold);
+    }
+}
+
+/// End of a `SampleBuffer` that reads data
+pub struct Consumer<'a> {
+    ring: Ring<'a>,
+}
+
+// SAFETY: only the consumer reads the bytes between tail and head and it
+// releases them by a release store of tail.
+unsafe impl Send for Consumer<'_> {}
+
+impl Consumer<'_> {
+    /// Number of bytes waiting in the buffer
+    pub fn len(&self) -> usize {
+        self.ring.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Number of bytes that can be popped, which is zero while the buffer
+    /// has not been filled to the depth set by `AudioClass::set_buffer_depth()`
+    pub fn available(&self) -> usize {
+        let ring = &self.ring;
+        let len = ring.len();
+        if !ring.primed.load(Ordering::Relaxed) {
+            if len == 0 || len < ring.threshold.load(Ordering::Relaxed) {
+                return 0;
+            }
+            ring.primed.store(true, Ordering::Relaxed);
+        }
+        len
+    }
+
+    /// Remove up to `data.len()` bytes from the buffer into `data` and return
+    /// their number. See `available()`.
+    pub fn pop(&mut self, data: &mut [u8]) -> usize {
+        let available = self.available();
+        let ring = &self.ring;
+        let len = data.len().min(available);
+        if len == available {
+            // the buffer runs empty and is filled to the depth again
+            ring.primed.store(false, Ordering::Relaxed);
+        }
+        let mut tail = ring.tail.load(Ordering::Relaxed);
+        for byte in &mut data[..len] {
+            // SAFETY: the producer does not access the occupied bytes
+            *byte = unsafe { *ring.data[tail].get() };
+            tail = (tail + 1) % ring.data.len();
+        }
+        ring.tail.store(tail, Ordering::Release);
+        len
+    }
+
+    pub(crate) fn set_threshold(&self, threshold: usize) {
+        self.ring.set_threshold(threshold);
+    }
+}
+
+/// Distribution of the audio frames of an input stream to packets at rates
+/// that are not a multiple of the packet rate, e.g. alternately 44 and 45
+/// frames per 1 ms packet at 44.1 kHz
+pub(crate) struct Pacer {
+    /// number of packets per second
+    packet_rate: u32,
+    /// fraction of an audio frame carried over to the next packet, in units of
+    /// 1 / `packet_rate`
+    remainder: u32,
+}
+
+impl Pacer {
+    pub(crate) fn new(packet_rate: u32) -> Self {
+        Pacer {
+            packet_rate: packet_rate.max(1),
+            remainder: 0,
+        }
+    }
+
+    /// Number of audio frames of the next packet, which is only taken from
+    /// the distribution by `commit()` once the packet has been written
+    pub(crate) fn peek(&self, sampling_rate: u32) -> usize {
+        ((sampling_rate + self.remainder) / self.packet_rate) as usize
+    }
+
+    /// Carry the fraction of the packet counted by `peek()` over to the next
+    /// packet
+    pub(crate) fn commit(&mut self, sampling_rate: u32) {
+        self.remainder = (sampling_rate + self.remainder) % self.packet_rate;
+    }
+
+    /// Start over with the next packet, e.g. when the stream is restarted
+    pub(crate) fn reset(&mut self) {
+        self.remainder = 0;
+    }
+}
+
+/// State of the class transmitting the data of an input stream from the
+/// consumer end of a sample buffer
+pub(crate) struct TxBuffer<'a, const P: usize> {
+    pub(crate) consumer: Consumer<'a>,
+    /// whether a packet has been written to the endpoint and not yet been
+    /// transmitted
+    pub(crate) pending: bool,
+    /// previous packet repeated by `UnderrunPolicy::Repeat`
+    pub(crate) last: [u8; P],
+}
+
+impl<'a, const P: usize> TxBuffer<'a, P> {
+    pub(crate) fn new(consumer: Consumer<'a>) -> Self {
+        TxBuffer {
+            consumer,
+            pending: false,
+            last: [0; P],
+        }
+    }
+
+    /// Length of the next packet and the length required by the sampling
+    /// rate, which is larger if data is lacking. The packet contains the
+    /// number of audio frames determined by `pacer`, one frame more if the
+    /// buffer fills up, and is limited to the data available and the maximum
+    /// packet size. Type II formats, which have no frame size, fill the packet.
+    pub(crate) fn packet_len(
+        &self,
+        pacer: &Pacer,
+        sampling_rate: u32,
+        frame_size: Option<usize>,
+        max_packet_size: usize,
+    ) -> (usize, usize) {
+        let available = self.consumer.available();
+        let Some(frame_size) = frame_size.filter(|&size| size > 0) else {
+            let len = available.min(max_packet_size);
+            return (len, len);
+        };
+        let align = |len: usize| len - len % frame_size;
+        let mut len = pacer.peek(sampling_rate) * frame_size;
+        let required = len.min(align(max_packet_size));
+        if available > 2 * len {
+            len += frame_size;
+        }
+        (
+            len.min(align(max_packet_size)).min(align(available)),
+            required,
+        )
+    }
+}
@@ -0,0 +1,105 @@
+//! Spatial locations of the channels of a cluster according to Universal
+//! Serial Bus Device Class Definition for Audio Devices, Release 1.0, section
+//! 3.7.2.3 (wChannelConfig) and Release 2.0, section 4.1 (bmChannelConfig)
+//!
+
+use core::ops::BitOr;
+
+/// Spatial locations of the logical channels of a stream. The channels are
+/// assigned to the locations in ascending order of the bits. Channels beyond
+/// the number of locations are non-predefined. The locations up to
+/// `TOP_CENTER` are common to USB Audio Class 1.0 (wChannelConfig) and 2.0
+/// (bmChannelConfig), the remaining ones exist for USB Audio Class 2.0 only.
+#[derive(Copy, Clone, Default, Eq, PartialEq, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct ChannelConfig(u32);
+
+impl ChannelConfig {
+    pub const FRONT_LEFT: Self = Self(1 << 0);
+    pub const FRONT_RIGHT: Self = Self(1 << 1);
+    pub const FRONT_CENTER: Self = Self(1 << 2);
+    pub const LOW_FREQUENCY_EFFECTS: Self = Self(1 << 3);
+    /// Back Left (Left Surround in USB Audio Class 1.0)
+    pub const BACK_LEFT: Self = Self(1 << 4);
+    /// Back Right (Right Surround in USB Audio Class 1.0)
+    pub const BACK_RIGHT: Self = Self(1 << 5);
+    pub const FRONT_LEFT_OF_CENTER: Self = Self(1 << 6);
+    pub const FRONT_RIGHT_OF_CENTER: Self = Self(1 << 7);
+    /// Back Center (Surround in USB Audio Class 1.0)
+    pub const BACK_CENTER: Self = Self(1 << 8);
+    pub const SIDE_LEFT: Self = Self(1 << 9);
+    pub const SIDE_RIGHT: Self = Self(1 << 10);
+    pub const TOP_CENTER: Self = Self(1 << 11);
+    pub const TOP_FRONT_LEFT: Self = Self(1 << 12);
+    pub const TOP_FRONT_CENTER: Self = Self(1 << 13);
+    pub const TOP_FRONT_RIGHT: Self = Self(1 << 14);
+    pub const TOP_BACK_LEFT: Self = Self(1 << 15);
+    pub const TOP_BACK_CENTER: Self = Self(1 << 16);
+    pub const TOP_BACK_RIGHT: Self = Self(1 << 17);
+    pub const TOP_FRONT_LEFT_OF_CENTER: Self = Self(1 << 18);
+    pub const TOP_FRONT_RIGHT_OF_CENTER: Self = Self(1 << 19);
+    pub const LEFT_LOW_FREQUENCY_EFFECTS: Self = Self(1 << 20);
+    pub const RIGHT_LOW_FREQUENCY_EFFECTS: Self = Self(1 << 21);
+    pub const TOP_SIDE_LEFT: Self = Self(1 << 22);
+    pub const TOP_SIDE_RIGHT: Self = Self(1 << 23);
+    pub const BOTTOM_CENTER: Self = Self(1 << 24);
+    pub const BACK_LEFT_OF_CENTER: Self = Self(1 << 25);
+    pub const BACK_RIGHT_OF_CENTER: Self = Self(1 << 26);
+
+    /// No predefined spatial locations
+    pub const NONE: Self = Self(0);
+    pub const MONO: Self = Self::FRONT_CENTER;
+    pub const STEREO: Self = Self(Self::FRONT_LEFT.0 | Self::FRONT_RIGHT.0);
+    pub const SURROUND_5_1: Self = Self(
+        Self::STEREO.0
+            | Self::FRONT_CENTER.0
+            | Self::LOW_FREQUENCY_EFFECTS.0
+            | Self::BACK_LEFT.0
+            | Self::BACK_RIGHT.0,
+    );
+    pub const SURROUND_7_1: Self =
+        Self(Self::SURROUND_5_1.0 | Self::SIDE_LEFT.0 | Self::SIDE_RIGHT.0);
+
+    /// Spatial locations defined by USB Audio Class 1.0
+    pub(crate) const UAC1_MASK: u32 = (1 << 12) - 1;
+
+    /// Create a channel configuration from a raw bitmap
+    pub const fn from_bits(bits: u32) -> Self {
+        Self(bits)
+    }
+
+    /// Raw bitmap of the channel configuration
+    pub const fn bits(self) -> u32 {
+        self.0
+    }
+
+    /// Number of spatial locations, i.e. the number of channels with a
+    /// predefined location
+    pub const fn num_locations(self) -> u8 {
+        self.0.count_ones() as u8
+    }
+
+    /// Locations of the first `channels` channels, for a cluster that is
+    /// reduced to fewer channels
+    pub(crate) fn truncate(self, channels: u8) -> Self {
+        let mut bits = self.0;
+        while bits.count_ones() > channels as u32 {
+            bits &= !(1 << (31 - bits.leading_zeros()));
+        }
+        Self(bits)
+    }
+}
+
+impl BitOr for ChannelConfig {
+    type Output = Self;
+
+    fn bitor(self, rhs: Self) -> Self {
+        Self(self.0 | rhs.0)
+    }
+}
+
+impl From<ChannelConfig> for u32 {
+    fn from(c: ChannelConfig) -> u32 {
+        c.0
+    }
+}
@@ -0,0 +1,133 @@
+//! Audio Device Class Codes as defined in Universal Serial Bus Device Class
+//! Definition for Audio Devices, Release 2.0, Appendix A and Universal Serial
+//! Bus Device Class Definition for Audio Data Formats, Release 2.0, Appendix
+//! A.2 (Audio Data Format Bit Allocations)
+//!
+//! Only codes that are new in Release 2.0 or whose value differs from Release
+//! 1.0 are listed here. All other codes are found in `class_codes`.
+//!
+#![allow(dead_code)]
+
+// Audio Function Class Code
+pub const AUDIO_FUNCTION: u8 = 0x01;
+
+// Audio Function Subclass Codes
+pub const FUNCTION_SUBCLASS_UNDEFINED: u8 = 0x00;
+
+// Audio Function Protocol Codes
+pub const FUNCTION_PROTOCOL_UNDEFINED: u8 = 0x00;
+pub const AF_VERSION_02_00: u8 = 0x20;
+
+// Audio Interface Protocol Codes
+pub const INTERFACE_PROTOCOL_UNDEFINED: u8 = 0x00;
+pub const IP_VERSION_02_00: u8 = 0x20;
+
+// Audio Function Category Codes
+pub const CATEGORY_UNDEFINED: u8 = 0x00;
+pub const DESKTOP_SPEAKER: u8 = 0x01;
+pub const HOME_THEATER: u8 = 0x02;
+pub const MICROPHONE: u8 = 0x03;
+pub const HEADSET: u8 = 0x04;
+pub const TELEPHONE: u8 = 0x05;
+pub const CONVERTER: u8 = 0x06;
+pub const VOICE_SOUND_RECORDER: u8 = 0x07;
+pub const IO_BOX: u8 = 0x08;
+pub const MUSICAL_INSTRUMENT: u8 = 0x09;
+pub const PRO_AUDIO: u8 = 0x0A;
+pub const AUDIO_VIDEO: u8 = 0x0B;
+pub const CONTROL_PANEL: u8 = 0x0C;
+pub const OTHER: u8 = 0xFF;
+
+// Audio Class-Specific AC Interface Descriptor Subtypes
+pub const EFFECT_UNIT: u8 = 0x07;
+pub const PROCESSING_UNIT_V2: u8 = 0x08;
+pub const EXTENSION_UNIT_V2: u8 = 0x09;
+pub const CLOCK_SOURCE: u8 = 0x0A;
+pub const CLOCK_SELECTOR: u8 = 0x0B;
+pub const CLOCK_MULTIPLIER: u8 = 0x0C;
+pub const SAMPLE_RATE_CONVERTER: u8 = 0x0D;
+
+// Audio Class-Specific Request Codes
+pub const REQUEST_CODE_UNDEFINED: u8 = 0x00;
+pub const CUR: u8 = 0x01;
+pub const RANGE: u8 = 0x02;
+pub const MEM: u8 = 0x03;
+
+// Clock Source Control Selectors
+pub const CS_CONTROL_UNDEFINED: u8 = 0x00;
+pub const CS_SAM_FREQ_CONTROL: u8 = 0x01;
+pub const CS_CLOCK_VALID_CONTROL: u8 = 0x02;
+
+// Clock Selector Control Selectors
+pub const CX_CONTROL_UNDEFINED: u8 = 0x00;
+pub const CX_CLOCK_SELECTOR_CONTROL: u8 = 0x01;
+
+// Mixer Unit Control Selectors
+pub const MU_CONTROL_UNDEFINED: u8 = 0x00;
+pub const MU_MIXER_CONTROL: u8 = 0x01;
+pub const MU_CLUSTER_CONTROL: u8 = 0x02;
+pub const MU_UNDERFLOW_CONTROL: u8 = 0x03;
+pub const MU_OVERFLOW_CONTROL: u8 = 0x04;
+pub const MU_LATENCY_CONTROL: u8 = 0x05;
+
+// Selector Unit Control Selectors
+pub const SU_CONTROL_UNDEFINED: u8 = 0x00;
+pub const SU_SELECTOR_CONTROL: u8 = 0x01;
+
+// Clock Multiplier Control Selectors
+pub const CM_CONTROL_UNDEFINED: u8 = 0x00;
+pub const CM_NUMERATOR_CONTROL: u8 = 0x01;
+pub const CM_DENOMINATOR_CONTROL: u8 = 0x02;
+
+// Terminal Control Selectors
+pub const TE_CONTROL_UNDEFINED: u8 = 0x00;
+pub const TE_COPY_PROTECT_CONTROL: u8 = 0x01;
+pub const TE_CONNECTOR_CONTROL: u8 = 0x02;
+pub const TE_OVERLOAD_CONTROL: u8 = 0x03;
+pub const TE_CLUSTER_CONTROL: u8 = 0x04;
+pub const TE_UNDERFLOW_CONTROL: u8 = 0x05;
+pub const TE_OVERFLOW_CONTROL: u8 = 0x06;
+pub const TE_LATENCY_CONTROL: u8 = 0x07;
+
+// AudioStreaming Interface Control Selectors
+pub const AS_CONTROL_UNDEFINED: u8 = 0x00;
+pub const AS_ACT_ALT_SETTING_CONTROL: u8 = 0x01;
+pub const AS_VAL_ALT_SETTINGS_CONTROL: u8 = 0x02;
+pub const AS_AUDIO_DATA_FORMAT_CONTROL: u8 = 0x03;
+
+// Endpoint Control Selectors
+pub const EP_CONTROL_UNDEFINED: u8 = 0x00;
+pub const EP_PITCH_CONTROL: u8 = 0x01;
+pub const EP_DATA_OVERRUN_CONTROL: u8 = 0x02;
+pub const EP_DATA_UNDERRUN_CONTROL: u8 = 0x03;
+
+// Audio Data Format Type I Bit Allocations
+pub const PCM: u32 = 1 << 0;
+pub const PCM8: u32 = 1 << 1;
+pub const IEEE_FLOAT: u32 = 1 << 2;
+pub const ALAW: u32 = 1 << 3;
+pub const MULAW: u32 = 1 << 4;
+pub const TYPE_I_RAW_DATA: u32 = 1 << 31;
+
+// Audio Data Format Type II Bit Allocations
+pub const MPEG_FORMAT: u32 = 1 << 0;
+pub const AC3_FORMAT: u32 = 1 << 1;
+pub const WMA_FORMAT: u32 = 1 << 2;
+pub const DTS_FORMAT: u32 = 1 << 3;
+pub const TYPE_II_RAW_DATA: u32 = 1 << 31;
+
+// Audio Data Format Type III Bit Allocations
+pub const IEC61937_AC3: u32 = 1 << 0;
+pub const IEC61937_MPEG1_LAYER1: u32 = 1 << 1;
+pub const IEC61937_MPEG1_LAYER2_3: u32 = 1 << 2;
+pub const IEC61937_MPEG2_NOEXT: u32 = 1 << 2;
+pub const IEC61937_MPEG2_EXT: u32 = 1 << 3;
+pub const IEC61937_MPEG2_AAC_ADTS: u32 = 1 << 4;
+pub const IEC61937_MPEG2_LAYER1_LS: u32 = 1 << 5;
+pub const IEC61937_MPEG2_LAYER2_3_LS: u32 = 1 << 6;
+pub const IEC61937_DTS_I: u32 = 1 << 7;
+pub const IEC61937_DTS_II: u32 = 1 << 8;
+pub const IEC61937_DTS_III: u32 = 1 << 9;
+pub const IEC61937_ATRAC: u32 = 1 << 10;
+pub const IEC61937_ATRAC2_3: u32 = 1 << 11;
+pub const TYPE_III_WMA: u32 = 1 << 12;
@@ -0,0 +1,39 @@
+//! Audio Device Class Codes as defined in Universal Serial Bus Device Class
+//! Definition for Audio Devices, Release 3.0, Appendix A and Universal Serial
+//! Bus Device Class Definition for Basic Audio Devices, Release 1.0
+//!
+//! Only codes that are required for Basic Audio Device Definition (BADD)
+//! profiles are listed here.
+//!
+#![allow(dead_code)]
+
+// Audio Function Protocol Codes
+pub const AF_VERSION_03_00: u8 = 0x30;
+
+// Audio Interface Protocol Codes
+pub const IP_VERSION_03_00: u8 = 0x30;
+
+// Audio Function Subclass Codes
+pub const FUNCTION_SUBCLASS_FULL_ADC_3_0: u8 = 0x01;
+pub const FUNCTION_SUBCLASS_GENERIC_IO: u8 = 0x20;
+pub const FUNCTION_SUBCLASS_HEADPHONE: u8 = 0x21;
+pub const FUNCTION_SUBCLASS_SPEAKER: u8 = 0x22;
+pub const FUNCTION_SUBCLASS_MICROPHONE: u8 = 0x23;
+pub const FUNCTION_SUBCLASS_HEADSET: u8 = 0x24;
+pub const FUNCTION_SUBCLASS_HEADSET_ADAPTER: u8 = 0x25;
+pub const FUNCTION_SUBCLASS_SPEAKERPHONE: u8 = 0x26;
+
+// Fixed Entity IDs of the BADD profiles
+pub const BADD_IT_ID1: u8 = 1;
+pub const BADD_FU_ID2: u8 = 2;
+pub const BADD_OT_ID3: u8 = 3;
+pub const BADD_IT_ID4: u8 = 4;
+pub const BADD_FU_ID5: u8 = 5;
+pub const BADD_OT_ID6: u8 = 6;
+pub const BADD_FU_ID7: u8 = 7;
+pub const BADD_MU_ID8: u8 = 8;
+pub const BADD_CS_ID9: u8 = 9;
+pub const BADD_PD_ID10: u8 = 10;
+
+// Sampling rate of all BADD profiles
+pub const BADD_SAMPLING_RATE: u32 = 48000;
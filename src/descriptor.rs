@@ -0,0 +1,251 @@
+//! Destinations of the descriptors written by the class
+//!
+//! The class writes its descriptors through `DescriptorSink`, which is
+//! implemented by the `DescriptorWriter` of `usb-device` answering the
+//! requests of the host and by `DescriptorBuffer`, which
+//! `AudioClass::dump_descriptors()` uses to copy the descriptors into a buffer
+//! of the application since `usb-device` does not let classes create a
+//! `DescriptorWriter`.
+
+use usb_device::class_prelude::*;
+use usb_device::descriptor::descriptor_type;
+use usb_device::device::DEFAULT_ALTERNATE_SETTING;
+use usb_device::endpoint::{Endpoint, EndpointDirection};
+
+/// The methods of `DescriptorWriter` used by the class
+pub(crate) trait DescriptorSink {
+    fn write_with(
+        &mut self,
+        descriptor_type: u8,
+        f: impl FnOnce(&mut [u8]) -> usb_device::Result<usize>,
+    ) -> usb_device::Result<()>;
+
+    fn iad(
+        &mut self,
+        first_interface: InterfaceNumber,
+        interface_count: u8,
+        function_class: u8,
+        function_sub_class: u8,
+        function_protocol: u8,
+        function_string: Option<StringIndex>,
+    ) -> usb_device::Result<()>;
+
+    fn interface_alt(
+        &mut self,
+        number: InterfaceNumber,
+        alternate_setting: u8,
+        interface_class: u8,
+        interface_sub_class: u8,
+        interface_protocol: u8,
+        interface_string: Option<StringIndex>,
+    ) -> usb_device::Result<()>;
+
+    fn endpoint_ex<B: UsbBus, D: EndpointDirection>(
+        &mut self,
+        endpoint: &Endpoint<'_, B, D>,
+        f: impl FnOnce(&mut [u8]) -> usb_device::Result<usize>,
+    ) -> usb_device::Result<()>;
+
+    fn write(&mut self, descriptor_type: u8, descriptor: &[u8]) -> usb_device::Result<()> {
+        self.write_with(descriptor_type, |buf| {
+            buf.get_mut(..descriptor.len())
+                .ok_or(UsbError::BufferOverflow)?
+                .copy_from_slice(descriptor);
+            Ok(descriptor.len())
+        })
+    }
+
+    fn interface(
+        &mut self,
+        number: InterfaceNumber,
+        interface_class: u8,
+        interface_sub_class: u8,
+        interface_protocol: u8,
+    ) -> usb_device::Result<()> {
+        self.interface_alt(
+            number,
+            DEFAULT_ALTERNATE_SETTING,
+            interface_class,
+            interface_sub_class,
+            interface_protocol,
+            None,
+        )
+    }
+
+    fn endpoint<B: UsbBus, D: EndpointDirection>(
+        &mut self,
+        endpoint: &Endpoint<'_, B, D>,
+    ) -> usb_device::Result<()> {
+        self.endpoint_ex(endpoint, |_| Ok(0))
+    }
+}
+
+impl DescriptorSink for DescriptorWriter<'_> {
+    fn write_with(
+        &mut self,
+        descriptor_type: u8,
+        f: impl FnOnce(&mut [u8]) -> usb_device::Result<usize>,
+    ) -> usb_device::Result<()> {
+        DescriptorWriter::write_with(self, descriptor_type, f)
+    }
+
+    fn iad(
+        &mut self,
+        first_interface: InterfaceNumber,
+        interface_count: u8,
+        function_class: u8,
+        function_sub_class: u8,
+        function_protocol: u8,
+        function_string: Option<StringIndex>,
+    ) -> usb_device::Result<()> {
+        DescriptorWriter::iad(
+            self,
+            first_interface,
+            interface_count,
+            function_class,
+            function_sub_class,
+            function_protocol,
+            function_string,
+        )
+    }
+
+    fn interface_alt(
+        &mut self,
+        number: InterfaceNumber,
+        alternate_setting: u8,
+        interface_class: u8,
+        interface_sub_class: u8,
+        interface_protocol: u8,
+        interface_string: Option<StringIndex>,
+    ) -> usb_device::Result<()> {
+        DescriptorWriter::interface_alt(
+            self,
+            number,
+            alternate_setting,
+            interface_class,
+            interface_sub_class,
+            interface_protocol,
+            interface_string,
+        )
+    }
+
+    fn endpoint_ex<B: UsbBus, D: EndpointDirection>(
+        &mut self,
+        endpoint: &Endpoint<'_, B, D>,
+        f: impl FnOnce(&mut [u8]) -> usb_device::Result<usize>,
+    ) -> usb_device::Result<()> {
+        DescriptorWriter::endpoint_ex(self, endpoint, f)
+    }
+}
+
+/// Buffer receiving the descriptors in the layout of `DescriptorWriter`,
+/// except that the Interface Association Descriptor is always written
+pub(crate) struct DescriptorBuffer<'b> {
+    buf: &'b mut [u8],
+    position: usize,
+    /// position of bNumEndpoints of the last interface descriptor
+    num_endpoints_mark: Option<usize>,
+}
+
+impl<'b> DescriptorBuffer<'b> {
+    pub(crate) fn new(buf: &'b mut [u8]) -> Self {
+        DescriptorBuffer {
+            buf,
+            position: 0,
+            num_endpoints_mark: None,
+        }
+    }
+
+    /// Number of bytes written
+    pub(crate) fn position(&self) -> usize {
+        self.position
+    }
+}
+
+impl DescriptorSink for DescriptorBuffer<'_> {
+    fn write_with(
+        &mut self,
+        descriptor_type: u8,
+        f: impl FnOnce(&mut [u8]) -> usb_device::Result<usize>,
+    ) -> usb_device::Result<()> {
+        let buf = self
+            .buf
+            .get_mut(self.position..)
+            .filter(|buf| buf.len() >= 2)
+            .ok_or(UsbError::BufferOverflow)?;
+        let end = buf.len().min(u8::MAX as usize);
+        let length = f(&mut buf[2..end])? + 2;
+        buf[0] = length as u8; // bLength
+        buf[1] = descriptor_type; // bDescriptorType
+        self.position += length;
+        Ok(())
+    }
+
+    fn iad(
+        &mut self,
+        first_interface: InterfaceNumber,
+        interface_count: u8,
+        function_class: u8,
+        function_sub_class: u8,
+        function_protocol: u8,
+        function_string: Option<StringIndex>,
+    ) -> usb_device::Result<()> {
+        self.write(
+            descriptor_type::IAD,
+            &[
+                first_interface.into(),                // bFirstInterface
+                interface_count,                       // bInterfaceCount
+                function_class,                        // bFunctionClass
+                function_sub_class,                    // bFunctionSubClass
+                function_protocol,                     // bFunctionProtocol
+                function_string.map_or(0, Into::into), // iFunction
+            ],
+        )
+    }
+
+    fn interface_alt(
+        &mut self,
+        number: InterfaceNumber,
+        alternate_setting: u8,
+        interface_class: u8,
+        interface_sub_class: u8,
+        interface_protocol: u8,
+        interface_string: Option<StringIndex>,
+    ) -> usb_device::Result<()> {
+        self.num_endpoints_mark = Some(self.position + 4);
+        self.write(
+            descriptor_type::INTERFACE,
+            &[
+                number.into(),                          // bInterfaceNumber
+                alternate_setting,                      // bAlternateSetting
+                0x00,                                   // bNumEndpoints
+                interface_class,                        // bInterfaceClass
+                interface_sub_class,                    // bInterfaceSubClass
+                interface_protocol,                     // bInterfaceProtocol
+                interface_string.map_or(0, Into::into), // iInterface
+            ],
+        )
+    }
+
+    fn endpoint_ex<B: UsbBus, D: EndpointDirection>(
+        &mut self,
+        endpoint: &Endpoint<'_, B, D>,
+        f: impl FnOnce(&mut [u8]) -> usb_device::Result<usize>,
+    ) -> usb_device::Result<()> {
+        let mark = self.num_endpoints_mark.ok_or(UsbError::InvalidState)?;
+        self.write_with(descriptor_type::ENDPOINT, |buf| {
+            let mps = endpoint.max_packet_size();
+            let fields = buf.get_mut(..5).ok_or(UsbError::BufferOverflow)?;
+            fields.copy_from_slice(&[
+                endpoint.address().into(),             // bEndpointAddress
+                endpoint.ep_type().to_bm_attributes(), // bmAttributes
+                mps as u8,
+                (mps >> 8) as u8,    // wMaxPacketSize
+                endpoint.interval(), // bInterval
+            ]);
+            Ok(f(&mut buf[5..])? + 5)
+        })?;
+        self.buf[mark] += 1;
+        Ok(())
+    }
+}
@@ -0,0 +1,102 @@
+//! State of the Graphic Equalizer control of a Feature Unit according to
+//! Universal Serial Bus Device Class Definition for Audio Devices, Release 1.0,
+//! section 5.2.2.4.3.6 and Release 2.0, section 5.2.5.7.6
+//!
+//! The bands are the ANSI S1.11-1986 bands 14 (25 Hz) to 43 (20 kHz), which are
+//! represented by the bits D0 to D29 of a bitmap (bmBandsPresent). The gain of
+//! each band is set in steps of 0.25 dB as a signed byte.
+
+/// Bands of a graphic equalizer at octave intervals: 31.5 Hz (band 15), 63 Hz,
+/// 125 Hz, 250 Hz, 500 Hz, 1 kHz, 2 kHz, 4 kHz, 8 kHz and 16 kHz (band 42)
+pub const EQUALIZER_OCTAVE_BANDS: u32 = 0x1249_2492;
+
+/// Number of the band represented by bit D0 of a band bitmap
+pub(crate) const FIRST_BAND: u8 = 14;
+
+/// Number of bands that can be present
+const NUM_BANDS: usize = 30;
+
+/// Bitmap of all bands that can be present
+const VALID_BANDS: u32 = (1 << NUM_BANDS) - 1;
+
+pub(crate) struct GraphicEqualizer {
+    /// bitmap of the bands present (bmBandsPresent)
+    bands: u32,
+    /// gains of the bands in 0.25 dB, indexed by bit position
+    gains: [i8; NUM_BANDS],
+    /// bitmap of bands whose changes have not yet been retrieved by the
+    /// application
+    pending_events: u32,
+}
+
+impl GraphicEqualizer {
+    /// Create an equalizer with the indicated bands, all at 0 dB. Returns
+    /// `None` if no or invalid bands are indicated.
+    pub(crate) fn new(bands: u32) -> Option<Self> {
+        if bands == 0 || bands & !VALID_BANDS != 0 {
+            return None;
+        }
+        Some(GraphicEqualizer {
+            bands,
+            gains: [0; NUM_BANDS],
+            pending_events: 0,
+        })
+    }
+
+    /// Gain of a band (14..43) in 0.25 dB or `None` if the band is not present
+    pub(crate) fn gain(&self, band: u8) -> Option<i8> {
+        let bit = band.checked_sub(FIRST_BAND)? as usize;
+        if bit >= NUM_BANDS || self.bands & (1 << bit) == 0 {
+            return None;
+        }
+        Some(self.gains[bit])
+    }
+
+    /// Write a parameter block (bmBandsPresent followed by one byte per present
+    /// band) holding the current gains or `value` for all bands, and return its
+    /// length
+    pub(crate) fn write(&self, buf: &mut [u8], value: Option<i8>) -> Option<usize> {
+        let length = 4 + self.bands.count_ones() as usize;
+        let buf = buf.get_mut(..length)?;
+        buf[..4].copy_from_slice(&self.bands.to_le_bytes());
+        let gains = (0..NUM_BANDS)
+            .filter(|bit| self.bands & (1 << bit) != 0)
+            .map(|bit| value.unwrap_or(self.gains[bit]));
+        for (byte, gain) in buf[4..].iter_mut().zip(gains) {
+            *byte = gain as u8;
+        }
+        Some(length)
+    }
+
+    /// Set the gains of the bands indicated by a parameter block and generate
+    /// an event for each band whose gain changes. Returns false if the block
+    /// is malformed or addresses a band that is not present.
+    pub(crate) fn set(&mut self, data: &[u8]) -> bool {
+        let Some((bands, gains)) = data.split_first_chunk::<4>() else {
+            return false;
+        };
+        let bands = u32::from_le_bytes(*bands);
+        if bands & !self.bands != 0 || gains.len() != bands.count_ones() as usize {
+            return false;
+        }
+        let bits = (0..NUM_BANDS).filter(|bit| bands & (1 << bit) != 0);
+        for (bit, &gain) in bits.zip(gains) {
+            let gain = gain as i8;
+            if gain != self.gains[bit] {
+                self.gains[bit] = gain;
+                self.pending_events |= 1 << bit;
+            }
+        }
+        true
+    }
+
+    /// Clear the next pending event and return the band (14..43) and its gain
+    pub(crate) fn take_event(&mut self) -> Option<(u8, i8)> {
+        if self.pending_events == 0 {
+            return None;
+        }
+        let bit = self.pending_events.trailing_zeros() as usize;
+        self.pending_events &= !(1 << bit);
+        Some((FIRST_BAND + bit as u8, self.gains[bit]))
+    }
+}
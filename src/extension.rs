@@ -0,0 +1,156 @@
+//! Extension Units according to Universal Serial Bus Device Class Definition
+//! for Audio Devices, Release 1.0, section 4.3.2.7 and Release 2.0, section
+//! 4.7.2.12
+//!
+//! The controls of an Extension Unit are vendor-specific. Class-specific
+//! requests addressed to an Extension Unit are therefore not handled by the
+//! class itself but passed to an `ExtensionUnitHandler` provided by the
+//! application.
+
+use crate::{AudioClass, AudioStream, StreamId, MAX_STREAMS};
+use usb_device::class_prelude::*;
+
+/// Maximum number of additional input pins of an Extension Unit
+pub(crate) const MAX_EXTENSION_SOURCES: usize = 2 * MAX_STREAMS - 1;
+
+/// Vendor-specific Extension Unit in the signal path of a stream
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct ExtensionUnit<'a> {
+    pub(crate) code: u16,
+    pub(crate) enable_control: bool,
+    pub(crate) sources: &'a [StreamId],
+}
+
+impl<'a> ExtensionUnit<'a> {
+    /// Create an Extension Unit with the indicated vendor-specific extension
+    /// code (wExtensionCode). The signal path of the stream is connected to
+    /// its first input pin.
+    pub fn new(code: u16) -> Self {
+        ExtensionUnit {
+            code,
+            enable_control: false,
+            sources: &[],
+        }
+    }
+
+    /// Indicate an Enable Processing control in the descriptor. Like all
+    /// other controls, it is handled by the `ExtensionUnitHandler`.
+    pub fn with_enable_control(self) -> Self {
+        ExtensionUnit {
+            enable_control: true,
+            ..self
+        }
+    }
+
+    /// Connect additional input pins to the sources of other streams, i.e.
+    /// the Selector Unit or the Input Terminal, e.g. the signal played by the
+    /// host as a reference for an echo canceller in the signal path of a
+    /// microphone. `AudioClassBuilder::build()` returns
+    /// `BuildError::InvalidExtensionSources` if a stream does not exist, refers
+    /// to the stream of the Extension Unit itself or if there are more than
+    /// `2 * MAX_STREAMS - 1` sources.
+    pub fn with_sources(self, sources: &'a [StreamId]) -> Self {
+        ExtensionUnit { sources, ..self }
+    }
+
+    /// Number of input pins
+    pub(crate) fn num_pins(&self) -> u8 {
+        1 + self.sources.len() as u8
+    }
+}
+
+/// Class-specific request addressed to an Extension Unit
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct ExtensionRequest {
+    /// Stream whose signal path contains the Extension Unit
+    pub stream: StreamId,
+    /// Request code (bRequest), e.g. GET_CUR (0x81) for USB Audio Class 1.0 or
+    /// CUR (0x01) for USB Audio Class 2.0
+    pub request: u8,
+    /// Control selector (high byte of wValue)
+    pub control_selector: u8,
+    /// Channel number or other control-specific value (low byte of wValue)
+    pub channel: u8,
+    /// Number of bytes requested by the host or provided with a SET request
+    /// (wLength)
+    pub length: u16,
+}
+
+/// Handler of the vendor-specific controls of Extension Units
+pub trait ExtensionUnitHandler {
+    /// Handle a GET request by writing the response into `buf` and returning
+    /// its length, which should not exceed `request.length`. Returning `None`
+    /// stalls the request.
+    fn get(&mut self, request: &ExtensionRequest, buf: &mut [u8]) -> Option<usize>;
+
+    /// Handle a SET request with the indicated data. Returning false stalls
+    /// the request.
+    fn set(&mut self, request: &ExtensionRequest, data: &[u8]) -> bool;
+}
+
+impl<B: UsbBus, const P: usize> AudioClass<'_, B, P> {
+    /// Stream whose signal path contains the Extension Unit with the indicated
+    /// ID
+    pub(crate) fn extension_unit_stream(&self, entity: u8) -> Option<StreamId> {
+        let entity = Some(entity);
+        self.inputs
+            .iter()
+            .flatten()
+            .find(|info| entity == info.extension_unit_id())
+            .map(AudioStream::stream_id)
+            .or_else(|| {
+                self.outputs
+                    .iter()
+                    .flatten()
+                    .find(|info| entity == info.extension_unit_id())
+                    .map(AudioStream::stream_id)
+            })
+    }
+
+    /// Pass a class-specific GET request addressed to an Extension Unit to the
+    /// application
+    pub(crate) fn extension_unit_in(&mut self, stream: StreamId, xfer: ControlIn<B>) {
+        let req = *xfer.request();
+        let request = ExtensionRequest {
+            stream,
+            request: req.request,
+            control_selector: (req.value >> 8) as u8,
+            channel: req.value as u8,
+            length: req.length,
+        };
+        match self.extension_handler.as_mut() {
+            Some(handler) => xfer.accept(|buf| {
+                handler
+                    .get(&request, buf)
+                    .filter(|&length| length <= buf.len())
+                    .ok_or(UsbError::InvalidState)
+            }),
+            None => xfer.reject(),
+        }
+        .ok();
+    }
+
+    /// Pass a class-specific SET request addressed to an Extension Unit to the
+    /// application
+    pub(crate) fn extension_unit_out(&mut self, stream: StreamId, xfer: ControlOut<B>) {
+        let req = *xfer.request();
+        let request = ExtensionRequest {
+            stream,
+            request: req.request,
+            control_selector: (req.value >> 8) as u8,
+            channel: req.value as u8,
+            length: req.length,
+        };
+        let accepted = match self.extension_handler.as_mut() {
+            Some(handler) => handler.set(&request, xfer.data()),
+            None => false,
+        };
+        if accepted {
+            xfer.accept().ok();
+        } else {
+            xfer.reject().ok();
+        }
+    }
+}
@@ -0,0 +1,76 @@
+//! Conversion of sampling rates into feedback values according to the
+//! Universal Serial Bus Specification, Revision 2.0, section 5.12.4.2
+//!
+//! A feedback value indicates the number of samples per (micro)frame at which
+//! the host shall send audio data to an asynchronous output stream. Full-speed
+//! devices report the number of samples per 1 ms frame in 10.14 format (three
+//! bytes), high-speed devices report the number of samples per 125 µs
+//! microframe in 16.16 format (four bytes).
+//!
+//! All functions round to the nearest representable value, with ties rounded
+//! up. Truncating instead introduces a constant bias of up to one LSB, which
+//! slowly drains or fills the buffer of the device.
+//!
+//! ```
+//! use usbd_audio::feedback::*;
+//!
+//! assert_eq!(rate_to_q10_14(48000), 48 << 14);
+//! assert_eq!(rate_to_q10_14(44100), 0x0B_0666);
+//! assert_eq!(rate_to_q16_16(44100), 0x0005_8333);
+//! // 45158 samples counted during 1024 frames
+//! assert_eq!(count_to_q10_14(45158, 1024), 0x0B_0660);
+//! ```
+
+/// Largest value that fits into the three bytes of a 10.14 feedback value
+const Q10_14_MAX: u64 = 0xFF_FFFF;
+
+/// Divide and round to the nearest integer, ties rounded up
+pub(crate) fn div_round(dividend: u64, divisor: u64) -> u64 {
+    (dividend + divisor / 2) / divisor
+}
+
+/// Convert a sampling rate in samples/second into a full-speed feedback value
+/// in 10.14 format (samples per 1 ms frame)
+pub fn rate_to_q10_14(rate: u32) -> u32 {
+    div_round((rate as u64) << 14, 1000).min(Q10_14_MAX) as u32
+}
+
+/// Convert a sampling rate in samples/second into a high-speed feedback value
+/// in 16.16 format (samples per 125 µs microframe)
+pub fn rate_to_q16_16(rate: u32) -> u32 {
+    div_round((rate as u64) << 16, 8000) as u32
+}
+
+/// Convert the number of samples counted during a number of frames, e.g. by
+/// accumulating the samples consumed between start-of-frame events, into a
+/// full-speed feedback value in 10.14 format. Counting over 2^n frames gives a
+/// resolution of 2^-n samples per frame.
+///
+/// # Panics
+///
+/// Panics if `frames` is zero.
+pub fn count_to_q10_14(samples: u32, frames: u32) -> u32 {
+    div_round((samples as u64) << 14, frames as u64).min(Q10_14_MAX) as u32
+}
+
+/// Convert the number of samples counted during a number of microframes into
+/// a high-speed feedback value in 16.16 format.
+///
+/// # Panics
+///
+/// Panics if `microframes` is zero.
+pub fn count_to_q16_16(samples: u32, microframes: u32) -> u32 {
+    div_round((samples as u64) << 16, microframes as u64).min(u32::MAX as u64) as u32
+}
+
+/// Convert a full-speed feedback value in 10.14 format back into a sampling
+/// rate in samples/second
+pub fn q10_14_to_rate(value: u32) -> u32 {
+    div_round(value as u64 * 1000, 1 << 14) as u32
+}
+
+/// Convert a high-speed feedback value in 16.16 format back into a sampling
+/// rate in samples/second
+pub fn q16_16_to_rate(value: u32) -> u32 {
+    div_round(value as u64 * 8000, 1 << 16) as u32
+}
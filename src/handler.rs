@@ -0,0 +1,66 @@
+//! Synchronous handling of control requests by the application
+//!
+//! Changes of controls made by the host are normally retrieved later by
+//! `AudioClass::poll_event()`. An `AudioControlHandler` passed to
+//! `AudioClassBuilder::control_handler()` is instead called while the control
+//! transfer is processed, e.g. to program a codec before the request completes,
+//! and may reject the request.
+
+use crate::StreamId;
+use usb_device::control::Request;
+
+/// Handler of control requests called during the control transfer. All methods
+/// have default implementations accepting the standard requests and rejecting
+/// vendor-specific requests, so that only the relevant ones need to be
+/// implemented. A rejected request is stalled and leaves the control
+/// unchanged; events are still reported by `AudioClass::poll_event()` for
+/// accepted requests.
+pub trait AudioControlHandler {
+    /// The host sets the mute control of the indicated channel (0 = master
+    /// channel) of a stream. Returning false stalls the request.
+    fn on_set_mute(&mut self, stream: StreamId, channel: u8, mute: bool) -> bool {
+        let _ = (stream, channel, mute);
+        true
+    }
+
+    /// The host sets the volume control of the indicated channel (0 = master
+    /// channel) of a stream to the indicated value in 1/256 dB, already limited
+    /// to the range of the control. Returning false stalls the request.
+    fn on_set_volume(&mut self, stream: StreamId, channel: u8, volume: i16) -> bool {
+        let _ = (stream, channel, volume);
+        true
+    }
+
+    /// The host sets the sampling rate of a stream to the indicated rate in
+    /// samples/second, which is one of the rates of the stream. Returning false
+    /// stalls the request.
+    fn on_set_sample_rate(&mut self, stream: StreamId, rate: u32) -> bool {
+        let _ = (stream, rate);
+        true
+    }
+
+    /// A start-of-frame event has been forwarded by
+    /// `AudioClass::start_of_frame()`, e.g. to align the generation of audio
+    /// data with the bus timing. `frame_number` is `None` if no function was
+    /// passed to `AudioClassBuilder::frame_number()`.
+    fn on_start_of_frame(&mut self, frame_number: Option<u16>) {
+        let _ = frame_number;
+    }
+
+    /// Handle a vendor-specific request with a data stage from device to host
+    /// addressed to one of the interfaces of the function by writing the
+    /// response into `buf` and returning its length, which should not exceed
+    /// `request.length`. Returning `None` stalls the request.
+    fn on_vendor_request_in(&mut self, request: &Request, buf: &mut [u8]) -> Option<usize> {
+        let _ = (request, buf);
+        None
+    }
+
+    /// Handle a vendor-specific request with the indicated data from host to
+    /// device addressed to one of the interfaces of the function. Returning
+    /// false stalls the request.
+    fn on_vendor_request_out(&mut self, request: &Request, data: &[u8]) -> bool {
+        let _ = (request, data);
+        false
+    }
+}
@@ -0,0 +1,207 @@
+//! HID consumer-control interface reporting the volume buttons of the device
+//! according to the Device Class Definition for Human Interface Devices (HID),
+//! Version 1.11 and the Consumer Page of the HID Usage Tables
+//!
+//! Commercial headsets report their volume and mute buttons through such an
+//! interface instead of changing their Feature Units themselves. The host
+//! adjusts its volume, shows its volume OSD and then sets the volume and mute
+//! controls of the function, so that the Feature Units stay in sync with the
+//! host and the application learns about the change by
+//! `AudioClass::poll_event()` like about any other change made by the host.
+
+use crate::descriptor::DescriptorSink;
+use usb_device::class_prelude::*;
+use usb_device::control::{Recipient, Request, RequestType};
+use usb_device::endpoint::{Endpoint, In};
+
+/// HID interface class code
+const HID: u8 = 0x03;
+/// HID class descriptor types
+const HID_DESCRIPTOR: u8 = 0x21;
+const REPORT_DESCRIPTOR: u8 = 0x22;
+/// HID class-specific requests
+const GET_REPORT: u8 = 0x01;
+const GET_IDLE: u8 = 0x02;
+const SET_IDLE: u8 = 0x0a;
+
+/// Report descriptor of a single input report with one bit per
+/// `ConsumerKey`
+const REPORT: [u8; 27] = [
+    0x05, 0x0c, // Usage Page (Consumer)
+    0x09, 0x01, // Usage (Consumer Control)
+    0xa1, 0x01, // Collection (Application)
+    0x15, 0x00, //   Logical Minimum (0)
+    0x25, 0x01, //   Logical Maximum (1)
+    0x75, 0x01, //   Report Size (1)
+    0x95, 0x03, //   Report Count (3)
+    0x09, 0xe9, //   Usage (Volume Increment)
+    0x09, 0xea, //   Usage (Volume Decrement)
+    0x09, 0xe2, //   Usage (Mute)
+    0x81, 0x02, //   Input (Data, Variable, Absolute)
+    0x95, 0x05, //   Report Count (5)
+    0x81, 0x03, //   Input (Constant, Variable, Absolute)
+    0xc0, // End Collection
+];
+
+/// Payload of the HID descriptor (9 bytes)
+const HID_DESCRIPTOR_PAYLOAD: [u8; 7] = [
+    0x11,
+    0x01,              // bcdHID
+    0x00,              // bCountryCode
+    0x01,              // bNumDescriptors
+    REPORT_DESCRIPTOR, // bDescriptorType
+    REPORT.len() as u8,
+    0x00, // wDescriptorLength
+];
+
+/// Polling interval of the interrupt endpoint: 10 ms at full speed, 8 ms
+/// (2^(7-1) microframes) at high speed
+const INTERVAL: u8 = 10;
+const INTERVAL_HS: u8 = 7;
+
+/// Button of the device reported by `AudioClass::press_key()`
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum ConsumerKey {
+    /// Volume Increment, repeated by the host while the key is held
+    VolumeUp,
+    /// Volume Decrement, repeated by the host while the key is held
+    VolumeDown,
+    /// Mute, toggling the mute state of the host on each press
+    Mute,
+}
+
+impl ConsumerKey {
+    /// Bit of the key in the input report
+    fn bit(self) -> u8 {
+        match self {
+            ConsumerKey::VolumeUp => 0x01,
+            ConsumerKey::VolumeDown => 0x02,
+            ConsumerKey::Mute => 0x04,
+        }
+    }
+}
+
+/// Maximum number of reports waiting for transmission
+const QUEUE_LENGTH: usize = 8;
+
+/// HID interface added by `AudioClassBuilder::consumer_control()`
+pub(crate) struct ConsumerControl<'a, B: UsbBus> {
+    pub(crate) interface: InterfaceNumber,
+    endpoint: Endpoint<'a, B, In>,
+    /// reports waiting for transmission, oldest first
+    queue: [u8; QUEUE_LENGTH],
+    len: usize,
+    /// whether a report has been written to the endpoint and not yet been
+    /// read by the host
+    busy: bool,
+    /// keys currently pressed, as last queued
+    keys: u8,
+}
+
+impl<'a, B: UsbBus> ConsumerControl<'a, B> {
+    pub(crate) fn new(alloc: &'a UsbBusAllocator<B>, high_speed: bool) -> Self {
+        let interval = if high_speed { INTERVAL_HS } else { INTERVAL };
+        ConsumerControl {
+            interface: alloc.interface(),
+            endpoint: alloc.interrupt(1, interval),
+            queue: [0; QUEUE_LENGTH],
+            len: 0,
+            busy: false,
+            keys: 0,
+        }
+    }
+
+    pub(crate) fn write_descriptors<W: DescriptorSink>(
+        &self,
+        writer: &mut W,
+    ) -> usb_device::Result<()> {
+        writer.interface(self.interface, HID, 0x00, 0x00)?;
+        writer.write(HID_DESCRIPTOR, &HID_DESCRIPTOR_PAYLOAD)?;
+        writer.endpoint(&self.endpoint)
+    }
+
+    /// Number of reports that can be queued
+    pub(crate) fn free(&self) -> usize {
+        QUEUE_LENGTH - self.len
+    }
+
+    /// Queue a report with the indicated key pressed in addition to the keys
+    /// already pressed, or with all keys released. Returns false if the queue
+    /// is full.
+    pub(crate) fn push(&mut self, key: Option<ConsumerKey>) -> bool {
+        if self.len == QUEUE_LENGTH {
+            return false;
+        }
+        self.keys = key.map_or(0, |key| self.keys | key.bit());
+        self.queue[self.len] = self.keys;
+        self.len += 1;
+        self.send();
+        true
+    }
+
+    /// Write the next queued report to the endpoint unless the host has not
+    /// read the previous one yet
+    pub(crate) fn send(&mut self) {
+        if self.busy || self.len == 0 {
+            return;
+        }
+        if self.endpoint.write(&self.queue[..1]).is_ok() {
+            self.busy = true;
+            self.queue.copy_within(1..self.len, 0);
+            self.len -= 1;
+        }
+    }
+
+    /// Handle the completion of a transfer on the indicated endpoint
+    pub(crate) fn endpoint_in_complete(&mut self, addr: EndpointAddress) {
+        if self.endpoint.address() == addr {
+            self.busy = false;
+            self.send();
+        }
+    }
+
+    pub(crate) fn reset(&mut self) {
+        self.len = 0;
+        self.busy = false;
+        self.keys = 0;
+    }
+
+    /// Whether a request is addressed to the HID interface
+    pub(crate) fn owns(&self, req: &Request) -> bool {
+        req.recipient == Recipient::Interface && req.index == u8::from(self.interface) as u16
+    }
+
+    pub(crate) fn control_in(&mut self, xfer: ControlIn<B>) {
+        let req = *xfer.request();
+        match (req.request_type, req.request) {
+            (RequestType::Standard, Request::GET_DESCRIPTOR) => match (req.value >> 8) as u8 {
+                REPORT_DESCRIPTOR => xfer.accept_with_static(&REPORT).ok(),
+                HID_DESCRIPTOR => {
+                    let mut descriptor = [0u8; 9];
+                    descriptor[0] = descriptor.len() as u8;
+                    descriptor[1] = HID_DESCRIPTOR;
+                    descriptor[2..].copy_from_slice(&HID_DESCRIPTOR_PAYLOAD);
+                    xfer.accept_with(&descriptor).ok()
+                }
+                _ => xfer.reject().ok(),
+            },
+            (RequestType::Class, GET_REPORT) => xfer.accept_with(&[self.keys]).ok(),
+            // reports are only sent on changes
+            (RequestType::Class, GET_IDLE) => xfer.accept_with(&[0]).ok(),
+            (RequestType::Class, _) => xfer.reject().ok(),
+            _ => None,
+        };
+    }
+
+    pub(crate) fn control_out(&mut self, xfer: ControlOut<B>) {
+        let req = *xfer.request();
+        match (req.request_type, req.request) {
+            // only the indefinite idle rate, i.e. reports on changes only, is
+            // supported
+            (RequestType::Class, SET_IDLE) if req.value >> 8 == 0 => xfer.accept().ok(),
+            (RequestType::Class, _) => xfer.reject().ok(),
+            _ => None,
+        };
+    }
+}
@@ -0,0 +1,130 @@
+//! Interrupt endpoint of the AudioControl interface according to Universal
+//! Serial Bus Device Class Definition for Audio Devices, Release 1.0, section
+//! 3.7.1.2 and Release 2.0, section 6.1
+//!
+//! The function informs the host about changes of controls that were not made
+//! by the host itself, e.g. the loss of an external clock, by status words
+//! (USB Audio Class 1.0) or interrupt data messages (USB Audio Class 2.0). The
+//! messages are queued until the host polls the endpoint.
+
+use crate::class_codes_v2::CUR;
+use crate::{AudioVersion, StreamId};
+
+/// Maximum size of a message (interrupt data message of UAC 2.0)
+pub(crate) const MESSAGE_SIZE: usize = 6;
+
+/// Size of a status word of UAC 1.0
+const STATUS_WORD_SIZE: usize = 2;
+
+/// Originator of a status message posted by `AudioClass::post_status()`
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Originator {
+    /// Feature Unit in the signal path of the indicated stream
+    FeatureUnit(StreamId),
+    /// Terminal of type `terminal_type` of the indicated stream, i.e. the
+    /// Input Terminal of an input stream or the Output Terminal of an output
+    /// stream
+    Terminal(StreamId),
+    /// AudioStreaming interface of the indicated stream
+    Interface(StreamId),
+    /// Isochronous data endpoint of the indicated stream
+    Endpoint(StreamId),
+}
+
+/// Addressee of a message as derived from its originator
+#[derive(Clone, Copy)]
+pub(crate) enum Source {
+    /// Entity of the AudioControl interface with the indicated ID
+    Entity(u8),
+    /// AudioStreaming interface with the indicated number
+    Interface(u8),
+    /// Endpoint with the indicated address
+    Endpoint(u8),
+}
+
+/// Size of the messages of a version
+pub(crate) fn message_size(version: AudioVersion) -> usize {
+    match version {
+        AudioVersion::Uac1 => STATUS_WORD_SIZE,
+        _ => MESSAGE_SIZE,
+    }
+}
+
+/// Create a message informing the host that the control with the indicated
+/// control selector and channel number changed. The status word of UAC 1.0
+/// only indicates the originator.
+pub(crate) fn message(
+    version: AudioVersion,
+    source: Source,
+    control_iface: u8,
+    control_selector: u8,
+    channel: u8,
+) -> [u8; MESSAGE_SIZE] {
+    if version == AudioVersion::Uac1 {
+        // bStatusType: interrupt pending and originator, bOriginator
+        let (originator, id) = match source {
+            Source::Entity(id) => (0x00, id),
+            Source::Interface(number) => (0x01, number),
+            Source::Endpoint(address) => (0x02, address),
+        };
+        return [0x80 | originator, id, 0, 0, 0, 0];
+    }
+    // bInfo, wIndex
+    let (info, index) = match source {
+        Source::Entity(id) => (0x00, [control_iface, id]),
+        Source::Interface(number) => (0x00, [number, 0x00]),
+        Source::Endpoint(address) => (0x02, [address, 0x00]),
+    };
+    // bInfo, bAttribute, wValue, wIndex
+    [info, CUR, channel, control_selector, index[0], index[1]]
+}
+
+/// Maximum number of messages waiting for transmission
+const QUEUE_LENGTH: usize = 8;
+
+pub(crate) struct InterruptQueue {
+    messages: [[u8; MESSAGE_SIZE]; QUEUE_LENGTH],
+    len: usize,
+}
+
+impl InterruptQueue {
+    pub(crate) fn new() -> Self {
+        InterruptQueue {
+            messages: [[0; MESSAGE_SIZE]; QUEUE_LENGTH],
+            len: 0,
+        }
+    }
+
+    /// Queue a message unless an identical message is already waiting. Since
+    /// the host reads the current state of the control, a single message per
+    /// control suffices. Returns false if the queue is full.
+    pub(crate) fn push(&mut self, message: [u8; MESSAGE_SIZE]) -> bool {
+        if self.messages[..self.len].contains(&message) {
+            return true;
+        }
+        if self.len == QUEUE_LENGTH {
+            return false;
+        }
+        self.messages[self.len] = message;
+        self.len += 1;
+        true
+    }
+
+    /// Oldest message waiting for transmission
+    pub(crate) fn front(&self) -> Option<&[u8; MESSAGE_SIZE]> {
+        self.messages[..self.len].first()
+    }
+
+    /// Remove the oldest message
+    pub(crate) fn pop(&mut self) {
+        if self.len > 0 {
+            self.messages.copy_within(1..self.len, 0);
+            self.len -= 1;
+        }
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.len = 0;
+    }
+}
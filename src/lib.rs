@@ -1,12 +1,20 @@
 //! USB Audio class
 //!
 //! This crate provides a USB device class based on "Universal Serial Bus Device
-//! Class Definition for Audio Devices", Release 1.0 (experimental
-//! implementation without the aim of standard compliance).
+//! Class Definition for Audio Devices", Release 1.0 and Release 2.0, and on
+//! the Basic Audio Device Definition (BADD) of Release 3.0 (experimental
+//! implementation without the aim of standard compliance). The version is
+//! selected by `AudioClassBuilder::version()`.
 //!
 //! Since the USB descriptor can be quite large, it may be required to activate the feature
 //! `control-buffer-256` of the `usb-device` crate.
 //!
+//! The crate does not need a global allocator. Descriptors are written
+//! directly into the control buffer of `usb-device` and all state, including
+//! the buffers for packets and sample data, has a fixed size known at compile
+//! time. `AudioClassBuilder::build_sized()` tunes the size of the packet
+//! buffers.
+//!
 //! Example
 //!
 //! ```ignore
@@ -32,34 +40,486 @@
 //! This example creates an audio device having a one channel (Mono) microphone
 //! with a fixed sampling frequency of 48 KHz and a two channel (Stereo) speaker
 //! output that supports three different sampling rates.
+//!
+//! Each stream has its own sampling rates and its own sampling frequency
+//! control, so that the host may e.g. run a microphone at 16 kHz while playing
+//! back at 48 kHz. `AudioClass::sampling_rate()` returns the rate of a stream
+//! and `AudioEvent::SampleRateSet` names the stream whose rate the host set.
+//!
+//! Further streams can be added by calling `.input()` or `.output()` several
+//! times. Methods like `AudioClass::input_mute()` relate to the first stream of
+//! a direction, whereas methods like `AudioClass::mute()` take a `StreamId`.
+//!
+//! By default, descriptors according to USB Audio Class 1.0 are generated. A
+//! USB Audio Class 2.0 function can be created by calling
+//! `.version(AudioVersion::Uac2)` on the builder. In this case, the `UsbDevice`
+//! should be built with `composite_with_iads()` since many hosts require an
+//! Interface Association Descriptor for Audio Class 2.0 functions.
+//!
+//! Several `AudioClass` objects built on the same allocator form independent
+//! audio functions of one device, each named by `.function_name()`. Their
+//! entity IDs are scoped to their AudioControl interfaces.
+//!
+//! `AudioVersion::Uac3Badd` creates a USB Audio Class 3.0 function according to
+//! the Generic I/O profile of the Basic Audio Device Definition (BADD), which
+//! restricts the streams to one or two channels sampled at 48 kHz.
 #![no_std]
 
+#[cfg(feature = "test-util")]
+extern crate std;
+
 use class_codes::*;
+use core::cell::Cell;
 use core::convert::From;
+use descriptor::{DescriptorBuffer, DescriptorSink};
 use usb_device::control::{Recipient, Request, RequestType};
-use usb_device::device::DEFAULT_ALTERNATE_SETTING;
+use usb_device::device::{UsbDevice, UsbDeviceState, DEFAULT_ALTERNATE_SETTING};
 use usb_device::endpoint::{Endpoint, EndpointDirection, In, Out};
 use usb_device::{class_prelude::*, UsbDirection};
 
+#[macro_use]
+mod macros;
+
+mod channel_config;
 mod terminal_type;
+pub use buffer::{BufferDepth, Consumer, Producer, SampleBuffer};
+use buffer::{Pacer, TxBuffer};
+pub use channel_config::ChannelConfig;
+use equalizer::GraphicEqualizer;
+pub use equalizer::EQUALIZER_OCTAVE_BANDS;
+use extension::MAX_EXTENSION_SOURCES;
+pub use extension::{ExtensionRequest, ExtensionUnit, ExtensionUnitHandler};
+pub use handler::AudioControlHandler;
+use hid::ConsumerControl;
+pub use hid::ConsumerKey;
+pub use interrupt::Originator;
+use interrupt::{InterruptQueue, Source};
+pub use midi::MidiEvent;
+use midi::MidiStreaming;
+use mixer::Mixer;
+use processing::Processing;
+pub use processing::{ProcessingControl, ProcessingUnit};
+pub use sample::{Frames, Sample, I24};
+use split::{AltSettings, EndpointDescriptor};
+pub use split::{InputStream, OutputStream, StreamHandle, StreamState};
 pub use terminal_type::TerminalType;
+#[cfg(all(feature = "input", feature = "output"))]
+pub use uc_headset::ECHO_CANCELLER_CODE;
+pub use volume::{Volume, VolumeRamp};
+#[cfg(feature = "asrc")]
+pub mod asrc;
+mod badd;
+mod buffer;
 mod class_codes;
+mod class_codes_v2;
+mod class_codes_v3;
+mod descriptor;
+mod equalizer;
+mod extension;
+pub mod feedback;
+mod handler;
+mod hid;
+mod interrupt;
+mod midi;
+mod mixer;
+mod processing;
+pub mod rate;
+pub mod sample;
+mod split;
+#[cfg(feature = "test-util")]
+pub mod test_util;
+mod uac2;
+#[cfg(all(feature = "input", feature = "output"))]
+mod uc_headset;
+mod volume;
 
 const ID_INPUT_TERMINAL: u8 = 0x01;
 const ID_OUTPUT_TERMINAL: u8 = 0x02;
+const ID_CLOCK_SOURCE: u8 = 0x03;
+const ID_FEATURE_UNIT: u8 = 0x04;
+// Selector Units and their additional Input Terminals as well as Mixer Units and
+// Processing Units use a separate block of IDs per stream
+const ID_SELECTOR_UNIT: u8 = 0x40;
+const SELECTOR_ID_BLOCK: u8 = 0x10;
+// Extension Units use the same offsets as the basic entities of a stream
+const ID_EXTENSION_UNIT: u8 = 0xc0;
+// Clock Selectors and the external Clock Sources connected to them as well as
+// Clock Multipliers
+const ID_CLOCK_SELECTOR: u8 = 0xe0;
+const ID_EXTERNAL_CLOCK: u8 = 0xe1;
+const ID_CLOCK_MULTIPLIER: u8 = 0xe2;
+
+// pending event flags of a stream
+const MUTE_CHANGED: u16 = 0x01;
+const VOLUME_CHANGED: u16 = 0x02;
+const SELECTOR_CHANGED: u16 = 0x04;
+const AGC_CHANGED: u16 = 0x08;
+/// first of the flags of the tone controls in the order of `ToneControl::ALL`
+const TONE_CHANGED: u16 = 0x10;
+const COPY_PROTECT_CHANGED: u16 = 0x80;
+const CLOCK_SELECTOR_CHANGED: u16 = 0x100;
+const ALT_SETTING_CHANGED: u16 = 0x200;
+const SAMPLING_RATE_SET: u16 = 0x400;
+const UNDERRUN: u16 = 0x800;
+const OVERRUN: u16 = 0x1000;
+const STREAM_STARTED: u16 = 0x2000;
+const STREAM_STOPPED: u16 = 0x4000;
+const PITCH_CHANGED: u16 = 0x8000;
+
+// pending event flags of the function
+const SUSPENDED: u8 = 0x01;
+const RESUMED: u8 = 0x02;
 
 const MAX_ISO_EP_SIZE: u32 = 1023;
+const MAX_ISO_EP_SIZE_HS: u32 = 1024;
+// transactions per microframe of a high-bandwidth endpoint
+const MAX_TRANSACTIONS_HS: u32 = 3;
 
-#[derive(Clone, Copy, Debug)]
+// bytes per (micro)frame that may be allocated to periodic transfers: 90 % of
+// a full-speed frame and 80 % of a high-speed microframe (USB 2.0, 5.6.4)
+const MAX_PERIODIC_BYTES: u32 = 1350;
+const MAX_PERIODIC_BYTES_HS: u32 = 6000;
+// protocol overhead of an isochronous transaction (USB 2.0, table 5-4)
+const ISO_OVERHEAD: u32 = 9;
+const ISO_OVERHEAD_HS: u32 = 38;
+
+// maximum number of channels of a stream with per-channel controls
+const MAX_CHANNELS: usize = 8;
+
+/// Maximum number of streams per direction
+pub const MAX_STREAMS: usize = 4;
+
+// number of streams per direction compiled in, so that the code of a
+// direction disabled by its feature is optimized away
+const MAX_INPUTS: usize = if cfg!(feature = "input") {
+    MAX_STREAMS
+} else {
+    0
+};
+const MAX_OUTPUTS: usize = if cfg!(feature = "output") {
+    MAX_STREAMS
+} else {
+    0
+};
+
+/// Default size of the packet buffers of an `AudioClass`, which holds the
+/// largest isochronous packet of a high-speed endpoint
+pub const DEFAULT_PACKET_SIZE: usize = MAX_ISO_EP_SIZE_HS as usize;
+
+// size of a full-speed feedback value in 10.14 format
+const FEEDBACK_EP_SIZE: u16 = 3;
+// size of a high-speed feedback value in 16.16 format
+const FEEDBACK_EP_SIZE_HS: u16 = 4;
+// feedback interval at high speed: 2^(4-1) microframes = 1 ms
+const FEEDBACK_INTERVAL_HS: u8 = 4;
+// largest service intervals of data endpoints: 2^(10-1) frames and
+// 2^(13-1) microframes = 512 ms
+const MAX_SERVICE_INTERVAL_FS: u8 = 10;
+const MAX_SERVICE_INTERVAL_HS: u8 = 13;
+// feedback refresh period as an exponent of 2 ms (UAC 1.0 bRefresh)
+const FEEDBACK_REFRESH: u8 = 0x05;
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum Format {
+    /// Signed, 8 bits per subframe
+    S8,
+    /// Unsigned, 8 bits per subframe (PCM8)
+    U8,
     /// Signed, 16 bits per subframe, little endian
     S16le,
     /// Signed, 24 bits per subframe, little endian
     S24le,
+    /// Signed, 32 bits per subframe, little endian
+    S32le,
+    /// Signed, 24 bits in the most significant bytes of 32 bit subframes,
+    /// little endian
+    S24in32le,
+    /// IEEE 754 single precision floating point, 32 bits per subframe, little
+    /// endian
+    F32le,
+    /// MPEG-1 Layer I, II or III encoded data (Type II format) with a maximum
+    /// bit rate of `max_bit_rate` kbits/second and `samples_per_frame` audio
+    /// samples per encoded frame
+    Mpeg {
+        max_bit_rate: u16,
+        samples_per_frame: u16,
+    },
+    /// AC-3 encoded data (Type II format) with a maximum bit rate of
+    /// `max_bit_rate` kbits/second and `samples_per_frame` audio samples per
+    /// encoded frame
+    Ac3 {
+        max_bit_rate: u16,
+        samples_per_frame: u16,
+    },
+    /// Encoded data wrapped into IEC 61937 bursts carried by two 16 bit
+    /// subframes (Type III format). Requires a stream with two channels.
+    Iec61937(Iec61937),
+    /// Type I-style format with caller-specified parameters for prototyping
+    /// formats that are not supported otherwise. UAC 1.0 descriptors indicate
+    /// `format_tag` as wFormatTag, UAC 2.0 descriptors indicate the
+    /// TYPE_I_RAW_DATA format. `subframe_size` must be in the range 1..=4.
+    Raw {
+        subframe_size: u8,
+        bit_resolution: u8,
+        format_tag: u16,
+    },
+}
+
+/// Encodings of IEC 61937 streams (Type III formats)
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Iec61937 {
+    /// AC-3
+    Ac3,
+    /// MPEG-1 Layer I
+    Mpeg1Layer1,
+    /// MPEG-1 Layer II or III and MPEG-2 without extension
+    Mpeg1Layer23,
+    /// MPEG-2 with extension
+    Mpeg2Ext,
+    /// MPEG-2 Layer I at low sampling frequencies
+    Mpeg2Layer1Ls,
+    /// MPEG-2 Layer II or III at low sampling frequencies
+    Mpeg2Layer23Ls,
+}
+
+impl Format {
+    /// Number of octets per subframe (bSubframeSize / bSubslotSize)
+    fn subframe_size(self) -> u8 {
+        match self {
+            Format::S8 | Format::U8 => 1,
+            Format::S16le | Format::Iec61937(_) => 2,
+            Format::S24le => 3,
+            Format::S32le | Format::S24in32le | Format::F32le => 4,
+            Format::Mpeg { .. } | Format::Ac3 { .. } => 0,
+            Format::Raw { subframe_size, .. } => subframe_size,
+        }
+    }
+
+    /// Audio Data Format code (wFormatTag) of UAC 1.0, where 8 bit PCM data is
+    /// signed and PCM8 data is unsigned
+    fn format_tag(self) -> u16 {
+        match self {
+            Format::U8 => PCM8,
+            Format::F32le => IEEE_FLOAT,
+            Format::Mpeg { .. } => MPEG,
+            Format::Ac3 { .. } => AC3,
+            Format::Iec61937(Iec61937::Ac3) => IEC1937_AC3,
+            Format::Iec61937(Iec61937::Mpeg1Layer1) => IEC1937_MPEG1_LAYER1,
+            Format::Iec61937(Iec61937::Mpeg1Layer23) => IEC1937_MPEG1_LAYER2_3,
+            Format::Iec61937(Iec61937::Mpeg2Ext) => IEC1937_MPEG2_EXT,
+            Format::Iec61937(Iec61937::Mpeg2Layer1Ls) => IEC1937_MPEG2_LAYER1_LS,
+            Format::Iec61937(Iec61937::Mpeg2Layer23Ls) => IEC1937_MPEG2_LAYER2_3_LS,
+            Format::Raw { format_tag, .. } => format_tag,
+            _ => PCM,
+        }
+    }
+
+    /// Format Type Code (bFormatType)
+    fn format_type(self) -> u8 {
+        match self {
+            Format::Mpeg { .. } | Format::Ac3 { .. } => FORMAT_TYPE_II,
+            Format::Iec61937(_) => FORMAT_TYPE_III,
+            _ => FORMAT_TYPE_I,
+        }
+    }
+
+    /// Value of the bytes of a subframe representing silence
+    fn silence(self) -> u8 {
+        match self {
+            Format::U8 => 0x80,
+            _ => 0,
+        }
+    }
+
+    /// Maximum bit rate in kbits/second and number of samples per encoded
+    /// frame of a Type II format or `None` for a Type I or Type III format
+    fn type_ii(self) -> Option<(u16, u16)> {
+        match self {
+            Format::Mpeg {
+                max_bit_rate,
+                samples_per_frame,
+            }
+            | Format::Ac3 {
+                max_bit_rate,
+                samples_per_frame,
+            } => Some((max_bit_rate, samples_per_frame)),
+            _ => None,
+        }
+    }
+
+    /// Number of effectively used bits per subframe (bBitResolution)
+    fn bit_resolution(self) -> u8 {
+        match self {
+            Format::S8 | Format::U8 => 8,
+            Format::S16le | Format::Iec61937(_) => 16,
+            Format::S24le | Format::S24in32le => 24,
+            Format::S32le | Format::F32le => 32,
+            Format::Mpeg { .. } | Format::Ac3 { .. } => 0,
+            Format::Raw { bit_resolution, .. } => bit_resolution,
+        }
+    }
+}
+
+/// Identification of a stream by its direction and its index, i.e. the order
+/// in which it has been added by `AudioClassBuilder::input()` or
+/// `AudioClassBuilder::output()` (starting at 0)
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum StreamId {
+    Input(u8),
+    Output(u8),
+}
+
+/// Parameters of the audio data transferred in the alternate setting of a
+/// stream selected by the host as returned by `AudioClass::stream_format()`
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct StreamFormat {
+    pub format: Format,
+    pub channels: u8,
+    /// Sampling rate in samples/second
+    pub sampling_rate: u32,
+}
+
+/// Metadata of a packet read by `AudioClass::read_packet()` or
+/// `StreamHandle::read_packet()`
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct PacketInfo {
+    /// Number of bytes read
+    pub len: usize,
+    /// Number of whole audio frames contained in the packet or `None` if the
+    /// stream is idle or its format is not transferred in frames (Type II)
+    pub frames: Option<usize>,
+    /// USB frame number at which the packet was received, i.e. at which the
+    /// class handled its reception in `UsbDevice::poll()`, or `None` if no
+    /// function was passed to `AudioClassBuilder::frame_number()` or no packet
+    /// has been received yet. For buffered streams, it is the frame number of
+    /// the last packet received into the buffer.
+    pub frame_number: Option<u16>,
+}
+
+impl StreamFormat {
+    /// Size of an audio frame (number of channels times subframe size) or
+    /// `None` for Type II formats, which are not transferred in frames
+    pub(crate) fn frame_size(&self) -> Option<usize> {
+        match self.format.type_ii() {
+            Some(_) => None,
+            None => Some(self.channels as usize * self.format.subframe_size() as usize),
+        }
+    }
+}
+
+/// Version of the USB Audio Class specification the generated descriptors and
+/// the handling of class-specific requests are based on
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum AudioVersion {
+    /// Universal Serial Bus Device Class Definition for Audio Devices,
+    /// Release 1.0
+    Uac1,
+    /// Universal Serial Bus Device Class Definition for Audio Devices,
+    /// Release 2.0
+    Uac2,
+    /// Generic I/O profile of the Universal Serial Bus Device Class Definition
+    /// for Basic Audio Devices (BADD) as part of Audio Devices Release 3.0.
+    /// Streams must have one or two channels of format `S16le` or `S24le` and
+    /// a single sampling rate of 48000 samples/second.
+    Uac3Badd,
+}
+
+/// Primary use of a USB Audio Class 2.0 function declared by the bCategory
+/// field of its AudioControl interface header, from which hosts derive default
+/// roles and icons
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum AudioCategory {
+    /// Function category undefined
+    Undefined,
+    DesktopSpeaker,
+    HomeTheater,
+    Microphone,
+    Headset,
+    Telephone,
+    Converter,
+    VoiceSoundRecorder,
+    /// Generic audio interface, the default
+    IoBox,
+    MusicalInstrument,
+    ProAudio,
+    AudioVideo,
+    ControlPanel,
+    /// Any other use
+    Other,
+}
+
+/// Synchronization type of an isochronous audio data endpoint
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Synchronization {
+    /// The audio clock is locked to the USB start-of-frame clock.
+    Synchronous,
+    /// The device adapts its audio clock to the data rate of the host (output
+    /// streams) or the host adapts to the data rate requested by the device
+    /// (input streams).
+    Adaptive,
+    /// The audio clock of the device runs independently of the USB clock. An
+    /// output stream requires explicit or implicit feedback.
+    Asynchronous,
+}
+
+/// Content of the packets of a buffered input stream when the sample buffer
+/// does not hold enough data, selected by `StreamConfig::with_underrun_policy()`
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum UnderrunPolicy {
+    /// Send the available data only, down to a zero-length packet
+    #[default]
+    ZeroLength,
+    /// Complete the packet with digital silence
+    Silence,
+    /// Complete the packet with the corresponding part of the previous packet
+    Repeat,
+}
+
+/// Time the clock recovery of a data endpoint needs to lock to the data
+/// stream, selected by `StreamConfig::with_lock_delay()`
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum LockDelay {
+    /// Milliseconds
+    Millis(u16),
+    /// Decoded PCM samples
+    Samples(u16),
+}
+
+impl LockDelay {
+    /// bLockDelayUnits and wLockDelay
+    fn fields(lock_delay: Option<LockDelay>) -> [u8; 3] {
+        let (units, delay) = match lock_delay {
+            None => (0x00, 0),
+            Some(LockDelay::Millis(delay)) => (0x01, delay),
+            Some(LockDelay::Samples(delay)) => (0x02, delay),
+        };
+        [units, delay as u8, (delay >> 8) as u8]
+    }
+}
+
+impl Synchronization {
+    fn usb_type(self) -> IsochronousSynchronizationType {
+        match self {
+            Synchronization::Synchronous => IsochronousSynchronizationType::Synchronous,
+            Synchronization::Adaptive => IsochronousSynchronizationType::Adaptive,
+            Synchronization::Asynchronous => IsochronousSynchronizationType::Asynchronous,
+        }
+    }
 }
 
 /// Sampling rates that shall be supported by an steaming endpoint
 #[derive(Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum Rates<'a> {
     /// A continuous range of sampling rates in samples/second defined by a
     /// tuple including a minimum value and a maximum value. The maximum value
@@ -69,44 +529,253 @@ pub enum Rates<'a> {
     Discrete(&'a [u32]),
 }
 
+impl Rates<'_> {
+    /// Highest sampling rate of the set or range
+    fn max(&self) -> u32 {
+        match *self {
+            Rates::Continuous(_, max) => max,
+            Rates::Discrete(rates) => rates.iter().copied().max().unwrap_or(0),
+        }
+    }
+
+    /// Check whether a sampling rate is part of the set or range
+    fn contains(&self, rate: u32) -> bool {
+        match *self {
+            Rates::Continuous(min, max) => (min..=max).contains(&rate),
+            Rates::Discrete(rates) => rates.contains(&rate),
+        }
+    }
+
+    /// Minimum, maximum and resolution reported by the range requests of
+    /// the sampling frequency control of a UAC 1.0 endpoint, or `None` if
+    /// there is a single rate. The resolution of a set is the greatest common
+    /// divisor of the distances between its rates, so that each rate is a
+    /// multiple of the resolution above the minimum.
+    fn range(&self) -> Option<(u32, u32, u32)> {
+        match *self {
+            Rates::Continuous(min, max) => Some((min, max, 1)),
+            Rates::Discrete(rates) => {
+                let min = rates.iter().copied().min()?;
+                let res = rates.iter().fold(0, |res, rate| gcd(res, rate - min));
+                (res > 0).then(|| (min, self.max(), res))
+            }
+        }
+    }
+}
+
+fn gcd(mut a: u32, mut b: u32) -> u32 {
+    while b != 0 {
+        (a, b) = (b, a % b);
+    }
+    a
+}
+
+/// Tone controls of a Feature Unit. Their range is -32 dB to +31.75 dB in
+/// steps of 0.25 dB and they are initially set to 0 dB.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum ToneControl {
+    /// Bass control (low frequencies)
+    Bass,
+    /// Mid control (middle frequencies)
+    Mid,
+    /// Treble control (high frequencies)
+    Treble,
+}
+
+impl ToneControl {
+    const ALL: [ToneControl; 3] = [ToneControl::Bass, ToneControl::Mid, ToneControl::Treble];
+
+    /// Control selector of the tone control
+    fn selector(self) -> u8 {
+        match self {
+            ToneControl::Bass => BASS_CONTROL,
+            ToneControl::Mid => MID_CONTROL,
+            ToneControl::Treble => TREBLE_CONTROL,
+        }
+    }
+
+    /// Tone control addressed by a control selector
+    fn from_selector(selector: u8) -> Option<Self> {
+        ToneControl::ALL
+            .into_iter()
+            .find(|control| control.selector() == selector)
+    }
+}
+
+/// Copy Protection Level (CPL) of the Copy Protect control of a terminal, e.g.
+/// the SCMS state of an S/PDIF connection
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum CopyProtectLevel {
+    /// Copying is permitted without restriction
+    Cpl0 = 0,
+    /// One generation of copies may be made
+    Cpl1 = 1,
+    /// No copying is permitted
+    Cpl2 = 2,
+}
+
+impl CopyProtectLevel {
+    fn from_u8(value: u8) -> Option<Self> {
+        match value {
+            0 => Some(CopyProtectLevel::Cpl0),
+            1 => Some(CopyProtectLevel::Cpl1),
+            2 => Some(CopyProtectLevel::Cpl2),
+            _ => None,
+        }
+    }
+}
+
+/// Range of a volume control in 1/256 dB
+#[derive(Clone, Copy, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+struct VolumeRange {
+    min: i16,
+    max: i16,
+    res: i16,
+}
+
 #[derive(Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub struct StreamConfig<'a> {
     format: Format,
     channels: u8,
     rates: Rates<'a>,
     terminal_type: TerminalType,
-    /// ISO endpoint size calculated from format, channels and rates (may be
-    /// removed in future)
-    ep_size: u16,
+    /// Feature Unit with a master mute control in the signal path
+    mute_control: bool,
+    /// Feature Unit with a master volume control in the signal path
+    volume_range: Option<VolumeRange>,
+    /// Feature Unit with a mute control per channel in the signal path
+    channel_mute_control: bool,
+    /// Feature Unit with a volume control per channel in the signal path
+    channel_volume_range: Option<VolumeRange>,
+    /// Feature Unit with a master automatic gain control in the signal path
+    agc_control: bool,
+    /// Feature Unit with master bass, mid and treble controls in the signal
+    /// path
+    tone_controls: bool,
+    /// Feature Unit with a master graphic equalizer with the indicated bands
+    /// in the signal path (0 = none)
+    equalizer_bands: u32,
+    /// Copy Protect control of the terminal of type `terminal_type`
+    copy_protect_control: bool,
+    /// Connector control of the terminal of type `terminal_type` reporting
+    /// whether a plug is inserted (USB Audio Class 2.0 only)
+    connector_control: bool,
+    /// Latency of the signal path in microseconds reported to the host
+    latency: Option<u32>,
+    /// Clock Source of type external (USB Audio Class 2.0 only)
+    external_clock: bool,
+    /// Clock Selector choosing between an internal and an external Clock
+    /// Source (USB Audio Class 2.0 only)
+    clock_selector: bool,
+    /// Clock Multiplier deriving the clock of the stream from the Clock
+    /// Source as (numerator, denominator) (USB Audio Class 2.0 only)
+    clock_multiplier: Option<(u16, u16)>,
+    /// Formats of additional operational alternate settings
+    alt_formats: &'a [Format],
+    /// Channel counts of additional operational alternate settings
+    alt_channels: &'a [u8],
+    /// Spatial locations of the channels if not the default ones
+    channel_config: Option<ChannelConfig>,
+    /// Names of the first logical channels
+    channel_names: &'a [&'a str],
+    /// Name of the terminal of type `terminal_type`
+    terminal_name: Option<&'a str>,
+    /// Terminal types of the additional sources of a Selector Unit
+    selector_sources: &'a [TerminalType],
+    /// Processing Unit in the signal path
+    processing_unit: Option<ProcessingUnit<'a>>,
+    /// Extension Unit in the signal path
+    extension_unit: Option<ExtensionUnit<'a>>,
+    /// Explicit feedback endpoint paired with the data endpoint
+    feedback: bool,
+    /// Synchronization type of the data endpoint if not the default one
+    synchronization: Option<Synchronization>,
+    /// Lock delay of the data endpoint if declared
+    lock_delay: Option<LockDelay>,
+    /// Pitch control of the data endpoint
+    pitch_control: bool,
+    /// Service interval of the data endpoint (bInterval)
+    interval: u8,
+    /// wMaxPacketSize of the data endpoint if not the computed one
+    max_packet_size: Option<u16>,
+    /// MaxPacketsOnly attribute of the data endpoint
+    max_packets_only: bool,
+    /// content of packets lacking data of a buffered input stream
+    underrun_policy: UnderrunPolicy,
 }
 
-impl StreamConfig<'_> {
+impl<'a> StreamConfig<'a> {
+    /// Create a stream configuration without controls and units
+    fn new(
+        format: Format,
+        channels: u8,
+        rates: Rates<'a>,
+        terminal_type: TerminalType,
+    ) -> StreamConfig<'a> {
+        StreamConfig {
+            format,
+            channels,
+            rates,
+            terminal_type,
+            mute_control: false,
+            volume_range: None,
+            channel_mute_control: false,
+            channel_volume_range: None,
+            agc_control: false,
+            tone_controls: false,
+            equalizer_bands: 0,
+            copy_protect_control: false,
+            connector_control: false,
+            latency: None,
+            external_clock: false,
+            clock_selector: false,
+            clock_multiplier: None,
+            alt_formats: &[],
+            alt_channels: &[],
+            channel_config: None,
+            channel_names: &[],
+            terminal_name: None,
+            selector_sources: &[],
+            processing_unit: None,
+            extension_unit: None,
+            feedback: false,
+            synchronization: None,
+            lock_delay: None,
+            pitch_control: false,
+            interval: 1,
+            max_packet_size: None,
+            max_packets_only: false,
+            underrun_policy: UnderrunPolicy::ZeroLength,
+        }
+    }
+
     /// Create a stream configuration with one or more discrete sampling rates
     /// indicated in samples/second. An input stream or an output stream will
     /// have an Input Terminal or Output Terminal of Terminal Type
-    /// `terminal_type`, respectively.
+    /// `terminal_type`, respectively. The number of channels must be at least
+    /// one and is otherwise limited by the bandwidth of the endpoint.
     pub fn new_discrete(
         format: Format,
         channels: u8,
         rates: &'_ [u32],
         terminal_type: TerminalType,
     ) -> Result<StreamConfig<'_>> {
-        let max_rate = rates.iter().max().unwrap();
-        let ep_size = Self::ep_size(format, channels, *max_rate)?;
+        if rates.is_empty() || channels == 0 {
+            return Err(Error::InvalidValue);
+        }
         let rates = Rates::Discrete(rates);
-        Ok(StreamConfig {
-            format,
-            channels,
-            rates,
-            terminal_type,
-            ep_size,
-        })
+        Ok(StreamConfig::new(format, channels, rates, terminal_type))
     }
 
     /// Create a stream configuration with a continuous range of supported
     /// sampling rates indicated in samples/second. An input stream or an output
     /// stream will have an Input Terminal or Output Terminal of Terminal Type
-    /// `terminal_type`, respectively.
+    /// `terminal_type`, respectively. The number of channels must be at least
+    /// one and is otherwise limited by the bandwidth of the endpoint.
     pub fn new_continuous(
         format: Format,
         channels: u8,
@@ -114,424 +783,4916 @@ impl StreamConfig<'_> {
         max_rate: u32,
         terminal_type: TerminalType,
     ) -> Result<StreamConfig<'static>> {
-        if min_rate >= max_rate {
+        if min_rate >= max_rate || channels == 0 {
             return Err(Error::InvalidValue);
         }
-        let ep_size = Self::ep_size(format, channels, max_rate)?;
         let rates = Rates::Continuous(min_rate, max_rate);
+        Ok(StreamConfig::new(format, channels, rates, terminal_type))
+    }
+
+    /// Add a Feature Unit with a master mute control to the signal path of the
+    /// stream. The current state of the control can be queried by
+    /// `AudioClass::input_mute()` or `AudioClass::output_mute()`, respectively.
+    /// Changes by the host are signalled by `AudioClass::poll_event()`.
+    pub fn with_mute_control(self) -> Self {
+        StreamConfig {
+            mute_control: true,
+            ..self
+        }
+    }
+
+    /// Add a Feature Unit with a master volume control to the signal path of
+    /// the stream. The minimum value `min`, the maximum value `max` and the
+    /// resolution `res` of the control are indicated in 1/256 dB. The volume
+    /// is initially set to 0 dB or to the closest value within the range. The
+    /// current setting can be queried by `AudioClass::input_volume()` or
+    /// `AudioClass::output_volume()`, respectively. Changes by the host are
+    /// signalled by `AudioClass::poll_event()`.
+    pub fn with_volume_control(self, min: i16, max: i16, res: i16) -> Result<Self> {
+        if min >= max || res <= 0 {
+            return Err(Error::InvalidValue);
+        }
         Ok(StreamConfig {
-            format,
-            channels,
-            rates,
-            terminal_type,
-            ep_size,
+            volume_range: Some(VolumeRange { min, max, res }),
+            ..self
         })
     }
 
-    /// calculate ISO endpoint size from format, channels and rates
-    fn ep_size(format: Format, channels: u8, max_rate: u32) -> Result<u16> {
-        let octets_per_frame = channels as u32
-            * match format {
-                Format::S16le => 2,
-                Format::S24le => 3,
-            };
-        let ep_size = octets_per_frame * max_rate / 1000;
-        if ep_size > MAX_ISO_EP_SIZE {
-            return Err(Error::BandwidthExceeded);
+    /// Add a Feature Unit with a mute control for each channel to the signal
+    /// path of the stream. Per-channel controls are supported for streams with
+    /// up to 8 channels. The current state of a control can be queried by
+    /// `AudioClass::input_channel_mute()` or
+    /// `AudioClass::output_channel_mute()`, respectively. Changes by the host
+    /// are signalled by `AudioClass::poll_event()`.
+    pub fn with_channel_mute_control(self) -> Result<Self> {
+        if self.channels as usize > MAX_CHANNELS {
+            return Err(Error::InvalidValue);
         }
-        Ok(ep_size as u16)
+        Ok(StreamConfig {
+            channel_mute_control: true,
+            ..self
+        })
     }
-}
 
-/// USB audio errors, including possible USB Stack errors
-#[derive(Debug)]
-pub enum Error {
-    InvalidValue,
-    BandwidthExceeded,
-    StreamNotInitialized,
-    UsbError(usb_device::UsbError),
-}
+    /// Add a Feature Unit with a volume control for each channel to the signal
+    /// path of the stream. Per-channel controls are supported for streams with
+    /// up to 8 channels. The range of the controls is indicated in 1/256 dB as
+    /// for `with_volume_control()`. The current setting of a control can be
+    /// queried by `AudioClass::input_channel_volume()` or
+    /// `AudioClass::output_channel_volume()`, respectively. Changes by the host
+    /// are signalled by `AudioClass::poll_event()`.
+    pub fn with_channel_volume_control(self, min: i16, max: i16, res: i16) -> Result<Self> {
+        if self.channels as usize > MAX_CHANNELS || min >= max || res <= 0 {
+            return Err(Error::InvalidValue);
+        }
+        Ok(StreamConfig {
+            channel_volume_range: Some(VolumeRange { min, max, res }),
+            ..self
+        })
+    }
 
-impl From<UsbError> for Error {
-    fn from(err: UsbError) -> Self {
-        Error::UsbError(err)
+    /// Add a Feature Unit with a master Automatic Gain Control to the signal
+    /// path of the stream, e.g. for the microphone of a communications device.
+    /// The control is initially enabled. Its current state can be queried by
+    /// `AudioClass::agc()` and changes by the host are signalled by
+    /// `AudioClass::poll_event()`.
+    pub fn with_agc_control(self) -> Self {
+        StreamConfig {
+            agc_control: true,
+            ..self
+        }
     }
-}
 
-/// Result type alias for the USB Audio Class
-type Result<T> = core::result::Result<T, Error>;
+    /// Add a Feature Unit with master Bass, Mid and Treble controls to the
+    /// signal path of the stream, e.g. for the tone controls of a speaker.
+    /// Their current settings can be queried by `AudioClass::tone()` and
+    /// changes by the host are signalled by `AudioClass::poll_event()`.
+    pub fn with_tone_controls(self) -> Self {
+        StreamConfig {
+            tone_controls: true,
+            ..self
+        }
+    }
 
-/// Internal state related to audio streaming in a certain direction
-struct AudioStream<'a, B: UsbBus, D: EndpointDirection> {
-    stream_config: StreamConfig<'a>,
-    interface: InterfaceNumber,
-    endpoint: Endpoint<'a, B, D>,
-    alt_setting: u8,
-}
+    /// Add a Feature Unit with a master Graphic Equalizer control to the
+    /// signal path of the stream. Bit n of `bands` indicates the presence of
+    /// the ANSI band 14 + n, i.e. bands 14 (25 Hz) to 43 (20 kHz) correspond
+    /// to bits 0 to 29; `EQUALIZER_OCTAVE_BANDS` selects the octave bands from
+    /// 31.5 Hz to 16 kHz. All bands are initially set to 0 dB. Their gains can
+    /// be queried by `AudioClass::equalizer_gain()` and changes by the host
+    /// are signalled by `AudioClass::poll_event()`. Returns
+    /// `Error::InvalidValue` if no band or a band above 43 is indicated.
+    pub fn with_graphic_equalizer(self, bands: u32) -> Result<Self> {
+        if GraphicEqualizer::new(bands).is_none() {
+            return Err(Error::InvalidValue);
+        }
+        Ok(StreamConfig {
+            equalizer_bands: bands,
+            ..self
+        })
+    }
 
-macro_rules! append {
-    ($iter:ident, $value:expr) => {
-        *($iter.next().ok_or(UsbError::BufferOverflow)?.1) = $value;
-    };
-}
+    /// Add a Copy Protect control to the terminal of type `terminal_type`, e.g.
+    /// an S/PDIF interface. For an input stream, the Input Terminal reports
+    /// the Copy Protection Level of the incoming signal, which the application
+    /// sets by `AudioClass::set_copy_protect()`. For an output stream, the host
+    /// sets the Copy Protection Level the Output Terminal shall apply, which
+    /// can be queried by `AudioClass::copy_protect()` and whose changes are
+    /// signalled by `AudioClass::poll_event()`. The level is initially
+    /// `CopyProtectLevel::Cpl0`.
+    pub fn with_copy_protect_control(self) -> Self {
+        StreamConfig {
+            copy_protect_control: true,
+            ..self
+        }
+    }
 
-macro_rules! append_u24le {
-    ($iter:ident, $value:expr) => {
-        append!($iter, $value as u8);
-        append!($iter, ($value >> 8) as u8);
-        append!($iter, ($value >> 16) as u8);
-    };
-}
+    /// Add a Connector control to the terminal of type `terminal_type`, e.g. a
+    /// headphone or microphone jack (USB Audio Class 2.0 only). The application
+    /// reports the insertion and removal of the plug by
+    /// `AudioClass::set_connected()`, which notifies the host by a message on
+    /// the interrupt endpoint of the AudioControl interface so that it may
+    /// switch to another device. The plug is initially reported as inserted.
+    /// `AudioClassBuilder::build()` returns `BuildError::UnsupportedByVersion`
+    /// for other versions.
+    pub fn with_connector_control(self) -> Self {
+        StreamConfig {
+            connector_control: true,
+            ..self
+        }
+    }
 
-impl<B: UsbBus, D: EndpointDirection> AudioStream<'_, B, D> {
-    fn write_ac_descriptors(&self, writer: &mut DescriptorWriter) -> usb_device::Result<()> {
-        let is_input = self.endpoint.address().direction() == UsbDirection::In;
-        let terminal_type: u16 = self.stream_config.terminal_type.into();
-        let id_offset = if is_input { 0 } else { 4 };
+    /// Report the latency of the signal path of the stream in microseconds,
+    /// e.g. the delay of the codec plus the data buffered by the application,
+    /// so that the host can compensate it, e.g. for lip sync. A USB Audio
+    /// Class 1.0 function declares it by the bDelay field of the
+    /// AudioStreaming interface, rounded up to whole milliseconds and limited
+    /// to 255 ms, a USB Audio Class 2.0 function by a read-only Latency
+    /// control of the terminal of type `terminal_type`.
+    /// `AudioClass::set_latency()` updates it. `AudioClassBuilder::build()`
+    /// returns `BuildError::UnsupportedByVersion` for a BADD function.
+    pub fn with_latency(self, micros: u32) -> Self {
+        StreamConfig {
+            latency: Some(micros),
+            ..self
+        }
+    }
 
-        // write Input Terminal Descriptor (12 bytes)
-        let tt = if is_input {
-            terminal_type
-        } else {
-            TerminalType::UsbStreaming.into()
+    /// Declare the Clock Source of the stream as an external clock, e.g. a word
+    /// clock input or the clock recovered from an S/PDIF receiver (USB Audio
+    /// Class 2.0 only). The host cannot program the sampling rate of an
+    /// external clock. Instead, the application reports the rate of the clock
+    /// and whether it is valid by `AudioClass::set_external_clock()`. The clock
+    /// is initially reported as invalid. The AudioControl interface of a
+    /// function with external clocks has an interrupt endpoint to notify the
+    /// host about changes. `AudioClassBuilder::build()` returns
+    /// `BuildError::UnsupportedByVersion` for other versions.
+    pub fn with_external_clock(self) -> Self {
+        StreamConfig {
+            external_clock: true,
+            ..self
         }
-        .to_le_bytes();
+    }
 
-        writer.write(
-            CS_INTERFACE,
-            &[
-                INPUT_TERMINAL,                // bDescriptorSubtype
-                ID_INPUT_TERMINAL + id_offset, // bTerminalID
-                tt[0],                         // wTerminalType
-                tt[1],
-                0x00,                        // bAssocTerminal
-                self.stream_config.channels, // bNrChannels
-                0x03,
-                0x00, // wChannelConfig: Left Front and Right Front
-                0x00, // iChannelNames
-                0x00, // iTerminal
-            ],
-        )?;
+    /// Add a Clock Selector that lets the host choose between the internal
+    /// Clock Source of the stream (input pin 1) and an external Clock Source
+    /// (input pin 2), e.g. a word clock input (USB Audio Class 2.0 only). The
+    /// internal clock is initially selected. The rate and validity of the
+    /// external clock are reported by `AudioClass::set_external_clock()`. The
+    /// current selection can be queried by `AudioClass::clock_selector()` and
+    /// changes by the host are signalled by `AudioClass::poll_event()`.
+    /// `AudioClassBuilder::build()` returns `BuildError::UnsupportedByVersion`
+    /// for other versions.
+    pub fn with_clock_selector(self) -> Self {
+        StreamConfig {
+            external_clock: true,
+            clock_selector: true,
+            ..self
+        }
+    }
 
-        // write Output Terminal Descriptor (9 bytes)
-        let tt = if is_input {
-            TerminalType::UsbStreaming.into()
-        } else {
-            terminal_type
+    /// Add a Clock Multiplier that derives the clock of the stream from its
+    /// Clock Source or Clock Selector by multiplying the frequency by
+    /// `numerator / denominator` (USB Audio Class 2.0 only), e.g. 1/512 to
+    /// derive the 44.1 kHz family from a 22.5792 MHz master clock. The Clock
+    /// Sources then report the frequency of the master clock, whereas the
+    /// sampling rates of the stream remain to be indicated in samples/second.
+    /// Returns `Error::InvalidValue` if the numerator or the denominator is 0.
+    /// `AudioClassBuilder::build()` returns `BuildError::UnsupportedByVersion`
+    /// for other versions.
+    pub fn with_clock_multiplier(self, numerator: u16, denominator: u16) -> Result<Self> {
+        if numerator == 0 || denominator == 0 {
+            return Err(Error::InvalidValue);
         }
-        .to_le_bytes();
-        writer.write(
-            CS_INTERFACE,
-            &[
-                OUTPUT_TERMINAL,                // bDescriptorSubtype
-                ID_OUTPUT_TERMINAL + id_offset, // bTerminalID
-                tt[0],                          // wTerminalType
-                tt[1],
-                0x00,                          // bAssocTerminal
-                ID_INPUT_TERMINAL + id_offset, // bSourceID
-                0x00,                          // iTerminal
-            ],
-        )
+        Ok(StreamConfig {
+            clock_multiplier: Some((numerator, denominator)),
+            ..self
+        })
     }
 
-    fn write_as_and_ep_descriptors(&self, writer: &mut DescriptorWriter) -> usb_device::Result<()> {
-        let is_input = self.endpoint.address().direction() == UsbDirection::In;
-        let id_offset = if is_input { 0 } else { 4 };
-        // Standard AS Interface Descriptor (Alt. Set. 0)
-        writer.interface(self.interface, AUDIO, AUDIOSTREAMING, 0x00)?;
+    /// Add an explicit feedback endpoint to an output stream. The data endpoint
+    /// becomes asynchronous and the host adjusts the number of samples sent
+    /// per frame according to the feedback value set by
+    /// `AudioClass::set_feedback_rate()`. `AudioClassBuilder::build()` returns
+    /// `BuildError::FeedbackNotSupported` if the configuration is used for an
+    /// input stream and `BuildError::UnsupportedByVersion` for a BADD function.
+    pub fn with_feedback_endpoint(self) -> Self {
+        StreamConfig {
+            feedback: true,
+            ..self
+        }
+    }
 
-        // Standard AS Interface Descriptor (Alt. Set. 1)
-        writer.interface_alt(self.interface, 0x01, AUDIO, AUDIOSTREAMING, 0x00, None)?;
+    /// Offer the stream in additional formats, e.g. with other bit depths. The
+    /// format passed to the constructor is provided by alternate setting 1,
+    /// the formats listed here by alternate settings 2, 3 etc. The alternate
+    /// setting selected by the host is reported by
+    /// `AudioClass::input_alt_setting()` or
+    /// `AudioClass::output_alt_setting()`, respectively.
+    pub fn with_alt_formats(self, formats: &'a [Format]) -> Self {
+        StreamConfig {
+            alt_formats: formats,
+            ..self
+        }
+    }
 
-        // Class-specific AS General Interface Descriptor
-        let terminal_link = id_offset
-            + if is_input {
-                ID_OUTPUT_TERMINAL
-            } else {
-                ID_INPUT_TERMINAL
-            };
-        writer.write(
-            CS_INTERFACE,
-            &[
-                AS_GENERAL,    // bDescriptorSubtype:
-                terminal_link, // bTerminalLink
-                0x01,          // bDelay
-                PCM as u8,
-                (PCM >> 8) as u8, // wFormatTag
-            ],
-        )?;
+    /// Assign spatial locations to the channels of the stream, e.g.
+    /// `ChannelConfig::SURROUND_5_1` for a six channel stream. Returns an error
+    /// if there are more locations than channels. Alternate settings with fewer
+    /// channels keep the locations of their channels. Without a channel
+    /// configuration, the streams are described as Left Front and Right Front.
+    /// Locations beyond `ChannelConfig::TOP_CENTER` make `build()` fail for USB
+    /// Audio Class 1.0.
+    pub fn with_channel_config(self, channel_config: ChannelConfig) -> Result<Self> {
+        if channel_config.num_locations() > self.channels {
+            return Err(Error::InvalidValue);
+        }
+        Ok(StreamConfig {
+            channel_config: Some(channel_config),
+            ..self
+        })
+    }
 
-        // Type 1 Format Type Descriptor
-        let mut format_desc = [0x00u8; 128];
-        let mut iter = format_desc.iter_mut().enumerate();
-        append!(iter, FORMAT_TYPE); // bDescriptorSubtype;
-        append!(iter, FORMAT_TYPE_I); // bFormatType
-        append!(iter, self.stream_config.channels); // bNrChannels
-        append!(
-            iter,
-            match self.stream_config.format {
-                // bSubFrameSize
-                Format::S16le => 2,
-                Format::S24le => 3,
-            }
-        );
-        append!(
-            iter,
-            match self.stream_config.format {
-                // bBitResolution
-                Format::S16le => 16,
-                Format::S24le => 24,
-            }
-        );
-        match self.stream_config.rates {
-            Rates::Continuous(min, max) => {
-                append!(iter, 0x00); // bSamFreqType
-                append_u24le!(iter, min);
-                append_u24le!(iter, max);
-            }
-            Rates::Discrete(rates) => {
+    /// Name the logical channels of the stream, starting with channel 1, e.g.
+    /// `&["Kick", "Snare"]`. The names are provided as string descriptors and
+    /// let the host label the channels. Returns an error if there are more
+    /// names than channels.
+    pub fn with_channel_names(self, names: &'a [&'a str]) -> Result<Self> {
+        if names.len() > self.channels as usize {
+            return Err(Error::InvalidValue);
+        }
+        Ok(StreamConfig {
+            channel_names: names,
+            ..self
+        })
+    }
+
+    /// Add a Selector Unit to the signal path of an input stream that lets the
+    /// host choose the source of the stream among the Input Terminal of type
+    /// `terminal_type` (input pin 1) and additional Input Terminals of the
+    /// indicated types (input pins 2 and higher), e.g. a microphone and a line
+    /// input. The selected pin can be queried by `AudioClass::selector()` and
+    /// changes by the host are signalled by `AudioClass::poll_event()`.
+    /// Returns an error if there are no or more than 14 additional sources.
+    /// `build()` returns `BuildError::SelectorNotSupported` for output streams.
+    pub fn with_selector(self, sources: &'a [TerminalType]) -> Result<Self> {
+        if sources.is_empty() || sources.len() >= SELECTOR_ID_BLOCK as usize - 1 {
+            return Err(Error::InvalidValue);
+        }
+        Ok(StreamConfig {
+            selector_sources: sources,
+            ..self
+        })
+    }
+
+    /// Add a Processing Unit such as a Dynamic Range Compressor to the signal
+    /// path of the stream, between the source of the stream and the Feature
+    /// Unit. The current values of its controls can be queried by
+    /// `AudioClass::processing_control()` and changes by the host are
+    /// signalled by `AudioClass::poll_event()`. `build()` returns
+    /// `BuildError::UnsupportedByVersion` if the version is not
+    /// `AudioVersion::Uac1` and `BuildError::InvalidProcessingUnit` if the
+    /// operating modes of a Dolby Prologic decoder do not match the channel
+    /// configuration of the stream.
+    pub fn with_processing_unit(self, unit: ProcessingUnit<'a>) -> Self {
+        StreamConfig {
+            processing_unit: Some(unit),
+            ..self
+        }
+    }
+
+    /// Add a vendor-specific Extension Unit to the signal path of the stream,
+    /// between the Processing Unit or the source of the stream and the Feature
+    /// Unit. Requests addressed to it are passed to the handler set by
+    /// `AudioClassBuilder::extension_unit_handler()` and rejected if there is
+    /// none.
+    pub fn with_extension_unit(self, unit: ExtensionUnit<'a>) -> Self {
+        StreamConfig {
+            extension_unit: Some(unit),
+            ..self
+        }
+    }
+
+    /// Number of input pins of the Selector Unit or 0 if there is none
+    fn selector_pins(&self) -> u8 {
+        match self.selector_sources.len() {
+            0 => 0,
+            n => 1 + n as u8,
+        }
+    }
+
+    /// Name the Input Terminal of an input stream or the Output Terminal of an
+    /// output stream, i.e. the terminal of type `terminal_type`, e.g. "XLR Mic
+    /// 1" or "Headphone Out". The name is provided as a string descriptor and
+    /// shown by host mixers.
+    pub fn with_terminal_name(self, name: &'a str) -> Self {
+        StreamConfig {
+            terminal_name: Some(name),
+            ..self
+        }
+    }
+
+    /// Spatial locations of the channels of a cluster with the indicated
+    /// number of channels (wChannelConfig or bmChannelConfig)
+    fn channel_config(&self, channels: u8) -> u32 {
+        match self.channel_config {
+            Some(channel_config) => channel_config.truncate(channels).bits(),
+            None => ChannelConfig::STEREO.bits(),
+        }
+    }
+
+    /// Offer the stream with fewer channels in additional alternate settings,
+    /// so that hosts with limited bandwidth can fall back to them. The number
+    /// of channels passed to the constructor is the number of channels of the
+    /// terminal and must not be exceeded. The alternate settings follow the
+    /// ones added by `with_alt_formats()` and use the format passed to the
+    /// constructor. The number of channels of the alternate setting selected by
+    /// the host is reported by `AudioClass::input_channels()` or
+    /// `AudioClass::output_channels()`, respectively.
+    pub fn with_alt_channels(self, channels: &'a [u8]) -> Self {
+        StreamConfig {
+            alt_channels: channels,
+            ..self
+        }
+    }
+
+    /// Number of operational alternate settings
+    fn num_alt_settings(&self) -> u8 {
+        1 + self.alt_formats.len() as u8 + self.alt_channels.len() as u8
+    }
+
+    /// Formats and numbers of channels of the operational alternate settings
+    fn alt_settings(&self) -> AltSettings<'a> {
+        AltSettings {
+            format: self.format,
+            channels: self.channels,
+            alt_formats: self.alt_formats,
+            alt_channels: self.alt_channels,
+        }
+    }
+
+    /// Format and number of channels of an operational alternate setting
+    fn alt_setting(&self, alt_setting: u8) -> (Format, u8) {
+        self.alt_settings().get(alt_setting)
+    }
+
+    /// Select the synchronization type of the data endpoint. By default, input
+    /// streams are asynchronous and output streams are adaptive, or
+    /// asynchronous if they use feedback. `AudioClassBuilder::build()` returns
+    /// `BuildError::InvalidSynchronization` if a stream using feedback is not
+    /// asynchronous.
+    pub fn with_synchronization(self, synchronization: Synchronization) -> Self {
+        StreamConfig {
+            synchronization: Some(synchronization),
+            ..self
+        }
+    }
+
+    /// Declare the time the clock recovery of an adaptive or asynchronous
+    /// data endpoint needs to lock to the data stream, e.g. the lock time of
+    /// the PLL of the codec, in the bLockDelayUnits and wLockDelay fields of
+    /// its class-specific endpoint descriptor. Undefined by default.
+    /// `AudioClassBuilder::build()` returns `BuildError::UnsupportedByVersion`
+    /// for a BADD function.
+    pub fn with_lock_delay(self, lock_delay: LockDelay) -> Self {
+        StreamConfig {
+            lock_delay: Some(lock_delay),
+            ..self
+        }
+    }
+
+    /// Add a Pitch control to the data endpoint of an adaptive stream, by
+    /// which the host allows the device to vary its sampling rate slightly
+    /// around the nominal one, e.g. to track the data rate of the host more
+    /// closely. Whether the host enabled it can be queried by
+    /// `AudioClass::pitch_enabled()` and changes are signalled by
+    /// `AudioClass::poll_event()`. The control is initially disabled.
+    /// `AudioClassBuilder::build()` returns `BuildError::InvalidSynchronization`
+    /// if the stream is not adaptive and `BuildError::UnsupportedByVersion` for
+    /// a BADD function.
+    pub fn with_pitch_control(self) -> Self {
+        StreamConfig {
+            pitch_control: true,
+            ..self
+        }
+    }
+
+    /// Select what a buffered input stream sends when the application did not
+    /// push enough data into the sample buffer for the next packet. Some hosts
+    /// handle short or zero-length packets badly, while others prefer them to
+    /// made-up data. Defaults to `UnderrunPolicy::ZeroLength` and only affects
+    /// input streams serviced from a sample buffer, except for
+    /// `UnderrunPolicy::Silence`, which also completes the packets written by
+    /// `AudioClass::write_packet()`.
+    pub fn with_underrun_policy(self, policy: UnderrunPolicy) -> Self {
+        StreamConfig {
+            underrun_policy: policy,
+            ..self
+        }
+    }
+
+    /// Select the service interval of the data endpoint. The endpoint is
+    /// serviced every 2^(`interval` - 1) frames (full speed) or microframes
+    /// (high speed) with packets that are enlarged accordingly, which reduces
+    /// the overhead of low-rate streams. `interval` defaults to 1 and must be
+    /// in the range 1..=10 at full speed and 1..=13 at high speed, where the
+    /// endpoint is serviced at least once per 512 ms.
+    /// `AudioClassBuilder::build()` returns
+    /// `BuildError::InvalidServiceInterval` for a larger interval at full
+    /// speed and `BuildError::UnsupportedByVersion` for an interval other than
+    /// 1 with USB Audio Class 1.0, whose data endpoints are serviced every
+    /// frame.
+    pub fn with_service_interval(self, interval: u8) -> Result<Self> {
+        if !(1..=MAX_SERVICE_INTERVAL_HS).contains(&interval) {
+            return Err(Error::InvalidValue);
+        }
+        Ok(StreamConfig { interval, ..self })
+    }
+
+    /// Override the wMaxPacketSize of the data endpoint, which is otherwise
+    /// computed from the formats, channels, sampling rates and service
+    /// interval of the stream, e.g. to reserve bandwidth for a faster clock
+    /// or to work around a host. `AudioClassBuilder::build()` returns
+    /// `BuildError::BandwidthExceeded` if `size` is smaller than the computed
+    /// size or larger than an isochronous packet and
+    /// `BuildError::UnsupportedByVersion` for a BADD function.
+    pub fn with_max_packet_size(self, size: u16) -> Self {
+        StreamConfig {
+            max_packet_size: Some(size),
+            ..self
+        }
+    }
+
+    /// Set the MaxPacketsOnly attribute of the data endpoint, which indicates
+    /// that all packets of the endpoint have wMaxPacketSize bytes. Packets of
+    /// an output stream are then padded by the host, while the application
+    /// pads the packets of an input stream itself, e.g. by choosing a sampling
+    /// rate that fills each packet, possibly together with
+    /// `with_max_packet_size()`. `AudioClassBuilder::build()` returns
+    /// `BuildError::UnsupportedByVersion` for a BADD function.
+    pub fn with_max_packets_only(self) -> Self {
+        StreamConfig {
+            max_packets_only: true,
+            ..self
+        }
+    }
+
+    /// Synchronization type of the data endpoint, where `default` applies
+    /// unless a type has been selected explicitly
+    fn synchronization(&self, default: Synchronization) -> Synchronization {
+        self.synchronization.unwrap_or(default)
+    }
+
+    /// Whether the signal path of the stream includes a Feature Unit
+    fn has_feature_unit(&self) -> bool {
+        self.mute_control
+            || self.volume_range.is_some()
+            || self.channel_mute_control
+            || self.channel_volume_range.is_some()
+            || self.agc_control
+            || self.tone_controls
+            || self.equalizer_bands != 0
+    }
+
+    /// Whether the indicated channel has an automatic gain control
+    fn has_agc_control(&self, channel: u8) -> bool {
+        channel == 0 && self.agc_control
+    }
+
+    /// Whether the indicated channel has bass, mid and treble controls
+    fn has_tone_controls(&self, channel: u8) -> bool {
+        channel == 0 && self.tone_controls
+    }
+
+    /// Whether the indicated channel has a graphic equalizer
+    fn has_equalizer(&self, channel: u8) -> bool {
+        channel == 0 && self.equalizer_bands != 0
+    }
+
+    /// Whether the indicated channel has a mute control (channel 0 is the
+    /// master channel)
+    fn has_mute_control(&self, channel: u8) -> bool {
+        match channel {
+            0 => self.mute_control,
+            c if c <= self.channels => self.channel_mute_control,
+            _ => false,
+        }
+    }
+
+    /// Range of the volume control of the indicated channel or `None` if the
+    /// channel has no volume control (channel 0 is the master channel)
+    fn volume_control(&self, channel: u8) -> Option<VolumeRange> {
+        match channel {
+            0 => self.volume_range,
+            c if c <= self.channels => self.channel_volume_range,
+            _ => None,
+        }
+    }
+
+    /// Feature Unit controls of the indicated channel as a bitmap according to
+    /// the bmaControls field of UAC 1.0
+    fn controls(&self, channel: u8) -> u8 {
+        let mut controls = 0;
+        if self.has_mute_control(channel) {
+            controls |= 0x01;
+        }
+        if self.volume_control(channel).is_some() {
+            controls |= 0x02;
+        }
+        if self.has_tone_controls(channel) {
+            controls |= 0x1c;
+        }
+        if self.has_equalizer(channel) {
+            controls |= 0x20;
+        }
+        if self.has_agc_control(channel) {
+            controls |= 0x40;
+        }
+        controls
+    }
+
+    /// Initial settings of the volume controls: 0 dB or the closest value
+    /// within the range of the respective control
+    fn initial_volume(&self) -> [i16; MAX_CHANNELS + 1] {
+        core::array::from_fn(|channel| {
+            self.volume_control(channel as u8)
+                .map_or(0, |range| 0.clamp(range.min, range.max))
+        })
+    }
+
+    /// Number of packets per second transferred by the data endpoint
+    fn packet_rate(&self, high_speed: bool) -> u32 {
+        let intervals_per_second: u32 = if high_speed { 8000 } else { 1000 };
+        intervals_per_second >> (self.interval - 1)
+    }
+
+    /// calculate ISO endpoint size as required by the largest operational
+    /// alternate setting
+    fn ep_size(
+        &self,
+        stream: StreamId,
+        high_speed: bool,
+        high_bandwidth: bool,
+    ) -> BuildResult<u16> {
+        let mut ep_size = 0;
+        for alt_setting in 1..=self.num_alt_settings() {
+            let (format, channels) = self.alt_setting(alt_setting);
+            let packet_size =
+                self.packet_size(stream, format, channels, high_speed, high_bandwidth)?;
+            ep_size = ep_size.max(packet_size);
+        }
+        Ok(ep_size)
+    }
+
+    /// calculate ISO packet size from format, channels, rates and service
+    /// interval for 1 ms frames (full speed) or 125 µs microframes (high speed)
+    fn packet_size(
+        &self,
+        stream: StreamId,
+        format: Format,
+        channels: u8,
+        high_speed: bool,
+        high_bandwidth: bool,
+    ) -> BuildResult<u16> {
+        let intervals_per_second = if high_speed { 8000 } else { 1000 };
+        let max_ep_size = max_iso_payload(high_speed, high_bandwidth);
+        if channels > self.channels {
+            return Err(BuildError::TooManyChannels {
+                stream,
+                max: self.channels,
+            });
+        }
+        if channels == 0 {
+            return Err(BuildError::InvalidFormat(stream));
+        }
+        match format {
+            Format::Iec61937(_) if channels != 2 => return Err(BuildError::InvalidFormat(stream)),
+            Format::Raw {
+                subframe_size,
+                bit_resolution,
+                ..
+            } if !(1..=4).contains(&subframe_size) || bit_resolution > 8 * subframe_size => {
+                return Err(BuildError::InvalidFormat(stream))
+            }
+            _ => {}
+        }
+        let frames_per_interval = 1u64 << (self.interval - 1);
+        let ep_size = match format.type_ii() {
+            Some((max_bit_rate, _)) => {
+                max_bit_rate as u64 * 1000 / 8 * frames_per_interval / intervals_per_second
+            }
+            None => {
+                // rates that are not a multiple of the packet rate alternate
+                // between packets of n and n + 1 audio frames
+                let octets_per_frame = channels as u64 * format.subframe_size() as u64;
+                let audio_frames =
+                    (self.rates.max() as u64 * frames_per_interval).div_ceil(intervals_per_second);
+                audio_frames * octets_per_frame
+            }
+        };
+        if ep_size > max_ep_size as u64 {
+            return Err(BuildError::BandwidthExceeded {
+                stream,
+                needed: u32::try_from(ep_size).unwrap_or(u32::MAX),
+                max: max_ep_size,
+            });
+        }
+        Ok(ep_size as u16)
+    }
+
+    /// ISO endpoint size depending on the version of the USB Audio Class and
+    /// the speed of the device
+    fn iso_ep_size(
+        &self,
+        stream: StreamId,
+        version: AudioVersion,
+        high_speed: bool,
+        high_bandwidth: bool,
+    ) -> BuildResult<u16> {
+        // the Feature Unit Descriptor holds a bitmap of controls per channel
+        // (1 byte in UAC 1.0, 4 bytes otherwise) and must not exceed 255 bytes
+        let fu_length = match version {
+            AudioVersion::Uac1 => 7 + self.channels as usize + 1,
+            _ => 6 + (self.channels as usize + 1) * 4,
+        };
+        if self.has_feature_unit() && fu_length > 255 {
+            return Err(BuildError::DescriptorTooLarge {
+                stream,
+                needed: fu_length,
+                max: 255,
+            });
+        }
+        let unsupported = BuildError::UnsupportedByVersion(Some(stream));
+        if version == AudioVersion::Uac1
+            && self.channel_config(self.channels) & !ChannelConfig::UAC1_MASK != 0
+        {
+            return Err(unsupported);
+        }
+        if (self.external_clock || self.connector_control || self.clock_multiplier.is_some())
+            && version != AudioVersion::Uac2
+        {
+            return Err(unsupported);
+        }
+        if (self.latency.is_some()
+            || self.lock_delay.is_some()
+            || self.pitch_control
+            || self.max_packet_size.is_some()
+            || self.max_packets_only)
+            && version == AudioVersion::Uac3Badd
+        {
+            return Err(unsupported);
+        }
+        if self.interval != 1 && version == AudioVersion::Uac1 {
+            return Err(unsupported);
+        }
+        let max_interval = if high_speed {
+            MAX_SERVICE_INTERVAL_HS
+        } else {
+            MAX_SERVICE_INTERVAL_FS
+        };
+        if self.interval > max_interval {
+            return Err(BuildError::InvalidServiceInterval(stream));
+        }
+        if let Some(unit) = self.processing_unit {
+            if version != AudioVersion::Uac1 {
+                return Err(unsupported);
+            }
+            if !unit.is_valid(self.channel_config(self.channels)) {
+                return Err(BuildError::InvalidProcessingUnit(stream));
+            }
+        }
+        let ep_size = self.ep_size(stream, high_speed, high_bandwidth)?;
+        if let Some(size) = self.max_packet_size {
+            let (needed, max) = if size < ep_size {
+                (ep_size as u32, size as u32)
+            } else {
+                (size as u32, max_iso_payload(high_speed, high_bandwidth))
+            };
+            if needed > max {
+                return Err(BuildError::BandwidthExceeded {
+                    stream,
+                    needed,
+                    max,
+                });
+            }
+            return Ok(size);
+        }
+        match version {
+            AudioVersion::Uac3Badd => self.badd_ep_size(ep_size).ok_or(unsupported),
+            _ => Ok(ep_size),
+        }
+    }
+}
+
+/// USB audio errors, including possible USB Stack errors
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Error {
+    /// An argument is out of range or does not match the configuration, e.g.
+    /// a sample type that does not match the selected format
+    InvalidValue,
+    /// A stream does not fit into a single isochronous packet per (micro)frame
+    /// or the streams do not fit into a (micro)frame together
+    BandwidthExceeded,
+    /// The stream is not configured or its data endpoint has been handed over
+    /// to a stream handle or a sample buffer
+    StreamNotInitialized,
+    /// The host has not started the stream (alternate setting 0)
+    StreamInactive,
+    /// The previous packet has not been transmitted yet or no packet has been
+    /// received; the operation can be retried later
+    WouldBlock,
+    /// A buffer is too small for the packet or the samples
+    BufferTooSmall,
+    /// The length of the data is not a multiple of the frame size (number of
+    /// channels times subframe size) of the selected alternate setting
+    InvalidLength,
+    /// Other error reported by the USB stack
+    Usb(UsbError),
+}
+
+impl From<UsbError> for Error {
+    fn from(err: UsbError) -> Self {
+        match err {
+            UsbError::WouldBlock => Error::WouldBlock,
+            UsbError::BufferOverflow => Error::BufferTooSmall,
+            err => Error::Usb(err),
+        }
+    }
+}
+
+impl core::fmt::Display for Error {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Error::InvalidValue => f.write_str("invalid value"),
+            Error::BandwidthExceeded => f.write_str("bandwidth exceeded"),
+            Error::StreamNotInitialized => f.write_str("stream not initialized"),
+            Error::StreamInactive => f.write_str("stream inactive"),
+            Error::WouldBlock => f.write_str("operation would block"),
+            Error::BufferTooSmall => f.write_str("buffer too small"),
+            Error::InvalidLength => f.write_str("length is not a multiple of the frame size"),
+            Error::Usb(err) => write!(f, "USB error: {:?}", err),
+        }
+    }
+}
+
+impl core::error::Error for Error {}
+
+/// Result type alias for the USB Audio Class
+type Result<T> = core::result::Result<T, Error>;
+
+/// Result type alias for the builder
+type BuildResult<T> = core::result::Result<T, BuildError>;
+
+/// Reasons for `AudioClassBuilder::build()` to fail
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum BuildError {
+    /// More than `MAX_STREAMS` input or output streams were added.
+    TooManyStreams,
+    /// The selected version does not support a feature of the indicated stream
+    /// or, for `None`, of the function, e.g. an external clock with USB Audio
+    /// Class 1.0 or a second stream of a BADD function.
+    UnsupportedByVersion(Option<StreamId>),
+    /// An alternate setting of the indicated stream has no channels or a
+    /// format that is invalid for its number of channels.
+    InvalidFormat(StreamId),
+    /// An alternate setting of the indicated stream has more channels than the
+    /// stream itself (`max`).
+    TooManyChannels { stream: StreamId, max: u8 },
+    /// A descriptor of the indicated stream, e.g. the Feature Unit Descriptor
+    /// of a stream with many channels, needs `needed` bytes, but its length
+    /// is limited to `max` bytes.
+    DescriptorTooLarge {
+        stream: StreamId,
+        needed: usize,
+        max: usize,
+    },
+    /// The indicated stream needs packets of `needed` bytes, but an
+    /// isochronous packet holds at most `max` bytes per (micro)frame.
+    BandwidthExceeded {
+        stream: StreamId,
+        needed: u32,
+        max: u32,
+    },
+    /// The packets of all isochronous endpoints of the function including
+    /// their protocol overhead need `needed` bytes if they are transferred in
+    /// the same (micro)frame, but at most `max` bytes of a (micro)frame may be
+    /// allocated to periodic transfers.
+    FrameBandwidthExceeded { needed: u32, max: u32 },
+    /// The service interval of the indicated stream exceeds 10 at full speed.
+    InvalidServiceInterval(StreamId),
+    /// The indicated input stream has a feedback endpoint.
+    FeedbackNotSupported(StreamId),
+    /// The indicated output stream has a Selector Unit.
+    SelectorNotSupported(StreamId),
+    /// The indicated stream uses feedback but is not asynchronous or has a
+    /// Pitch control but is not adaptive.
+    InvalidSynchronization(StreamId),
+    /// The operating modes of the Processing Unit of the indicated stream do
+    /// not match its channel configuration.
+    InvalidProcessingUnit(StreamId),
+    /// The Extension Unit of the indicated stream refers to a stream that does
+    /// not exist or to its own stream or has too many sources.
+    InvalidExtensionSources(StreamId),
+    /// `AudioClassBuilder::associate_terminals()` refers to a stream that does
+    /// not exist or associates a terminal twice.
+    InvalidAssociation,
+    /// `AudioClassBuilder::monitor()` refers to a stream that does not exist.
+    InvalidMonitor,
+    /// The Mixer Unit of the indicated output stream would have more than 64
+    /// controls.
+    TooManyMixerControls(StreamId),
+    /// `AudioClassBuilder::implicit_feedback()` is used without both a first
+    /// input and a first output stream or with an output stream that has an
+    /// explicit feedback endpoint.
+    InvalidImplicitFeedback,
+    /// The USB peripheral could not allocate an endpoint, e.g. because it ran
+    /// out of endpoints or packet memory.
+    EndpointAllocFailed(UsbError),
+}
+
+impl From<UsbError> for BuildError {
+    fn from(err: UsbError) -> Self {
+        BuildError::EndpointAllocFailed(err)
+    }
+}
+
+impl From<BuildError> for Error {
+    fn from(err: BuildError) -> Self {
+        match err {
+            BuildError::BandwidthExceeded { .. } | BuildError::FrameBandwidthExceeded { .. } => {
+                Error::BandwidthExceeded
+            }
+            BuildError::EndpointAllocFailed(err) => Error::Usb(err),
+            _ => Error::InvalidValue,
+        }
+    }
+}
+
+impl core::fmt::Display for BuildError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            BuildError::TooManyStreams => f.write_str("too many streams"),
+            BuildError::UnsupportedByVersion(Some(stream)) => {
+                write!(f, "{:?} not supported by the version", stream)
+            }
+            BuildError::UnsupportedByVersion(None) => f.write_str("not supported by the version"),
+            BuildError::InvalidFormat(stream) => write!(f, "{:?}: invalid format", stream),
+            BuildError::TooManyChannels { stream, max } => {
+                write!(f, "{:?}: more than {} channels", stream, max)
+            }
+            BuildError::DescriptorTooLarge {
+                stream,
+                needed,
+                max,
+            } => write!(
+                f,
+                "{:?}: descriptor of {} bytes exceeds {} bytes",
+                stream, needed, max
+            ),
+            BuildError::BandwidthExceeded {
+                stream,
+                needed,
+                max,
+            } => write!(
+                f,
+                "{:?}: packets of {} bytes exceed {} bytes",
+                stream, needed, max
+            ),
+            BuildError::InvalidServiceInterval(stream) => {
+                write!(f, "{:?}: invalid service interval", stream)
+            }
+            BuildError::FeedbackNotSupported(stream) => {
+                write!(f, "{:?}: feedback not supported", stream)
+            }
+            BuildError::SelectorNotSupported(stream) => {
+                write!(f, "{:?}: selector not supported", stream)
+            }
+            BuildError::FrameBandwidthExceeded { needed, max } => write!(
+                f,
+                "isochronous endpoints need {} bytes per frame, max. {}",
+                needed, max
+            ),
+            BuildError::InvalidSynchronization(stream) => {
+                write!(f, "{:?}: invalid synchronization", stream)
+            }
+            BuildError::InvalidProcessingUnit(stream) => {
+                write!(f, "{:?}: invalid processing unit", stream)
+            }
+            BuildError::InvalidExtensionSources(stream) => {
+                write!(f, "{:?}: invalid extension unit sources", stream)
+            }
+            BuildError::InvalidAssociation => f.write_str("invalid terminal association"),
+            BuildError::InvalidMonitor => f.write_str("invalid monitor mix"),
+            BuildError::TooManyMixerControls(stream) => {
+                write!(f, "{:?}: too many mixer controls", stream)
+            }
+            BuildError::InvalidImplicitFeedback => f.write_str("invalid implicit feedback"),
+            BuildError::EndpointAllocFailed(err) => {
+                write!(f, "endpoint allocation failed: {:?}", err)
+            }
+        }
+    }
+}
+
+impl core::error::Error for BuildError {}
+
+/// Changes of controls and stream settings made by the host as returned by
+/// `AudioClass::poll_event()`
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum AudioEvent {
+    /// The host suspended the bus, e.g. because it went to sleep. The device
+    /// should enter a low-power state.
+    Suspended,
+    /// The bus resumed from suspend.
+    Resumed,
+    /// The host selected the indicated alternate setting of the AudioStreaming
+    /// interface of the indicated stream, i.e. started (1..n) or stopped (0)
+    /// the stream. The format of the selected alternate setting is returned by
+    /// `AudioClass::stream_format()`.
+    AltSettingChanged(StreamId, u8),
+    /// The host set the sampling rate of the indicated stream, resulting in
+    /// the indicated rate in samples/second. Reported even if the rate did not
+    /// change, since hosts typically set it before starting the stream.
+    SampleRateSet(StreamId, u32),
+    /// The host started the indicated stream by selecting an operational
+    /// alternate setting, e.g. to enable the clocks and the DMA of a codec.
+    /// Reported after `AltSettingChanged`.
+    StreamStarted(StreamId),
+    /// The host stopped the indicated stream by selecting alternate setting 0.
+    /// If the stream is started and stopped again before the events are
+    /// polled, only the last transition is reported.
+    StreamStopped(StreamId),
+    /// The sample buffer of the indicated input stream did not hold the data
+    /// of a complete packet when it was due, so that the host received fewer
+    /// audio frames than the sampling rate requires.
+    Underrun(StreamId),
+    /// A packet received for the indicated output stream did not fit into its
+    /// sample buffer and was partially discarded.
+    Overrun(StreamId),
+    /// The host changed the mute control of the indicated stream to the
+    /// indicated state.
+    MuteChanged(StreamId, bool),
+    /// The host selected the indicated input pin (1..n) of the Selector Unit of
+    /// the indicated stream.
+    SelectorChanged(StreamId, u8),
+    /// The host changed the volume control of the indicated stream to the
+    /// indicated value in 1/256 dB.
+    VolumeChanged(StreamId, i16),
+    /// The host enabled or disabled the automatic gain control of the
+    /// indicated stream.
+    AgcChanged(StreamId, bool),
+    /// The host changed the indicated tone control of the indicated stream to
+    /// the indicated value in 1/256 dB.
+    ToneChanged(StreamId, ToneControl, i16),
+    /// The host changed the Copy Protection Level of the Output Terminal of
+    /// the indicated output stream.
+    CopyProtectChanged(StreamId, CopyProtectLevel),
+    /// The host selected the indicated input pin of the Clock Selector of the
+    /// indicated stream (1 = internal clock, 2 = external clock).
+    ClockSelectorChanged(StreamId, u8),
+    /// The host changed the indicated band (14..43) of the graphic equalizer
+    /// of the indicated stream to the indicated gain in 1/256 dB.
+    EqualizerChanged(StreamId, u8, i16),
+    /// The host changed the mute control of the indicated channel of the
+    /// indicated stream to the indicated state.
+    ChannelMuteChanged(StreamId, u8, bool),
+    /// The host changed the volume control of the indicated channel of the
+    /// indicated stream to the indicated value in 1/256 dB.
+    ChannelVolumeChanged(StreamId, u8, i16),
+    /// The host changed the mixer control connecting the indicated input
+    /// channel with the indicated output channel of the Mixer Unit of the
+    /// indicated output stream to the indicated gain in 1/256 dB (`i16::MIN`
+    /// for -∞ dB).
+    MixerChanged(StreamId, u8, u8, i16),
+    /// The host changed the indicated control of the Processing Unit of the
+    /// indicated stream to the indicated value.
+    ProcessingChanged(StreamId, ProcessingControl, i16),
+    /// The host enabled or disabled the Pitch control of the data endpoint of
+    /// the indicated stream.
+    PitchChanged(StreamId, bool),
+}
+
+/// Entity in the signal path of a stream that class-specific requests of the
+/// AudioControl interface are addressed to
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+enum Entity {
+    /// Input or Output Terminal with controls
+    Terminal,
+    SelectorUnit,
+    MixerUnit,
+    ProcessingUnit,
+    FeatureUnit,
+    /// Clock Source, which is external if the flag is set (USB Audio Class 2.0)
+    ClockSource(bool),
+    /// Clock Selector (USB Audio Class 2.0)
+    ClockSelector,
+    /// Clock Multiplier (USB Audio Class 2.0)
+    ClockMultiplier,
+}
+
+/// Internal state related to audio streaming in a certain direction
+struct AudioStream<'a, B: UsbBus, D: EndpointDirection, const P: usize> {
+    stream_config: StreamConfig<'a>,
+    /// index of the stream among the streams of the same direction
+    index: u8,
+    interface: InterfaceNumber,
+    /// data endpoint or `None` if it has been handed over to a stream handle
+    endpoint: Option<Endpoint<'a, B, D>>,
+    ep_descriptor: EndpointDescriptor,
+    /// state shared with the stream handle
+    shared: Option<&'a StreamState>,
+    /// sample buffer the class transmits the data of an input stream from
+    tx_buffer: Option<TxBuffer<'a, P>>,
+    /// sample buffer the class stores the data of an output stream into
+    rx_buffer: Option<Producer<'a>>,
+    /// depth of the sample buffer
+    buffer_depth: Option<BufferDepth>,
+    /// number of audio frames per packet of an input stream
+    pacer: Pacer,
+    /// number of bytes transferred since the alternate setting changed
+    position: Cell<u64>,
+    alt_setting: u8,
+    /// sampling rate of the clock currently used by the stream
+    sampling_rate: u32,
+    /// sampling rate of the internal clock
+    internal_rate: u32,
+    /// sampling rate of the external clock or `None` if it is not valid
+    external_rate: Option<u32>,
+    /// input pin selected by the Clock Selector (1 = internal clock, 2 =
+    /// external clock)
+    clock_selector: u8,
+    /// state of the mute controls, indexed by channel number (0 = master)
+    mute: [bool; MAX_CHANNELS + 1],
+    /// state of the volume controls in 1/256 dB, indexed by channel number
+    volume: [i16; MAX_CHANNELS + 1],
+    /// flags of events that have not yet been retrieved by the application,
+    /// indexed by channel number
+    pending_events: [u16; MAX_CHANNELS + 1],
+    /// explicit feedback endpoint of an output stream
+    feedback: Option<Endpoint<'a, B, In>>,
+    /// current feedback value in 10.14 format (full speed) or 16.16 format
+    /// (high speed)
+    feedback_rate: u32,
+    /// address of the endpoint that provides synchronization information for
+    /// the data endpoint or 0 if there is none
+    synch_address: u8,
+    /// index of the string descriptor naming the first channel or 0 if the
+    /// channels have no names (iChannelNames)
+    channel_names_index: u8,
+    /// index of the string descriptor naming the terminal of type
+    /// `terminal_type` or 0 if it has no name (iTerminal)
+    terminal_name_index: u8,
+    /// ID of the terminal of another stream the terminal of type
+    /// `terminal_type` is associated with or 0 if there is none
+    /// (bAssocTerminal)
+    assoc_terminal: u8,
+    /// input pin selected by the Selector Unit (1..n)
+    selector: u8,
+    /// state of the automatic gain control
+    agc: bool,
+    /// Copy Protection Level of the terminal of type `terminal_type`
+    copy_protect: CopyProtectLevel,
+    /// state of the Pitch control of the data endpoint
+    pitch: bool,
+    /// USB frame number at which the last packet of an output stream was
+    /// received
+    rx_frame_number: Option<u16>,
+    /// whether a plug is inserted into the connector of the terminal of type
+    /// `terminal_type`
+    connected: bool,
+    /// latency of the signal path in microseconds
+    latency: u32,
+    /// state of the tone controls in 0.25 dB in the order of `ToneControl::ALL`
+    tone: [i8; 3],
+    /// state of the graphic equalizer
+    equalizer: Option<GraphicEqualizer>,
+    /// Mixer Unit of an output stream that mixes input streams into the
+    /// signal path
+    mixer: Option<Mixer>,
+    /// state of the Processing Unit
+    processing: Option<Processing<'a>>,
+    /// IDs of the entities connected to the additional input pins of the
+    /// Extension Unit
+    extension_sources: [u8; MAX_EXTENSION_SOURCES],
+}
+
+/// Answer a GET request with a parameter block of fixed size. The host has to
+/// request the whole block, so a request with another wLength, e.g. by a host
+/// probing a control it misinterprets, is stalled rather than answered with
+/// a truncated block.
+pub(crate) fn accept_param<B: UsbBus>(xfer: ControlIn<B>, param: &[u8]) -> usb_device::Result<()> {
+    if xfer.request().length as usize != param.len() {
+        return xfer.reject();
+    }
+    xfer.accept_with(param)
+}
+
+/// Offset of the entity IDs of a stream with respect to the first input stream.
+/// Each stream owns a block of four IDs, the blocks of input and output streams
+/// alternate.
+fn id_offset(stream: StreamId) -> u8 {
+    match stream {
+        StreamId::Input(index) => 8 * index,
+        StreamId::Output(index) => 8 * index + 4,
+    }
+}
+
+/// ID of the entity providing the signal of a stream before any processing,
+/// i.e. the Selector Unit or the Input Terminal
+fn source_id(config: &StreamConfig, stream: StreamId) -> u8 {
+    match config.selector_pins() {
+        0 => ID_INPUT_TERMINAL + id_offset(stream),
+        _ => ID_SELECTOR_UNIT + SELECTOR_ID_BLOCK * (id_offset(stream) / 4),
+    }
+}
+
+macro_rules! append {
+    ($iter:ident, $value:expr) => {
+        *($iter.next().ok_or(UsbError::BufferOverflow)?.1) = $value;
+    };
+}
+
+macro_rules! append_u24le {
+    ($iter:ident, $value:expr) => {
+        append!($iter, $value as u8);
+        append!($iter, ($value >> 8) as u8);
+        append!($iter, ($value >> 16) as u8);
+    };
+}
+
+impl<'a, B: UsbBus, D: EndpointDirection, const P: usize> AudioStream<'a, B, D, P> {
+    fn is_input(&self) -> bool {
+        self.ep_descriptor.address.direction() == UsbDirection::In
+    }
+
+    fn stream_id(&self) -> StreamId {
+        if self.is_input() {
+            StreamId::Input(self.index)
+        } else {
+            StreamId::Output(self.index)
+        }
+    }
+
+    fn id_offset(&self) -> u8 {
+        id_offset(self.stream_id())
+    }
+
+    /// IDs of the terminals the Input Terminal and the Output Terminal are
+    /// associated with (bAssocTerminal)
+    fn assoc_terminals(&self) -> (u8, u8) {
+        if self.is_input() {
+            (self.assoc_terminal, 0)
+        } else {
+            (0, self.assoc_terminal)
+        }
+    }
+
+    /// First ID of the block of IDs of the units of the stream
+    fn unit_id_block(&self) -> u8 {
+        ID_SELECTOR_UNIT + SELECTOR_ID_BLOCK * (self.id_offset() / 4)
+    }
+
+    /// ID of the Selector Unit in the signal path if the stream has one
+    fn selector_unit_id(&self) -> Option<u8> {
+        match self.stream_config.selector_pins() {
+            0 => None,
+            _ => Some(self.unit_id_block()),
+        }
+    }
+
+    /// ID of the Mixer Unit in the signal path if the stream has one. It uses
+    /// the ID of a Selector Unit, which output streams do not have.
+    fn mixer_unit_id(&self) -> Option<u8> {
+        self.mixer.as_ref().map(|_| self.unit_id_block())
+    }
+
+    /// ID of the Processing Unit in the signal path if the stream has one. It
+    /// uses the last ID of the block, which remains free since a Selector Unit
+    /// has at most 14 additional Input Terminals.
+    fn processing_unit_id(&self) -> Option<u8> {
+        self.processing
+            .as_ref()
+            .map(|_| self.unit_id_block() + SELECTOR_ID_BLOCK - 1)
+    }
+
+    /// ID of the Extension Unit in the signal path if the stream has one
+    fn extension_unit_id(&self) -> Option<u8> {
+        self.stream_config
+            .extension_unit
+            .map(|_| ID_EXTENSION_UNIT + self.id_offset())
+    }
+
+    /// Entity of the stream with the indicated ID. The Extension Unit is not
+    /// included since its requests are passed to the application.
+    fn entity(&self, id: u8, version: AudioVersion) -> Option<Entity> {
+        if version == AudioVersion::Uac2 {
+            if let Some(entity) = self.clock_entity(id) {
+                return Some(entity);
+            }
+        }
+        let id = Some(id);
+        if id == self.controlled_terminal_id() {
+            Some(Entity::Terminal)
+        } else if id == self.selector_unit_id() {
+            Some(Entity::SelectorUnit)
+        } else if id == self.mixer_unit_id() {
+            Some(Entity::MixerUnit)
+        } else if id == self.processing_unit_id() {
+            Some(Entity::ProcessingUnit)
+        } else if id == self.feature_unit_id() {
+            Some(Entity::FeatureUnit)
+        } else {
+            None
+        }
+    }
+
+    /// ID of the entity providing the signal of a stream before any processing,
+    /// i.e. the Selector Unit or the Input Terminal
+    fn source_id(&self) -> u8 {
+        source_id(&self.stream_config, self.stream_id())
+    }
+
+    /// Set the Selector Unit to an input pin and generate an event if the
+    /// selection changes
+    fn set_selector(&mut self, selector: u8) {
+        if selector != self.selector {
+            self.selector = selector;
+            self.pending_events[0] |= SELECTOR_CHANGED;
+        }
+    }
+
+    /// Set the rate and validity of an external clock
+    fn set_external_clock(&mut self, rate: Option<u32>) -> Result<()> {
+        if !self.stream_config.external_clock {
+            return Err(Error::InvalidValue);
+        }
+        if rate.is_some_and(|rate| !self.stream_config.rates.contains(rate)) {
+            return Err(Error::InvalidValue);
+        }
+        self.external_rate = rate;
+        self.update_sampling_rate();
+        Ok(())
+    }
+
+    /// Whether the stream uses the external clock
+    fn uses_external_clock(&self) -> bool {
+        self.stream_config.external_clock
+            && (!self.stream_config.clock_selector || self.clock_selector == 2)
+    }
+
+    /// Set the sampling rate of the internal clock as requested by the host if
+    /// the control handler accepts it and generate an event. Returns whether
+    /// the request is accepted.
+    fn set_internal_rate(
+        &mut self,
+        rate: u32,
+        handler: Option<&mut (dyn AudioControlHandler + Send + '_)>,
+    ) -> bool {
+        let stream = self.stream_id();
+        if !handler.is_none_or(|handler| handler.on_set_sample_rate(stream, rate)) {
+            return false;
+        }
+        self.internal_rate = rate;
+        self.update_sampling_rate();
+        self.pending_events[0] |= SAMPLING_RATE_SET;
+        true
+    }
+
+    /// Select an alternate setting and generate an event if it changes
+    fn set_alt_setting(&mut self, alt_setting: u8) {
+        if alt_setting != self.alt_setting {
+            debug!(
+                "usbd-audio: {} alternate setting {=u8}",
+                self.stream_id(),
+                alt_setting
+            );
+            let previous = self.alt_setting;
+            self.alt_setting = alt_setting;
+            if let Some(shared) = self.shared {
+                shared.set_alt_setting(alt_setting);
+            }
+            // a packet written before the host stopped the stream is not
+            // transmitted
+            if let Some(ref mut tx) = self.tx_buffer {
+                tx.pending = false;
+            }
+            self.pacer.reset();
+            self.position.set(0);
+            self.update_buffer_threshold();
+            self.pending_events[0] |= ALT_SETTING_CHANGED;
+            let (transition, opposite) = match alt_setting {
+                0 => (STREAM_STOPPED, STREAM_STARTED),
+                _ => (STREAM_STARTED, STREAM_STOPPED),
+            };
+            if (previous == 0) != (alt_setting == 0) {
+                self.pending_events[0] = (self.pending_events[0] & !opposite) | transition;
+            }
+        }
+    }
+
+    /// Update the sampling rate of the stream after a change of the clocks.
+    /// The rate of an invalid external clock is unknown and the previous rate
+    /// is retained.
+    fn update_sampling_rate(&mut self) {
+        if !self.uses_external_clock() {
+            self.sampling_rate = self.internal_rate;
+        } else if let Some(rate) = self.external_rate {
+            self.sampling_rate = rate;
+        }
+        if let Some(shared) = self.shared {
+            shared.set_sampling_rate(self.sampling_rate);
+        }
+        self.update_buffer_threshold();
+    }
+
+    /// Convert the depth of the sample buffer into bytes for the format
+    /// selected by the host. Type II formats are not buffered to a depth.
+    fn update_buffer_threshold(&self) {
+        let frame_size = self.frame_size().unwrap_or(0);
+        let threshold = self
+            .buffer_depth
+            .map_or(0, |depth| depth.bytes(frame_size, self.sampling_rate));
+        if let Some(ref tx) = self.tx_buffer {
+            tx.consumer.set_threshold(threshold);
+        }
+        if let Some(ref rx) = self.rx_buffer {
+            rx.set_threshold(threshold);
+        }
+    }
+
+    /// Count bytes transferred by the data endpoint
+    fn advance(&self, len: usize) {
+        self.position.set(self.position.get() + len as u64);
+    }
+
+    /// Number of audio frames transferred since the alternate setting changed
+    /// or of bytes for Type II formats
+    fn position(&self) -> u64 {
+        let frame_size = self.frame_size().filter(|&size| size > 0).unwrap_or(1);
+        self.position.get() / frame_size as u64
+    }
+
+    /// Number of bytes in the sample buffer or `None` if the stream is not
+    /// buffered
+    fn buffer_level(&self) -> Option<usize> {
+        match (&self.tx_buffer, &self.rx_buffer) {
+            (Some(tx), _) => Some(tx.consumer.len()),
+            (_, Some(rx)) => Some(rx.len()),
+            _ => None,
+        }
+    }
+
+    /// Set the input pin of the Clock Selector and generate an event if the
+    /// selection changes
+    fn set_clock_selector(&mut self, selector: u8) {
+        if selector != self.clock_selector {
+            self.clock_selector = selector;
+            self.update_sampling_rate();
+            self.pending_events[0] |= CLOCK_SELECTOR_CHANGED;
+        }
+    }
+
+    /// ID of the terminal of type `terminal_type`
+    fn terminal_id(&self) -> u8 {
+        if self.is_input() {
+            ID_INPUT_TERMINAL + self.id_offset()
+        } else {
+            ID_OUTPUT_TERMINAL + self.id_offset()
+        }
+    }
+
+    /// ID of the terminal of type `terminal_type` if it has a Copy Protect or
+    /// Connector control
+    fn controlled_terminal_id(&self) -> Option<u8> {
+        let config = &self.stream_config;
+        (config.copy_protect_control || config.connector_control || config.latency.is_some())
+            .then(|| self.terminal_id())
+    }
+
+    /// bDelay of the AudioStreaming interface in frames
+    fn delay(&self) -> u8 {
+        match self.stream_config.latency {
+            Some(_) => self.latency.div_ceil(1000).min(u8::MAX as u32) as u8,
+            None => 1,
+        }
+    }
+
+    /// Addressee of messages from an originator related to this stream or
+    /// `None` if the stream has no such entity
+    fn status_source(&self, originator: Originator) -> Option<Source> {
+        match originator {
+            Originator::FeatureUnit(_) => self.feature_unit_id().map(Source::Entity),
+            Originator::Terminal(_) => Some(Source::Entity(self.terminal_id())),
+            Originator::Interface(_) => Some(Source::Interface(self.interface.into())),
+            Originator::Endpoint(_) => Some(Source::Endpoint(self.ep_descriptor.address.into())),
+        }
+    }
+
+    /// Set the Pitch control and generate an event if it changes
+    fn set_pitch(&mut self, pitch: bool) {
+        if pitch != self.pitch {
+            self.pitch = pitch;
+            self.pending_events[0] |= PITCH_CHANGED;
+        }
+    }
+
+    /// Set the Copy Protection Level and generate an event if it changes
+    fn set_copy_protect(&mut self, level: CopyProtectLevel) {
+        if level != self.copy_protect {
+            self.copy_protect = level;
+            self.pending_events[0] |= COPY_PROTECT_CHANGED;
+        }
+    }
+
+    /// ID of the Feature Unit in the signal path if the stream has one
+    fn feature_unit_id(&self) -> Option<u8> {
+        if self.stream_config.has_feature_unit() {
+            Some(ID_FEATURE_UNIT + self.id_offset())
+        } else {
+            None
+        }
+    }
+
+    /// Set the mute control of a channel as requested by the host if the
+    /// control handler accepts it and generate an event if the state changes.
+    /// Returns whether the request is accepted.
+    fn set_mute(
+        &mut self,
+        channel: u8,
+        mute: bool,
+        handler: Option<&mut (dyn AudioControlHandler + Send + '_)>,
+    ) -> bool {
+        let stream = self.stream_id();
+        if !handler.is_none_or(|handler| handler.on_set_mute(stream, channel, mute)) {
+            return false;
+        }
+        let channel = channel as usize;
+        if mute != self.mute[channel] {
+            self.mute[channel] = mute;
+            self.pending_events[channel] |= MUTE_CHANGED;
+        }
+        true
+    }
+
+    /// Set the volume control of a channel as requested by the host, limited to
+    /// the indicated range, if the control handler accepts it and generate an
+    /// event if the setting changes. Returns whether the request is accepted.
+    fn set_volume(
+        &mut self,
+        channel: u8,
+        volume: i16,
+        range: VolumeRange,
+        handler: Option<&mut (dyn AudioControlHandler + Send + '_)>,
+    ) -> bool {
+        let stream = self.stream_id();
+        let volume = volume.clamp(range.min, range.max);
+        if !handler.is_none_or(|handler| handler.on_set_volume(stream, channel, volume)) {
+            return false;
+        }
+        let channel = channel as usize;
+        if volume != self.volume[channel] {
+            self.volume[channel] = volume;
+            self.pending_events[channel] |= VOLUME_CHANGED;
+        }
+        true
+    }
+
+    /// Set the automatic gain control and generate an event if the state
+    /// changes
+    fn set_agc(&mut self, agc: bool) {
+        if agc != self.agc {
+            self.agc = agc;
+            self.pending_events[0] |= AGC_CHANGED;
+        }
+    }
+
+    /// Set a tone control to a value in 0.25 dB and generate an event if the
+    /// value changes
+    fn set_tone(&mut self, control: ToneControl, value: i8) {
+        let index = control as usize;
+        if value != self.tone[index] {
+            self.tone[index] = value;
+            self.pending_events[0] |= TONE_CHANGED << index;
+        }
+    }
+
+    /// Get the state of the mute control of a channel (1..n). Streams of more
+    /// than `MAX_CHANNELS` channels have no per-channel controls.
+    fn channel_mute(&self, channel: u8) -> Result<bool> {
+        if channel == 0 || channel > self.stream_config.channels {
+            return Err(Error::InvalidValue);
+        }
+        Ok(self.mute.get(channel as usize).copied().unwrap_or(false))
+    }
+
+    /// Get the setting of the volume control of a channel (1..n)
+    fn channel_volume(&self, channel: u8) -> Result<i16> {
+        if channel == 0 || channel > self.stream_config.channels {
+            return Err(Error::InvalidValue);
+        }
+        Ok(self.volume.get(channel as usize).copied().unwrap_or(0))
+    }
+
+    /// Number of channels of the selected alternate setting, 0 if idle
+    fn channels(&self) -> u8 {
+        self.stream_format().map_or(0, |f| f.channels)
+    }
+
+    /// Parameters of the selected alternate setting, `None` if idle
+    fn stream_format(&self) -> Option<StreamFormat> {
+        match self.alt_setting {
+            0 => None,
+            alt_setting => {
+                let (format, channels) = self.stream_config.alt_setting(alt_setting);
+                Some(StreamFormat {
+                    format,
+                    channels,
+                    sampling_rate: self.sampling_rate,
+                })
+            }
+        }
+    }
+
+    /// Size of an audio frame (one subframe per channel) in bytes in the
+    /// selected alternate setting or `None` if idle or if the format is not
+    /// based on frames (Type II)
+    fn frame_size(&self) -> Option<usize> {
+        self.stream_format()?.frame_size()
+    }
+
+    /// Hand the data endpoint over to a handle sharing `state` with the stream
+    fn take_handle(&mut self, state: &'a StreamState) -> Result<StreamHandle<'a, B, D>> {
+        if !self.endpoint_available() {
+            return Err(Error::StreamNotInitialized);
+        }
+        let endpoint = self.endpoint.take().ok_or(Error::StreamNotInitialized)?;
+        state.set_alt_setting(self.alt_setting);
+        state.set_sampling_rate(self.sampling_rate);
+        self.shared = Some(state);
+        Ok(StreamHandle::new(
+            self.stream_id(),
+            endpoint,
+            self.stream_config.alt_settings(),
+            state,
+        ))
+    }
+
+    /// Whether the data endpoint is still available to `AudioClass::read()`,
+    /// `AudioClass::write()` or for a handle, i.e. neither handed over to a
+    /// stream handle nor serviced from a sample buffer
+    fn endpoint_available(&self) -> bool {
+        self.endpoint.is_some() && self.tx_buffer.is_none() && self.rx_buffer.is_none()
+    }
+
+    /// Queue the current feedback value for transmission to the host. Errors
+    /// are ignored since a value still pending in the endpoint buffer will be
+    /// followed by the current one after completion.
+    fn write_feedback(&self) {
+        if let Some(ref feedback) = self.feedback {
+            let length = feedback.max_packet_size() as usize;
+            feedback
+                .write(&self.feedback_rate.to_le_bytes()[..length])
+                .ok();
+        }
+    }
+
+    /// Clear the next pending event flag and return it together with the
+    /// number of the channel it relates to
+    fn take_event(&mut self) -> Option<(u8, u16)> {
+        let channel = self.pending_events.iter().position(|&flags| flags != 0)?;
+        let flags = &mut self.pending_events[channel];
+        let flag = *flags & flags.wrapping_neg();
+        *flags &= !flag;
+        Some((channel as u8, flag))
+    }
+
+    /// Indices of the string descriptors naming the Input Terminal and the
+    /// Output Terminal (iTerminal)
+    fn terminal_name_indices(&self) -> (u8, u8) {
+        if self.is_input() {
+            (self.terminal_name_index, 0)
+        } else {
+            (0, self.terminal_name_index)
+        }
+    }
+
+    /// Channel or terminal name described by the string descriptor with the
+    /// indicated index if it belongs to the stream
+    fn string(&self, index: u8) -> Option<&str> {
+        if index == 0 {
+            return None;
+        }
+        if index == self.terminal_name_index {
+            return self.stream_config.terminal_name;
+        }
+        if self.channel_names_index == 0 {
+            return None;
+        }
+        let channel = index.checked_sub(self.channel_names_index)?;
+        self.stream_config
+            .channel_names
+            .get(channel as usize)
+            .copied()
+    }
+
+    /// Clear the next pending event flag and return the corresponding event
+    fn poll_event(&mut self) -> Option<AudioEvent> {
+        let stream = self.stream_id();
+        let Some((channel, flag)) = self.take_event() else {
+            if let Some((band, gain)) = self
+                .equalizer
+                .as_mut()
+                .and_then(GraphicEqualizer::take_event)
+            {
+                return Some(AudioEvent::EqualizerChanged(stream, band, gain as i16 * 64));
+            }
+            if let Some((in_channel, out_channel, gain)) =
+                self.mixer.as_mut().and_then(Mixer::take_event)
+            {
+                return Some(AudioEvent::MixerChanged(
+                    stream,
+                    in_channel,
+                    out_channel,
+                    gain,
+                ));
+            }
+            let (control, value) = self.processing.as_mut()?.take_event()?;
+            return Some(AudioEvent::ProcessingChanged(stream, control, value));
+        };
+        let mute = self.mute[channel as usize];
+        let volume = self.volume[channel as usize];
+        Some(match (channel, flag) {
+            (0, MUTE_CHANGED) => AudioEvent::MuteChanged(stream, mute),
+            (0, SELECTOR_CHANGED) => AudioEvent::SelectorChanged(stream, self.selector),
+            (0, AGC_CHANGED) => AudioEvent::AgcChanged(stream, self.agc),
+            (0, VOLUME_CHANGED) => AudioEvent::VolumeChanged(stream, volume),
+            (0, COPY_PROTECT_CHANGED) => AudioEvent::CopyProtectChanged(stream, self.copy_protect),
+            (0, CLOCK_SELECTOR_CHANGED) => {
+                AudioEvent::ClockSelectorChanged(stream, self.clock_selector)
+            }
+            (0, ALT_SETTING_CHANGED) => AudioEvent::AltSettingChanged(stream, self.alt_setting),
+            (0, SAMPLING_RATE_SET) => AudioEvent::SampleRateSet(stream, self.sampling_rate),
+            (0, STREAM_STARTED) => AudioEvent::StreamStarted(stream),
+            (0, STREAM_STOPPED) => AudioEvent::StreamStopped(stream),
+            (0, UNDERRUN) => AudioEvent::Underrun(stream),
+            (0, OVERRUN) => AudioEvent::Overrun(stream),
+            (0, PITCH_CHANGED) => AudioEvent::PitchChanged(stream, self.pitch),
+            (0, _) => {
+                let index = (flag / TONE_CHANGED).trailing_zeros() as usize;
+                let value = self.tone[index] as i16 * 64;
+                AudioEvent::ToneChanged(stream, ToneControl::ALL[index], value)
+            }
+            (_, MUTE_CHANGED) => AudioEvent::ChannelMuteChanged(stream, channel, mute),
+            (_, _) => AudioEvent::ChannelVolumeChanged(stream, channel, volume),
+        })
+    }
+
+    /// Total length of the AC descriptors written by `write_ac_descriptors()`
+    fn ac_descriptors_length(&self) -> u16 {
+        let fu_length = match self.feature_unit_id() {
+            Some(_) => 7 + self.stream_config.channels as u16 + 1,
+            None => 0,
+        };
+        let selector_length = match self.stream_config.selector_pins() {
+            0 => 0,
+            pins => 12 * (pins as u16 - 1) + 6 + pins as u16,
+        };
+        let mixer_length = self.mixer.as_ref().map_or(0, |mixer| {
+            10 + mixer.num_pins() as u16 + mixer.num_nodes().div_ceil(8) as u16
+        });
+        let processing_length = self.processing.as_ref().map_or(0, |processing| {
+            15 + processing.unit.specific_length() as u16
+        });
+        let extension_length = self
+            .stream_config
+            .extension_unit
+            .map_or(0, |unit| 14 + unit.num_pins() as u16);
+        12 + selector_length + mixer_length + processing_length + extension_length + 9 + fu_length
+    }
+
+    fn write_ac_descriptors<W: DescriptorSink>(&self, writer: &mut W) -> usb_device::Result<()> {
+        let is_input = self.is_input();
+        let terminal_type: u16 = self.stream_config.terminal_type.into();
+        let id_offset = self.id_offset();
+        let (it_name, ot_name) = self.terminal_name_indices();
+        let (it_assoc, ot_assoc) = self.assoc_terminals();
+
+        // write Input Terminal Descriptor (12 bytes)
+        let channel_config = (self
+            .stream_config
+            .channel_config(self.stream_config.channels) as u16)
+            .to_le_bytes();
+        let tt = if is_input {
+            terminal_type
+        } else {
+            TerminalType::UsbStreaming.into()
+        }
+        .to_le_bytes();
+
+        let write_input_terminal = |writer: &mut W, id: u8, tt: [u8; 2], assoc, name| {
+            writer.write(
+                CS_INTERFACE,
+                &[
+                    INPUT_TERMINAL, // bDescriptorSubtype
+                    id,             // bTerminalID
+                    tt[0],          // wTerminalType
+                    tt[1],
+                    assoc,                       // bAssocTerminal
+                    self.stream_config.channels, // bNrChannels
+                    channel_config[0],
+                    channel_config[1],        // wChannelConfig
+                    self.channel_names_index, // iChannelNames
+                    name,                     // iTerminal
+                ],
+            )
+        };
+        write_input_terminal(writer, ID_INPUT_TERMINAL + id_offset, tt, it_assoc, it_name)?;
+        let mut source_id = ID_INPUT_TERMINAL + id_offset;
+
+        // write Input Terminal Descriptors of the additional sources and
+        // Selector Unit Descriptor (6 + number of input pins bytes)
+        if let Some(unit_id) = self.selector_unit_id() {
+            for (pin, &tt) in self.stream_config.selector_sources.iter().enumerate() {
+                let tt = u16::from(tt).to_le_bytes();
+                write_input_terminal(writer, unit_id + 1 + pin as u8, tt, 0x00, 0x00)?;
+            }
+            writer.write_with(CS_INTERFACE, |buf| {
+                let pins = self.stream_config.selector_pins();
+                let length = 4 + pins as usize;
+                let buf = buf.get_mut(..length).ok_or(UsbError::BufferOverflow)?;
+                buf[0] = SELECTOR_UNIT; // bDescriptorSubtype
+                buf[1] = unit_id; // bUnitID
+                buf[2] = pins; // bNrInPins
+                buf[3] = source_id; // baSourceID(1)
+                for (pin, id) in buf[4..length - 1].iter_mut().enumerate() {
+                    *id = unit_id + 1 + pin as u8; // baSourceID(i)
+                }
+                buf[length - 1] = 0x00; // iSelector
+                Ok(length)
+            })?;
+            source_id = unit_id;
+        }
+
+        // write Mixer Unit Descriptor (10 + number of input pins + size of
+        // bmControls bytes)
+        if let (Some(unit_id), Some(mixer)) = (self.mixer_unit_id(), &self.mixer) {
+            writer.write_with(CS_INTERFACE, |buf| {
+                let pins = mixer.num_pins() as usize;
+                let controls_length = mixer.num_nodes().div_ceil(8);
+                let length = 8 + pins + controls_length;
+                let buf = buf.get_mut(..length).ok_or(UsbError::BufferOverflow)?;
+                buf[0] = MIXER_UNIT; // bDescriptorSubtype
+                buf[1] = unit_id; // bUnitID
+                buf[2] = mixer.num_pins(); // bNrInPins
+                buf[3] = source_id; // baSourceID(1)
+                buf[4..3 + pins].copy_from_slice(mixer.sources()); // baSourceID(i)
+                buf[3 + pins] = self.stream_config.channels; // bNrChannels
+                buf[4 + pins] = channel_config[0]; // wChannelConfig
+                buf[5 + pins] = channel_config[1];
+                buf[6 + pins] = self.channel_names_index; // iChannelNames
+                mixer.write_controls(&mut buf[7 + pins..]); // bmControls
+                buf[length - 1] = 0x00; // iMixer
+                Ok(length)
+            })?;
+            source_id = unit_id;
+        }
+
+        // write Processing Unit Descriptor (15 + size of process-specific part
+        // bytes)
+        if let (Some(unit_id), Some(processing)) = (self.processing_unit_id(), &self.processing) {
+            writer.write_with(CS_INTERFACE, |buf| {
+                let length = 13 + processing.unit.specific_length();
+                let buf = buf.get_mut(..length).ok_or(UsbError::BufferOverflow)?;
+                let process_type = processing.unit.process_type().to_le_bytes();
+                buf[0] = PROCESSING_UNIT; // bDescriptorSubtype
+                buf[1] = unit_id; // bUnitID
+                buf[2] = process_type[0]; // wProcessType
+                buf[3] = process_type[1];
+                buf[4] = 0x01; // bNrInPins
+                buf[5] = source_id; // baSourceID(1)
+                buf[6] = self.stream_config.channels; // bNrChannels
+                buf[7] = channel_config[0]; // wChannelConfig
+                buf[8] = channel_config[1];
+                buf[9] = self.channel_names_index; // iChannelNames
+                buf[10] = 0x01; // bControlSize
+                buf[11] = processing.unit.controls(); // bmControls
+                buf[12] = 0x00; // iProcessing
+                processing.unit.write_specific(&mut buf[13..]);
+                Ok(length)
+            })?;
+            source_id = unit_id;
+        }
+
+        // write Extension Unit Descriptor (14 + number of input pins bytes)
+        if let (Some(unit_id), Some(unit)) =
+            (self.extension_unit_id(), self.stream_config.extension_unit)
+        {
+            writer.write_with(CS_INTERFACE, |buf| {
+                let pins = unit.num_pins() as usize;
+                let length = 12 + pins;
+                let buf = buf.get_mut(..length).ok_or(UsbError::BufferOverflow)?;
+                let code = unit.code.to_le_bytes();
+                buf[0] = EXTENSION_UNIT; // bDescriptorSubtype
+                buf[1] = unit_id; // bUnitID
+                buf[2] = code[0]; // wExtensionCode
+                buf[3] = code[1];
+                buf[4] = unit.num_pins(); // bNrInPins
+                buf[5] = source_id; // baSourceID(1)
+                buf[6..5 + pins].copy_from_slice(&self.extension_sources[..pins - 1]); // baSourceID(i)
+                buf[5 + pins] = self.stream_config.channels; // bNrChannels
+                buf[6 + pins] = channel_config[0]; // wChannelConfig
+                buf[7 + pins] = channel_config[1];
+                buf[8 + pins] = self.channel_names_index; // iChannelNames
+                buf[9 + pins] = 0x01; // bControlSize
+                buf[10 + pins] = unit.enable_control as u8; // bmControls
+                buf[11 + pins] = 0x00; // iExtension
+                Ok(length)
+            })?;
+            source_id = unit_id;
+        }
+
+        // write Feature Unit Descriptor (7 + number of channels + 1 bytes)
+        if let Some(unit_id) = self.feature_unit_id() {
+            writer.write_with(CS_INTERFACE, |buf| {
+                let length = 5 + self.stream_config.channels as usize + 1;
+                let buf = buf.get_mut(..length).ok_or(UsbError::BufferOverflow)?;
+                buf[0] = FEATURE_UNIT; // bDescriptorSubtype
+                buf[1] = unit_id; // bUnitID
+                buf[2] = source_id; // bSourceID
+                buf[3] = 0x01; // bControlSize
+                for (channel, controls) in buf[4..length - 1].iter_mut().enumerate() {
+                    *controls = self.stream_config.controls(channel as u8); // bmaControls(i)
+                }
+                buf[length - 1] = 0x00; // iFeature
+                Ok(length)
+            })?;
+            source_id = unit_id;
+        }
+
+        // write Output Terminal Descriptor (9 bytes)
+        let tt = if is_input {
+            TerminalType::UsbStreaming.into()
+        } else {
+            terminal_type
+        }
+        .to_le_bytes();
+        writer.write(
+            CS_INTERFACE,
+            &[
+                OUTPUT_TERMINAL,                // bDescriptorSubtype
+                ID_OUTPUT_TERMINAL + id_offset, // bTerminalID
+                tt[0],                          // wTerminalType
+                tt[1],
+                ot_assoc,  // bAssocTerminal
+                source_id, // bSourceID
+                ot_name,   // iTerminal
+            ],
+        )
+    }
+
+    /// Handle a GET request addressed to an entity of the stream
+    fn entity_in(&self, entity: Entity, version: AudioVersion, xfer: ControlIn<B>) {
+        match (version, entity) {
+            (AudioVersion::Uac2, _) => self.entity_in_v2(entity, xfer),
+            (_, Entity::Terminal) => self.terminal_in(xfer),
+            (_, Entity::SelectorUnit) => self.selector_unit_in(xfer),
+            (_, Entity::MixerUnit) => self.mixer_unit_in(xfer),
+            (_, Entity::ProcessingUnit) => self.processing_unit_in(xfer),
+            (_, Entity::FeatureUnit) => self.feature_unit_in(xfer),
+            _ => {
+                xfer.reject().ok();
+            }
+        }
+    }
+
+    /// Handle a SET request addressed to an entity of the stream
+    fn entity_out(
+        &mut self,
+        entity: Entity,
+        version: AudioVersion,
+        xfer: ControlOut<B>,
+        handler: Option<&mut (dyn AudioControlHandler + Send + '_)>,
+    ) {
+        match (version, entity) {
+            (AudioVersion::Uac2, _) => self.entity_out_v2(entity, xfer, handler),
+            (_, Entity::Terminal) => self.terminal_out(xfer),
+            (_, Entity::SelectorUnit) => self.selector_unit_out(xfer),
+            (_, Entity::MixerUnit) => self.mixer_unit_out(xfer),
+            (_, Entity::ProcessingUnit) => self.processing_unit_out(xfer),
+            (_, Entity::FeatureUnit) => self.feature_unit_out(xfer, handler),
+            _ => {
+                xfer.reject().ok();
+            }
+        }
+    }
+
+    /// Handle a GET request addressed to the Feature Unit of the stream
+    fn feature_unit_in(&self, xfer: ControlIn<B>) {
+        let req = *xfer.request();
+        let cs = (req.value >> 8) as u8;
+        let channel = req.value as u8;
+        let mute_control = self.stream_config.has_mute_control(channel);
+        let agc_control = self.stream_config.has_agc_control(channel);
+        let tone_controls = self.stream_config.has_tone_controls(channel);
+        let ch = channel as usize;
+        match (req.request, cs, self.stream_config.volume_control(channel)) {
+            (GET_CUR, MUTE_CONTROL, _) if mute_control => {
+                accept_param(xfer, &[self.mute[ch] as u8])
+            }
+            (GET_CUR, VOLUME_CONTROL, Some(_)) => {
+                accept_param(xfer, &self.volume[ch].to_le_bytes())
+            }
+            (GET_CUR, AUTOMATIC_GAIN_CONTROL, _) if agc_control => {
+                accept_param(xfer, &[self.agc as u8])
+            }
+            (GET_CUR, BASS_CONTROL | MID_CONTROL | TREBLE_CONTROL, _) if tone_controls => {
+                let value = ToneControl::from_selector(cs).map_or(0, |c| self.tone[c as usize]);
+                accept_param(xfer, &[value as u8])
+            }
+            (GET_MIN, BASS_CONTROL | MID_CONTROL | TREBLE_CONTROL, _) if tone_controls => {
+                accept_param(xfer, &[i8::MIN as u8])
+            }
+            (GET_MAX, BASS_CONTROL | MID_CONTROL | TREBLE_CONTROL, _) if tone_controls => {
+                accept_param(xfer, &[i8::MAX as u8])
+            }
+            (GET_RES, BASS_CONTROL | MID_CONTROL | TREBLE_CONTROL, _) if tone_controls => {
+                accept_param(xfer, &[1])
+            }
+            (GET_CUR | GET_MIN | GET_MAX | GET_RES, GRAPHIC_EQUALIZER_CONTROL, _) => {
+                let value = match req.request {
+                    GET_MIN => Some(i8::MIN),
+                    GET_MAX => Some(i8::MAX),
+                    GET_RES => Some(1),
+                    _ => None,
+                };
+                match self.equalizer.as_ref().filter(|_| channel == 0) {
+                    Some(equalizer) => xfer.accept(|buf| {
+                        equalizer
+                            .write(buf, value)
+                            .filter(|&length| length == req.length as usize)
+                            .ok_or(UsbError::InvalidState)
+                    }),
+                    None => xfer.reject(),
+                }
+            }
+            (GET_MIN, VOLUME_CONTROL, Some(range)) => accept_param(xfer, &range.min.to_le_bytes()),
+            (GET_MAX, VOLUME_CONTROL, Some(range)) => accept_param(xfer, &range.max.to_le_bytes()),
+            (GET_RES, VOLUME_CONTROL, Some(range)) => accept_param(xfer, &range.res.to_le_bytes()),
+            _ => xfer.reject(),
+        }
+        .ok();
+    }
+
+    /// Handle a GET request addressed to the terminal with the Copy Protect
+    /// control, which is read-only for an Input Terminal
+    fn terminal_in(&self, xfer: ControlIn<B>) {
+        let req = *xfer.request();
+        let cs = (req.value >> 8) as u8;
+        let channel = req.value as u8;
+        match (req.request, cs, channel) {
+            (GET_CUR, COPY_PROTECT_CONTROL, 0)
+                if self.is_input() && self.stream_config.copy_protect_control =>
+            {
+                accept_param(xfer, &[self.copy_protect as u8])
+            }
+            _ => xfer.reject(),
+        }
+        .ok();
+    }
+
+    /// Handle a GET request addressed to the Selector Unit of the stream
+    fn selector_unit_in(&self, xfer: ControlIn<B>) {
+        let req = *xfer.request();
+        let pins = self.stream_config.selector_pins();
+        // the Selector Unit has a single control addressed by wValue 0
+        match (req.request, req.value) {
+            (GET_CUR, 0) => accept_param(xfer, &[self.selector]),
+            (GET_MIN | GET_RES, 0) => accept_param(xfer, &[1]),
+            (GET_MAX, 0) => accept_param(xfer, &[pins]),
+            _ => xfer.reject(),
+        }
+        .ok();
+    }
+
+    /// Handle a SET request addressed to the Selector Unit of the stream
+    fn selector_unit_out(&mut self, xfer: ControlOut<B>) {
+        let req = *xfer.request();
+        let pins = self.stream_config.selector_pins();
+        match (req.request, req.value, xfer.data()) {
+            (SET_CUR, 0, &[selector]) if (1..=pins).contains(&selector) => {
+                self.set_selector(selector);
+                xfer.accept()
+            }
+            _ => xfer.reject(),
+        }
+        .ok();
+    }
+
+    /// Handle a SET request addressed to the terminal with the Copy Protect
+    /// control, which is write-only for an Output Terminal
+    fn terminal_out(&mut self, xfer: ControlOut<B>) {
+        let req = *xfer.request();
+        let cs = (req.value >> 8) as u8;
+        let channel = req.value as u8;
+        match (req.request, cs, channel, xfer.data()) {
+            (SET_CUR, COPY_PROTECT_CONTROL, 0, &[level])
+                if !self.is_input() && self.stream_config.copy_protect_control =>
+            {
+                match CopyProtectLevel::from_u8(level) {
+                    Some(level) => {
+                        self.set_copy_protect(level);
+                        xfer.accept()
+                    }
+                    None => xfer.reject(),
+                }
+            }
+            _ => xfer.reject(),
+        }
+        .ok();
+    }
+
+    /// Handle a SET request addressed to the Feature Unit of the stream
+    fn feature_unit_out(
+        &mut self,
+        xfer: ControlOut<B>,
+        handler: Option<&mut (dyn AudioControlHandler + Send + '_)>,
+    ) {
+        let req = *xfer.request();
+        let cs = (req.value >> 8) as u8;
+        let channel = req.value as u8;
+        let mute_control = self.stream_config.has_mute_control(channel);
+        let agc_control = self.stream_config.has_agc_control(channel);
+        let tone_controls = self.stream_config.has_tone_controls(channel);
+        match (req.request, cs, xfer.data()) {
+            (SET_CUR, MUTE_CONTROL, &[mute]) if mute_control => {
+                if self.set_mute(channel, mute != 0, handler) {
+                    xfer.accept()
+                } else {
+                    xfer.reject()
+                }
+            }
+            (SET_CUR, AUTOMATIC_GAIN_CONTROL, &[agc]) if agc_control => {
+                self.set_agc(agc != 0);
+                xfer.accept()
+            }
+            (SET_CUR, BASS_CONTROL | MID_CONTROL | TREBLE_CONTROL, &[value]) if tone_controls => {
+                if let Some(control) = ToneControl::from_selector(cs) {
+                    self.set_tone(control, value as i8);
+                }
+                xfer.accept()
+            }
+            (SET_CUR, GRAPHIC_EQUALIZER_CONTROL, data) if channel == 0 => {
+                match self.equalizer.as_mut().map(|equalizer| equalizer.set(data)) {
+                    Some(true) => xfer.accept(),
+                    _ => xfer.reject(),
+                }
+            }
+            (SET_CUR, VOLUME_CONTROL, &[lsb, msb]) => {
+                let volume = i16::from_le_bytes([lsb, msb]);
+                match self.stream_config.volume_control(channel) {
+                    Some(range) if self.set_volume(channel, volume, range, handler) => {
+                        xfer.accept()
+                    }
+                    _ => xfer.reject(),
+                }
+            }
+            _ => xfer.reject(),
+        }
+        .ok();
+    }
+
+    /// Handle a GET request addressed to the isochronous endpoint of the stream
+    fn endpoint_control_in(&self, xfer: ControlIn<B>) {
+        let req = *xfer.request();
+        let cs = req.value >> 8;
+        let channel = req.value as u8;
+        match (req.request, cs, channel) {
+            (GET_CUR, SAMPLING_FREQ_CONTROL, 0) => {
+                accept_param(xfer, &self.sampling_rate.to_le_bytes()[..3])
+            }
+            (GET_CUR, PITCH_CONTROL, 0) if self.stream_config.pitch_control => {
+                accept_param(xfer, &[self.pitch as u8])
+            }
+            (GET_MIN | GET_MAX | GET_RES, SAMPLING_FREQ_CONTROL, 0) => {
+                match self.stream_config.rates.range() {
+                    Some((min, max, res)) => {
+                        let value = match req.request {
+                            GET_MIN => min,
+                            GET_MAX => max,
+                            _ => res,
+                        };
+                        accept_param(xfer, &value.to_le_bytes()[..3])
+                    }
+                    None => xfer.reject(),
+                }
+            }
+            _ => xfer.reject(),
+        }
+        .ok();
+    }
+
+    /// Handle a SET request addressed to the isochronous endpoint of the stream
+    fn endpoint_control_out(
+        &mut self,
+        xfer: ControlOut<B>,
+        handler: Option<&mut (dyn AudioControlHandler + Send + '_)>,
+    ) {
+        let req = *xfer.request();
+        let cs = req.value >> 8;
+        let channel = req.value as u8;
+        match (req.request, cs, channel, xfer.data()) {
+            (SET_CUR, SAMPLING_FREQ_CONTROL, 0, &[b0, b1, b2]) => {
+                let rate = u32::from_le_bytes([b0, b1, b2, 0]);
+                if self.stream_config.rates.contains(rate) && self.set_internal_rate(rate, handler)
+                {
+                    xfer.accept()
+                } else {
+                    xfer.reject()
+                }
+            }
+            (SET_CUR, PITCH_CONTROL, 0, &[pitch]) if self.stream_config.pitch_control => {
+                self.set_pitch(pitch != 0);
+                xfer.accept()
+            }
+            _ => xfer.reject(),
+        }
+        .ok();
+    }
+
+    fn write_as_and_ep_descriptors<W: DescriptorSink>(
+        &self,
+        writer: &mut W,
+    ) -> usb_device::Result<()> {
+        // Standard AS Interface Descriptor (Alt. Set. 0)
+        writer.interface(self.interface, AUDIO, AUDIOSTREAMING, 0x00)?;
+
+        for alt_setting in 1..=self.stream_config.num_alt_settings() {
+            self.write_alt_setting_descriptors(writer, alt_setting)?;
+        }
+        Ok(())
+    }
+
+    /// Write the Standard AS Interface Descriptor of an operational alternate
+    /// setting with the indicated interface protocol. The descriptor and those
+    /// of the endpoints are written directly rather than by
+    /// `DescriptorWriter::interface_alt()` and `DescriptorWriter::endpoint()`,
+    /// which require the endpoint objects, since the data endpoint may have
+    /// been handed over to a stream handle.
+    fn write_alt_interface_descriptor<W: DescriptorSink>(
+        &self,
+        writer: &mut W,
+        alt_setting: u8,
+        protocol: u8,
+    ) -> usb_device::Result<()> {
+        let num_endpoints = 1 + self.feedback.is_some() as u8;
+        writer.write(
+            usb_device::descriptor::descriptor_type::INTERFACE,
+            &[
+                self.interface.into(), // bInterfaceNumber
+                alt_setting,           // bAlternateSetting
+                num_endpoints,         // bNumEndpoints
+                AUDIO,                 // bInterfaceClass
+                AUDIOSTREAMING,        // bInterfaceSubClass
+                protocol,              // bInterfaceProtocol
+                0x00,                  // iInterface
+            ],
+        )
+    }
+
+    /// Write the AS and EP descriptors of an operational alternate setting
+    fn write_alt_setting_descriptors<W: DescriptorSink>(
+        &self,
+        writer: &mut W,
+        alt_setting: u8,
+    ) -> usb_device::Result<()> {
+        let is_input = self.is_input();
+        let id_offset = self.id_offset();
+        let (format, channels) = self.stream_config.alt_setting(alt_setting);
+        let format_tag = format.format_tag();
+
+        // Standard AS Interface Descriptor (Alt. Set. n)
+        self.write_alt_interface_descriptor(writer, alt_setting, 0x00)?;
+
+        // Class-specific AS General Interface Descriptor
+        let terminal_link = id_offset
+            + if is_input {
+                ID_OUTPUT_TERMINAL
+            } else {
+                ID_INPUT_TERMINAL
+            };
+        writer.write(
+            CS_INTERFACE,
+            &[
+                AS_GENERAL,    // bDescriptorSubtype:
+                terminal_link, // bTerminalLink
+                self.delay(),  // bDelay
+                format_tag as u8,
+                (format_tag >> 8) as u8, // wFormatTag
+            ],
+        )?;
+
+        // Type I, Type II or Type III Format Type Descriptor
+        let mut format_desc = [0x00u8; 128];
+        let mut iter = format_desc.iter_mut().enumerate();
+        append!(iter, FORMAT_TYPE); // bDescriptorSubtype;
+        if let Some((max_bit_rate, samples_per_frame)) = format.type_ii() {
+            append!(iter, format.format_type()); // bFormatType
+            append!(iter, max_bit_rate as u8);
+            append!(iter, (max_bit_rate >> 8) as u8); // wMaxBitRate
+            append!(iter, samples_per_frame as u8);
+            append!(iter, (samples_per_frame >> 8) as u8); // wSamplesPerFrame
+        } else {
+            append!(iter, format.format_type()); // bFormatType
+            append!(iter, channels); // bNrChannels
+            append!(iter, format.subframe_size()); // bSubFrameSize
+            append!(iter, format.bit_resolution()); // bBitResolution
+        }
+        match self.stream_config.rates {
+            Rates::Continuous(min, max) => {
+                append!(iter, 0x00); // bSamFreqType
+                append_u24le!(iter, min);
+                append_u24le!(iter, max);
+            }
+            Rates::Discrete(rates) => {
                 append!(iter, rates.len() as u8); // bSamFreqType
                 for rate in rates {
                     append_u24le!(iter, *rate);
                 }
             }
         }
-        let length = iter.next().unwrap().0;
-        writer.write(CS_INTERFACE, &format_desc[..length])?;
+        let length = iter.next().unwrap().0;
+        writer.write(CS_INTERFACE, &format_desc[..length])?;
+
+        // Format-Specific Descriptor of Type II formats
+        let tag = format_tag.to_le_bytes();
+        match format {
+            Format::Mpeg { .. } => writer.write(
+                CS_INTERFACE,
+                &[
+                    FORMAT_SPECIFIC, // bDescriptorSubtype
+                    tag[0],          // wFormatTag
+                    tag[1],
+                    0x0f,
+                    0x00, // bmMPEGCapabilities: MPEG-1 Layer I, II and III
+                    0x00, // bmMPEGFeatures
+                ],
+            )?,
+            Format::Ac3 { .. } => writer.write(
+                CS_INTERFACE,
+                &[
+                    FORMAT_SPECIFIC, // bDescriptorSubtype
+                    tag[0],          // wFormatTag
+                    tag[1],
+                    0xff,
+                    0x01,
+                    0x00,
+                    0x00, // bmBSID: bit stream IDs 0 to 8
+                    0x00, // bmAC3Features
+                ],
+            )?,
+            _ => {}
+        }
+
+        // Standard AS Isochronous Audio Data Endpoint Descriptor (9 bytes)
+        self.ep_descriptor.write(
+            writer,
+            &[
+                0x00,               // bRefresh
+                self.synch_address, // bSynchAddress
+            ],
+        )?;
+
+        // Class-specific Isoc. Audio Data Endpoint Descriptor
+        let lock_delay = LockDelay::fields(self.stream_config.lock_delay);
+        // Sampling Frequency control, optional Pitch control and MaxPacketsOnly
+        let attributes = 0x01
+            | (self.stream_config.pitch_control as u8) << 1
+            | (self.stream_config.max_packets_only as u8) << 7;
+        writer.write(
+            0x25,
+            &[
+                // bDescriptorType: CS_ENDPOINT
+                0x01,          // bDescriptorSubtype: GENERAL
+                attributes,    // bmAttributes
+                lock_delay[0], // bLockDelayUnits
+                lock_delay[1],
+                lock_delay[2], // wLockDelay
+            ],
+        )?;
+
+        // Standard AS Isochronous Synch Endpoint Descriptor (9 bytes)
+        if let Some(ref feedback) = self.feedback {
+            EndpointDescriptor::new(feedback).write(
+                writer,
+                &[
+                    FEEDBACK_REFRESH, // bRefresh
+                    0x00,             // bSynchAddress
+                ],
+            )?;
+        }
+        Ok(())
+    }
+}
+
+impl<B: UsbBus, const P: usize> AudioStream<'_, B, In, P> {
+    /// Write a packet to the data endpoint unless it is no longer available.
+    /// Returns `Error::StreamInactive` if the host has not started the stream
+    /// and `Error::InvalidLength` if the length of `data` is not a multiple of
+    /// the frame size.
+    fn write(&self, data: &[u8]) -> Result<usize> {
+        let endpoint = match self.endpoint {
+            Some(ref endpoint) if self.endpoint_available() => endpoint,
+            _ => return Err(Error::StreamNotInitialized),
+        };
+        if self.alt_setting == 0 {
+            return Err(Error::StreamInactive);
+        }
+        match self.frame_size() {
+            Some(size) if !data.len().is_multiple_of(size) => Err(Error::InvalidLength),
+            _ => {
+                let len = endpoint.write(data)?;
+                self.advance(len);
+                Ok(len)
+            }
+        }
+    }
+
+    /// Write the next packet from the sample buffer to the data endpoint if the
+    /// stream is active and the previous packet has been transmitted
+    fn transmit_buffered(&mut self) {
+        let Some(format) = self.stream_format() else {
+            return;
+        };
+        let (Some(tx), Some(endpoint)) = (self.tx_buffer.as_mut(), self.endpoint.as_ref()) else {
+            return;
+        };
+        if tx.pending {
+            return;
+        }
+        let mut packet = [0u8; P];
+        let max_packet_size = payload_size(endpoint.max_packet_size()).min(packet.len());
+        let (len, required) = tx.packet_len(
+            &self.pacer,
+            self.sampling_rate,
+            format.frame_size(),
+            max_packet_size,
+        );
+        let mut len = tx.consumer.pop(&mut packet[..len]);
+        if len < required {
+            // the buffer is filled for the first time after the start of the
+            // stream
+            if self.position.get() > 0 {
+                self.pending_events[0] |= UNDERRUN;
+            }
+            match self.stream_config.underrun_policy {
+                UnderrunPolicy::ZeroLength => {}
+                UnderrunPolicy::Silence => {
+                    packet[len..required].fill(format.format.silence());
+                    len = required;
+                }
+                UnderrunPolicy::Repeat => {
+                    packet[len..required].copy_from_slice(&tx.last[len..required]);
+                    len = required;
+                }
+            }
+        }
+        if self.stream_config.underrun_policy == UnderrunPolicy::Repeat {
+            tx.last[..len].copy_from_slice(&packet[..len]);
+        }
+        // an empty packet keeps the completions coming while the buffer is
+        // empty
+        tx.pending = endpoint.write(&packet[..len]).is_ok();
+        if tx.pending {
+            self.pacer.commit(self.sampling_rate);
+            self.advance(len);
+        }
+    }
+}
+
+impl<B: UsbBus, const P: usize> AudioStream<'_, B, Out, P> {
+    /// Read a packet from the data endpoint unless it is no longer available.
+    /// Returns `Error::StreamInactive` if the host has not started the stream.
+    fn read(&self, data: &mut [u8]) -> Result<usize> {
+        match self.endpoint {
+            Some(_) if self.endpoint_available() && self.alt_setting == 0 => {
+                Err(Error::StreamInactive)
+            }
+            Some(ref endpoint) if self.endpoint_available() => {
+                let len = endpoint.read(data)?;
+                self.advance(len);
+                Ok(len)
+            }
+            _ => Err(Error::StreamNotInitialized),
+        }
+    }
+
+    /// Read a received packet from the data endpoint into the sample buffer.
+    /// Data that does not fit into the buffer is discarded.
+    fn receive_buffered(&mut self) {
+        let (Some(rx), Some(endpoint)) = (self.rx_buffer.as_mut(), self.endpoint.as_ref()) else {
+            return;
+        };
+        let mut packet = [0u8; P];
+        if let Ok(len) = endpoint.read(&mut packet) {
+            if rx.push(&packet[..len]) < len {
+                self.pending_events[0] |= OVERRUN;
+            }
+            self.advance(len);
+        }
+    }
+}
+
+/// Builder class to create an `AudioClass` structure.
+pub struct AudioClassBuilder<'a> {
+    version: AudioVersion,
+    inputs: [Option<StreamConfig<'a>>; MAX_INPUTS],
+    outputs: [Option<StreamConfig<'a>>; MAX_OUTPUTS],
+    /// first error detected while the builder was configured
+    config_error: Option<BuildError>,
+    /// index of the output stream associated with each input stream
+    associations: [Option<u8>; MAX_STREAMS],
+    /// bitmap of the input streams mixed into each output stream
+    monitors: [u8; MAX_STREAMS],
+    extension_handler: Option<&'a mut (dyn ExtensionUnitHandler + Send)>,
+    control_handler: Option<&'a mut (dyn AudioControlHandler + Send)>,
+    implicit_feedback: bool,
+    high_speed: bool,
+    high_bandwidth: bool,
+    interrupt_endpoint: bool,
+    category: AudioCategory,
+    function_name: Option<&'a str>,
+    midi: bool,
+    consumer_control: bool,
+    frame_number: Option<fn() -> u16>,
+    remote_wakeup: Option<fn()>,
+}
+
+/// Allocate consecutive string descriptors for the channel names of a stream
+/// and return the index of the first one or 0 if there are no names
+fn alloc_channel_names<B: UsbBus>(alloc: &UsbBusAllocator<B>, config: &StreamConfig) -> u8 {
+    let mut first = 0;
+    for i in 0..config.channel_names.len() {
+        let index = alloc.string().into();
+        if i == 0 {
+            first = index;
+        }
+    }
+    first
+}
+
+/// Allocate a string descriptor for the terminal name of a stream and return
+/// its index or 0 if the terminal has no name
+fn alloc_terminal_name<B: UsbBus>(alloc: &UsbBusAllocator<B>, config: &StreamConfig) -> u8 {
+    config.terminal_name.map_or(0, |_| alloc.string().into())
+}
+
+/// Put a stream configuration into the first free slot and return whether
+/// there was one
+#[cfg(any(feature = "input", feature = "output"))]
+fn add_stream<'a>(slots: &mut [Option<StreamConfig<'a>>], config: StreamConfig<'a>) -> bool {
+    match slots.iter_mut().find(|slot| slot.is_none()) {
+        Some(slot) => {
+            *slot = Some(config);
+            true
+        }
+        None => false,
+    }
+}
+
+/// Check that packets of `ep_size` bytes fit into the packet buffers of size
+/// `P`
+fn check_packet_size<const P: usize>(stream: StreamId, ep_size: u16) -> BuildResult<u16> {
+    if ep_size as usize > P {
+        return Err(BuildError::BandwidthExceeded {
+            stream,
+            needed: ep_size as u32,
+            max: P as u32,
+        });
+    }
+    Ok(ep_size)
+}
+
+/// Largest number of bytes an isochronous endpoint transfers per (micro)frame
+fn max_iso_payload(high_speed: bool, high_bandwidth: bool) -> u32 {
+    match (high_speed, high_bandwidth) {
+        (false, _) => MAX_ISO_EP_SIZE,
+        (true, false) => MAX_ISO_EP_SIZE_HS,
+        (true, true) => MAX_TRANSACTIONS_HS * MAX_ISO_EP_SIZE_HS,
+    }
+}
+
+/// wMaxPacketSize of an isochronous endpoint transferring `size` bytes per
+/// (micro)frame. Beyond 1024 bytes, the packet is split into two or three
+/// transactions of equal size, whose number minus one is held in bits 12..11.
+fn encode_max_packet_size(size: u16) -> u16 {
+    let transactions = size.div_ceil(MAX_ISO_EP_SIZE_HS as u16).max(1);
+    (transactions - 1) << 11 | size.div_ceil(transactions)
+}
+
+/// Number of bytes per (micro)frame of an isochronous endpoint with the
+/// indicated wMaxPacketSize, see `encode_max_packet_size()`
+fn payload_size(max_packet_size: u16) -> usize {
+    let transactions = 1 + (max_packet_size >> 11 & 0x3) as usize;
+    (max_packet_size & 0x7ff) as usize * transactions
+}
+
+/// Check that the packets of all isochronous endpoints of a function fit into
+/// the share of a (micro)frame available to periodic transfers, even if the
+/// host schedules them all in the same (micro)frame
+fn check_frame_bandwidth<B: UsbBus, const P: usize>(
+    ac: &AudioClass<'_, B, P>,
+    high_speed: bool,
+) -> BuildResult<()> {
+    let (overhead, feedback_size, max) = if high_speed {
+        (ISO_OVERHEAD_HS, FEEDBACK_EP_SIZE_HS, MAX_PERIODIC_BYTES_HS)
+    } else {
+        (ISO_OVERHEAD, FEEDBACK_EP_SIZE, MAX_PERIODIC_BYTES)
+    };
+    let packet = |max_packet_size: u16| {
+        let transactions = 1 + (max_packet_size as u32 >> 11 & 0x3);
+        payload_size(max_packet_size) as u32 + transactions * overhead
+    };
+    let inputs = ac
+        .inputs
+        .iter()
+        .flatten()
+        .map(|info| packet(info.ep_descriptor.max_packet_size));
+    let outputs = ac.outputs.iter().flatten().map(|info| {
+        let feedback = info.feedback.as_ref().map_or(0, |_| packet(feedback_size));
+        packet(info.ep_descriptor.max_packet_size) + feedback
+    });
+    let needed = inputs.chain(outputs).sum();
+    if needed > max {
+        return Err(BuildError::FrameBandwidthExceeded { needed, max });
+    }
+    Ok(())
+}
+
+impl Default for AudioClassBuilder<'_> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<'a> AudioClassBuilder<'a> {
+    /// Configuration of a stream if it exists
+    fn stream_config(&self, stream: StreamId) -> Option<&StreamConfig<'a>> {
+        let (configs, index) = match stream {
+            StreamId::Input(index) => (&self.inputs[..], index),
+            StreamId::Output(index) => (&self.outputs[..], index),
+        };
+        configs.get(index as usize)?.as_ref()
+    }
+
+    /// IDs of the entities connected to the additional input pins of the
+    /// Extension Unit of a stream or `None` if they are invalid
+    fn extension_sources(&self, stream: StreamId) -> Option<[u8; MAX_EXTENSION_SOURCES]> {
+        let mut ids = [0; MAX_EXTENSION_SOURCES];
+        let Some(unit) = self.stream_config(stream).and_then(|c| c.extension_unit) else {
+            return Some(ids);
+        };
+        if unit.sources.len() > MAX_EXTENSION_SOURCES {
+            return None;
+        }
+        for (id, &source) in ids.iter_mut().zip(unit.sources) {
+            if source == stream {
+                return None;
+            }
+            *id = source_id(self.stream_config(source)?, source);
+        }
+        Some(ids)
+    }
+
+    /// Create a new AudioClassBuilder
+    pub fn new() -> AudioClassBuilder<'a> {
+        AudioClassBuilder {
+            version: AudioVersion::Uac1,
+            inputs: Default::default(),
+            outputs: Default::default(),
+            config_error: None,
+            associations: [None; MAX_STREAMS],
+            monitors: [0; MAX_STREAMS],
+            extension_handler: None,
+            control_handler: None,
+            implicit_feedback: false,
+            high_speed: false,
+            high_bandwidth: false,
+            interrupt_endpoint: false,
+            category: AudioCategory::IoBox,
+            function_name: None,
+            midi: false,
+            consumer_control: false,
+            frame_number: None,
+            remote_wakeup: None,
+        }
+    }
+
+    /// Select the version of the USB Audio Class specification. Defaults to
+    /// `AudioVersion::Uac1`.
+    pub fn version(self, version: AudioVersion) -> AudioClassBuilder<'a> {
+        AudioClassBuilder { version, ..self }
+    }
+
+    /// Add an input audio stream configured according to a `StreamConfig`.
+    /// Each call adds another stream with its own interface, endpoint and
+    /// terminals, which is identified by `StreamId::Input(n)` with `n` counting
+    /// the previous calls. `build()` returns `BuildError::TooManyStreams` if more
+    /// than `MAX_STREAMS` input streams are added. Requires the `input`
+    /// feature.
+    #[cfg(feature = "input")]
+    pub fn input(mut self, input: StreamConfig<'a>) -> AudioClassBuilder<'a> {
+        if !add_stream(&mut self.inputs, input) {
+            self.config_error(BuildError::TooManyStreams);
+        }
+        self
+    }
+
+    /// Add an output audio stream configured according to a `StreamConfig`.
+    /// Each call adds another stream with its own interface, endpoint and
+    /// terminals, which is identified by `StreamId::Output(n)` with `n`
+    /// counting the previous calls. `build()` returns
+    /// `BuildError::TooManyStreams` if more than `MAX_STREAMS` output streams
+    /// are added. Requires the `output` feature.
+    #[cfg(feature = "output")]
+    pub fn output(mut self, output: StreamConfig<'a>) -> AudioClassBuilder<'a> {
+        if !add_stream(&mut self.outputs, output) {
+            self.config_error(BuildError::TooManyStreams);
+        }
+        self
+    }
+
+    /// Record an error to be returned by `build()` unless an earlier one was
+    /// recorded
+    fn config_error(&mut self, err: BuildError) {
+        self.config_error.get_or_insert(err);
+    }
+
+    /// Associate the Input Terminal of the input stream `input` with the Output
+    /// Terminal of the output stream `output` (bAssocTerminal), e.g. the
+    /// microphone and the earphones of a headset, so that the host treats
+    /// them as one bidirectional device. Both streams should use the same
+    /// bi-directional terminal type such as `TerminalType::BidirHeadset`.
+    /// `build()` returns `BuildError::InvalidAssociation` if one of the streams
+    /// does not exist or if a terminal is associated twice and
+    /// `BuildError::UnsupportedByVersion` for a BADD function.
+    pub fn associate_terminals(mut self, input: u8, output: u8) -> AudioClassBuilder<'a> {
+        match self.associations.get_mut(input as usize) {
+            Some(association @ None) => *association = Some(output),
+            _ => self.config_error(BuildError::InvalidAssociation),
+        }
+        self
+    }
+
+    /// Mix the input stream `input` into the output stream `output`, e.g. to
+    /// let the user of a headset hear the own voice (monitor mix). The first
+    /// call for an output stream inserts a Mixer Unit between its Input
+    /// Terminal and its Feature Unit, further calls connect additional input
+    /// streams to the same Mixer Unit. The host programs the gain of each
+    /// combination of an input channel and an output channel, which can be
+    /// queried by `AudioClass::mixer_gain()`. Initially the channels of the
+    /// output stream pass through at 0 dB and the input streams are
+    /// disconnected. `build()` returns `BuildError::InvalidMonitor` if one of
+    /// the streams does not exist, `BuildError::TooManyMixerControls` if the
+    /// Mixer Unit would have more than 64 controls (input channels times
+    /// output channels) and `BuildError::UnsupportedByVersion` for a BADD
+    /// function.
+    pub fn monitor(mut self, input: u8, output: u8) -> AudioClassBuilder<'a> {
+        match self.monitors.get_mut(output as usize) {
+            Some(inputs) if (input as usize) < MAX_STREAMS => *inputs |= 1 << input,
+            _ => self.config_error(BuildError::InvalidMonitor),
+        }
+        self
+    }
+
+    /// Pass the class-specific requests addressed to the Extension Units of all
+    /// streams to `handler`. The handler must be `Send` so that the class can
+    /// be moved to the context servicing the USB interrupt.
+    pub fn extension_unit_handler(
+        self,
+        handler: &'a mut (dyn ExtensionUnitHandler + Send),
+    ) -> AudioClassBuilder<'a> {
+        AudioClassBuilder {
+            extension_handler: Some(handler),
+            ..self
+        }
+    }
+
+    /// Call `handler` during the control transfers setting the mute and volume
+    /// controls and the sampling rates of all streams as well as for
+    /// vendor-specific requests addressed to the interfaces of the function.
+    /// The handler must be `Send` so that the class can be moved to the
+    /// context servicing the USB interrupt.
+    pub fn control_handler(
+        self,
+        handler: &'a mut (dyn AudioControlHandler + Send),
+    ) -> AudioClassBuilder<'a> {
+        AudioClassBuilder {
+            control_handler: Some(handler),
+            ..self
+        }
+    }
+
+    /// Generate descriptors for a high-speed device. The endpoints are serviced
+    /// every 125 µs microframe and their packet sizes are calculated
+    /// accordingly. Feedback values are transmitted once per millisecond in
+    /// 16.16 format.
+    pub fn high_speed(self) -> AudioClassBuilder<'a> {
+        AudioClassBuilder {
+            high_speed: true,
+            ..self
+        }
+    }
+
+    /// Let the isochronous data endpoints of a high-speed device perform up to
+    /// three transactions per microframe (high-bandwidth endpoints), so that a
+    /// stream transfers up to 3072 instead of 1024 bytes per microframe, e.g.
+    /// for many channels at high sampling rates. Endpoints needing more than
+    /// 1024 bytes declare the number of transactions in bits 12..11 of their
+    /// wMaxPacketSize, which the USB peripheral must support. Such packets
+    /// also need larger packet buffers, see `build_sized()`. Has no effect at
+    /// full speed.
+    pub fn high_bandwidth(self) -> AudioClassBuilder<'a> {
+        AudioClassBuilder {
+            high_bandwidth: true,
+            ..self
+        }
+    }
+
+    /// Add an interrupt endpoint to the AudioControl interface, through which
+    /// `AudioClass::post_status()` informs the host about changes of controls
+    /// that were not made by the host, e.g. by a volume knob of the device.
+    /// USB Audio Class 2.0 functions with external clocks always have this
+    /// endpoint. `build()` returns `BuildError::UnsupportedByVersion` for a
+    /// BADD function.
+    pub fn interrupt_endpoint(self) -> AudioClassBuilder<'a> {
+        AudioClassBuilder {
+            interrupt_endpoint: true,
+            ..self
+        }
+    }
+
+    /// Set the category of a USB Audio Class 2.0 function, e.g.
+    /// `AudioCategory::Headset`. Defaults to `AudioCategory::IoBox`. Other
+    /// versions have no category and ignore it.
+    pub fn category(self, category: AudioCategory) -> AudioClassBuilder<'a> {
+        AudioClassBuilder { category, ..self }
+    }
+
+    /// Name the function, e.g. "Speakers" or "Monitoring" for one of two audio
+    /// functions of a device. The name is the string of the Interface
+    /// Association Descriptor (iFunction) and of the AudioControl interface
+    /// (iInterface), which hosts show to tell the functions apart.
+    pub fn function_name(self, name: &'a str) -> AudioClassBuilder<'a> {
+        AudioClassBuilder {
+            function_name: Some(name),
+            ..self
+        }
+    }
+
+    /// Add a MIDIStreaming interface with one MIDI cable in each direction to
+    /// the function, see `AudioClass::read_midi()` and
+    /// `AudioClass::write_midi()`. The interface follows the AudioStreaming
+    /// interfaces. `build()` returns `BuildError::UnsupportedByVersion` for a
+    /// BADD function.
+    pub fn midi(self) -> AudioClassBuilder<'a> {
+        AudioClassBuilder { midi: true, ..self }
+    }
+
+    /// Add a HID interface reporting the volume buttons of the device as
+    /// consumer controls, see `AudioClass::press_key()`. The interface is
+    /// allocated after the interfaces of the audio function and not covered by
+    /// its Interface Association Descriptor, so that the host binds its HID
+    /// driver to it.
+    pub fn consumer_control(self) -> AudioClassBuilder<'a> {
+        AudioClassBuilder {
+            consumer_control: true,
+            ..self
+        }
+    }
+
+    /// Provide a function returning the current USB frame number, e.g. read
+    /// from the frame number register of the USB peripheral, since `usb-device`
+    /// does not expose it. `AudioClass::frame_number()` returns it and
+    /// `AudioClass::read_packet()` reports the frame number at which a packet
+    /// was received. At high speed, the function may return the number of the
+    /// microframe instead, e.g. `frame << 3 | microframe`, to timestamp the
+    /// packets with a resolution of 125 µs.
+    pub fn frame_number(self, frame_number: fn() -> u16) -> AudioClassBuilder<'a> {
+        AudioClassBuilder {
+            frame_number: Some(frame_number),
+            ..self
+        }
+    }
+
+    /// Provide a function signalling remote wakeup on the bus, e.g. by setting
+    /// the resume bit of the USB peripheral for 1 to 15 ms, since `usb-device`
+    /// cannot trigger it. `AudioClass::remote_wakeup()` calls it. The device
+    /// must be built with `UsbDeviceBuilder::supports_remote_wakeup(true)`.
+    pub fn remote_wakeup(self, signal: fn()) -> AudioClassBuilder<'a> {
+        AudioClassBuilder {
+            remote_wakeup: Some(signal),
+            ..self
+        }
+    }
+
+    /// Use the data endpoint of the first input stream as implicit feedback
+    /// for the first output stream. Both data endpoints become asynchronous and
+    /// the host derives the rate of the output stream from the amount of data
+    /// sent by the input stream, which saves the endpoint required for explicit
+    /// feedback. `build()` returns `BuildError::InvalidImplicitFeedback` if not
+    /// both streams are configured or if the output stream has an explicit
+    /// feedback endpoint and `BuildError::UnsupportedByVersion` for a BADD
+    /// function.
+    pub fn implicit_feedback(self) -> AudioClassBuilder<'a> {
+        AudioClassBuilder {
+            implicit_feedback: true,
+            ..self
+        }
+    }
+
+    /// Create the `AudioClass` structure. Returns a `BuildError` describing
+    /// the first problem found, e.g. `BuildError::UnsupportedByVersion` if a
+    /// stream configuration is not supported by the selected version or
+    /// `BuildError::BandwidthExceeded` if a stream does not fit into a single
+    /// isochronous packet per (micro)frame or
+    /// `BuildError::FrameBandwidthExceeded` if the packets of all streams do
+    /// not fit into a (micro)frame together. A BADD function supports at most
+    /// one stream per direction. `BuildError` converts into `Error` for
+    /// applications handling both alike.
+    pub fn build<B: UsbBus>(self, alloc: &'a UsbBusAllocator<B>) -> BuildResult<AudioClass<'a, B>> {
+        self.build_sized(alloc)
+    }
+
+    /// Create an `AudioClass` structure with packet buffers of `P` bytes
+    /// instead of `DEFAULT_PACKET_SIZE`, e.g. `build_sized::<_, 192>()` for a
+    /// full-speed stereo stream of 16 bit samples at 48 kHz. The class holds
+    /// one buffer plus one per buffered input stream and some methods place
+    /// another one on the stack, so small values save considerable RAM. Returns
+    /// `BuildError::BandwidthExceeded` if the packets of a stream are larger
+    /// than `P` and the errors of `build()` otherwise.
+    pub fn build_sized<B: UsbBus, const P: usize>(
+        self,
+        alloc: &'a UsbBusAllocator<B>,
+    ) -> BuildResult<AudioClass<'a, B, P>> {
+        if let Some(err) = self.config_error {
+            return Err(err);
+        }
+        let badd = self.version == AudioVersion::Uac3Badd;
+        let unsupported = BuildError::UnsupportedByVersion(None);
+        let second_stream =
+            |streams: &[Option<StreamConfig>]| streams.get(1).is_some_and(Option::is_some);
+        if badd && (second_stream(&self.inputs) || second_stream(&self.outputs)) {
+            return Err(unsupported);
+        }
+        for (input, output) in self.associations.iter().enumerate() {
+            let Some(output) = *output else { continue };
+            let output_exists = self
+                .outputs
+                .get(output as usize)
+                .is_some_and(Option::is_some);
+            let output_unique = self.associations[..input]
+                .iter()
+                .all(|&other| other != Some(output));
+            let input_exists = self.inputs.get(input).is_some_and(Option::is_some);
+            if !input_exists || !output_exists || !output_unique {
+                return Err(BuildError::InvalidAssociation);
+            }
+            if badd {
+                return Err(unsupported);
+            }
+        }
+        for (output, &inputs) in self.monitors.iter().enumerate() {
+            if inputs == 0 {
+                continue;
+            }
+            let inputs_exist = (0..MAX_STREAMS)
+                .filter(|input| inputs & (1 << input) != 0)
+                .all(|input| self.inputs.get(input).is_some_and(Option::is_some));
+            let output_exists = self.outputs.get(output).is_some_and(Option::is_some);
+            if !output_exists || !inputs_exist {
+                return Err(BuildError::InvalidMonitor);
+            }
+            if badd {
+                return Err(unsupported);
+            }
+        }
+        if self.implicit_feedback {
+            let explicit_feedback = self
+                .outputs
+                .first()
+                .and_then(Option::as_ref)
+                .map(|config| config.feedback);
+            if self.inputs.first().is_none_or(Option::is_none) || explicit_feedback != Some(false) {
+                return Err(BuildError::InvalidImplicitFeedback);
+            }
+            if badd {
+                return Err(unsupported);
+            }
+        }
+        let input_sources: [_; MAX_STREAMS] =
+            core::array::from_fn(|i| self.extension_sources(StreamId::Input(i as u8)));
+        let output_sources: [_; MAX_STREAMS] =
+            core::array::from_fn(|i| self.extension_sources(StreamId::Output(i as u8)));
+        let control_iface = alloc.interface();
+        let function_name = self.function_name.map(|name| (alloc.string(), name));
+        // the host is informed about changes of external clocks and
+        // connectors by interrupt messages (USB Audio Class 2.0 only)
+        let notifications = self
+            .inputs
+            .iter()
+            .chain(self.outputs.iter())
+            .flatten()
+            .any(|config| config.external_clock || config.connector_control);
+        if (self.interrupt_endpoint || self.midi) && badd {
+            return Err(unsupported);
+        }
+        let interrupt =
+            if self.interrupt_endpoint || self.version == AudioVersion::Uac2 && notifications {
+                let interval = if self.high_speed { 4 } else { 1 };
+                let size = interrupt::message_size(self.version) as u16;
+                Some(alloc.interrupt(size, interval))
+            } else {
+                None
+            };
+        let mut ac = AudioClass {
+            version: self.version,
+            category: self.category,
+            control_iface,
+            function_name,
+            inputs: Default::default(),
+            outputs: Default::default(),
+            extension_handler: self.extension_handler,
+            control_handler: self.control_handler,
+            frame_number: self.frame_number,
+            remote_wakeup: self.remote_wakeup,
+            interrupt,
+            interrupt_queue: InterruptQueue::new(),
+            interrupt_busy: false,
+            midi: None,
+            consumer_control: None,
+            suspended: false,
+            pending_events: 0,
+            packet: [0; P],
+            loopback: false,
+        };
+        for (index, stream_config) in self.inputs.into_iter().flatten().enumerate() {
+            let stream = StreamId::Input(index as u8);
+            if stream_config.feedback {
+                return Err(BuildError::FeedbackNotSupported(stream));
+            }
+            let implicit_feedback = self.implicit_feedback && index == 0;
+            let ep_size = stream_config.iso_ep_size(
+                stream,
+                self.version,
+                self.high_speed,
+                self.high_bandwidth,
+            )?;
+            let ep_size = check_packet_size::<P>(stream, ep_size)?;
+            let synchronization = stream_config.synchronization(Synchronization::Asynchronous);
+            if (implicit_feedback && synchronization != Synchronization::Asynchronous)
+                || (stream_config.pitch_control && synchronization != Synchronization::Adaptive)
+            {
+                return Err(BuildError::InvalidSynchronization(stream));
+            }
+            let interface = alloc.interface();
+            let channel_names_index = alloc_channel_names(alloc, &stream_config);
+            let terminal_name_index = alloc_terminal_name(alloc, &stream_config);
+            let assoc_terminal = self.associations[index].map_or(0, |output| {
+                ID_OUTPUT_TERMINAL + id_offset(StreamId::Output(output))
+            });
+            let usage = if implicit_feedback {
+                IsochronousUsageType::ImplicitFeedbackData
+            } else {
+                IsochronousUsageType::Data
+            };
+            let endpoint = alloc.alloc(
+                None,
+                EndpointType::Isochronous {
+                    synchronization: synchronization.usb_type(),
+                    usage,
+                },
+                encode_max_packet_size(ep_size),
+                stream_config.interval,
+            )?;
+            let alt_setting = DEFAULT_ALTERNATE_SETTING;
+            let sampling_rate = stream_config.rates.max();
+            let volume = stream_config.initial_volume();
+            let latency = stream_config.latency.unwrap_or(0);
+            let equalizer = GraphicEqualizer::new(stream_config.equalizer_bands);
+            let processing = stream_config.processing_unit.map(Processing::new);
+            let extension_sources =
+                input_sources[index].ok_or(BuildError::InvalidExtensionSources(stream))?;
+            let pacer = Pacer::new(stream_config.packet_rate(self.high_speed));
+            ac.inputs[index] = Some(AudioStream {
+                stream_config,
+                index: index as u8,
+                interface,
+                ep_descriptor: EndpointDescriptor::new(&endpoint),
+                endpoint: Some(endpoint),
+                shared: None,
+                tx_buffer: None,
+                rx_buffer: None,
+                buffer_depth: None,
+                pacer,
+                position: Cell::new(0),
+                alt_setting,
+                sampling_rate,
+                internal_rate: sampling_rate,
+                external_rate: None,
+                clock_selector: 1,
+                mute: [false; MAX_CHANNELS + 1],
+                volume,
+                pending_events: [0; MAX_CHANNELS + 1],
+                feedback: None,
+                feedback_rate: 0,
+                synch_address: 0,
+                channel_names_index,
+                terminal_name_index,
+                assoc_terminal,
+                selector: 1,
+                agc: true,
+                tone: [0; 3],
+                copy_protect: CopyProtectLevel::Cpl0,
+                pitch: false,
+                rx_frame_number: None,
+                connected: true,
+                latency,
+                equalizer,
+                mixer: None,
+                processing,
+                extension_sources,
+            })
+        }
+
+        for (index, stream_config) in self.outputs.into_iter().flatten().enumerate() {
+            let stream = StreamId::Output(index as u8);
+            if !stream_config.selector_sources.is_empty() {
+                return Err(BuildError::SelectorNotSupported(stream));
+            }
+            let implicit_feedback = self.implicit_feedback && index == 0;
+            let ep_size = stream_config.iso_ep_size(
+                stream,
+                self.version,
+                self.high_speed,
+                self.high_bandwidth,
+            )?;
+            let ep_size = check_packet_size::<P>(stream, ep_size)?;
+            let uses_feedback = stream_config.feedback || implicit_feedback;
+            let synchronization = stream_config.synchronization(if uses_feedback {
+                Synchronization::Asynchronous
+            } else {
+                Synchronization::Adaptive
+            });
+            if (uses_feedback && synchronization != Synchronization::Asynchronous)
+                || (stream_config.pitch_control && synchronization != Synchronization::Adaptive)
+            {
+                return Err(BuildError::InvalidSynchronization(stream));
+            }
+            let interface = alloc.interface();
+            let channel_names_index = alloc_channel_names(alloc, &stream_config);
+            let terminal_name_index = alloc_terminal_name(alloc, &stream_config);
+            let assoc_terminal = self
+                .associations
+                .iter()
+                .position(|&output| output == Some(index as u8))
+                .map_or(0, |input| {
+                    ID_INPUT_TERMINAL + id_offset(StreamId::Input(input as u8))
+                });
+            let endpoint = alloc.alloc(
+                None,
+                EndpointType::Isochronous {
+                    synchronization: synchronization.usb_type(),
+                    usage: IsochronousUsageType::Data,
+                },
+                encode_max_packet_size(ep_size),
+                stream_config.interval,
+            )?;
+            let feedback = if stream_config.feedback {
+                let (ep_size, interval) = if self.high_speed {
+                    (FEEDBACK_EP_SIZE_HS, FEEDBACK_INTERVAL_HS)
+                } else {
+                    (FEEDBACK_EP_SIZE, 1)
+                };
+                Some(alloc.alloc(
+                    None,
+                    EndpointType::Isochronous {
+                        synchronization: IsochronousSynchronizationType::NoSynchronization,
+                        usage: IsochronousUsageType::Feedback,
+                    },
+                    ep_size,
+                    interval,
+                )?)
+            } else {
+                None
+            };
+            let synch_address = match (&feedback, ac.inputs.first().and_then(Option::as_ref)) {
+                (Some(feedback), _) => feedback.address().into(),
+                (None, Some(input)) if implicit_feedback => input.ep_descriptor.address.into(),
+                _ => 0,
+            };
+            let alt_setting = DEFAULT_ALTERNATE_SETTING;
+            let sampling_rate = stream_config.rates.max();
+            let feedback_rate = if self.high_speed {
+                feedback::rate_to_q16_16(sampling_rate)
+            } else {
+                feedback::rate_to_q10_14(sampling_rate)
+            };
+            let volume = stream_config.initial_volume();
+            let latency = stream_config.latency.unwrap_or(0);
+            let equalizer = GraphicEqualizer::new(stream_config.equalizer_bands);
+            let processing = stream_config.processing_unit.map(Processing::new);
+            let extension_sources =
+                output_sources[index].ok_or(BuildError::InvalidExtensionSources(stream))?;
+            let pacer = Pacer::new(stream_config.packet_rate(self.high_speed));
+            let mixer = match self.monitors[index] {
+                0 => None,
+                inputs => {
+                    let mut mixer = Mixer::new(stream_config.channels);
+                    for (input, info) in ac.inputs.iter().enumerate() {
+                        let Some(info) = info.as_ref().filter(|_| inputs & (1 << input) != 0)
+                        else {
+                            continue;
+                        };
+                        if !mixer.add_source(info.source_id(), info.stream_config.channels) {
+                            return Err(BuildError::TooManyMixerControls(stream));
+                        }
+                    }
+                    Some(mixer)
+                }
+            };
+            ac.outputs[index] = Some(AudioStream {
+                stream_config,
+                index: index as u8,
+                interface,
+                ep_descriptor: EndpointDescriptor::new(&endpoint),
+                endpoint: Some(endpoint),
+                shared: None,
+                tx_buffer: None,
+                rx_buffer: None,
+                buffer_depth: None,
+                pacer,
+                position: Cell::new(0),
+                alt_setting,
+                sampling_rate,
+                internal_rate: sampling_rate,
+                external_rate: None,
+                clock_selector: 1,
+                mute: [false; MAX_CHANNELS + 1],
+                volume,
+                pending_events: [0; MAX_CHANNELS + 1],
+                feedback,
+                feedback_rate,
+                synch_address,
+                channel_names_index,
+                terminal_name_index,
+                assoc_terminal,
+                selector: 1,
+                agc: true,
+                tone: [0; 3],
+                copy_protect: CopyProtectLevel::Cpl0,
+                pitch: false,
+                rx_frame_number: None,
+                connected: true,
+                latency,
+                equalizer,
+                mixer,
+                processing,
+                extension_sources,
+            })
+        }
+        check_frame_bandwidth(&ac, self.high_speed)?;
+        if self.midi {
+            ac.midi = Some(MidiStreaming::new(alloc, self.high_speed));
+        }
+        if self.consumer_control {
+            ac.consumer_control = Some(ConsumerControl::new(alloc, self.high_speed));
+        }
+
+        Ok(ac)
+    }
+}
+
+/// USB device class for audio devices.
+///
+/// This device class based on the "Universal Serial Bus Device Class Definition
+/// for Audio Devices", Release 1.0 or Release 2.0. It supports up to
+/// `MAX_STREAMS` input streams and up to `MAX_STREAMS` output streams.
+pub struct AudioClass<'a, B: UsbBus, const P: usize = DEFAULT_PACKET_SIZE> {
+    version: AudioVersion,
+    /// bCategory of a USB Audio Class 2.0 function
+    category: AudioCategory,
+    control_iface: InterfaceNumber,
+    /// string descriptor naming the function
+    function_name: Option<(StringIndex, &'a str)>,
+    inputs: [Option<AudioStream<'a, B, In, P>>; MAX_INPUTS],
+    outputs: [Option<AudioStream<'a, B, Out, P>>; MAX_OUTPUTS],
+    extension_handler: Option<&'a mut (dyn ExtensionUnitHandler + Send)>,
+    control_handler: Option<&'a mut (dyn AudioControlHandler + Send)>,
+    /// function returning the current USB frame number
+    frame_number: Option<fn() -> u16>,
+    /// function signalling remote wakeup
+    remote_wakeup: Option<fn()>,
+    /// interrupt endpoint of the AudioControl interface
+    interrupt: Option<Endpoint<'a, B, In>>,
+    /// interrupt messages waiting for transmission
+    interrupt_queue: InterruptQueue,
+    /// whether a message has been written to the interrupt endpoint and not
+    /// yet been read by the host
+    interrupt_busy: bool,
+    /// MIDIStreaming interface of the function
+    midi: Option<MidiStreaming<'a, B>>,
+    /// HID interface reporting the volume buttons
+    consumer_control: Option<ConsumerControl<'a, B>>,
+    /// whether the bus is suspended according to `update_device_state()`
+    suspended: bool,
+    /// pending event flags of the function
+    pending_events: u8,
+    /// packet lent to the application by `read_stream_with()` and
+    /// `write_stream_with()`
+    packet: [u8; P],
+    /// whether the packets of the first output stream are sent back on the
+    /// first input stream
+    loopback: bool,
+}
+
+// The class, the stream handles and the ends of the sample buffers are moved
+// to the contexts servicing them, e.g. RTIC tasks, and the stream states are
+// shared between these contexts as statics.
+const _: () = {
+    fn assert_send<T: Send>() {}
+    fn assert_sync<T: Sync>() {}
+    #[allow(dead_code)]
+    fn assert_partitionable<B: UsbBus + 'static>() {
+        assert_send::<AudioClass<'static, B>>();
+        assert_send::<InputStream<'static, B>>();
+        assert_send::<OutputStream<'static, B>>();
+        assert_send::<Producer<'static>>();
+        assert_send::<Consumer<'static>>();
+        assert_sync::<StreamState>();
+    }
+};
+
+/// Evaluate an expression for the stream identified by a `StreamId`. Returns
+/// `Error::StreamNotInitialized` if there is no such stream.
+macro_rules! with_stream {
+    ($self:ident, $stream:expr, |$si:ident| $body:expr) => {
+        match $stream {
+            StreamId::Input(index) => $self
+                .inputs
+                .get(index as usize)
+                .and_then(Option::as_ref)
+                .map(|$si| $body),
+            StreamId::Output(index) => $self
+                .outputs
+                .get(index as usize)
+                .and_then(Option::as_ref)
+                .map(|$si| $body),
+        }
+        .ok_or(Error::StreamNotInitialized)
+    };
+}
+
+/// Evaluate an expression for the stream identified by a `StreamId` with
+/// mutable access. Returns `Error::StreamNotInitialized` if there is no such
+/// stream.
+macro_rules! with_stream_mut {
+    ($self:ident, $stream:expr, |$si:ident| $body:expr) => {
+        match $stream {
+            StreamId::Input(index) => $self
+                .inputs
+                .get_mut(index as usize)
+                .and_then(Option::as_mut)
+                .map(|$si| $body),
+            StreamId::Output(index) => $self
+                .outputs
+                .get_mut(index as usize)
+                .and_then(Option::as_mut)
+                .map(|$si| $body),
+        }
+        .ok_or(Error::StreamNotInitialized)
+    };
+}
+
+impl<'a, B: UsbBus, const P: usize> AudioClass<'a, B, P> {
+    /// Read audio frames as output by the host to the first output stream.
+    /// Returns an Error if no output stream has been configured.
+    pub fn read(&self, data: &mut [u8]) -> Result<usize> {
+        self.read_stream(0, data)
+    }
+
+    /// Get the current USB frame number or `None` if no function was passed
+    /// to `AudioClassBuilder::frame_number()`
+    pub fn frame_number(&self) -> Option<u16> {
+        self.frame_number.map(|frame_number| frame_number())
+    }
+
+    /// Inform the class about a start-of-frame event, which `usb-device` does
+    /// not report to classes, by calling this from the SOF interrupt of the
+    /// USB peripheral. The class calls
+    /// `AudioControlHandler::on_start_of_frame()` and transmits the next packet
+    /// of buffered input streams whose previous packet has been sent, so that
+    /// the packets follow the bus timing.
+    pub fn start_of_frame(&mut self) {
+        let frame_number = self.frame_number();
+        if let Some(handler) = self.control_handler.as_deref_mut() {
+            handler.on_start_of_frame(frame_number);
+        }
+        for info in self.inputs.iter_mut().flatten() {
+            info.transmit_buffered();
+        }
+    }
+
+    /// Pass a packet as output by the host to the first output stream to `f`
+    /// and return its result. See `read_stream_with()`.
+    pub fn read_with<R>(&mut self, f: impl FnOnce(&[u8]) -> R) -> Result<R> {
+        self.read_stream_with(0, f)
+    }
+
+    /// Read a packet as output by the host to the output stream with the
+    /// indicated index into a buffer of the class and pass it to `f`, e.g. to
+    /// convert the samples directly into the DMA buffer of a codec. Since
+    /// `usb-device` does not give access to the packet memory of the
+    /// peripheral, this saves the copy into a packet buffer on the stack of the
+    /// application, but not the one out of the peripheral. Returns the result
+    /// of `f` or the errors of `read_stream()`, in which case `f` is not
+    /// called.
+    pub fn read_stream_with<R>(&mut self, index: u8, f: impl FnOnce(&[u8]) -> R) -> Result<R> {
+        let len = match self.outputs.get(index as usize) {
+            Some(Some(info)) => info.read(&mut self.packet)?,
+            _ => return Err(Error::StreamNotInitialized),
+        };
+        Ok(f(&self.packet[..len]))
+    }
+
+    /// Read a packet as output by the host to the first output stream together
+    /// with its metadata. See `read_stream_packet()`.
+    pub fn read_packet(&self, data: &mut [u8]) -> Result<PacketInfo> {
+        self.read_stream_packet(0, data)
+    }
+
+    /// Read a packet as output by the host to the output stream with the
+    /// indicated index like `read_stream()` and return, besides its length,
+    /// the number of whole audio frames it contains and the USB frame number at
+    /// reception, so that the application can detect short packets and keep
+    /// track of the rate at which data arrives.
+    pub fn read_stream_packet(&self, index: u8, data: &mut [u8]) -> Result<PacketInfo> {
+        let frame_size = self
+            .stream_format(StreamId::Output(index))?
+            .and_then(|format| format.frame_size())
+            .filter(|&size| size > 0);
+        let len = self.read_stream(index, data)?;
+        let frame_number = match self.outputs.get(index as usize) {
+            Some(Some(info)) => info.rx_frame_number,
+            _ => None,
+        };
+        Ok(PacketInfo {
+            len,
+            frames: frame_size.map(|size| len / size),
+            frame_number,
+        })
+    }
+
+    /// Read audio frames as output by the host to the first output stream into
+    /// `buf` and iterate over them. See `read_stream_frames()`.
+    pub fn read_frames<'b, S: Sample, const C: usize>(
+        &self,
+        buf: &'b mut [u8],
+    ) -> Result<Frames<'b, S, C>> {
+        self.read_stream_frames(0, buf)
+    }
+
+    /// Read audio frames as output by the host to the output stream with the
+    /// indicated index into `buf` and return an iterator over the frames, each
+    /// an array of `C` samples of type `S`, e.g. `[i16; 2]` for a stereo
+    /// `Format::S16le` stream. Returns `Error::InvalidValue` if `S` or `C` do
+    /// not match the subframe size or the number of channels of the alternate
+    /// setting selected by the host and the errors of `read_stream()`.
+    pub fn read_stream_frames<'b, S: Sample, const C: usize>(
+        &self,
+        index: u8,
+        buf: &'b mut [u8],
+    ) -> Result<Frames<'b, S, C>> {
+        sample::check::<S>(self.stream_format(StreamId::Output(index))?, C)?;
+        let len = self.read_stream(index, buf)?;
+        Ok(Frames::new(&buf[..len]))
+    }
+
+    /// Read audio frames as output by the host to the first output stream
+    /// de-interleaved into one slice per channel. See `read_stream_channels()`.
+    pub fn read_channels<S: Sample>(&self, channels: &mut [&mut [S]]) -> Result<usize> {
+        self.read_stream_channels(0, channels)
+    }
+
+    /// Read audio frames as output by the host to the output stream with the
+    /// indicated index and store the samples of each channel in the
+    /// corresponding slice of `channels`. Returns the number of frames read,
+    /// `Error::InvalidValue` if the size of `S` or the number of slices do not
+    /// match the subframe size or the number of channels of the alternate
+    /// setting selected by the host, `Error::BufferTooSmall` if the frames do
+    /// not fit into the slices and the errors of `read_stream()`.
+    pub fn read_stream_channels<S: Sample>(
+        &self,
+        index: u8,
+        channels: &mut [&mut [S]],
+    ) -> Result<usize> {
+        let format = self.stream_format(StreamId::Output(index))?;
+        sample::check::<S>(format, channels.len())?;
+        let mut buf = [0u8; P];
+        let len = self.read_stream(index, &mut buf)?;
+        sample::unpack_channels(&buf[..len], channels)
+    }
+
+    /// Write audio frames to be input by the host from the first input stream.
+    /// Returns an Error when no input stream has been configured. See
+    /// `write_stream()`.
+    pub fn write(&self, data: &[u8]) -> Result<usize> {
+        self.write_stream(0, data)
+    }
+
+    /// Read audio frames as output by the host to the output stream with the
+    /// indicated index. Returns `Error::StreamNotInitialized` if the stream
+    /// has not been configured or has been handed over to a stream handle by
+    /// `split()`, `Error::StreamInactive` if the host has not started the
+    /// stream and `Error::WouldBlock` if no packet has been received.
+    pub fn read_stream(&self, index: u8, data: &mut [u8]) -> Result<usize> {
+        match self.outputs.get(index as usize) {
+            Some(Some(info)) => info.read(data),
+            _ => Err(Error::StreamNotInitialized),
+        }
+    }
+
+    /// Write audio frames to be input by the host from the input stream with
+    /// the indicated index. Returns `Error::StreamNotInitialized` if the
+    /// stream has not been configured or has been handed over to a stream
+    /// handle by `split()`, `Error::StreamInactive` if the host has not started
+    /// the stream, `Error::WouldBlock` if the previous packet has not been
+    /// transmitted yet and `Error::InvalidLength` if the length of `data` is
+    /// not a multiple of the frame size (number of channels times subframe
+    /// size) of the alternate setting selected by the host.
+    pub fn write_stream(&self, index: u8, data: &[u8]) -> Result<usize> {
+        match self.inputs.get(index as usize) {
+            Some(Some(info)) => info.write(data),
+            _ => Err(Error::StreamNotInitialized),
+        }
+    }
+
+    /// Write the next packet to be input by the host from the first input
+    /// stream. See `write_stream_packet()`.
+    pub fn write_packet(&mut self, data: &[u8]) -> Result<usize> {
+        self.write_stream_packet(0, data)
+    }
+
+    /// Write the audio frames of the next packet to be input by the host from
+    /// the input stream with the indicated index, taken from the beginning of
+    /// `data`, and return the number of bytes written. At sampling rates that
+    /// are not a multiple of the packet rate the number of frames per packet
+    /// alternates, e.g. between 44 and 45 frames per 1 ms packet at 44.1 kHz,
+    /// so that calling this once per packet transmits the data at the sampling
+    /// rate. The remaining data is passed in the next call. If `data` lacks
+    /// frames, `AudioEvent::Underrun` is reported and a stream with
+    /// `UnderrunPolicy::Silence` completes the packet with digital silence,
+    /// which is not included in the returned number of bytes. Type II formats
+    /// and idle streams are written like `write_stream()`.
+    pub fn write_stream_packet(&mut self, index: u8, data: &[u8]) -> Result<usize> {
+        let info = match self.inputs.get_mut(index as usize) {
+            Some(Some(info)) => info,
+            _ => return Err(Error::StreamNotInitialized),
+        };
+        let format = info.stream_format();
+        let Some((format, frame_size)) = format
+            .zip(format.and_then(|format| format.frame_size()))
+            .filter(|&(_, size)| size > 0)
+        else {
+            return info.write(data);
+        };
+        // the frames are taken from the distribution only if the packet is
+        // written, so that e.g. retries after `Error::WouldBlock` keep the rate
+        let frames = info.pacer.peek(info.sampling_rate);
+        let max_packet_size =
+            payload_size(info.ep_descriptor.max_packet_size).min(self.packet.len());
+        let required = (frames * frame_size).min(max_packet_size - max_packet_size % frame_size);
+        let len = required.min(data.len() - data.len() % frame_size);
+        if len == required {
+            let written = info.write(&data[..len])?;
+            info.pacer.commit(info.sampling_rate);
+            return Ok(written);
+        }
+        if info.position.get() > 0 {
+            info.pending_events[0] |= UNDERRUN;
+        }
+        if info.stream_config.underrun_policy != UnderrunPolicy::Silence {
+            let written = info.write(&data[..len])?;
+            info.pacer.commit(info.sampling_rate);
+            return Ok(written);
+        }
+        let packet = &mut self.packet[..required];
+        packet[..len].copy_from_slice(&data[..len]);
+        packet[len..].fill(format.format.silence());
+        info.write(packet)?;
+        info.pacer.commit(info.sampling_rate);
+        Ok(len)
+    }
+
+    /// Let `f` fill a packet of `len` bytes to be input by the host from the
+    /// first input stream. See `write_stream_with()`.
+    pub fn write_with(&mut self, len: usize, f: impl FnOnce(&mut [u8])) -> Result<usize> {
+        self.write_stream_with(0, len, f)
+    }
+
+    /// Let `f` synthesize a packet of `len` bytes to be input by the host from
+    /// the input stream with the indicated index directly in a buffer of the
+    /// class and write it to the data endpoint, which saves staging the
+    /// samples in a separate array. Returns `Error::BufferTooSmall` if `len`
+    /// exceeds the maximum isochronous packet size and the errors of
+    /// `write_stream()`.
+    pub fn write_stream_with(
+        &mut self,
+        index: u8,
+        len: usize,
+        f: impl FnOnce(&mut [u8]),
+    ) -> Result<usize> {
+        let info = match self.inputs.get(index as usize) {
+            Some(Some(info)) => info,
+            _ => return Err(Error::StreamNotInitialized),
+        };
+        let packet = self.packet.get_mut(..len).ok_or(Error::BufferTooSmall)?;
+        f(packet);
+        info.write(packet)
+    }
+
+    /// Write samples to be input by the host from the first input stream. See
+    /// `write_stream_samples()`.
+    pub fn write_samples<S: Sample>(&self, samples: &[S]) -> Result<usize> {
+        self.write_stream_samples(0, samples)
+    }
+
+    /// Write samples to be input by the host from the input stream with the
+    /// indicated index, packed into little-endian subframes. Returns the
+    /// number of samples written, `Error::InvalidValue` if the size of `S`
+    /// does not match the subframe size of the alternate setting selected by
+    /// the host, `Error::InvalidLength` if the number of samples is not a
+    /// multiple of its number of channels, and the errors of `write_stream()`.
+    pub fn write_stream_samples<S: Sample>(&self, index: u8, samples: &[S]) -> Result<usize> {
+        let format = self.stream_format(StreamId::Input(index))?;
+        let mut buf = [0u8; P];
+        let data = sample::pack(format, samples, &mut buf)?;
+        Ok(self.write_stream(index, data)? / S::SIZE)
+    }
+
+    /// Hand the data endpoints of the first input stream and the first output
+    /// stream over to independent handles, e.g. to service capture and
+    /// playback in different tasks. The class continues to handle the control
+    /// requests and publishes the alternate settings and sampling rates
+    /// selected by the host in `input_state` and `output_state`. Afterwards,
+    /// `read()` and `write()` of the class return an error for these streams.
+    /// Returns an error if one of the streams is not configured or has already
+    /// been handed over.
+    pub fn split(
+        &mut self,
+        input_state: &'a StreamState,
+        output_state: &'a StreamState,
+    ) -> Result<(InputStream<'a, B>, OutputStream<'a, B>)> {
+        let available = |endpoint: Option<bool>| endpoint == Some(true);
+        if !available(
+            self.inputs
+                .first()
+                .and_then(Option::as_ref)
+                .map(|info| info.endpoint.is_some()),
+        ) || !available(
+            self.outputs
+                .first()
+                .and_then(Option::as_ref)
+                .map(|info| info.endpoint.is_some()),
+        ) {
+            return Err(Error::StreamNotInitialized);
+        }
+        Ok((
+            self.take_input_stream(0, input_state)?,
+            self.take_output_stream(0, output_state)?,
+        ))
+    }
+
+    /// Attach sample buffers to the first input stream and the first output
+    /// stream and return their application ends: the producer of `tx`, into
+    /// which the application pushes the audio frames to be input by the host,
+    /// and the consumer of `rx`, from which it pulls the audio frames output by
+    /// the host. The class services the data endpoints from the other ends
+    /// while the device is polled, so that the application ends can be used
+    /// from a different interrupt priority, e.g. by the codec DMA handler.
+    /// Afterwards, `read()` and `write()` of the class return an error for
+    /// these streams. Returns an error if one of the streams is not configured
+    /// or its data endpoint is no longer available.
+    pub fn split_buffered<const TX: usize, const RX: usize>(
+        &mut self,
+        tx: &'a mut SampleBuffer<TX>,
+        rx: &'a mut SampleBuffer<RX>,
+    ) -> Result<(Producer<'a>, Consumer<'a>)> {
+        let available = |available: Option<bool>| available == Some(true);
+        if !available(
+            self.inputs
+                .first()
+                .and_then(Option::as_ref)
+                .map(AudioStream::endpoint_available),
+        ) || !available(
+            self.outputs
+                .first()
+                .and_then(Option::as_ref)
+                .map(AudioStream::endpoint_available),
+        ) {
+            return Err(Error::StreamNotInitialized);
+        }
+        Ok((self.buffer_input(0, tx)?, self.buffer_output(0, rx)?))
+    }
+
+    /// Transmit the data of the input stream with the indicated index from a
+    /// sample buffer and return its producer end. See `split_buffered()`.
+    pub fn buffer_input<const N: usize>(
+        &mut self,
+        index: u8,
+        buffer: &'a mut SampleBuffer<N>,
+    ) -> Result<Producer<'a>> {
+        match self.inputs.get_mut(index as usize) {
+            Some(Some(info)) if info.endpoint_available() => {
+                let (producer, consumer) = buffer.split();
+                info.tx_buffer = Some(TxBuffer::new(consumer));
+                Ok(producer)
+            }
+            _ => Err(Error::StreamNotInitialized),
+        }
+    }
+
+    /// Store the data of the output stream with the indicated index into a
+    /// sample buffer and return its consumer end. See `split_buffered()`.
+    pub fn buffer_output<const N: usize>(
+        &mut self,
+        index: u8,
+        buffer: &'a mut SampleBuffer<N>,
+    ) -> Result<Consumer<'a>> {
+        match self.outputs.get_mut(index as usize) {
+            Some(Some(info)) if info.endpoint_available() => {
+                let (producer, consumer) = buffer.split();
+                info.rx_buffer = Some(producer);
+                Ok(consumer)
+            }
+            _ => Err(Error::StreamNotInitialized),
+        }
+    }
+
+    /// Set the depth of the sample buffer of a stream attached by
+    /// `split_buffered()`, `buffer_input()` or `buffer_output()`, so that it
+    /// absorbs scheduling jitter: the data is held back until the buffer holds
+    /// the indicated number of frames or milliseconds of audio, at the start
+    /// of the stream and after an underrun. An input stream sends empty
+    /// packets meanwhile and the consumer of an output stream pops no data.
+    /// The depth is converted for the format selected by the host and limited
+    /// to the capacity of the buffer. Returns an error if the stream is not
+    /// buffered.
+    pub fn set_buffer_depth(&mut self, stream: StreamId, depth: BufferDepth) -> Result<()> {
+        with_stream_mut!(self, stream, |si| {
+            si.buffer_level()?;
+            si.buffer_depth = Some(depth);
+            si.update_buffer_threshold();
+            Some(())
+        })?
+        .ok_or(Error::StreamNotInitialized)
+    }
+
+    /// Get the position of a stream: the number of audio frames written to the
+    /// host for an input stream or read from the host for an output stream
+    /// since its alternate setting changed, i.e. since the host started or
+    /// stopped it, or the number of bytes for Type II formats. This includes
+    /// the data transferred through sample buffers, but not through the handles
+    /// created by `split()`. Applications use it e.g. for A/V synchronization
+    /// and latency measurements.
+    pub fn stream_position(&self, stream: StreamId) -> Result<u64> {
+        with_stream!(self, stream, |si| si.position())
+    }
+
+    /// Get the number of bytes in the sample buffer of a stream, i.e. the
+    /// samples pushed by the application and not yet transmitted for an input
+    /// stream or received and not yet popped by the application for an output
+    /// stream. Returns an error if the stream is not buffered.
+    pub fn buffer_level(&self, stream: StreamId) -> Result<usize> {
+        with_stream!(self, stream, |si| si.buffer_level())?.ok_or(Error::StreamNotInitialized)
+    }
+
+    /// Enable or disable the loopback self-test mode, in which the class reads
+    /// each packet received on the first output stream and queues it on the
+    /// first input stream, so that the USB audio path can be verified with
+    /// the host, e.g. by playing and recording a test signal, without a
+    /// codec. The samples are converted between the formats selected by the
+    /// host (signed 16, 24 or 32 bit PCM); the input channels take the
+    /// output channel with the same number or the last one, e.g. a mono
+    /// input records the left channel of stereo output. No rate conversion
+    /// takes place, so both streams should run at the same sampling rate.
+    /// Packets are dropped while the input stream is idle, its previous
+    /// packet has not been transmitted yet or the formats cannot be
+    /// converted. Meanwhile, `read()` returns no packets of the output stream.
+    ///
+    /// Returns `Error::StreamNotInitialized` if the first input or output
+    /// stream is not configured or its data endpoint has been handed over to
+    /// a stream handle or a sample buffer.
+    pub fn set_loopback(&mut self, enabled: bool) -> Result<()> {
+        let available = |available: Option<bool>| available == Some(true);
+        if enabled
+            && !(available(
+                self.inputs
+                    .first()
+                    .and_then(Option::as_ref)
+                    .map(AudioStream::endpoint_available),
+            ) && available(
+                self.outputs
+                    .first()
+                    .and_then(Option::as_ref)
+                    .map(AudioStream::endpoint_available),
+            ))
+        {
+            return Err(Error::StreamNotInitialized);
+        }
+        self.loopback = enabled;
+        Ok(())
+    }
+
+    /// Whether the loopback self-test mode is enabled. See `set_loopback()`.
+    pub fn loopback(&self) -> bool {
+        self.loopback
+    }
+
+    /// Read a packet of the first output stream and write it, converted into
+    /// the format of the first input stream, to its data endpoint
+    fn loop_back(&mut self) {
+        let (Some(Some(output)), Some(Some(input))) = (self.outputs.first(), self.inputs.first())
+        else {
+            return;
+        };
+        let Ok(len) = output.read(&mut self.packet) else {
+            return;
+        };
+        let (Some(from), Some(to), Some(endpoint)) = (
+            output.stream_format(),
+            input.stream_format(),
+            input.endpoint.as_ref(),
+        ) else {
+            return;
+        };
+        let mut packet = [0u8; P];
+        let max_packet_size = payload_size(endpoint.max_packet_size()).min(P);
+        if let Some(len) = sample::convert_frames(
+            &self.packet[..len],
+            from,
+            to,
+            &mut packet[..max_packet_size],
+        ) {
+            input.write(&packet[..len]).ok();
+        }
+    }
+
+    /// Hand the data endpoint of the input stream with the indicated index
+    /// over to an independent handle. See `split()`.
+    pub fn take_input_stream(
+        &mut self,
+        index: u8,
+        state: &'a StreamState,
+    ) -> Result<InputStream<'a, B>> {
+        match self.inputs.get_mut(index as usize) {
+            Some(Some(info)) => info.take_handle(state),
+            _ => Err(Error::StreamNotInitialized),
+        }
+    }
+
+    /// Hand the data endpoint of the output stream with the indicated index
+    /// over to an independent handle. See `split()`.
+    pub fn take_output_stream(
+        &mut self,
+        index: u8,
+        state: &'a StreamState,
+    ) -> Result<OutputStream<'a, B>> {
+        match self.outputs.get_mut(index as usize) {
+            Some(Some(info)) => info.take_handle(state),
+            _ => Err(Error::StreamNotInitialized),
+        }
+    }
+
+    /// Set the feedback value of the first output stream, i.e. the number of
+    /// samples per frame the host shall send, in 10.14 format (full speed) or
+    /// the number of samples per microframe in 16.16 format (high speed). The
+    /// value is initially set to the nominal value of the highest supported
+    /// sampling rate and is transmitted whenever the host polls the feedback
+    /// endpoint. The functions of the `feedback` module convert measured rates
+    /// into these formats. Returns an error if the output stream is not
+    /// configured with a feedback endpoint.
+    pub fn set_feedback_rate(&mut self, value: u32) -> Result<()> {
+        self.set_stream_feedback_rate(0, value)
+    }
+
+    /// Set the feedback value of the output stream with the indicated index.
+    /// See `set_feedback_rate()`.
+    pub fn set_stream_feedback_rate(&mut self, index: u8, value: u32) -> Result<()> {
+        match self.outputs.get_mut(index as usize) {
+            Some(Some(info)) if info.feedback.is_some() => {
+                info.feedback_rate = value;
+                info.write_feedback();
+                Ok(())
+            }
+            _ => Err(Error::StreamNotInitialized),
+        }
+    }
+
+    /// Set the Copy Protection Level reported by the Input Terminal of the
+    /// input stream with the indicated index, e.g. according to the SCMS state
+    /// of a received S/PDIF signal. Returns an error if the input stream is not
+    /// configured with a Copy Protect control.
+    pub fn set_copy_protect(&mut self, index: u8, level: CopyProtectLevel) -> Result<()> {
+        match self.inputs.get_mut(index as usize) {
+            Some(Some(info)) if info.stream_config.copy_protect_control => {
+                info.copy_protect = level;
+                Ok(())
+            }
+            _ => Err(Error::StreamNotInitialized),
+        }
+    }
+
+    /// Get the Copy Protection Level of the terminal of a stream with a Copy
+    /// Protect control. Returns an error if the stream is not configured or has
+    /// no Copy Protect control.
+    pub fn copy_protect(&self, stream: StreamId) -> Result<CopyProtectLevel> {
+        with_stream!(self, stream, |si| si
+            .stream_config
+            .copy_protect_control
+            .then_some(si.copy_protect))?
+        .ok_or(Error::InvalidValue)
+    }
+
+    /// Get whether the host enabled the Pitch control of the data endpoint of
+    /// a stream. Returns an error if the stream is not configured or has no
+    /// Pitch control.
+    pub fn pitch_enabled(&self, stream: StreamId) -> Result<bool> {
+        with_stream!(self, stream, |si| si
+            .stream_config
+            .pitch_control
+            .then_some(si.pitch))?
+        .ok_or(Error::InvalidValue)
+    }
+
+    /// Report whether a plug is inserted into the connector of the terminal of
+    /// a stream, e.g. by a jack detection switch. Changes are signalled to the
+    /// host by a message on the interrupt endpoint of the AudioControl
+    /// interface. Returns an error if the stream is not configured or has no
+    /// Connector control.
+    pub fn set_connected(&mut self, stream: StreamId, connected: bool) -> Result<()> {
+        let (id, changed) = with_stream_mut!(self, stream, |si| {
+            si.stream_config.connector_control.then(|| {
+                let changed = si.connected != connected;
+                si.connected = connected;
+                (si.terminal_id(), changed)
+            })
+        })?
+        .ok_or(Error::InvalidValue)?;
+        if changed {
+            let cs = class_codes_v2::TE_CONNECTOR_CONTROL;
+            self.post_interrupt(Source::Entity(id), cs, 0);
+        }
+        Ok(())
+    }
+
+    /// Update the latency of the signal path of a stream declared by
+    /// `StreamConfig::with_latency()` in microseconds, e.g. when the
+    /// application changes the depth of its buffer. A USB Audio Class 2.0
+    /// function informs the host by a message on the interrupt endpoint of
+    /// the AudioControl interface, if any, whereas the bDelay field of USB
+    /// Audio Class 1.0 is only read again when the device is enumerated.
+    /// Returns an error if the stream is not configured or has no latency.
+    pub fn set_latency(&mut self, stream: StreamId, micros: u32) -> Result<()> {
+        let (id, changed) = with_stream_mut!(self, stream, |si| {
+            si.stream_config.latency.map(|_| {
+                let changed = si.latency != micros;
+                si.latency = micros;
+                (si.terminal_id(), changed)
+            })
+        })?
+        .ok_or(Error::InvalidValue)?;
+        if changed && self.version == AudioVersion::Uac2 {
+            let cs = class_codes_v2::TE_LATENCY_CONTROL;
+            self.post_interrupt(Source::Entity(id), cs, 0);
+        }
+        Ok(())
+    }
+
+    /// Get current Alternate Setting of a stream. Returns an error if the
+    /// stream is not configured.
+    pub fn alt_setting(&self, stream: StreamId) -> Result<u8> {
+        with_stream!(self, stream, |si| si.alt_setting)
+    }
+
+    /// Get the format, the number of channels and the sampling rate of the
+    /// alternate setting of a stream currently selected by the host or `None`
+    /// if the stream is idle (alternate setting 0). Returns an error if the
+    /// stream is not configured.
+    pub fn stream_format(&self, stream: StreamId) -> Result<Option<StreamFormat>> {
+        with_stream!(self, stream, |si| si.stream_format())
+    }
+
+    /// Get whether the host streams audio data through a stream, i.e. has
+    /// selected an operational alternate setting. Returns an error if the
+    /// stream is not configured.
+    pub fn is_streaming(&self, stream: StreamId) -> Result<bool> {
+        with_stream!(self, stream, |si| si.alt_setting != 0)
+    }
+
+    /// Get the number of the AudioControl interface, which is the first
+    /// interface of the function, e.g. to refer to the function in the
+    /// Microsoft OS descriptors of a composite device.
+    pub fn control_interface(&self) -> InterfaceNumber {
+        self.control_iface
+    }
+
+    /// Get the number of the AudioStreaming interface of a stream. Returns an
+    /// error if the stream is not configured.
+    pub fn stream_interface(&self, stream: StreamId) -> Result<InterfaceNumber> {
+        with_stream!(self, stream, |si| si.interface)
+    }
+
+    /// Get the address of the isochronous data endpoint of a stream, e.g. to
+    /// set up the DMA of the USB peripheral for it. The address remains valid
+    /// after the stream has been split off. Returns an error if the stream is
+    /// not configured.
+    pub fn data_endpoint(&self, stream: StreamId) -> Result<EndpointAddress> {
+        with_stream!(self, stream, |si| si.ep_descriptor.address)
+    }
+
+    /// Get the address of the explicit feedback endpoint of a stream, `None`
+    /// if the stream has none. Returns an error if the stream is not
+    /// configured.
+    pub fn feedback_endpoint(&self, stream: StreamId) -> Result<Option<EndpointAddress>> {
+        with_stream!(self, stream, |si| si
+            .feedback
+            .as_ref()
+            .map(|ep| ep.address()))
+    }
+
+    /// Get the address of the interrupt endpoint of the AudioControl
+    /// interface, `None` if the function has none
+    pub fn interrupt_endpoint(&self) -> Option<EndpointAddress> {
+        self.interrupt.as_ref().map(|ep| ep.address())
+    }
+
+    /// Write the descriptors of the function, as sent to the host within the
+    /// configuration descriptor, into `buf` and return their length, e.g. to
+    /// log them over a debug channel when the host fails to enumerate the
+    /// device. Each descriptor starts with its length (bLength) and type
+    /// (bDescriptorType). The Interface Association Descriptor is included
+    /// although `usb-device` only sends it if the device was built with
+    /// `composite_with_iads()`. Returns `Error::BufferTooSmall` if the
+    /// descriptors do not fit into `buf`.
+    pub fn dump_descriptors(&self, buf: &mut [u8]) -> Result<usize> {
+        let mut writer = DescriptorBuffer::new(buf);
+        self.write_descriptors(&mut writer)?;
+        Ok(writer.position())
+    }
+
+    /// Get the number of interfaces of the function. The AudioStreaming
+    /// interfaces follow the AudioControl interface consecutively, input
+    /// streams first, and the MIDIStreaming interface comes last, regardless
+    /// of the classes allocated before or after the function.
+    pub fn num_interfaces(&self) -> u8 {
+        let inputs = self.inputs.iter().flatten().count();
+        let outputs = self.outputs.iter().flatten().count();
+        let midi = self.midi.iter().count();
+        (1 + inputs + outputs + midi) as u8
+    }
+
+    /// Get the number of the MIDIStreaming interface added by
+    /// `AudioClassBuilder::midi()`
+    pub fn midi_interface(&self) -> Option<InterfaceNumber> {
+        self.midi.as_ref().map(|midi| midi.interface)
+    }
+
+    /// Read the MIDI events of the next packet received from the host into
+    /// `events` and return their number. `events` must have room for a full
+    /// packet, i.e. 16 events at full speed and 128 events at high speed, and
+    /// `Error::BufferTooSmall` is returned otherwise. Returns
+    /// `Error::WouldBlock` if no packet has been received and
+    /// `Error::Usb(UsbError::Unsupported)` if there is no MIDIStreaming
+    /// interface.
+    pub fn read_midi(&mut self, events: &mut [MidiEvent]) -> Result<usize> {
+        self.midi
+            .as_mut()
+            .ok_or(UsbError::Unsupported)?
+            .read(events)
+    }
+
+    /// Send MIDI events to the host and return the number of events sent,
+    /// which is limited to a single packet. Returns `Error::WouldBlock` if the
+    /// previous packet has not been read by the host yet and
+    /// `Error::Usb(UsbError::Unsupported)` if there is no MIDIStreaming
+    /// interface.
+    pub fn write_midi(&mut self, events: &[MidiEvent]) -> Result<usize> {
+        self.midi
+            .as_mut()
+            .ok_or(UsbError::Unsupported)?
+            .write(events)
+    }
+
+    /// Get the number of the HID interface added by
+    /// `AudioClassBuilder::consumer_control()`, which follows the interfaces
+    /// of the function
+    pub fn consumer_control_interface(&self) -> Option<InterfaceNumber> {
+        self.consumer_control.as_ref().map(|hid| hid.interface)
+    }
+
+    /// Get the number of channels of the alternate setting of a stream
+    /// currently selected by the host or 0 if the stream is idle (alternate
+    /// setting 0). Returns an error if the stream is not configured.
+    pub fn channels(&self, stream: StreamId) -> Result<u8> {
+        with_stream!(self, stream, |si| si.channels())
+    }
+
+    /// Get the sampling rate of a stream in samples/second as currently
+    /// selected by the host. Returns an error if the stream is not configured.
+    pub fn sampling_rate(&self, stream: StreamId) -> Result<u32> {
+        with_stream!(self, stream, |si| si.sampling_rate)
+    }
+
+    /// Report the sampling rate of the external clock of a stream in
+    /// samples/second or `None` if the clock is not valid, e.g. because no
+    /// signal is received. Changes of the validity or the rate are signalled
+    /// to the host by messages on the interrupt endpoint of the AudioControl
+    /// interface. Returns an error if the stream is not configured, has no
+    /// external clock or if the rate is not supported by the stream.
+    pub fn set_external_clock(&mut self, stream: StreamId, rate: Option<u32>) -> Result<()> {
+        let (id, controls) = with_stream_mut!(self, stream, |si| {
+            let previous = si.external_rate;
+            si.set_external_clock(rate)
+                .map(|()| si.external_clock_changes(previous))
+        })??;
+        for control in controls.into_iter().flatten() {
+            self.post_interrupt(Source::Entity(id), control, 0);
+        }
+        Ok(())
+    }
+
+    /// Inform the host by a message on the interrupt endpoint that the control
+    /// with the indicated control selector and channel number (0 = master
+    /// channel) of an originator changed, e.g. after the volume was changed by
+    /// a knob of the device. The host then reads the current state of the
+    /// control. Messages of USB Audio Class 1.0 only indicate the originator.
+    /// Returns `Error::InvalidValue` if the function has no interrupt endpoint
+    /// (see `AudioClassBuilder::interrupt_endpoint()`), if the stream has no
+    /// such originator or if too many messages are waiting for transmission.
+    pub fn post_status(
+        &mut self,
+        originator: Originator,
+        control_selector: u8,
+        channel: u8,
+    ) -> Result<()> {
+        let stream = match originator {
+            Originator::FeatureUnit(stream)
+            | Originator::Terminal(stream)
+            | Originator::Interface(stream)
+            | Originator::Endpoint(stream) => stream,
+        };
+        let source = with_stream!(self, stream, |si| si.status_source(originator))?
+            .ok_or(Error::InvalidValue)?;
+        if !self.post_interrupt(source, control_selector, channel) {
+            return Err(Error::InvalidValue);
+        }
+        Ok(())
+    }
+
+    /// Get the input pin currently selected by the Clock Selector of a stream
+    /// (1 = internal clock, 2 = external clock). Returns an error if the
+    /// stream is not configured or has no Clock Selector.
+    pub fn clock_selector(&self, stream: StreamId) -> Result<u8> {
+        with_stream!(self, stream, |si| si
+            .stream_config
+            .clock_selector
+            .then_some(si.clock_selector))?
+        .ok_or(Error::InvalidValue)
+    }
+
+    /// Get the input pin (1..n) currently selected by the Selector Unit of a
+    /// stream. Returns an error if the stream is not configured or has no
+    /// Selector Unit.
+    pub fn selector(&self, stream: StreamId) -> Result<u8> {
+        with_stream!(self, stream, |si| si
+            .selector_unit_id()
+            .map(|_| si.selector))?
+        .ok_or(Error::InvalidValue)
+    }
 
-        // Standard Endpoint Descriptor
-        writer.endpoint(&self.endpoint)?;
+    /// Get the state of the automatic gain control of a stream. Returns an
+    /// error if the stream is not configured or has no automatic gain control.
+    pub fn agc(&self, stream: StreamId) -> Result<bool> {
+        with_stream!(self, stream, |si| si
+            .stream_config
+            .agc_control
+            .then_some(si.agc))?
+        .ok_or(Error::InvalidValue)
+    }
 
-        // Class-specific Isoc. Audio Data Endpoint Descriptor
-        writer.write(
-            0x25,
-            &[
-                // bDescriptorType: CS_ENDPOINT
-                0x01, // bDescriptorSubtype: GENERAL
-                0x00, // bmAttributes
-                0x00, // bLockDelayUnits
-                0x00, 0x00, // wLockDelay
-            ],
-        )
+    /// Get the setting of a tone control of a stream in 1/256 dB. Returns an
+    /// error if the stream is not configured or has no tone controls.
+    pub fn tone(&self, stream: StreamId, control: ToneControl) -> Result<i16> {
+        with_stream!(self, stream, |si| si
+            .stream_config
+            .tone_controls
+            .then_some(si.tone[control as usize] as i16 * 64))?
+        .ok_or(Error::InvalidValue)
     }
-}
 
-/// Builder class to create an `AudioClass` structure.
-pub struct AudioClassBuilder<'a> {
-    input: Option<StreamConfig<'a>>,
-    output: Option<StreamConfig<'a>>,
-}
+    /// Get the gain of the indicated band (14..43) of the graphic equalizer of
+    /// a stream in 1/256 dB. Returns an error if the stream is not configured,
+    /// has no graphic equalizer or if the band is not present.
+    pub fn equalizer_gain(&self, stream: StreamId, band: u8) -> Result<i16> {
+        with_stream!(self, stream, |si| si
+            .equalizer
+            .as_ref()
+            .and_then(|equalizer| equalizer.gain(band)))?
+        .map(|gain| gain as i16 * 64)
+        .ok_or(Error::InvalidValue)
+    }
 
-impl<'a> AudioClassBuilder<'a> {
-    /// Create a new AudioClassBuilder
-    pub fn new() -> AudioClassBuilder<'static> {
-        AudioClassBuilder {
-            input: None,
-            output: None,
+    /// Get the gain of the mixer control connecting the indicated input channel
+    /// with the indicated output channel of the Mixer Unit of an output stream
+    /// in 1/256 dB, `i16::MIN` standing for -∞ dB. The input channels (1..n)
+    /// start with the channels of the output stream itself, followed by the
+    /// channels of the mixed input streams in ascending order of their index.
+    /// Returns an error if the stream is not configured, has no Mixer Unit or
+    /// if the channels do not exist.
+    pub fn mixer_gain(&self, stream: StreamId, in_channel: u8, out_channel: u8) -> Result<i16> {
+        with_stream!(self, stream, |si| si
+            .mixer
+            .as_ref()
+            .and_then(|mixer| mixer.gain(in_channel, out_channel)))?
+        .ok_or(Error::InvalidValue)
+    }
+
+    /// Get the current value of a control of the Processing Unit of a stream.
+    /// Returns an error if the stream is not configured, has no Processing
+    /// Unit or if the Processing Unit does not have the control.
+    pub fn processing_control(&self, stream: StreamId, control: ProcessingControl) -> Result<i16> {
+        with_stream!(self, stream, |si| si
+            .processing
+            .as_ref()
+            .and_then(|processing| processing.value(control)))?
+        .ok_or(Error::InvalidValue)
+    }
+
+    /// Get the state of the mute control of a stream. Returns an error if the
+    /// stream is not configured.
+    pub fn mute(&self, stream: StreamId) -> Result<bool> {
+        with_stream!(self, stream, |si| si.mute[0])
+    }
+
+    /// Get the setting of the volume control of a stream in 1/256 dB. Returns
+    /// an error if the stream is not configured.
+    pub fn volume(&self, stream: StreamId) -> Result<i16> {
+        with_stream!(self, stream, |si| si.volume[0])
+    }
+
+    /// Get the state of the mute control of the indicated channel (1..n) of a
+    /// stream. Returns an error if the stream is not configured or if the
+    /// channel does not exist. Channels without a mute control are reported as
+    /// not muted.
+    pub fn channel_mute(&self, stream: StreamId, channel: u8) -> Result<bool> {
+        with_stream!(self, stream, |si| si.channel_mute(channel))?
+    }
+
+    /// Get the setting of the volume control of the indicated channel (1..n)
+    /// of a stream in 1/256 dB. Returns an error if the stream is not
+    /// configured or if the channel does not exist. Channels without a volume
+    /// control are reported at 0 dB.
+    pub fn channel_volume(&self, stream: StreamId, channel: u8) -> Result<i16> {
+        with_stream!(self, stream, |si| si.channel_volume(channel))?
+    }
+
+    /// Get current Alternate Setting of the first input stream. Returns an
+    /// error if the stream is not configured.
+    pub fn input_alt_setting(&self) -> Result<u8> {
+        self.alt_setting(StreamId::Input(0))
+    }
+
+    /// Get current Alternate Setting of the first output stream. Returns an
+    /// error if the stream is not configured.
+    pub fn output_alt_setting(&self) -> Result<u8> {
+        self.alt_setting(StreamId::Output(0))
+    }
+
+    /// Get the format, the number of channels and the sampling rate of the
+    /// alternate setting of the first input stream currently selected by the
+    /// host or `None` if the stream is idle. See `stream_format()`.
+    pub fn input_format(&self) -> Result<Option<StreamFormat>> {
+        self.stream_format(StreamId::Input(0))
+    }
+
+    /// Get the format, the number of channels and the sampling rate of the
+    /// alternate setting of the first output stream currently selected by the
+    /// host or `None` if the stream is idle. See `stream_format()`.
+    pub fn output_format(&self) -> Result<Option<StreamFormat>> {
+        self.stream_format(StreamId::Output(0))
+    }
+
+    /// Get the number of channels of the alternate setting of the first input
+    /// stream currently selected by the host. See `channels()`.
+    pub fn input_channels(&self) -> Result<u8> {
+        self.channels(StreamId::Input(0))
+    }
+
+    /// Get the number of channels of the alternate setting of the first output
+    /// stream currently selected by the host. See `channels()`.
+    pub fn output_channels(&self) -> Result<u8> {
+        self.channels(StreamId::Output(0))
+    }
+
+    /// Get the sampling rate of the first input stream in samples/second as
+    /// currently selected by the host. Returns an error if the stream is not
+    /// configured.
+    pub fn input_sampling_rate(&self) -> Result<u32> {
+        self.sampling_rate(StreamId::Input(0))
+    }
+
+    /// Get the sampling rate of the first output stream in samples/second as
+    /// currently selected by the host. Returns an error if the stream is not
+    /// configured.
+    pub fn output_sampling_rate(&self) -> Result<u32> {
+        self.sampling_rate(StreamId::Output(0))
+    }
+
+    /// Get the input pin (1..n) currently selected by the Selector Unit of the
+    /// first input stream. See `selector()`.
+    pub fn input_selector(&self) -> Result<u8> {
+        self.selector(StreamId::Input(0))
+    }
+
+    /// Get the state of the mute control of the first input stream. Returns an
+    /// error if the stream is not configured.
+    pub fn input_mute(&self) -> Result<bool> {
+        self.mute(StreamId::Input(0))
+    }
+
+    /// Get the state of the mute control of the first output stream. Returns an
+    /// error if the stream is not configured.
+    pub fn output_mute(&self) -> Result<bool> {
+        self.mute(StreamId::Output(0))
+    }
+
+    /// Get the setting of the volume control of the first input stream in
+    /// 1/256 dB. Returns an error if the stream is not configured.
+    pub fn input_volume(&self) -> Result<i16> {
+        self.volume(StreamId::Input(0))
+    }
+
+    /// Get the setting of the volume control of the first output stream in
+    /// 1/256 dB. Returns an error if the stream is not configured.
+    pub fn output_volume(&self) -> Result<i16> {
+        self.volume(StreamId::Output(0))
+    }
+
+    /// Get the state of the mute control of the indicated channel (1..n) of the
+    /// first input stream. See `channel_mute()`.
+    pub fn input_channel_mute(&self, channel: u8) -> Result<bool> {
+        self.channel_mute(StreamId::Input(0), channel)
+    }
+
+    /// Get the state of the mute control of the indicated channel (1..n) of the
+    /// first output stream. See `channel_mute()`.
+    pub fn output_channel_mute(&self, channel: u8) -> Result<bool> {
+        self.channel_mute(StreamId::Output(0), channel)
+    }
+
+    /// Get the setting of the volume control of the indicated channel (1..n)
+    /// of the first input stream in 1/256 dB. See `channel_volume()`.
+    pub fn input_channel_volume(&self, channel: u8) -> Result<i16> {
+        self.channel_volume(StreamId::Input(0), channel)
+    }
+
+    /// Get the setting of the volume control of the indicated channel (1..n)
+    /// of the first output stream in 1/256 dB. See `channel_volume()`.
+    pub fn output_channel_volume(&self, channel: u8) -> Result<i16> {
+        self.channel_volume(StreamId::Output(0), channel)
+    }
+
+    /// Inform the class about the state of the device as returned by
+    /// `UsbDevice::state()`, since `usb-device` does not report suspend and
+    /// resume to classes. Call this after `UsbDevice::poll()`. Entering and
+    /// leaving the suspended state are reported as `AudioEvent::Suspended` and
+    /// `AudioEvent::Resumed`. The alternate settings are retained while
+    /// suspended, as required by the USB specification, but packets of
+    /// buffered input streams that were pending at the time of the suspend are
+    /// considered lost, so that transmission restarts after the resume.
+    pub fn update_device_state(&mut self, state: UsbDeviceState) {
+        let suspended = state == UsbDeviceState::Suspend;
+        if suspended == self.suspended {
+            return;
         }
+        self.suspended = suspended;
+        self.pending_events &= !(SUSPENDED | RESUMED);
+        self.pending_events |= if suspended { SUSPENDED } else { RESUMED };
+        self.discard_pending_packets();
     }
 
-    /// Configure the input audio stream according to a `StreamConfig`.
-    /// At most one input stream can be configured. When calling this method
-    /// multiple times, the last call matters.
-    pub fn input(self, input: StreamConfig<'a>) -> AudioClassBuilder<'a> {
-        AudioClassBuilder {
-            input: Some(input),
-            output: self.output,
+    /// Forget the packets of buffered input streams written to the endpoints,
+    /// which are not transmitted after a reset or suspend
+    fn discard_pending_packets(&mut self) {
+        for tx in self
+            .inputs
+            .iter_mut()
+            .flatten()
+            .filter_map(|info| info.tx_buffer.as_mut())
+        {
+            tx.pending = false;
         }
     }
 
-    /// Configure the output audio stream according to a `StreamConfig`.
-    /// At most one output stream can be configured. When calling this method
-    /// multiple times, the last call matters.
-    pub fn output(self, output: StreamConfig<'a>) -> AudioClassBuilder<'a> {
-        AudioClassBuilder {
-            input: self.input,
-            output: Some(output),
+    /// Wake the host up from suspend, e.g. when a mute or talk button of the
+    /// device is pressed, by calling the function passed to
+    /// `AudioClassBuilder::remote_wakeup()`. Returns
+    /// `Error::Usb(UsbError::InvalidState)` if `usb_dev` is not suspended or
+    /// the host did not enable remote wakeup and
+    /// `Error::Usb(UsbError::Unsupported)` if no function was passed.
+    pub fn remote_wakeup(&mut self, usb_dev: &UsbDevice<'_, B>) -> Result<()> {
+        let signal = self.remote_wakeup.ok_or(UsbError::Unsupported)?;
+        if usb_dev.state() != UsbDeviceState::Suspend || !usb_dev.remote_wakeup_enabled() {
+            return Err(Error::Usb(UsbError::InvalidState));
         }
+        signal();
+        Ok(())
     }
 
-    /// Create the `AudioClass` structure
-    pub fn build<B: UsbBus>(self, alloc: &'a UsbBusAllocator<B>) -> Result<AudioClass<'a, B>> {
-        let control_iface = alloc.interface();
-        let mut ac = AudioClass {
-            control_iface,
-            input: None,
-            output: None,
-        };
-        if let Some(stream_config) = self.input {
-            let interface = alloc.interface();
-            let endpoint = alloc.alloc(
-                None,
-                EndpointType::Isochronous {
-                    synchronization: IsochronousSynchronizationType::Asynchronous,
-                    usage: IsochronousUsageType::Data,
-                },
-                stream_config.ep_size,
-                1,
-            )?;
-            let alt_setting = DEFAULT_ALTERNATE_SETTING;
-            ac.input = Some(AudioStream {
-                stream_config,
-                interface,
-                endpoint,
-                alt_setting,
-            })
+    /// Report a button of the device as pressed through the HID interface
+    /// added by `AudioClassBuilder::consumer_control()`, in addition to the
+    /// keys already pressed, until `release_keys()` is called. The host
+    /// changes its volume or mute state and sets the controls of the function
+    /// accordingly, which `poll_event()` reports. Returns `Error::WouldBlock`
+    /// if too many reports are waiting for the host and
+    /// `Error::Usb(UsbError::Unsupported)` if there is no HID interface.
+    pub fn press_key(&mut self, key: ConsumerKey) -> Result<()> {
+        let hid = self
+            .consumer_control
+            .as_mut()
+            .ok_or(UsbError::Unsupported)?;
+        if !hid.push(Some(key)) {
+            return Err(Error::WouldBlock);
         }
+        Ok(())
+    }
 
-        if let Some(stream_config) = self.output {
-            let interface = alloc.interface();
-            let endpoint = alloc.alloc(
-                None,
-                EndpointType::Isochronous {
-                    synchronization: IsochronousSynchronizationType::Adaptive,
-                    usage: IsochronousUsageType::Data,
-                },
-                stream_config.ep_size,
-                1,
-            )?;
-            let alt_setting = DEFAULT_ALTERNATE_SETTING;
-            ac.output = Some(AudioStream {
-                stream_config,
-                interface,
-                endpoint,
-                alt_setting,
-            })
+    /// Report all buttons pressed by `press_key()` as released. Returns the
+    /// errors of `press_key()`.
+    pub fn release_keys(&mut self) -> Result<()> {
+        let hid = self
+            .consumer_control
+            .as_mut()
+            .ok_or(UsbError::Unsupported)?;
+        if !hid.push(None) {
+            return Err(Error::WouldBlock);
         }
+        Ok(())
+    }
 
-        Ok(ac)
+    /// Report a button as pressed and released at once, e.g. for a single
+    /// step of the volume. Returns the errors of `press_key()` and queues
+    /// nothing if the reports do not fit.
+    pub fn click_key(&mut self, key: ConsumerKey) -> Result<()> {
+        let hid = self
+            .consumer_control
+            .as_mut()
+            .ok_or(UsbError::Unsupported)?;
+        if hid.free() < 2 {
+            return Err(Error::WouldBlock);
+        }
+        hid.push(Some(key));
+        hid.push(None);
+        Ok(())
     }
-}
 
-/// USB device class for audio devices.
-///
-/// This device class based on the "Universal Serial Bus Device Class Definition
-/// for Audio Devices", Release 1.0. It supports one input stream and/or one
-/// output stream.
-pub struct AudioClass<'a, B: UsbBus> {
-    control_iface: InterfaceNumber,
-    input: Option<AudioStream<'a, B, In>>,
-    output: Option<AudioStream<'a, B, Out>>,
-}
+    /// Get whether the bus is suspended according to `update_device_state()`
+    pub fn is_suspended(&self) -> bool {
+        self.suspended
+    }
 
-impl<B: UsbBus> AudioClass<'_, B> {
-    /// Read audio frames as output by the host. Returns an Error if no output
-    /// stream has been configured.
-    pub fn read(&self, data: &mut [u8]) -> Result<usize> {
-        if let Some(ref info) = self.output {
-            info.endpoint.read(data).map_err(Error::UsbError)
-        } else {
-            Err(Error::StreamNotInitialized)
+    /// Get the next change of a control or a stream setting made by the host.
+    /// Returns `None` if no changes happened since the last call. Repeated
+    /// changes of the same control are reported only once, with the most
+    /// recent value.
+    pub fn poll_event(&mut self) -> Option<AudioEvent> {
+        for (flag, event) in [
+            (SUSPENDED, AudioEvent::Suspended),
+            (RESUMED, AudioEvent::Resumed),
+        ] {
+            if self.pending_events & flag != 0 {
+                self.pending_events &= !flag;
+                return Some(event);
+            }
+        }
+        for info in self.inputs.iter_mut().flatten() {
+            if let Some(event) = info.poll_event() {
+                return Some(event);
+            }
+        }
+        for info in self.outputs.iter_mut().flatten() {
+            if let Some(event) = info.poll_event() {
+                return Some(event);
+            }
         }
+        None
     }
 
-    /// Write audio frames to be input by the host. Returns an Error when no
-    /// input stream has been configured.
-    pub fn write(&self, data: &[u8]) -> Result<usize> {
-        if let Some(ref info) = self.input {
-            info.endpoint.write(data).map_err(Error::UsbError)
+    /// Whether the interface with the indicated number belongs to the function
+    fn owns_interface(&self, iface: u8) -> bool {
+        iface == self.control_iface.into()
+            || self
+                .midi
+                .as_ref()
+                .is_some_and(|midi| iface == midi.interface.into())
+            || self
+                .inputs
+                .iter()
+                .flatten()
+                .any(|info| iface == info.interface.into())
+            || self
+                .outputs
+                .iter()
+                .flatten()
+                .any(|info| iface == info.interface.into())
+    }
+
+    /// Pass a vendor-specific GET request addressed to an interface of the
+    /// function to the application
+    fn vendor_request_in(&mut self, xfer: ControlIn<B>) {
+        let req = *xfer.request();
+        match self.control_handler.as_mut() {
+            Some(handler) => xfer.accept(|buf| {
+                handler
+                    .on_vendor_request_in(&req, buf)
+                    .filter(|&length| length <= buf.len())
+                    .ok_or(UsbError::InvalidState)
+            }),
+            None => xfer.reject(),
+        }
+        .ok();
+    }
+
+    /// Pass a vendor-specific SET request addressed to an interface of the
+    /// function to the application
+    fn vendor_request_out(&mut self, xfer: ControlOut<B>) {
+        let req = *xfer.request();
+        let accepted = match self.control_handler.as_mut() {
+            Some(handler) => handler.on_vendor_request_out(&req, xfer.data()),
+            None => false,
+        };
+        if accepted {
+            xfer.accept().ok();
         } else {
-            Err(Error::StreamNotInitialized)
+            xfer.reject().ok();
         }
     }
 
-    /// Get current Alternate Setting of the input stream. Returns an error if
-    /// the stream is not configured.
-    pub fn input_alt_setting(&self) -> Result<u8> {
-        self.input
-            .as_ref()
-            .ok_or(Error::StreamNotInitialized)
-            .map(|si| si.alt_setting)
+    /// Handle a class-specific GET request addressed to an entity of the
+    /// AudioControl interface
+    fn entity_control_in(&mut self, xfer: ControlIn<B>) {
+        let id = (xfer.request().index >> 8) as u8;
+        for info in self.inputs.iter().flatten() {
+            if let Some(entity) = info.entity(id, self.version) {
+                return info.entity_in(entity, self.version, xfer);
+            }
+        }
+        for info in self.outputs.iter().flatten() {
+            if let Some(entity) = info.entity(id, self.version) {
+                return info.entity_in(entity, self.version, xfer);
+            }
+        }
+        xfer.reject().ok();
     }
 
-    /// Get current Alternate Setting of the output stream. Returns an error if
-    /// the stream is not configured.
-    pub fn output_alt_setting(&self) -> Result<u8> {
-        self.output
-            .as_ref()
-            .ok_or(Error::StreamNotInitialized)
-            .map(|si| si.alt_setting)
+    /// Handle a class-specific SET request addressed to an entity of the
+    /// AudioControl interface
+    fn entity_control_out(&mut self, xfer: ControlOut<B>) {
+        let id = (xfer.request().index >> 8) as u8;
+        let handler = self.control_handler.as_deref_mut();
+        for info in self.inputs.iter_mut().flatten() {
+            if let Some(entity) = info.entity(id, self.version) {
+                return info.entity_out(entity, self.version, xfer, handler);
+            }
+        }
+        for info in self.outputs.iter_mut().flatten() {
+            if let Some(entity) = info.entity(id, self.version) {
+                return info.entity_out(entity, self.version, xfer, handler);
+            }
+        }
+        xfer.reject().ok();
     }
-}
 
-impl<B: UsbBus> UsbClass<B> for AudioClass<'_, B> {
-    fn get_configuration_descriptors(
-        &self,
-        writer: &mut DescriptorWriter,
-    ) -> usb_device::Result<()> {
-        writer.interface(self.control_iface, AUDIO, AUDIOCONTROL, 0x00)?;
+    /// Handle a class-specific GET request addressed to an isochronous
+    /// endpoint. Requests for endpoints of other classes are ignored.
+    fn endpoint_control_in(&mut self, xfer: ControlIn<B>) {
+        let ep_addr = xfer.request().index as u8;
+        if let Some(info) = self
+            .inputs
+            .iter()
+            .flatten()
+            .find(|info| ep_addr == info.ep_descriptor.address.into())
+        {
+            match self.version {
+                AudioVersion::Uac2 => info.endpoint_control_in_v2(xfer),
+                _ => info.endpoint_control_in(xfer),
+            }
+            return;
+        }
+        if let Some(info) = self
+            .outputs
+            .iter()
+            .flatten()
+            .find(|info| ep_addr == info.ep_descriptor.address.into())
+        {
+            match self.version {
+                AudioVersion::Uac2 => info.endpoint_control_in_v2(xfer),
+                _ => info.endpoint_control_in(xfer),
+            }
+        }
+    }
 
-        // write Class-specific Audio Control (AC) Interface Descriptors
-        let mut in_collection = 0u8;
-        if self.input.is_some() {
-            in_collection += 1;
+    /// Handle a class-specific SET request addressed to an isochronous
+    /// endpoint. Requests for endpoints of other classes are ignored.
+    fn endpoint_control_out(&mut self, xfer: ControlOut<B>) {
+        let ep_addr = xfer.request().index as u8;
+        if let Some(info) = self
+            .inputs
+            .iter_mut()
+            .flatten()
+            .find(|info| ep_addr == info.ep_descriptor.address.into())
+        {
+            match self.version {
+                AudioVersion::Uac2 => info.endpoint_control_out_v2(xfer),
+                _ => info.endpoint_control_out(xfer, self.control_handler.as_deref_mut()),
+            }
+            return;
         }
-        if self.output.is_some() {
-            in_collection += 1;
+        if let Some(info) = self
+            .outputs
+            .iter_mut()
+            .flatten()
+            .find(|info| ep_addr == info.ep_descriptor.address.into())
+        {
+            match self.version {
+                AudioVersion::Uac2 => info.endpoint_control_out_v2(xfer),
+                _ => info.endpoint_control_out(xfer, self.control_handler.as_deref_mut()),
+            }
         }
-        let total_length = 8u16 + (1 + 21) * in_collection as u16;
+    }
 
-        let mut ac_header = [
-            HEADER, // bDescriptorSubtype
+    /// Queue an interrupt message for the host. Returns false if the function
+    /// has no interrupt endpoint or the queue is full.
+    fn post_interrupt(&mut self, source: Source, control_selector: u8, channel: u8) -> bool {
+        if self.interrupt.is_none() {
+            return false;
+        }
+        let control_iface = self.control_iface.into();
+        let message = interrupt::message(
+            self.version,
+            source,
+            control_iface,
+            control_selector,
+            channel,
+        );
+        let queued = self.interrupt_queue.push(message);
+        self.send_interrupt();
+        queued
+    }
+
+    /// Write the next queued interrupt message to the endpoint unless the
+    /// host has not yet read the previous one
+    fn send_interrupt(&mut self) {
+        let Some(ep) = self.interrupt.as_ref() else {
+            return;
+        };
+        if self.interrupt_busy {
+            return;
+        }
+        if let Some(message) = self.interrupt_queue.front() {
+            if ep
+                .write(&message[..interrupt::message_size(self.version)])
+                .is_ok()
+            {
+                self.interrupt_busy = true;
+                self.interrupt_queue.pop();
+            }
+        }
+    }
+
+    /// Index of the string descriptor naming the function (iFunction and
+    /// iInterface of the AudioControl interface)
+    pub(crate) fn function_string(&self) -> Option<StringIndex> {
+        self.function_name.map(|(index, _)| index)
+    }
+
+    /// Write the descriptors of the function and of the interfaces bundled
+    /// with it
+    fn write_descriptors<W: DescriptorSink>(&self, writer: &mut W) -> usb_device::Result<()> {
+        match self.version {
+            AudioVersion::Uac1 => self.write_descriptors_v1(writer)?,
+            AudioVersion::Uac2 => self.write_descriptors_v2(writer)?,
+            AudioVersion::Uac3Badd => self.write_descriptors_badd(writer)?,
+        }
+        if let Some(hid) = self.consumer_control.as_ref() {
+            hid.write_descriptors(writer)?;
+        }
+        Ok(())
+    }
+
+    /// Write the descriptors of a USB Audio Class 1.0 function
+    fn write_descriptors_v1<W: DescriptorSink>(&self, writer: &mut W) -> usb_device::Result<()> {
+        // Interface Association Descriptor, which USB Audio Class 1.0 does not
+        // require, but which keeps the interfaces of the function together in
+        // a composite device built with `composite_with_iads()`
+        writer.iad(
+            self.control_iface,
+            self.num_interfaces(),
+            AUDIO,
+            AUDIOCONTROL,
             0x00,
-            0x01, // bcdADC
-            total_length as u8,
-            (total_length >> 8) as u8, // wTotalLength
-            in_collection,             // number of AS interfaces
+            self.function_string(),
+        )?;
+        writer.interface_alt(
+            self.control_iface,
+            DEFAULT_ALTERNATE_SETTING,
+            AUDIO,
+            AUDIOCONTROL,
             0x00,
-            0x00, // placeholders for baInterfaceNr
-        ];
+            self.function_string(),
+        )?;
+
+        // write Class-specific Audio Control (AC) Interface Descriptors
+        let mut ac_header = [0u8; 7 + 2 * MAX_STREAMS];
         let mut ndx = 6;
-        if let Some(ref input) = self.input {
-            ac_header[ndx] = input.interface.into();
+        let mut total_length = 8u16;
+        for a in self.inputs.iter().flatten() {
+            ac_header[ndx] = a.interface.into();
+            ndx += 1;
+            total_length += 1 + a.ac_descriptors_length();
+        }
+        for a in self.outputs.iter().flatten() {
+            ac_header[ndx] = a.interface.into();
             ndx += 1;
+            total_length += 1 + a.ac_descriptors_length();
         }
-        if let Some(ref output) = self.output {
-            ac_header[ndx] = output.interface.into();
+        if let Some(midi) = self.midi.as_ref() {
+            ac_header[ndx] = midi.interface.into();
             ndx += 1;
+            total_length += 1;
         }
+        ac_header[..6].copy_from_slice(&[
+            HEADER, // bDescriptorSubtype
+            0x00,
+            0x01, // bcdADC
+            total_length as u8,
+            (total_length >> 8) as u8, // wTotalLength
+            (ndx - 6) as u8,           // number of AS and MS interfaces
+        ]);
         writer.write(CS_INTERFACE, &ac_header[..ndx])?;
-        if let Some(ref a) = self.input {
+        for a in self.inputs.iter().flatten() {
             a.write_ac_descriptors(writer)?;
         }
-        if let Some(ref a) = self.output {
+        for a in self.outputs.iter().flatten() {
             a.write_ac_descriptors(writer)?;
         }
 
+        // Standard AC Interrupt Endpoint Descriptor (9 bytes)
+        if let Some(ep) = self.interrupt.as_ref() {
+            writer.endpoint_ex(ep, |buf| {
+                let buf = buf.get_mut(..2).ok_or(UsbError::BufferOverflow)?;
+                buf[0] = 0x00; // bRefresh
+                buf[1] = 0x00; // bSynchAddress
+                Ok(2)
+            })?;
+        }
+
         // write Audio Streaming (AS) and endpoint (EP) descriptors
-        if let Some(ref a) = self.input {
+        for a in self.inputs.iter().flatten() {
             a.write_as_and_ep_descriptors(writer)?;
         }
-        if let Some(ref a) = self.output {
+        for a in self.outputs.iter().flatten() {
             a.write_as_and_ep_descriptors(writer)?;
         }
+        if let Some(midi) = self.midi.as_ref() {
+            midi.write_descriptors(writer)?;
+        }
         Ok(())
     }
+}
+
+impl<B: UsbBus, const P: usize> UsbClass<B> for AudioClass<'_, B, P> {
+    fn get_configuration_descriptors(
+        &self,
+        writer: &mut DescriptorWriter,
+    ) -> usb_device::Result<()> {
+        self.write_descriptors(writer)
+    }
+
+    fn get_string(&self, index: StringIndex, _lang_id: LangID) -> Option<&str> {
+        if let Some((_, name)) = self.function_name.filter(|&(i, _)| i == index) {
+            return Some(name);
+        }
+        let index = u8::from(index);
+        self.inputs
+            .iter()
+            .flatten()
+            .find_map(|info| info.string(index))
+            .or_else(|| {
+                self.outputs
+                    .iter()
+                    .flatten()
+                    .find_map(|info| info.string(index))
+            })
+    }
+
+    fn reset(&mut self) {
+        debug!("usbd-audio: reset");
+        self.interrupt_queue.clear();
+        self.interrupt_busy = false;
+        if let Some(hid) = self.consumer_control.as_mut() {
+            hid.reset();
+        }
+        self.discard_pending_packets();
+    }
+
+    fn poll(&mut self) {
+        self.send_interrupt();
+        if let Some(hid) = self.consumer_control.as_mut() {
+            hid.send();
+        }
+        for info in self.inputs.iter_mut().flatten() {
+            info.transmit_buffered();
+        }
+    }
+
+    fn endpoint_out(&mut self, addr: EndpointAddress) {
+        let frame_number = self.frame_number();
+        if self.loopback {
+            if let Some(Some(output)) = self.outputs.first_mut() {
+                if output.ep_descriptor.address == addr {
+                    output.rx_frame_number = frame_number;
+                    self.loop_back();
+                    return;
+                }
+            }
+        }
+        if let Some(info) = self
+            .outputs
+            .iter_mut()
+            .flatten()
+            .find(|info| info.ep_descriptor.address == addr)
+        {
+            info.rx_frame_number = frame_number;
+            info.receive_buffered();
+            if let Some(shared) = info.shared {
+                shared.set_rx_frame_number(frame_number);
+            }
+        }
+    }
+
+    fn endpoint_in_complete(&mut self, addr: EndpointAddress) {
+        if self.interrupt.as_ref().map(|ep| ep.address()) == Some(addr) {
+            self.interrupt_busy = false;
+            self.send_interrupt();
+        }
+        if let Some(hid) = self.consumer_control.as_mut() {
+            hid.endpoint_in_complete(addr);
+        }
+        if let Some(info) = self
+            .inputs
+            .iter_mut()
+            .flatten()
+            .find(|info| info.ep_descriptor.address == addr)
+        {
+            if let Some(ref mut tx) = info.tx_buffer {
+                tx.pending = false;
+            }
+            info.transmit_buffered();
+        }
+        for info in self.outputs.iter().flatten() {
+            if info.feedback.as_ref().map(|ep| ep.address()) == Some(addr) {
+                info.write_feedback();
+            }
+        }
+    }
 
     fn control_in(&mut self, xfer: ControlIn<B>) {
         let req = xfer.request();
+        if let Some(hid) = self.consumer_control.as_mut().filter(|hid| hid.owns(req)) {
+            return hid.control_in(xfer);
+        }
+        if req.request_type == RequestType::Class
+            && req.recipient == Recipient::Interface
+            && req.index as u8 == self.control_iface.into()
+        {
+            trace!("usbd-audio: control IN {}", req);
+            if let Some(stream) = self.extension_unit_stream((req.index >> 8) as u8) {
+                return self.extension_unit_in(stream, xfer);
+            }
+            match self.version {
+                AudioVersion::Uac3Badd => return self.entity_control_in_badd(xfer),
+                _ => return self.entity_control_in(xfer),
+            }
+        }
+        if self.version != AudioVersion::Uac3Badd
+            && req.request_type == RequestType::Class
+            && req.recipient == Recipient::Endpoint
+        {
+            trace!("usbd-audio: endpoint control IN {}", req);
+            return self.endpoint_control_in(xfer);
+        }
+        if req.request_type == RequestType::Vendor
+            && req.recipient == Recipient::Interface
+            && self.owns_interface(req.index as u8)
+        {
+            trace!("usbd-audio: vendor request IN {}", req);
+            return self.vendor_request_in(xfer);
+        }
         if req.request_type == RequestType::Standard
             && req.recipient == Recipient::Interface
             && req.request == Request::GET_INTERFACE
             && req.length == 1
         {
             let iface = req.index as u8;
-            if let Some(info) = self.input.as_ref() {
-                if iface == info.interface.into() {
-                    xfer.accept_with(&[info.alt_setting]).ok();
-                    return;
-                }
-            }
-            if let Some(info) = self.output.as_ref() {
-                if iface == info.interface.into() {
-                    xfer.accept_with(&[info.alt_setting]).ok();
-                }
+            let alt_setting = self
+                .inputs
+                .iter()
+                .flatten()
+                .find(|info| iface == info.interface.into())
+                .map(|info| info.alt_setting)
+                .or_else(|| {
+                    self.outputs
+                        .iter()
+                        .flatten()
+                        .find(|info| iface == info.interface.into())
+                        .map(|info| info.alt_setting)
+                });
+            if let Some(alt_setting) = alt_setting {
+                xfer.accept_with(&[alt_setting]).ok();
             }
         }
     }
 
     fn control_out(&mut self, xfer: ControlOut<B>) {
         let req = xfer.request();
+        if let Some(hid) = self.consumer_control.as_mut().filter(|hid| hid.owns(req)) {
+            return hid.control_out(xfer);
+        }
+        if req.request_type == RequestType::Class
+            && req.recipient == Recipient::Interface
+            && req.index as u8 == self.control_iface.into()
+        {
+            trace!("usbd-audio: control OUT {} {=[u8]:02x}", req, xfer.data());
+            if let Some(stream) = self.extension_unit_stream((req.index >> 8) as u8) {
+                return self.extension_unit_out(stream, xfer);
+            }
+            match self.version {
+                AudioVersion::Uac3Badd => return self.entity_control_out_badd(xfer),
+                _ => return self.entity_control_out(xfer),
+            }
+        }
+        if self.version != AudioVersion::Uac3Badd
+            && req.request_type == RequestType::Class
+            && req.recipient == Recipient::Endpoint
+        {
+            trace!(
+                "usbd-audio: endpoint control OUT {} {=[u8]:02x}",
+                req,
+                xfer.data()
+            );
+            return self.endpoint_control_out(xfer);
+        }
+        if req.request_type == RequestType::Vendor
+            && req.recipient == Recipient::Interface
+            && self.owns_interface(req.index as u8)
+        {
+            trace!("usbd-audio: vendor request OUT {}", req);
+            return self.vendor_request_out(xfer);
+        }
         if req.request_type == RequestType::Standard
             && req.recipient == Recipient::Interface
             && req.request == Request::SET_INTERFACE
@@ -539,18 +5700,35 @@ impl<B: UsbBus> UsbClass<B> for AudioClass<'_, B> {
             let iface = req.index as u8;
             let alt_setting = req.value;
 
-            if let Some(info) = self.input.as_mut() {
-                if iface == info.interface.into() {
-                    info.alt_setting = alt_setting as u8;
-                    xfer.accept().ok();
+            if let Some(info) = self
+                .inputs
+                .iter_mut()
+                .flatten()
+                .find(|info| iface == info.interface.into())
+            {
+                if alt_setting > info.stream_config.num_alt_settings() as u16 {
+                    debug!("usbd-audio: invalid alternate setting {=u16}", alt_setting);
+                    xfer.reject().ok();
                     return;
                 }
+                info.set_alt_setting(alt_setting as u8);
+                xfer.accept().ok();
+                return;
             }
-            if let Some(info) = self.output.as_mut() {
-                if iface == info.interface.into() {
-                    info.alt_setting = alt_setting as u8;
-                    xfer.accept().ok();
+            if let Some(info) = self
+                .outputs
+                .iter_mut()
+                .flatten()
+                .find(|info| iface == info.interface.into())
+            {
+                if alt_setting > info.stream_config.num_alt_settings() as u16 {
+                    debug!("usbd-audio: invalid alternate setting {=u16}", alt_setting);
+                    xfer.reject().ok();
+                    return;
                 }
+                info.set_alt_setting(alt_setting as u8);
+                xfer.accept().ok();
+                info.write_feedback();
             }
         }
     }
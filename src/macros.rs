@@ -0,0 +1,22 @@
+//! Trace points for debugging the enumeration and the control requests by
+//! `defmt`, which are compiled out without the `defmt` feature
+
+#[cfg(feature = "defmt")]
+macro_rules! trace {
+    ($($arg:expr),*) => { defmt::trace!($($arg),*) };
+}
+
+#[cfg(feature = "defmt")]
+macro_rules! debug {
+    ($($arg:expr),*) => { defmt::debug!($($arg),*) };
+}
+
+#[cfg(not(feature = "defmt"))]
+macro_rules! trace {
+    ($($arg:expr),*) => {{ $( let _ = &$arg; )* }};
+}
+
+#[cfg(not(feature = "defmt"))]
+macro_rules! debug {
+    ($($arg:expr),*) => {{ $( let _ = &$arg; )* }};
+}
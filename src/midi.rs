@@ -0,0 +1,257 @@
+//! MIDIStreaming interface according to Universal Serial Bus Device Class
+//! Definition for MIDI Devices, Release 1.0
+//!
+//! The interface belongs to the audio function and carries a single cable:
+//! an embedded MIDI IN jack fed by the bulk OUT endpoint and connected to an
+//! external MIDI OUT jack, and an external MIDI IN jack connected to an
+//! embedded MIDI OUT jack that feeds the bulk IN endpoint. MIDI data is
+//! exchanged as 32 bit USB-MIDI event packets.
+
+use crate::class_codes::{AUDIO, CS_ENDPOINT, CS_INTERFACE, MIDISTREAMING};
+use crate::descriptor::DescriptorSink;
+use crate::{Error, Result};
+use usb_device::class_prelude::*;
+use usb_device::endpoint::{Endpoint, In, Out};
+
+// MIDIStreaming class-specific descriptor subtypes
+const MS_HEADER: u8 = 0x01;
+const MIDI_IN_JACK: u8 = 0x02;
+const MIDI_OUT_JACK: u8 = 0x03;
+const MS_GENERAL: u8 = 0x01;
+
+// MIDI jack types
+const EMBEDDED: u8 = 0x01;
+const EXTERNAL: u8 = 0x02;
+
+// IDs of the jacks
+const ID_EMBEDDED_IN: u8 = 0x01;
+const ID_EXTERNAL_IN: u8 = 0x02;
+const ID_EMBEDDED_OUT: u8 = 0x03;
+const ID_EXTERNAL_OUT: u8 = 0x04;
+
+const MS_HEADER_SIZE: u16 = 7;
+const IN_JACK_SIZE: u16 = 6;
+const OUT_JACK_SIZE: u16 = 9;
+const ENDPOINT_SIZE: u16 = 9;
+const CS_ENDPOINT_SIZE: u16 = 5;
+
+/// Maximum packet size of the bulk endpoints
+const MAX_PACKET_SIZE: u16 = 64;
+const MAX_PACKET_SIZE_HS: u16 = 512;
+
+/// Size of a USB-MIDI event packet
+const EVENT_SIZE: usize = 4;
+
+/// USB-MIDI event packet carrying a MIDI message of up to three bytes
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct MidiEvent([u8; EVENT_SIZE]);
+
+impl MidiEvent {
+    /// Create an event from the four bytes of a USB-MIDI event packet: Cable
+    /// Number and Code Index Number followed by the MIDI bytes
+    pub const fn from_packet(packet: [u8; EVENT_SIZE]) -> Self {
+        MidiEvent(packet)
+    }
+
+    /// Create an event for a complete MIDI message other than a System
+    /// Exclusive message, e.g. `[0x90, 60, 100]` for a Note On. Returns
+    /// `None` if `message` does not start with a status byte or its length
+    /// does not match the status. System Exclusive messages are split into
+    /// packets by the application, see `from_packet()`.
+    pub fn from_message(message: &[u8]) -> Option<Self> {
+        let &status = message.first()?;
+        let code_index = match status {
+            0x80..=0xef => status >> 4,
+            // Song Position Pointer
+            0xf2 => 0x3,
+            // MIDI Time Code Quarter Frame, Song Select
+            0xf1 | 0xf3 => 0x2,
+            // Tune Request and System Real-Time messages
+            0xf6 | 0xf8..=0xff => 0xf,
+            _ => return None,
+        };
+        if message.len() != message_length(code_index) {
+            return None;
+        }
+        let mut packet = [code_index, 0, 0, 0];
+        packet[1..1 + message.len()].copy_from_slice(message);
+        Some(MidiEvent(packet))
+    }
+
+    /// Bytes of the USB-MIDI event packet
+    pub const fn packet(&self) -> [u8; EVENT_SIZE] {
+        self.0
+    }
+
+    /// Virtual cable number of the event, which is 0 for the single cable of
+    /// the interface
+    pub fn cable(&self) -> u8 {
+        self.0[0] >> 4
+    }
+
+    /// Code Index Number classifying the MIDI message
+    pub fn code_index(&self) -> u8 {
+        self.0[0] & 0x0f
+    }
+
+    /// Bytes of the MIDI message, without the padding of the packet
+    pub fn message(&self) -> &[u8] {
+        &self.0[1..1 + message_length(self.code_index())]
+    }
+}
+
+/// Number of MIDI bytes in an event of the indicated Code Index Number
+fn message_length(code_index: u8) -> usize {
+    match code_index {
+        0x5 | 0xf => 1,
+        0x2 | 0x6 | 0xc | 0xd => 2,
+        0x3 | 0x4 | 0x7..=0xb | 0xe => 3,
+        // reserved for future extensions
+        _ => 0,
+    }
+}
+
+/// Write the fields the endpoint descriptors of the audio class add to the
+/// standard endpoint descriptor
+fn write_audio_fields(buf: &mut [u8]) -> usb_device::Result<usize> {
+    let buf = buf.get_mut(..2).ok_or(UsbError::BufferOverflow)?;
+    buf[0] = 0x00; // bRefresh
+    buf[1] = 0x00; // bSynchAddress
+    Ok(2)
+}
+
+/// Write a Class-specific MS Bulk Data Endpoint Descriptor (5 bytes)
+fn write_cs_endpoint<W: DescriptorSink>(writer: &mut W, jack: u8) -> usb_device::Result<()> {
+    writer.write(
+        CS_ENDPOINT,
+        &[
+            MS_GENERAL, // bDescriptorSubtype
+            0x01,       // bNumEmbMIDIJack
+            jack,       // baAssocJackID
+        ],
+    )
+}
+
+/// MIDIStreaming interface added by `AudioClassBuilder::midi()`
+pub(crate) struct MidiStreaming<'a, B: UsbBus> {
+    pub(crate) interface: InterfaceNumber,
+    /// bulk endpoint receiving events from the host
+    ep_out: Endpoint<'a, B, Out>,
+    /// bulk endpoint sending events to the host
+    ep_in: Endpoint<'a, B, In>,
+}
+
+impl<'a, B: UsbBus> MidiStreaming<'a, B> {
+    pub(crate) fn new(alloc: &'a UsbBusAllocator<B>, high_speed: bool) -> Self {
+        let size = if high_speed {
+            MAX_PACKET_SIZE_HS
+        } else {
+            MAX_PACKET_SIZE
+        };
+        MidiStreaming {
+            interface: alloc.interface(),
+            ep_out: alloc.bulk(size),
+            ep_in: alloc.bulk(size),
+        }
+    }
+
+    pub(crate) fn write_descriptors<W: DescriptorSink>(
+        &self,
+        writer: &mut W,
+    ) -> usb_device::Result<()> {
+        // Standard MS Interface Descriptor
+        writer.interface(self.interface, AUDIO, MIDISTREAMING, 0x00)?;
+
+        // Class-specific MS Interface Header Descriptor (7 bytes)
+        let total_length = MS_HEADER_SIZE
+            + 2 * (IN_JACK_SIZE + OUT_JACK_SIZE)
+            + 2 * (ENDPOINT_SIZE + CS_ENDPOINT_SIZE);
+        writer.write(
+            CS_INTERFACE,
+            &[
+                MS_HEADER, // bDescriptorSubtype
+                0x00,
+                0x01, // bcdMSC
+                total_length as u8,
+                (total_length >> 8) as u8, // wTotalLength
+            ],
+        )?;
+
+        // MIDI IN Jack Descriptors (6 bytes)
+        for (jack_type, id) in [(EMBEDDED, ID_EMBEDDED_IN), (EXTERNAL, ID_EXTERNAL_IN)] {
+            writer.write(
+                CS_INTERFACE,
+                &[
+                    MIDI_IN_JACK, // bDescriptorSubtype
+                    jack_type,    // bJackType
+                    id,           // bJackID
+                    0x00,         // iJack
+                ],
+            )?;
+        }
+
+        // MIDI OUT Jack Descriptors (9 bytes), each connected to the MIDI IN
+        // Jack of the opposite type
+        for (jack_type, id, source) in [
+            (EMBEDDED, ID_EMBEDDED_OUT, ID_EXTERNAL_IN),
+            (EXTERNAL, ID_EXTERNAL_OUT, ID_EMBEDDED_IN),
+        ] {
+            writer.write(
+                CS_INTERFACE,
+                &[
+                    MIDI_OUT_JACK, // bDescriptorSubtype
+                    jack_type,     // bJackType
+                    id,            // bJackID
+                    0x01,          // bNrInputPins
+                    source,        // baSourceID
+                    0x01,          // baSourcePin
+                    0x00,          // iJack
+                ],
+            )?;
+        }
+
+        // Standard MS Bulk Data Endpoint Descriptors (9 bytes), each followed
+        // by a Class-specific MS Bulk Data Endpoint Descriptor naming the
+        // embedded jack of the endpoint
+        writer.endpoint_ex(&self.ep_out, write_audio_fields)?;
+        write_cs_endpoint(writer, ID_EMBEDDED_IN)?;
+        writer.endpoint_ex(&self.ep_in, write_audio_fields)?;
+        write_cs_endpoint(writer, ID_EMBEDDED_OUT)?;
+        Ok(())
+    }
+
+    /// Read the events of the next packet received from the host
+    pub(crate) fn read(&mut self, events: &mut [MidiEvent]) -> Result<usize> {
+        let max_events = self.ep_out.max_packet_size() as usize / EVENT_SIZE;
+        if events.len() < max_events {
+            return Err(Error::BufferTooSmall);
+        }
+        let mut buf = [0u8; MAX_PACKET_SIZE_HS as usize];
+        let len = self.ep_out.read(&mut buf)?;
+        let mut count = 0;
+        for packet in buf[..len].chunks_exact(EVENT_SIZE) {
+            // skip the padding of a packet
+            if packet == [0; EVENT_SIZE] {
+                continue;
+            }
+            let mut event = [0; EVENT_SIZE];
+            event.copy_from_slice(packet);
+            events[count] = MidiEvent(event);
+            count += 1;
+        }
+        Ok(count)
+    }
+
+    /// Send as many events as fit into a packet and return their number
+    pub(crate) fn write(&mut self, events: &[MidiEvent]) -> Result<usize> {
+        let max_events = self.ep_in.max_packet_size() as usize / EVENT_SIZE;
+        let count = events.len().min(max_events);
+        let mut buf = [0u8; MAX_PACKET_SIZE_HS as usize];
+        for (packet, event) in buf.chunks_exact_mut(EVENT_SIZE).zip(&events[..count]) {
+            packet.copy_from_slice(&event.0);
+        }
+        self.ep_in.write(&buf[..count * EVENT_SIZE])?;
+        Ok(count)
+    }
+}
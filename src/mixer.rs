@@ -0,0 +1,256 @@
+//! State of a Mixer Unit that mixes the sources of input streams into the
+//! signal path of an output stream (monitor mix)
+//!
+//! The input channels of the Mixer Unit are numbered across its input pins,
+//! starting with the channels of the output stream itself (input pin 1). Each
+//! combination of an input channel and an output channel is a mixer control
+//! whose gain is programmable by the host.
+
+use crate::class_codes::{GET_CUR, GET_MAX, GET_MIN, GET_RES, SET_CUR};
+use crate::class_codes_v2::{CUR, MU_MIXER_CONTROL, RANGE};
+use crate::{accept_param, AudioStream, VolumeRange, MAX_STREAMS};
+use usb_device::class_prelude::*;
+use usb_device::endpoint::EndpointDirection;
+
+/// Maximum number of mixer controls (input channels times output channels)
+pub(crate) const MAX_MIXER_NODES: usize = 64;
+
+/// Setting of a mixer control that disconnects the input channel (-∞ dB)
+pub(crate) const GAIN_OFF: i16 = i16::MIN;
+
+/// Range of the mixer controls in 1/256 dB
+pub(crate) const MIXER_RANGE: VolumeRange = VolumeRange {
+    min: -127 * 256,
+    max: 0,
+    res: 256,
+};
+
+pub(crate) struct Mixer {
+    /// IDs of the entities connected to input pins 2 and higher
+    sources: [u8; MAX_STREAMS],
+    /// number of channels of the input pins
+    pin_channels: [u8; MAX_STREAMS + 1],
+    num_pins: u8,
+    out_channels: u8,
+    /// gains of the mixer controls in 1/256 dB, indexed by node
+    gains: [i16; MAX_MIXER_NODES],
+    /// bitmap of nodes whose changes have not yet been retrieved by the
+    /// application
+    pending_events: u64,
+}
+
+impl Mixer {
+    /// Create a mixer that passes the output stream through unchanged
+    pub(crate) fn new(channels: u8) -> Self {
+        let mut pin_channels = [0; MAX_STREAMS + 1];
+        pin_channels[0] = channels;
+        let mut mixer = Mixer {
+            sources: [0; MAX_STREAMS],
+            pin_channels,
+            num_pins: 1,
+            out_channels: channels,
+            gains: [GAIN_OFF; MAX_MIXER_NODES],
+            pending_events: 0,
+        };
+        for channel in 1..=channels {
+            if let Some(node) = mixer.node(channel, channel) {
+                mixer.gains[node] = 0;
+            }
+        }
+        mixer
+    }
+
+    /// Connect an entity with the indicated number of channels to the next
+    /// input pin. Returns false if the mixer would have too many controls.
+    pub(crate) fn add_source(&mut self, id: u8, channels: u8) -> bool {
+        let in_channels = self.in_channels() as usize + channels as usize;
+        if in_channels * self.out_channels as usize > MAX_MIXER_NODES {
+            return false;
+        }
+        let pin = self.num_pins as usize;
+        self.sources[pin - 1] = id;
+        self.pin_channels[pin] = channels;
+        self.num_pins += 1;
+        true
+    }
+
+    /// Number of input pins
+    pub(crate) fn num_pins(&self) -> u8 {
+        self.num_pins
+    }
+
+    /// IDs of the entities connected to input pins 2 and higher
+    pub(crate) fn sources(&self) -> &[u8] {
+        &self.sources[..self.num_pins as usize - 1]
+    }
+
+    /// Total number of input channels of all input pins
+    pub(crate) fn in_channels(&self) -> u8 {
+        self.pin_channels.iter().sum()
+    }
+
+    /// Number of mixer controls
+    pub(crate) fn num_nodes(&self) -> usize {
+        self.in_channels() as usize * self.out_channels as usize
+    }
+
+    /// Index of the mixer control connecting an input channel with an output
+    /// channel (both 1..n)
+    pub(crate) fn node(&self, in_channel: u8, out_channel: u8) -> Option<usize> {
+        if in_channel == 0 || in_channel > self.in_channels() {
+            return None;
+        }
+        if out_channel == 0 || out_channel > self.out_channels {
+            return None;
+        }
+        Some((in_channel as usize - 1) * self.out_channels as usize + out_channel as usize - 1)
+    }
+
+    /// Input and output channel of a mixer control
+    fn channels(&self, node: usize) -> (u8, u8) {
+        let out_channels = self.out_channels as usize;
+        (
+            (node / out_channels + 1) as u8,
+            (node % out_channels + 1) as u8,
+        )
+    }
+
+    /// Gain of the mixer control connecting an input channel with an output
+    /// channel
+    pub(crate) fn gain(&self, in_channel: u8, out_channel: u8) -> Option<i16> {
+        self.node(in_channel, out_channel)
+            .map(|node| self.gains[node])
+    }
+
+    /// Gain of the mixer control with the indicated index
+    pub(crate) fn node_gain(&self, node: usize) -> Option<i16> {
+        self.gains[..self.num_nodes()].get(node).copied()
+    }
+
+    /// Set the gain of a mixer control, limited to the range of the controls
+    /// unless the input channel is disconnected, and generate an event if the
+    /// setting changes. Returns false if the control does not exist.
+    pub(crate) fn set_node_gain(&mut self, node: usize, gain: i16) -> bool {
+        if node >= self.num_nodes() {
+            return false;
+        }
+        let gain = match gain {
+            GAIN_OFF => GAIN_OFF,
+            gain => gain.clamp(MIXER_RANGE.min, MIXER_RANGE.max),
+        };
+        if gain != self.gains[node] {
+            self.gains[node] = gain;
+            self.pending_events |= 1 << node;
+        }
+        true
+    }
+
+    /// Clear the next pending event and return the input channel, the output
+    /// channel and the gain of the corresponding mixer control
+    pub(crate) fn take_event(&mut self) -> Option<(u8, u8, i16)> {
+        if self.pending_events == 0 {
+            return None;
+        }
+        let node = self.pending_events.trailing_zeros() as usize;
+        self.pending_events &= !(1 << node);
+        let (in_channel, out_channel) = self.channels(node);
+        Some((in_channel, out_channel, self.gains[node]))
+    }
+
+    /// Write the bitmap of programmable mixer controls (bmControls or
+    /// bmMixerControls), all of which are programmable, and return its length
+    pub(crate) fn write_controls(&self, buf: &mut [u8]) -> usize {
+        let nodes = self.num_nodes();
+        let length = nodes.div_ceil(8);
+        for (i, byte) in buf[..length].iter_mut().enumerate() {
+            let bits = (nodes - 8 * i).min(8);
+            *byte = (0xff00u16 >> bits) as u8;
+        }
+        length
+    }
+}
+
+impl<B: UsbBus, D: EndpointDirection, const P: usize> AudioStream<'_, B, D, P> {
+    /// Handle a GET request addressed to the Mixer Unit of the stream
+    pub(crate) fn mixer_unit_in(&self, xfer: ControlIn<B>) {
+        let Some(mixer) = &self.mixer else {
+            xfer.reject().ok();
+            return;
+        };
+        let req = *xfer.request();
+        let node = mixer.node((req.value >> 8) as u8, req.value as u8);
+        match (req.request, node.and_then(|node| mixer.node_gain(node))) {
+            (GET_CUR, Some(gain)) => accept_param(xfer, &gain.to_le_bytes()),
+            (GET_MIN, Some(_)) => accept_param(xfer, &MIXER_RANGE.min.to_le_bytes()),
+            (GET_MAX, Some(_)) => accept_param(xfer, &MIXER_RANGE.max.to_le_bytes()),
+            (GET_RES, Some(_)) => accept_param(xfer, &MIXER_RANGE.res.to_le_bytes()),
+            _ => xfer.reject(),
+        }
+        .ok();
+    }
+
+    /// Handle a SET request addressed to the Mixer Unit of the stream
+    pub(crate) fn mixer_unit_out(&mut self, xfer: ControlOut<B>) {
+        let Some(mixer) = &mut self.mixer else {
+            xfer.reject().ok();
+            return;
+        };
+        let req = *xfer.request();
+        let node = mixer.node((req.value >> 8) as u8, req.value as u8);
+        match (req.request, node, xfer.data()) {
+            (SET_CUR, Some(node), &[lsb, msb]) => {
+                mixer.set_node_gain(node, i16::from_le_bytes([lsb, msb]));
+                xfer.accept()
+            }
+            _ => xfer.reject(),
+        }
+        .ok();
+    }
+
+    /// Handle a GET request addressed to the Mixer Unit of the stream. The
+    /// mixer controls are addressed by their number (u - 1) * m + v, where u
+    /// is the input channel, v the output channel and m the number of output
+    /// channels.
+    pub(crate) fn mixer_unit_in_v2(&self, xfer: ControlIn<B>) {
+        let Some(mixer) = &self.mixer else {
+            xfer.reject().ok();
+            return;
+        };
+        let req = *xfer.request();
+        let cs = (req.value >> 8) as u8;
+        let node = (req.value as u8 as usize).checked_sub(1);
+        match (req.request, cs, node.and_then(|node| mixer.node_gain(node))) {
+            (CUR, MU_MIXER_CONTROL, Some(gain)) => accept_param(xfer, &gain.to_le_bytes()),
+            (RANGE, MU_MIXER_CONTROL, Some(_)) => {
+                let mut buf = [0u8; 8];
+                buf[0..2].copy_from_slice(&1u16.to_le_bytes()); // wNumSubRanges
+                buf[2..4].copy_from_slice(&MIXER_RANGE.min.to_le_bytes());
+                buf[4..6].copy_from_slice(&MIXER_RANGE.max.to_le_bytes());
+                buf[6..8].copy_from_slice(&MIXER_RANGE.res.to_le_bytes());
+                xfer.accept_with(&buf)
+            }
+            _ => xfer.reject(),
+        }
+        .ok();
+    }
+
+    /// Handle a SET request addressed to the Mixer Unit of the stream
+    pub(crate) fn mixer_unit_out_v2(&mut self, xfer: ControlOut<B>) {
+        let Some(mixer) = &mut self.mixer else {
+            xfer.reject().ok();
+            return;
+        };
+        let req = *xfer.request();
+        let cs = (req.value >> 8) as u8;
+        let node = (req.value as u8 as usize).checked_sub(1);
+        match (req.request, cs, node, xfer.data()) {
+            (CUR, MU_MIXER_CONTROL, Some(node), &[lsb, msb])
+                if mixer.set_node_gain(node, i16::from_le_bytes([lsb, msb])) =>
+            {
+                xfer.accept()
+            }
+            _ => xfer.reject(),
+        }
+        .ok();
+    }
+}
@@ -0,0 +1,343 @@
+//! Processing Units according to Universal Serial Bus Device Class Definition
+//! for Audio Devices, Release 1.0, section 4.3.2.6
+//!
+//! The controls of a Processing Unit are addressed by control selectors whose
+//! meaning depends on the process type. Their values are held on behalf of
+//! the application, which applies them to its signal processing.
+
+use crate::class_codes::{
+    COMPRESSION_RATE_CONTROL, DDD_ENABLE_CONTROL, DDD_SPACIOUSNESS_CONTROL,
+    DDD_STEREO_EXTENDER_PROCESS, DOLBY_PROLOGIC_PROCESS, DP_ENABLE_CONTROL, DP_MODE_SELECT_CONTROL,
+    DR_ENABLE_CONTROL, DYN_RANGE_COMP_PROCESS, GET_CUR, GET_MAX, GET_MIN, GET_RES, MAXAMPL_CONTROL,
+    SET_CUR, THRESHOLD_CONTROL,
+};
+use crate::{accept_param, AudioStream, ChannelConfig, VolumeRange};
+use usb_device::class_prelude::*;
+use usb_device::endpoint::EndpointDirection;
+
+// Dynamic Range Compressor control selectors missing in class_codes
+const ATTACK_TIME_CONTROL: u8 = 0x05;
+const RELEASE_TIME_CONTROL: u8 = 0x06;
+
+/// Maximum length of the process-specific part of a descriptor of at most 255
+/// bytes
+const MAX_SPECIFIC_LENGTH: usize = 255 - 15;
+
+/// Highest control selector of the supported process types
+const MAX_SELECTOR: usize = 6;
+
+/// Spatial locations supported by a Dolby Prologic decoder (Left, Right,
+/// Center and Surround)
+const PROLOGIC_LOCATIONS: u32 = ChannelConfig::FRONT_LEFT.bits()
+    | ChannelConfig::FRONT_RIGHT.bits()
+    | ChannelConfig::FRONT_CENTER.bits()
+    | ChannelConfig::BACK_CENTER.bits();
+
+/// Processing Unit in the signal path of a stream (USB Audio Class 1.0 only).
+/// All controls of a process type are programmable by the host and are
+/// initially set to the values indicated for the `ProcessingControl` variants.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum ProcessingUnit<'a> {
+    /// Dolby Prologic decoder with the indicated operating modes, each of
+    /// which is a combination of spatial locations among Left, Right, Center
+    /// and Surround that includes Left and Right. The locations must be part
+    /// of the channel configuration of the stream. Controls: `Enable`, `Mode`.
+    DolbyProLogic(&'a [ChannelConfig]),
+    /// 3D Stereo Extender operating on the Left and Right channels. Controls:
+    /// `Enable`, `Spaciousness`.
+    StereoExtender,
+    /// Dynamic Range Compressor. Controls: `Enable`, `CompressionRatio`,
+    /// `MaxAmplitude`, `Threshold`, `AttackTime`, `ReleaseTime`.
+    DynamicRangeCompressor,
+}
+
+/// Controls of a Processing Unit. The values are reported as `i16`.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum ProcessingControl {
+    /// Processing enabled (1) or bypassed (0), initially 1
+    Enable,
+    /// Selected operating mode (1..n), initially 1
+    Mode,
+    /// Spaciousness in percent (0..255), initially 100
+    Spaciousness,
+    /// Compression ratio in 1/256 dB/dB (1:1 to 20:1 in steps of 0.25),
+    /// initially 1:1
+    CompressionRatio,
+    /// Maximum output amplitude in 1/256 dB (-127 dB to 0 dB in steps of 1 dB),
+    /// initially 0 dB
+    MaxAmplitude,
+    /// Threshold above which the compression applies in 1/256 dB (-127 dB to
+    /// 0 dB in steps of 1 dB), initially -20 dB
+    Threshold,
+    /// Attack time in 1/256 ms (1 ms to 100 ms in steps of 1 ms), initially
+    /// 5 ms
+    AttackTime,
+    /// Release time in 1/256 ms (1 ms to 127 ms in steps of 1 ms), initially
+    /// 50 ms
+    ReleaseTime,
+}
+
+impl ProcessingControl {
+    /// Range of the control or `None` for the Enable control, which only
+    /// supports GET_CUR
+    fn range(self, modes: u8) -> Option<VolumeRange> {
+        let (min, max, res) = match self {
+            ProcessingControl::Enable => return None,
+            ProcessingControl::Mode => (1, modes as i16, 1),
+            ProcessingControl::Spaciousness => (0, 255, 1),
+            ProcessingControl::CompressionRatio => (256, 20 * 256, 64),
+            ProcessingControl::MaxAmplitude => (-127 * 256, 0, 256),
+            ProcessingControl::Threshold => (-127 * 256, 0, 256),
+            ProcessingControl::AttackTime => (256, 100 * 256, 256),
+            ProcessingControl::ReleaseTime => (256, 127 * 256, 256),
+        };
+        Some(VolumeRange { min, max, res })
+    }
+
+    fn initial_value(self) -> i16 {
+        match self {
+            ProcessingControl::Enable | ProcessingControl::Mode => 1,
+            ProcessingControl::Spaciousness => 100,
+            ProcessingControl::CompressionRatio => 256,
+            ProcessingControl::MaxAmplitude => 0,
+            ProcessingControl::Threshold => -20 * 256,
+            ProcessingControl::AttackTime => 5 * 256,
+            ProcessingControl::ReleaseTime => 50 * 256,
+        }
+    }
+
+    /// Size of the parameter block of the control in bytes
+    pub(crate) fn size(self) -> usize {
+        match self {
+            ProcessingControl::Enable
+            | ProcessingControl::Mode
+            | ProcessingControl::Spaciousness => 1,
+            _ => 2,
+        }
+    }
+}
+
+impl ProcessingUnit<'_> {
+    /// Process type of the Processing Unit (wProcessType)
+    pub(crate) fn process_type(&self) -> u16 {
+        match self {
+            ProcessingUnit::DolbyProLogic(_) => DOLBY_PROLOGIC_PROCESS,
+            ProcessingUnit::StereoExtender => DDD_STEREO_EXTENDER_PROCESS,
+            ProcessingUnit::DynamicRangeCompressor => DYN_RANGE_COMP_PROCESS,
+        }
+    }
+
+    /// Control addressed by a control selector
+    pub(crate) fn control(&self, selector: u8) -> Option<ProcessingControl> {
+        match (self, selector) {
+            (ProcessingUnit::DolbyProLogic(_), DP_ENABLE_CONTROL) => {
+                Some(ProcessingControl::Enable)
+            }
+            (ProcessingUnit::DolbyProLogic(_), DP_MODE_SELECT_CONTROL) => {
+                Some(ProcessingControl::Mode)
+            }
+            (ProcessingUnit::StereoExtender, DDD_ENABLE_CONTROL) => Some(ProcessingControl::Enable),
+            (ProcessingUnit::StereoExtender, DDD_SPACIOUSNESS_CONTROL) => {
+                Some(ProcessingControl::Spaciousness)
+            }
+            (ProcessingUnit::DynamicRangeCompressor, selector) => match selector {
+                DR_ENABLE_CONTROL => Some(ProcessingControl::Enable),
+                COMPRESSION_RATE_CONTROL => Some(ProcessingControl::CompressionRatio),
+                MAXAMPL_CONTROL => Some(ProcessingControl::MaxAmplitude),
+                THRESHOLD_CONTROL => Some(ProcessingControl::Threshold),
+                ATTACK_TIME_CONTROL => Some(ProcessingControl::AttackTime),
+                RELEASE_TIME_CONTROL => Some(ProcessingControl::ReleaseTime),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    /// Control selector of a control or `None` if the process type does not
+    /// have it
+    fn selector(&self, control: ProcessingControl) -> Option<u8> {
+        (1..=MAX_SELECTOR as u8).find(|&selector| self.control(selector) == Some(control))
+    }
+
+    /// Bitmap of the controls (bmControls), all of which are programmable. The
+    /// bits follow the order of the control selectors, which are not
+    /// contiguous for the 3D Stereo Extender.
+    pub(crate) fn controls(&self) -> u8 {
+        let count = (1..=MAX_SELECTOR as u8)
+            .filter(|&selector| self.control(selector).is_some())
+            .count();
+        (1 << count) - 1
+    }
+
+    /// Operating modes of the Processing Unit
+    fn modes(&self) -> &[ChannelConfig] {
+        match self {
+            ProcessingUnit::DolbyProLogic(modes) => modes,
+            _ => &[],
+        }
+    }
+
+    /// Check the Processing Unit against the channel configuration of the
+    /// stream
+    pub(crate) fn is_valid(&self, channel_config: u32) -> bool {
+        match self {
+            ProcessingUnit::DolbyProLogic(modes) => {
+                let stereo = ChannelConfig::STEREO.bits();
+                !modes.is_empty()
+                    && self.specific_length() <= MAX_SPECIFIC_LENGTH
+                    && modes.iter().all(|mode| {
+                        mode.bits() & stereo == stereo
+                            && mode.bits() & !(PROLOGIC_LOCATIONS & channel_config) == 0
+                    })
+            }
+            _ => true,
+        }
+    }
+
+    /// Length of the process-specific part of the descriptor
+    pub(crate) fn specific_length(&self) -> usize {
+        match self {
+            ProcessingUnit::DolbyProLogic(modes) => 1 + 2 * modes.len(),
+            _ => 0,
+        }
+    }
+
+    /// Write the process-specific part of the descriptor (bNrModes, waModes)
+    pub(crate) fn write_specific(&self, buf: &mut [u8]) {
+        if let ProcessingUnit::DolbyProLogic(modes) = self {
+            buf[0] = modes.len() as u8;
+            for (mode, bytes) in modes.iter().zip(buf[1..].chunks_exact_mut(2)) {
+                bytes.copy_from_slice(&(mode.bits() as u16).to_le_bytes());
+            }
+        }
+    }
+}
+
+/// Current values of the controls of a Processing Unit
+pub(crate) struct Processing<'a> {
+    pub(crate) unit: ProcessingUnit<'a>,
+    /// values of the controls, indexed by control selector
+    values: [i16; MAX_SELECTOR + 1],
+    /// bitmap of control selectors whose changes have not yet been retrieved
+    /// by the application
+    pending_events: u8,
+}
+
+impl<'a> Processing<'a> {
+    pub(crate) fn new(unit: ProcessingUnit<'a>) -> Self {
+        let mut values = [0; MAX_SELECTOR + 1];
+        for (selector, value) in values.iter_mut().enumerate() {
+            if let Some(control) = unit.control(selector as u8) {
+                *value = control.initial_value();
+            }
+        }
+        Processing {
+            unit,
+            values,
+            pending_events: 0,
+        }
+    }
+
+    /// Range of a control or `None` if it only supports GET_CUR
+    pub(crate) fn range(&self, control: ProcessingControl) -> Option<VolumeRange> {
+        control.range(self.unit.modes().len() as u8)
+    }
+
+    /// Current value of a control or `None` if the process type does not have
+    /// it
+    pub(crate) fn value(&self, control: ProcessingControl) -> Option<i16> {
+        self.unit
+            .selector(control)
+            .map(|selector| self.values[selector as usize])
+    }
+
+    /// Set a control to a value in its range and generate an event if the
+    /// value changes. Returns false if the value is out of range.
+    pub(crate) fn set_value(&mut self, selector: u8, value: i16) -> bool {
+        let Some(control) = self.unit.control(selector) else {
+            return false;
+        };
+        let valid = match self.range(control) {
+            Some(range) => (range.min..=range.max).contains(&value),
+            None => value == 0 || value == 1,
+        };
+        if !valid {
+            return false;
+        }
+        if value != self.values[selector as usize] {
+            self.values[selector as usize] = value;
+            self.pending_events |= 1 << selector;
+        }
+        true
+    }
+
+    /// Clear the next pending event and return the control and its value
+    pub(crate) fn take_event(&mut self) -> Option<(ProcessingControl, i16)> {
+        if self.pending_events == 0 {
+            return None;
+        }
+        let selector = self.pending_events.trailing_zeros() as u8;
+        self.pending_events &= !(1 << selector);
+        let control = self.unit.control(selector)?;
+        Some((control, self.values[selector as usize]))
+    }
+}
+
+impl<B: UsbBus, D: EndpointDirection, const P: usize> AudioStream<'_, B, D, P> {
+    /// Handle a GET request addressed to the Processing Unit of the stream
+    pub(crate) fn processing_unit_in(&self, xfer: ControlIn<B>) {
+        let Some(processing) = &self.processing else {
+            xfer.reject().ok();
+            return;
+        };
+        let req = *xfer.request();
+        let control = processing.unit.control((req.value >> 8) as u8);
+        // the controls are not addressed per channel
+        let Some(control) = control.filter(|_| req.value as u8 == 0) else {
+            xfer.reject().ok();
+            return;
+        };
+        let value = match (req.request, processing.range(control)) {
+            (GET_CUR, _) => processing.value(control),
+            (GET_MIN, Some(range)) => Some(range.min),
+            (GET_MAX, Some(range)) => Some(range.max),
+            (GET_RES, Some(range)) => Some(range.res),
+            _ => None,
+        };
+        match value {
+            Some(value) => accept_param(xfer, &value.to_le_bytes()[..control.size()]),
+            None => xfer.reject(),
+        }
+        .ok();
+    }
+
+    /// Handle a SET request addressed to the Processing Unit of the stream
+    pub(crate) fn processing_unit_out(&mut self, xfer: ControlOut<B>) {
+        let Some(processing) = &mut self.processing else {
+            xfer.reject().ok();
+            return;
+        };
+        let req = *xfer.request();
+        let selector = (req.value >> 8) as u8;
+        let size = processing
+            .unit
+            .control(selector)
+            .map(|control| control.size());
+        let value = match *xfer.data() {
+            [value] => Some(value as i16),
+            [lsb, msb] => Some(i16::from_le_bytes([lsb, msb])),
+            _ => None,
+        };
+        match (req.request, req.value as u8, value) {
+            (SET_CUR, 0, Some(value))
+                if size == Some(xfer.data().len()) && processing.set_value(selector, value) =>
+            {
+                xfer.accept()
+            }
+            _ => xfer.reject(),
+        }
+        .ok();
+    }
+}
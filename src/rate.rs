@@ -0,0 +1,202 @@
+//! Estimation of the effective sampling rate of the host
+//!
+//! The clock of the host and the local audio clock drift apart by up to a few
+//! hundred ppm. A `RateEstimator` counts the audio frames received from the
+//! host against a free-running local timer, e.g. a timer clocked by the audio
+//! master clock or a cycle counter, and averages the rate measured over
+//! windows of packets. The estimate gives the ratio of an asynchronous sample
+//! rate converter and the feedback value of an asynchronous output stream
+//! clocked by the local timebase.
+//!
+//! ```
+//! use usbd_audio::rate::RateEstimator;
+//!
+//! // 1 MHz local timer, nominal rate of 48 kHz
+//! let mut estimator = RateEstimator::new(48000, 1_000_000).with_window(100);
+//! let mut ticks = 0u32;
+//! for _ in 0..=300 {
+//!     // the host sends 48 frames each 1 ms, which the local timer measures
+//!     // as 1000.5 µs
+//!     estimator.packet(48, ticks / 2);
+//!     ticks = ticks.wrapping_add(2001);
+//! }
+//! assert_eq!(estimator.rate(), 47976);
+//! // the local clock is faster than the clock of the host, so the host shall
+//! // send more samples per frame
+//! assert_eq!(estimator.feedback_q10_14(), 0xC0189);
+//! ```
+
+use crate::feedback::div_round;
+
+/// Default number of packets of a measurement window
+const DEFAULT_WINDOW: u16 = 128;
+/// Default weight of a measurement in the average as a power of two
+const DEFAULT_AVERAGING: u8 = 3;
+/// Largest averaging shift, beyond which the estimate hardly moves
+const MAX_AVERAGING: u8 = 16;
+/// Largest value that fits into the three bytes of a 10.14 feedback value
+const Q10_14_MAX: u128 = 0xFF_FFFF;
+
+/// Estimator of the rate at which the host sends audio frames, measured in
+/// the local timebase
+#[derive(Clone, Debug)]
+pub struct RateEstimator {
+    /// sampling rate selected by the host in samples/second
+    nominal: u32,
+    /// frequency of the local timer in Hz
+    timebase: u32,
+    /// number of packets per measurement
+    window: u16,
+    /// weight 2^-averaging of a measurement in the average
+    averaging: u8,
+    /// local time at the end of the previous measurement
+    start: Option<u32>,
+    /// packets and audio frames received since `start`
+    packets: u16,
+    frames: u64,
+    /// averaged rate in samples/second in 16.16 format or `None` before the
+    /// first measurement
+    estimate: Option<u64>,
+}
+
+impl RateEstimator {
+    /// Create an estimator of a stream with the sampling rate `nominal` in
+    /// samples/second, measured by a local timer counting at `timebase` Hz.
+    /// Until the first measurement, the estimate is the nominal rate.
+    pub fn new(nominal: u32, timebase: u32) -> Self {
+        RateEstimator {
+            nominal,
+            timebase,
+            window: DEFAULT_WINDOW,
+            averaging: DEFAULT_AVERAGING,
+            start: None,
+            packets: 0,
+            frames: 0,
+            estimate: None,
+        }
+    }
+
+    /// Measure the rate over windows of `packets` packets instead of 128.
+    /// Longer windows improve the resolution, which is one frame per window,
+    /// and reduce the influence of jitter of the arrival times, but delay the
+    /// reaction to changes. The window must not exceed the wraparound period
+    /// of the timer.
+    pub fn with_window(self, packets: u16) -> Self {
+        RateEstimator {
+            window: packets.max(1),
+            ..self
+        }
+    }
+
+    /// Average the measurements with an exponential moving average weighting
+    /// each measurement by 2^-`shift`, instead of 2^-3. `shift` 0 disables the
+    /// averaging; it is limited to 16.
+    pub fn with_averaging(self, shift: u8) -> Self {
+        RateEstimator {
+            averaging: shift.min(MAX_AVERAGING),
+            ..self
+        }
+    }
+
+    /// Restart the estimation at the nominal rate `nominal`, e.g. when the
+    /// host selects another sampling rate or restarts the stream
+    pub fn reset(&mut self, nominal: u32) {
+        *self = RateEstimator::new(nominal, self.timebase)
+            .with_window(self.window)
+            .with_averaging(self.averaging);
+    }
+
+    /// Account for a packet of `frames` audio frames, e.g. `PacketInfo::frames`,
+    /// received at the local time `ticks` of the wrapping timer. The arrival
+    /// time of the first packet starts the measurement, so only the frames of
+    /// the following packets are counted.
+    pub fn packet(&mut self, frames: usize, ticks: u32) {
+        let Some(start) = self.start else {
+            self.start = Some(ticks);
+            return;
+        };
+        self.frames += frames as u64;
+        self.packets += 1;
+        if self.packets < self.window {
+            return;
+        }
+        let elapsed = ticks.wrapping_sub(start);
+        let frames = self.frames;
+        self.start = Some(ticks);
+        self.packets = 0;
+        self.frames = 0;
+        if elapsed == 0 {
+            return;
+        }
+        let rate = div_round_u128(
+            ((frames as u128) * self.timebase as u128) << 16,
+            elapsed as u128,
+        ) as u64;
+        self.estimate = Some(match self.estimate {
+            Some(estimate) => {
+                let delta = (rate as i64 - estimate as i64) >> self.averaging;
+                estimate.saturating_add_signed(delta)
+            }
+            None => rate,
+        });
+    }
+
+    /// Whether at least one measurement window has completed, so that the
+    /// estimate no longer is the nominal rate
+    pub fn is_measured(&self) -> bool {
+        self.estimate.is_some()
+    }
+
+    /// Estimated rate in samples/second in 16.16 format
+    fn estimate(&self) -> u64 {
+        self.estimate.unwrap_or((self.nominal as u64) << 16)
+    }
+
+    /// Estimated rate of the host in samples per second of the local
+    /// timebase, rounded to the nearest integer
+    pub fn rate(&self) -> u32 {
+        div_round(self.estimate(), 1 << 16) as u32
+    }
+
+    /// Ratio of the estimated to the nominal rate in 16.16 format, i.e. the
+    /// number of frames of the host an asynchronous sample rate converter
+    /// consumes per frame of the local clock running at the nominal rate.
+    /// Returns 0 if the nominal rate is 0.
+    pub fn ratio(&self) -> u32 {
+        match self.nominal {
+            0 => 0,
+            nominal => div_round(self.estimate(), nominal as u64).min(u32::MAX as u64) as u32,
+        }
+    }
+
+    /// Full-speed feedback value in 10.14 format of an output stream consumed
+    /// at the nominal rate of the local timebase, i.e. the number of samples
+    /// the host shall send per frame of its own clock
+    pub fn feedback_q10_14(&self) -> u32 {
+        self.feedback(14, 1000).min(Q10_14_MAX) as u32
+    }
+
+    /// High-speed feedback value in 16.16 format of an output stream consumed
+    /// at the nominal rate of the local timebase
+    pub fn feedback_q16_16(&self) -> u32 {
+        self.feedback(16, 8000).min(u32::MAX as u128) as u32
+    }
+
+    /// Samples consumed locally per (micro)frame of the host, which is the
+    /// nominal rate scaled by the ratio of the host to the local clock, i.e.
+    /// nominal / (estimate / nominal), in a fixed-point format with `bits`
+    /// fractional bits
+    fn feedback(&self, bits: u32, frames_per_second: u128) -> u128 {
+        let nominal = self.nominal as u128;
+        let divisor = self.estimate() as u128 * frames_per_second;
+        if divisor == 0 {
+            return u128::MAX;
+        }
+        div_round_u128((nominal * nominal) << (bits + 16), divisor)
+    }
+}
+
+/// Divide and round to the nearest integer, ties rounded up
+fn div_round_u128(dividend: u128, divisor: u128) -> u128 {
+    (dividend + divisor / 2) / divisor
+}
@@ -0,0 +1,460 @@
+//! Typed audio samples for `AudioClass::write_samples()`,
+//! `AudioClass::read_frames()` and `AudioClass::read_channels()`
+//!
+//! The data endpoints carry little-endian subframes. The `Sample` trait packs
+//! the samples of the application into this representation and unpacks
+//! received subframes, so that tables of e.g. `i16` values can be written and
+//! read without reinterpreting them as bytes.
+//!
+//! The same conversions are available for slices, e.g. to fill a packet
+//! buffer that is passed to `AudioClass::write()`. `pack_samples()` and
+//! `unpack_samples()` transfer samples whose size matches the subframe size,
+//! while `pack_i16()`, `pack_i32()`, `unpack_i16()` and `unpack_i32()` convert
+//! full-scale `i16` or `i32` samples from and to the subframes of a
+//! `Format`, e.g. the 16 bit samples of a codec into `Format::S24le`.
+//!
+//! Codecs and DSP code often process one buffer per channel. `interleave()`
+//! and `deinterleave()` convert between such planar buffers and the
+//! interleaved frames on the bus for any number of channels and subframe
+//! size, copying whole subframes at a time, and `pack_channels()` and
+//! `unpack_channels()` do the same for typed samples.
+//!
+//! ```
+//! use usbd_audio::sample::{pack_i16, unpack_i32};
+//! use usbd_audio::Format;
+//!
+//! let mut buf = [0u8; 6];
+//! assert_eq!(pack_i16(&[0x1234, -2], Format::S24le, &mut buf), Ok(6));
+//! assert_eq!(buf, [0x00, 0x34, 0x12, 0x00, 0xfe, 0xff]);
+//! let mut samples = [0i32; 2];
+//! assert_eq!(unpack_i32(&buf, Format::S24le, &mut samples), Ok(2));
+//! assert_eq!(samples, [0x1234_0000, -2 << 16]);
+//! ```
+
+use crate::{Error, Format, Result, StreamFormat};
+use core::marker::PhantomData;
+use core::slice::ChunksExact;
+
+/// Audio sample that occupies one subframe of `SIZE` bytes on the bus
+pub trait Sample: Copy {
+    /// Subframe size in bytes
+    const SIZE: usize;
+
+    /// Write the sample in little-endian byte order to `buf`, which has a
+    /// length of `SIZE`
+    fn write_le(self, buf: &mut [u8]);
+
+    /// Read a sample in little-endian byte order from `buf`, which has a
+    /// length of `SIZE`
+    fn read_le(buf: &[u8]) -> Self;
+}
+
+impl Sample for i16 {
+    const SIZE: usize = 2;
+
+    fn write_le(self, buf: &mut [u8]) {
+        buf.copy_from_slice(&self.to_le_bytes());
+    }
+
+    fn read_le(buf: &[u8]) -> Self {
+        i16::from_le_bytes([buf[0], buf[1]])
+    }
+}
+
+impl Sample for i32 {
+    const SIZE: usize = 4;
+
+    fn write_le(self, buf: &mut [u8]) {
+        buf.copy_from_slice(&self.to_le_bytes());
+    }
+
+    fn read_le(buf: &[u8]) -> Self {
+        i32::from_le_bytes([buf[0], buf[1], buf[2], buf[3]])
+    }
+}
+
+/// Signed 24 bit sample stored in the lower 24 bits of an `i32`, transmitted
+/// in 3 byte subframes (`Format::S24le`)
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct I24(pub i32);
+
+impl Sample for I24 {
+    const SIZE: usize = 3;
+
+    fn write_le(self, buf: &mut [u8]) {
+        buf.copy_from_slice(&self.0.to_le_bytes()[..3]);
+    }
+
+    fn read_le(buf: &[u8]) -> Self {
+        // sign extension by the arithmetic shift
+        I24(i32::from_le_bytes([0, buf[0], buf[1], buf[2]]) >> 8)
+    }
+}
+
+/// Check that the size of `S` and the number of channels match the format
+/// selected by the host, if any, before a packet is read
+pub(crate) fn check<S: Sample>(format: Option<StreamFormat>, channels: usize) -> Result<()> {
+    match format {
+        Some(format)
+            if format.format.subframe_size() as usize != S::SIZE
+                || format.channels as usize != channels =>
+        {
+            Err(Error::InvalidValue)
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Pack samples in the format selected by the host into `buf` and return the
+/// packed data. Returns `Error::InvalidValue` if the size of `S` differs from
+/// the subframe size and `Error::InvalidLength` if the number of samples is
+/// not a multiple of the number of channels. Without a selected format, the
+/// samples are packed unchecked.
+pub(crate) fn pack<'b, S: Sample>(
+    format: Option<StreamFormat>,
+    samples: &[S],
+    buf: &'b mut [u8],
+) -> Result<&'b [u8]> {
+    if let Some(format) = format {
+        let channels = format.channels as usize;
+        check::<S>(Some(format), channels)?;
+        if !samples.len().is_multiple_of(channels) {
+            return Err(Error::InvalidLength);
+        }
+    }
+    let len = pack_samples(samples, buf)?;
+    Ok(&buf[..len])
+}
+
+/// Pack `samples` into little-endian subframes of `S::SIZE` bytes in `buf` and
+/// return the number of bytes written. Returns `Error::BufferTooSmall` if
+/// `buf` cannot hold the samples.
+pub fn pack_samples<S: Sample>(samples: &[S], buf: &mut [u8]) -> Result<usize> {
+    let data = buf
+        .get_mut(..samples.len() * S::SIZE)
+        .ok_or(Error::BufferTooSmall)?;
+    for (sample, subframe) in samples.iter().zip(data.chunks_exact_mut(S::SIZE)) {
+        sample.write_le(subframe);
+    }
+    Ok(data.len())
+}
+
+/// Unpack the little-endian subframes of `S::SIZE` bytes in `data` into
+/// `samples` and return the number of samples. An incomplete subframe at the
+/// end of `data` is ignored. Returns `Error::BufferTooSmall` if `samples` is
+/// too short.
+pub fn unpack_samples<S: Sample>(data: &[u8], samples: &mut [S]) -> Result<usize> {
+    let subframes = data.chunks_exact(S::SIZE);
+    let samples = samples
+        .get_mut(..subframes.len())
+        .ok_or(Error::BufferTooSmall)?;
+    for (sample, subframe) in samples.iter_mut().zip(subframes) {
+        *sample = S::read_le(subframe);
+    }
+    Ok(samples.len())
+}
+
+/// Size of the subframes of `format` if the conversions of full-scale samples
+/// support it
+fn pcm_subframe_size(format: Format) -> Result<usize> {
+    match format {
+        Format::S16le | Format::S24le | Format::S32le | Format::S24in32le => {
+            Ok(format.subframe_size() as usize)
+        }
+        _ => Err(Error::InvalidValue),
+    }
+}
+
+/// Pack full-scale 16 bit `samples` into the subframes of `format` in `buf`,
+/// shifting them into the most significant bits of wider subframes, and
+/// return the number of bytes written. Returns `Error::InvalidValue` if
+/// `format` is not `S16le`, `S24le`, `S32le` or `S24in32le` and
+/// `Error::BufferTooSmall` if `buf` cannot hold the samples.
+pub fn pack_i16(samples: &[i16], format: Format, buf: &mut [u8]) -> Result<usize> {
+    let size = pcm_subframe_size(format)?;
+    let data = buf
+        .get_mut(..samples.len() * size)
+        .ok_or(Error::BufferTooSmall)?;
+    let subframes = data.chunks_exact_mut(size);
+    for (&sample, subframe) in samples.iter().zip(subframes) {
+        let [low, high] = sample.to_le_bytes();
+        match format {
+            Format::S16le => subframe.copy_from_slice(&[low, high]),
+            Format::S24le => subframe.copy_from_slice(&[0, low, high]),
+            _ => subframe.copy_from_slice(&[0, 0, low, high]),
+        }
+    }
+    Ok(data.len())
+}
+
+/// Pack full-scale 32 bit `samples` into the subframes of `format` in `buf`,
+/// truncating them to the most significant bits of narrower subframes, and
+/// return the number of bytes written. Returns the errors of `pack_i16()`.
+pub fn pack_i32(samples: &[i32], format: Format, buf: &mut [u8]) -> Result<usize> {
+    let size = pcm_subframe_size(format)?;
+    let data = buf
+        .get_mut(..samples.len() * size)
+        .ok_or(Error::BufferTooSmall)?;
+    let subframes = data.chunks_exact_mut(size);
+    for (&sample, subframe) in samples.iter().zip(subframes) {
+        match format {
+            Format::S16le => subframe.copy_from_slice(&((sample >> 16) as i16).to_le_bytes()),
+            Format::S24le => subframe.copy_from_slice(&sample.to_le_bytes()[1..]),
+            Format::S24in32le => subframe.copy_from_slice(&(sample & !0xff).to_le_bytes()),
+            _ => subframe.copy_from_slice(&sample.to_le_bytes()),
+        }
+    }
+    Ok(data.len())
+}
+
+/// Unpack the subframes of `format` in `data` into full-scale 16 bit
+/// `samples`, truncating wider subframes to their most significant bits, and
+/// return the number of samples. An incomplete subframe at the end of `data`
+/// is ignored. Returns `Error::InvalidValue` if `format` is not `S16le`,
+/// `S24le`, `S32le` or `S24in32le` and `Error::BufferTooSmall` if `samples` is
+/// too short.
+pub fn unpack_i16(data: &[u8], format: Format, samples: &mut [i16]) -> Result<usize> {
+    let size = pcm_subframe_size(format)?;
+    let subframes = data.chunks_exact(size);
+    let samples = samples
+        .get_mut(..subframes.len())
+        .ok_or(Error::BufferTooSmall)?;
+    for (sample, subframe) in samples.iter_mut().zip(subframes) {
+        *sample = i16::from_le_bytes([subframe[size - 2], subframe[size - 1]]);
+    }
+    Ok(samples.len())
+}
+
+/// Unpack the subframes of `format` in `data` into full-scale 32 bit
+/// `samples`, shifting narrower subframes into the most significant bits, and
+/// return the number of samples. Returns the errors of `unpack_i16()`.
+pub fn unpack_i32(data: &[u8], format: Format, samples: &mut [i32]) -> Result<usize> {
+    let size = pcm_subframe_size(format)?;
+    let subframes = data.chunks_exact(size);
+    let samples = samples
+        .get_mut(..subframes.len())
+        .ok_or(Error::BufferTooSmall)?;
+    for (sample, subframe) in samples.iter_mut().zip(subframes) {
+        let mut bytes = [0; 4];
+        bytes[4 - size..].copy_from_slice(subframe);
+        if format == Format::S24in32le {
+            // the least significant byte only pads the subframe
+            bytes[0] = 0;
+        }
+        *sample = i32::from_le_bytes(bytes);
+    }
+    Ok(samples.len())
+}
+
+/// Distribute the frames of a received packet to one slice per channel and
+/// return their number. An incomplete frame at the end of `data` is ignored.
+/// Returns `Error::InvalidValue` without channels and `Error::BufferTooSmall`
+/// if a slice is too short for the frames.
+pub fn unpack_channels<S: Sample>(data: &[u8], channels: &mut [&mut [S]]) -> Result<usize> {
+    let frame_size = channels.len() * S::SIZE;
+    if frame_size == 0 {
+        return Err(Error::InvalidValue);
+    }
+    let frames = data.len() / frame_size;
+    if channels.iter().any(|channel| channel.len() < frames) {
+        return Err(Error::BufferTooSmall);
+    }
+    for (i, frame) in data.chunks_exact(frame_size).enumerate() {
+        for (channel, subframe) in channels.iter_mut().zip(frame.chunks_exact(S::SIZE)) {
+            channel[i] = S::read_le(subframe);
+        }
+    }
+    Ok(frames)
+}
+
+/// Interleave the samples of one slice per channel into frames in `buf` and
+/// return the number of bytes written. Returns `Error::InvalidValue` without
+/// channels, `Error::InvalidLength` if the slices differ in length and
+/// `Error::BufferTooSmall` if `buf` cannot hold the frames.
+pub fn pack_channels<S: Sample>(channels: &[&[S]], buf: &mut [u8]) -> Result<usize> {
+    let frames = planar_frames(channels.iter().map(|channel| channel.len()))?;
+    let frame_size = channels.len() * S::SIZE;
+    let data = buf
+        .get_mut(..frames * frame_size)
+        .ok_or(Error::BufferTooSmall)?;
+    for (i, frame) in data.chunks_exact_mut(frame_size).enumerate() {
+        for (channel, subframe) in channels.iter().zip(frame.chunks_exact_mut(S::SIZE)) {
+            channel[i].write_le(subframe);
+        }
+    }
+    Ok(data.len())
+}
+
+/// Number of frames held by planar buffers of the lengths `lengths`, which
+/// must be equal
+fn planar_frames(mut lengths: impl Iterator<Item = usize>) -> Result<usize> {
+    let frames = lengths.next().ok_or(Error::InvalidValue)?;
+    if lengths.any(|len| len != frames) {
+        return Err(Error::InvalidLength);
+    }
+    Ok(frames)
+}
+
+/// Interleave the subframes of `subframe_size` bytes of one byte slice per
+/// channel into frames in `buf` and return the number of bytes written.
+/// Returns `Error::InvalidValue` without channels or with a subframe size of
+/// 0, `Error::InvalidLength` if the slices differ in length or hold an
+/// incomplete subframe and `Error::BufferTooSmall` if `buf` cannot hold the
+/// frames.
+pub fn interleave(channels: &[&[u8]], subframe_size: usize, buf: &mut [u8]) -> Result<usize> {
+    if subframe_size == 0 {
+        return Err(Error::InvalidValue);
+    }
+    let len = planar_frames(channels.iter().map(|channel| channel.len()))?;
+    if !len.is_multiple_of(subframe_size) {
+        return Err(Error::InvalidLength);
+    }
+    let data = buf
+        .get_mut(..len * channels.len())
+        .ok_or(Error::BufferTooSmall)?;
+    // the constant sizes let the compiler copy whole subframes as words
+    match subframe_size {
+        2 => interleave_with(channels, 2, data),
+        3 => interleave_with(channels, 3, data),
+        4 => interleave_with(channels, 4, data),
+        size => interleave_with(channels, size, data),
+    }
+    Ok(data.len())
+}
+
+#[inline(always)]
+fn interleave_with(channels: &[&[u8]], size: usize, data: &mut [u8]) {
+    let frame_size = channels.len() * size;
+    for (c, channel) in channels.iter().enumerate() {
+        let offset = c * size;
+        for (frame, subframe) in data
+            .chunks_exact_mut(frame_size)
+            .zip(channel.chunks_exact(size))
+        {
+            frame[offset..offset + size].copy_from_slice(subframe);
+        }
+    }
+}
+
+/// Distribute the frames of subframes of `subframe_size` bytes in `data` to
+/// one byte slice per channel and return the number of frames. An incomplete
+/// frame at the end of `data` is ignored. Returns `Error::InvalidValue`
+/// without channels or with a subframe size of 0 and `Error::BufferTooSmall`
+/// if a slice is too short for the frames.
+pub fn deinterleave(
+    data: &[u8],
+    subframe_size: usize,
+    channels: &mut [&mut [u8]],
+) -> Result<usize> {
+    let frame_size = channels.len() * subframe_size;
+    if frame_size == 0 {
+        return Err(Error::InvalidValue);
+    }
+    let frames = data.len() / frame_size;
+    if channels
+        .iter()
+        .any(|channel| channel.len() < frames * subframe_size)
+    {
+        return Err(Error::BufferTooSmall);
+    }
+    let data = &data[..frames * frame_size];
+    match subframe_size {
+        2 => deinterleave_with(data, 2, channels),
+        3 => deinterleave_with(data, 3, channels),
+        4 => deinterleave_with(data, 4, channels),
+        size => deinterleave_with(data, size, channels),
+    }
+    Ok(frames)
+}
+
+#[inline(always)]
+fn deinterleave_with(data: &[u8], size: usize, channels: &mut [&mut [u8]]) {
+    let frame_size = channels.len() * size;
+    for (c, channel) in channels.iter_mut().enumerate() {
+        let offset = c * size;
+        for (subframe, frame) in channel
+            .chunks_exact_mut(size)
+            .zip(data.chunks_exact(frame_size))
+        {
+            subframe.copy_from_slice(&frame[offset..offset + size]);
+        }
+    }
+}
+
+/// Convert the audio frames in `data` from the format `from` into the format
+/// `to` in `buf`, taking for each channel of `to` the channel with the same
+/// number or the last channel of `from`, and return the number of bytes
+/// written. Frames that do not fit into `buf` are dropped. Returns `None` if
+/// the formats cannot be converted.
+pub(crate) fn convert_frames(
+    data: &[u8],
+    from: StreamFormat,
+    to: StreamFormat,
+    buf: &mut [u8],
+) -> Option<usize> {
+    let (Some(from_size), Some(to_size)) = (from.frame_size(), to.frame_size()) else {
+        return None;
+    };
+    if from_size == 0 || to_size == 0 {
+        return None;
+    }
+    let frames = (data.len() / from_size).min(buf.len() / to_size);
+    let data = &data[..frames * from_size];
+    let buf = &mut buf[..frames * to_size];
+    if from.format == to.format && from.channels == to.channels {
+        buf.copy_from_slice(data);
+        return Some(buf.len());
+    }
+    let from_subframe = pcm_subframe_size(from.format).ok()?;
+    let to_subframe = pcm_subframe_size(to.format).ok()?;
+    for (src, dst) in data
+        .chunks_exact(from_size)
+        .zip(buf.chunks_exact_mut(to_size))
+    {
+        for (channel, subframe) in dst.chunks_exact_mut(to_subframe).enumerate() {
+            let offset = channel.min(from.channels as usize - 1) * from_subframe;
+            let mut sample = [0i32];
+            unpack_i32(
+                &src[offset..offset + from_subframe],
+                from.format,
+                &mut sample,
+            )
+            .ok()?;
+            pack_i32(&sample, to.format, subframe).ok()?;
+        }
+    }
+    Some(buf.len())
+}
+
+/// Iterator over the audio frames of a received packet, each holding one
+/// sample per channel, created by `AudioClass::read_frames()`. Incomplete
+/// frames at the end of the packet are skipped.
+pub struct Frames<'b, S: Sample, const C: usize> {
+    data: ChunksExact<'b, u8>,
+    sample: PhantomData<S>,
+}
+
+impl<'b, S: Sample, const C: usize> Frames<'b, S, C> {
+    pub(crate) fn new(data: &'b [u8]) -> Self {
+        Frames {
+            data: data.chunks_exact(C * S::SIZE),
+            sample: PhantomData,
+        }
+    }
+}
+
+impl<S: Sample, const C: usize> Iterator for Frames<'_, S, C> {
+    type Item = [S; C];
+
+    fn next(&mut self) -> Option<[S; C]> {
+        let frame = self.data.next()?;
+        Some(core::array::from_fn(|i| {
+            S::read_le(&frame[i * S::SIZE..(i + 1) * S::SIZE])
+        }))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.data.size_hint()
+    }
+}
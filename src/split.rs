@@ -0,0 +1,283 @@
+//! Independent handles for the audio data of streams
+//!
+//! `AudioClass::split()` hands the data endpoints of the first input and the
+//! first output stream over to an `InputStream` and an `OutputStream`, so that
+//! capture and playback can be serviced by different tasks or interrupt
+//! priorities than the one polling the `UsbDevice`. The class keeps handling
+//! the control requests and publishes the alternate setting and the sampling
+//! rate selected by the host in a `StreamState`, which is typically a static
+//! shared by the class and the handle.
+//!
+//! ```ignore
+//! static INPUT_STATE: StreamState = StreamState::new();
+//! static OUTPUT_STATE: StreamState = StreamState::new();
+//!
+//! let (input, output) = usb_audio.split(&INPUT_STATE, &OUTPUT_STATE)?;
+//! ```
+//!
+//! The class and the handles are `Send`, so that with RTIC the class and the
+//! `UsbDevice` become local resources of the USB interrupt task and the
+//! handles local resources of the audio DMA task, without locks. The class
+//! and a handle only share the `StreamState` and the data endpoint:
+//!
+//! - The class writes the alternate setting and the sampling rate of the
+//!   `StreamState` by atomic stores with release ordering and the handle
+//!   reads them by atomic loads with acquire ordering. Only loads and stores
+//!   are used, which are available on all targets including Cortex-M0.
+//! - The endpoint is only read or written by the handle; the class merely
+//!   keeps its address for the descriptors and the completion callbacks. The
+//!   endpoint registers are accessed by `UsbBus`, which is `Sync`.
+//!
+//! The ends of the sample buffers of `AudioClass::split_buffered()` are `Send`
+//! as well and provide the same guarantees for the data.
+
+use crate::descriptor::DescriptorSink;
+use crate::sample::{self, Frames, Sample};
+use crate::{Error, Format, PacketInfo, Result, StreamFormat, StreamId, MAX_ISO_EP_SIZE_HS};
+use core::sync::atomic::{AtomicU32, AtomicU8, Ordering};
+use usb_device::class_prelude::*;
+use usb_device::descriptor::descriptor_type;
+use usb_device::endpoint::{Endpoint, EndpointDirection, In, Out};
+
+/// Value of `StreamState::rx_frame_number` without a frame number
+const NO_FRAME_NUMBER: u32 = u32::MAX;
+
+/// State of a stream published by the class to its handle
+pub struct StreamState {
+    alt_setting: AtomicU8,
+    sampling_rate: AtomicU32,
+    /// USB frame number at which the last packet was received or
+    /// `NO_FRAME_NUMBER`
+    rx_frame_number: AtomicU32,
+}
+
+impl StreamState {
+    /// Create the state of an idle stream, usable as initializer of a static
+    pub const fn new() -> Self {
+        StreamState {
+            alt_setting: AtomicU8::new(0),
+            sampling_rate: AtomicU32::new(0),
+            rx_frame_number: AtomicU32::new(NO_FRAME_NUMBER),
+        }
+    }
+
+    pub(crate) fn set_alt_setting(&self, alt_setting: u8) {
+        self.alt_setting.store(alt_setting, Ordering::Release);
+    }
+
+    pub(crate) fn set_sampling_rate(&self, rate: u32) {
+        self.sampling_rate.store(rate, Ordering::Release);
+    }
+
+    pub(crate) fn set_rx_frame_number(&self, frame_number: Option<u16>) {
+        let frame_number = frame_number.map_or(NO_FRAME_NUMBER, u32::from);
+        self.rx_frame_number.store(frame_number, Ordering::Release);
+    }
+
+    fn rx_frame_number(&self) -> Option<u16> {
+        u16::try_from(self.rx_frame_number.load(Ordering::Acquire)).ok()
+    }
+}
+
+impl Default for StreamState {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Formats and numbers of channels of the operational alternate settings of
+/// a stream
+#[derive(Clone, Copy, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub(crate) struct AltSettings<'a> {
+    pub(crate) format: Format,
+    pub(crate) channels: u8,
+    pub(crate) alt_formats: &'a [Format],
+    pub(crate) alt_channels: &'a [u8],
+}
+
+impl AltSettings<'_> {
+    /// Format and number of channels of an operational alternate setting
+    pub(crate) fn get(&self, alt_setting: u8) -> (Format, u8) {
+        let i = alt_setting.saturating_sub(2) as usize;
+        if alt_setting < 2 {
+            (self.format, self.channels)
+        } else if i < self.alt_formats.len() {
+            (self.alt_formats[i], self.channels)
+        } else {
+            (self.format, self.alt_channels[i - self.alt_formats.len()])
+        }
+    }
+}
+
+/// Fields of the standard descriptor of an endpoint, which remain with the
+/// class when the endpoint is handed over to a stream handle
+#[derive(Clone, Copy)]
+pub(crate) struct EndpointDescriptor {
+    pub(crate) address: EndpointAddress,
+    attributes: u8,
+    pub(crate) max_packet_size: u16,
+    interval: u8,
+}
+
+impl EndpointDescriptor {
+    pub(crate) fn new<B: UsbBus, D: EndpointDirection>(endpoint: &Endpoint<B, D>) -> Self {
+        EndpointDescriptor {
+            address: endpoint.address(),
+            attributes: endpoint.ep_type().to_bm_attributes(),
+            max_packet_size: endpoint.max_packet_size(),
+            interval: endpoint.interval(),
+        }
+    }
+
+    /// Write the descriptor followed by the indicated extra fields, e.g.
+    /// bRefresh and bSynchAddress of USB Audio Class 1.0. The interface
+    /// descriptor must have been written with the number of endpoints since
+    /// `DescriptorWriter` does not count them.
+    pub(crate) fn write<W: DescriptorSink>(
+        &self,
+        writer: &mut W,
+        extra: &[u8],
+    ) -> usb_device::Result<()> {
+        let mps = self.max_packet_size.to_le_bytes();
+        let mut buf = [0u8; 7];
+        let length = 5 + extra.len();
+        buf[..5].copy_from_slice(&[
+            self.address.into(), // bEndpointAddress
+            self.attributes,     // bmAttributes
+            mps[0],              // wMaxPacketSize
+            mps[1],
+            self.interval, // bInterval
+        ]);
+        buf.get_mut(5..length)
+            .ok_or(UsbError::BufferOverflow)?
+            .copy_from_slice(extra);
+        writer.write(descriptor_type::ENDPOINT, &buf[..length])
+    }
+}
+
+/// Handle for the audio data of a stream created by `AudioClass::split()`
+pub struct StreamHandle<'a, B: UsbBus, D: EndpointDirection> {
+    stream: StreamId,
+    endpoint: Endpoint<'a, B, D>,
+    alt_settings: AltSettings<'a>,
+    state: &'a StreamState,
+}
+
+/// Handle for the audio data of an input stream
+pub type InputStream<'a, B> = StreamHandle<'a, B, In>;
+
+/// Handle for the audio data of an output stream
+pub type OutputStream<'a, B> = StreamHandle<'a, B, Out>;
+
+impl<'a, B: UsbBus, D: EndpointDirection> StreamHandle<'a, B, D> {
+    pub(crate) fn new(
+        stream: StreamId,
+        endpoint: Endpoint<'a, B, D>,
+        alt_settings: AltSettings<'a>,
+        state: &'a StreamState,
+    ) -> Self {
+        StreamHandle {
+            stream,
+            endpoint,
+            alt_settings,
+            state,
+        }
+    }
+
+    /// Stream the handle belongs to
+    pub fn stream_id(&self) -> StreamId {
+        self.stream
+    }
+
+    /// Get the alternate setting currently selected by the host (0 = idle)
+    pub fn alt_setting(&self) -> u8 {
+        self.state.alt_setting.load(Ordering::Acquire)
+    }
+
+    /// Get the format, the number of channels and the sampling rate of the
+    /// alternate setting currently selected by the host or `None` if the
+    /// stream is idle (alternate setting 0)
+    pub fn stream_format(&self) -> Option<StreamFormat> {
+        match self.alt_setting() {
+            0 => None,
+            alt_setting => {
+                let (format, channels) = self.alt_settings.get(alt_setting);
+                Some(StreamFormat {
+                    format,
+                    channels,
+                    sampling_rate: self.state.sampling_rate.load(Ordering::Acquire),
+                })
+            }
+        }
+    }
+}
+
+impl<B: UsbBus> StreamHandle<'_, B, In> {
+    /// Write audio frames to be input by the host. Returns
+    /// `Error::StreamInactive` if the host has not started the stream and
+    /// `Error::InvalidLength` if the length of `data` is not a multiple of the
+    /// frame size (number of channels times subframe size) of the alternate
+    /// setting selected by the host.
+    pub fn write(&self, data: &[u8]) -> Result<usize> {
+        let format = self.stream_format().ok_or(Error::StreamInactive)?;
+        match format.frame_size() {
+            Some(size) if !data.len().is_multiple_of(size) => Err(Error::InvalidLength),
+            _ => Ok(self.endpoint.write(data)?),
+        }
+    }
+
+    /// Write samples to be input by the host. See
+    /// `AudioClass::write_stream_samples()`.
+    pub fn write_samples<S: Sample>(&self, samples: &[S]) -> Result<usize> {
+        let mut buf = [0u8; MAX_ISO_EP_SIZE_HS as usize];
+        let data = sample::pack(self.stream_format(), samples, &mut buf)?;
+        Ok(self.write(data)? / S::SIZE)
+    }
+}
+
+impl<B: UsbBus> StreamHandle<'_, B, Out> {
+    /// Read audio frames as output by the host. Returns
+    /// `Error::StreamInactive` if the host has not started the stream.
+    pub fn read(&self, data: &mut [u8]) -> Result<usize> {
+        if self.alt_setting() == 0 {
+            return Err(Error::StreamInactive);
+        }
+        Ok(self.endpoint.read(data)?)
+    }
+
+    /// Read a packet as output by the host together with its metadata. See
+    /// `AudioClass::read_stream_packet()`.
+    pub fn read_packet(&self, data: &mut [u8]) -> Result<PacketInfo> {
+        let frame_size = self
+            .stream_format()
+            .and_then(|format| format.frame_size())
+            .filter(|&size| size > 0);
+        let len = self.read(data)?;
+        Ok(PacketInfo {
+            len,
+            frames: frame_size.map(|size| len / size),
+            frame_number: self.state.rx_frame_number(),
+        })
+    }
+
+    /// Read audio frames into `buf` and iterate over them. See
+    /// `AudioClass::read_stream_frames()`.
+    pub fn read_frames<'b, S: Sample, const C: usize>(
+        &self,
+        buf: &'b mut [u8],
+    ) -> Result<Frames<'b, S, C>> {
+        sample::check::<S>(self.stream_format(), C)?;
+        let len = self.read(buf)?;
+        Ok(Frames::new(&buf[..len]))
+    }
+
+    /// Read audio frames de-interleaved into one slice per channel. See
+    /// `AudioClass::read_stream_channels()`.
+    pub fn read_channels<S: Sample>(&self, channels: &mut [&mut [S]]) -> Result<usize> {
+        sample::check::<S>(self.stream_format(), channels.len())?;
+        let mut buf = [0u8; MAX_ISO_EP_SIZE_HS as usize];
+        let len = self.read(&mut buf)?;
+        sample::unpack_channels(&buf[..len], channels)
+    }
+}
@@ -1,13 +1,15 @@
 //! USB Audio Terminal Types from Universal Serial Bus Device Class Definition
-//! for Terminal Types, Release 1.0
+//! for Terminal Types, Release 1.0 and Release 2.0
 //!
 
 /// USB Audio Terminal Types from "Universal Serial Bus Device Class Definition
-/// for Terminal Types, Release 1.0"
+/// for Terminal Types, Release 1.0". Types marked as Release 2.0 are defined
+/// for USB Audio Class 2.0 functions only.
 #[rustfmt::skip]
 #[repr(u16)]
 #[non_exhaustive]
 #[derive(Copy, Clone, Eq, PartialEq, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum TerminalType {
 
     // USB Terminal Types
@@ -34,6 +36,20 @@ pub enum TerminalType {
     OutCommunicationSpeaker         = 0x0306,
     OutLowFrequencyEffectsSpeaker   = 0x0307,
 
+    // Bi-directional Terminal Types
+    BidirUndefined                  = 0x0400,
+    BidirHandset                    = 0x0401,
+    BidirHeadset                    = 0x0402,
+    BidirSpeakerphone               = 0x0403,
+    BidirEchoSuppressingSpeakerphone = 0x0404,
+    BidirEchoCancelingSpeakerphone  = 0x0405,
+
+    // Telephony Terminal Types
+    TelUndefined                    = 0x0500,
+    TelPhoneLine                    = 0x0501,
+    TelTelephone                    = 0x0502,
+    TelDownLinePhone                = 0x0503,
+
     // External Terminal Types
     ExtUndefined                    = 0x0600,
     ExtAnalogConnector              = 0x0601,
@@ -43,6 +59,35 @@ pub enum TerminalType {
     ExtSpdifConnector               = 0x0605,
     Ext1394DaStream                 = 0x0606,
     Ext1394DvStreamSoundtrack       = 0x0607,
+    ExtAdatLightpipe                = 0x0608, // Release 2.0
+    ExtTdif                         = 0x0609, // Release 2.0
+    ExtMadi                         = 0x060a, // Release 2.0
+
+    // Embedded Function Terminal Types
+    EmbUndefined                    = 0x0700,
+    EmbLevelCalibrationNoiseSource  = 0x0701,
+    EmbEqualizationNoise            = 0x0702,
+    EmbCdPlayer                     = 0x0703,
+    EmbDat                          = 0x0704,
+    EmbDcc                          = 0x0705,
+    EmbMiniDisk                     = 0x0706,
+    EmbAnalogTape                   = 0x0707,
+    EmbPhonograph                   = 0x0708,
+    EmbVcrAudio                     = 0x0709,
+    EmbVideoDiscAudio               = 0x070a,
+    EmbDvdAudio                     = 0x070b,
+    EmbTvTunerAudio                 = 0x070c,
+    EmbSatelliteReceiverAudio       = 0x070d,
+    EmbCableTunerAudio              = 0x070e,
+    EmbDssAudio                     = 0x070f,
+    EmbRadioReceiver                = 0x0710,
+    EmbRadioTransmitter             = 0x0711,
+    EmbMultiTrackRecorder           = 0x0712,
+    EmbSynthesizer                  = 0x0713,
+    EmbPiano                        = 0x0714, // Release 2.0
+    EmbGuitar                       = 0x0715, // Release 2.0
+    EmbDrums                        = 0x0716, // Release 2.0
+    EmbOtherMusicalInstrument       = 0x0717, // Release 2.0
 }
 
 impl From<TerminalType> for u16 {
@@ -0,0 +1,800 @@
+//! In-memory `UsbBus` and host for unit tests of applications on the host
+//!
+//! `MockHost::new()` returns a `MockBus`, from which the application creates
+//! its `UsbBusAllocator`, `AudioClass` and `UsbDevice` as on the target, and a
+//! `MockHost` sharing the state of the bus. `MockHost::allocator()` creates
+//! the allocator as well and `MockHost::device()` builds a device on it. The
+//! methods of the host play the part of the USB host: they queue SETUP and
+//! data packets, take the packets written by the device and poll the device
+//! until it has handled them. This way, class-specific requests, alternate
+//! setting changes and isochronous transfers can be tested without hardware.
+//!
+//! `MockFunction` covers the setup of most tests: it builds one `AudioClass`
+//! on a configured device and addresses requests and packets by entity ID and
+//! stream.
+//!
+//! The module requires `std` and is enabled by the feature `test-util`, which
+//! is meant for the `dev-dependencies` of an application.
+//!
+//! ```
+//! use usb_device::class::UsbClass;
+//! use usb_device::control::Recipient;
+//! use usbd_audio::test_util::{MockBus, MockHost};
+//! use usbd_audio::*;
+//!
+//! let (host, alloc) = MockHost::allocator();
+//! let mut audio = AudioClassBuilder::new()
+//!     .output(
+//!         StreamConfig::new_discrete(Format::S16le, 2, &[48000], TerminalType::OutSpeaker)
+//!             .unwrap()
+//!             .with_mute_control(),
+//!     )
+//!     .build(alloc)
+//!     .unwrap();
+//! let mut dev = MockHost::device(alloc);
+//! let classes: &mut [&mut dyn UsbClass<MockBus>] = &mut [&mut audio];
+//! host.configure(&mut dev, classes).unwrap();
+//!
+//! // SET_CUR of the mute control of the Feature Unit of the first output
+//! // stream (ID 8)
+//! let index = 8 << 8 | u8::from(audio.control_interface()) as u16;
+//! let classes: &mut [&mut dyn UsbClass<MockBus>] = &mut [&mut audio];
+//! host.class_out(&mut dev, classes, Recipient::Interface, 0x01, 0x0100, index, &[1])
+//!     .unwrap();
+//! assert_eq!(audio.output_mute(), Ok(true));
+//!
+//! // select the alternate setting of the stream and send a packet
+//! let stream = StreamId::Output(0);
+//! let interface = audio.stream_interface(stream).unwrap();
+//! let endpoint = audio.data_endpoint(stream).unwrap();
+//! let classes: &mut [&mut dyn UsbClass<MockBus>] = &mut [&mut audio];
+//! host.set_interface(&mut dev, classes, interface, 1).unwrap();
+//! host.send_packet(&mut dev, classes, endpoint, &[0; 192]).unwrap();
+//! let mut data = [0; 192];
+//! assert_eq!(audio.read(&mut data), Ok(192));
+//! ```
+
+use crate::{AudioClass, AudioClassBuilder, AudioEvent, Result, StreamId};
+use std::boxed::Box;
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::vec::Vec;
+use usb_device::bus::{PollResult, UsbBus, UsbBusAllocator};
+use usb_device::class::UsbClass;
+use usb_device::class_prelude::InterfaceNumber;
+use usb_device::control::{Recipient, Request, RequestType};
+use usb_device::device::{UsbDevice, UsbDeviceBuilder, UsbVidPid};
+use usb_device::endpoint::{EndpointAddress, EndpointType};
+use usb_device::{UsbDirection, UsbError};
+
+/// Number of endpoints per direction, including endpoint 0
+const MAX_ENDPOINTS: usize = 16;
+
+/// Number of polls after which a device that keeps reporting events is
+/// considered to be stuck
+const MAX_POLLS: usize = 64;
+
+/// Stall bits of endpoint 0, see `stall_bit()`
+const EP0_IN_STALLED: u32 = 1 << MAX_ENDPOINTS;
+const EP0_OUT_STALLED: u32 = 1;
+
+/// Error of a control transfer of `MockHost`
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum TransferError {
+    /// The device stalled the transfer, i.e. rejected the request
+    Stalled,
+    /// The device did not complete the transfer
+    Incomplete,
+}
+
+/// Bus events signalled to the device by the host
+#[derive(Clone, Copy, Eq, PartialEq)]
+enum BusEvent {
+    Reset,
+    Suspend,
+    Resume,
+}
+
+#[derive(Default)]
+struct State {
+    /// last allocated endpoint numbers of each direction
+    next_in: usize,
+    next_out: usize,
+    /// maximum packet size of endpoint 0
+    ep0_size: usize,
+    setup: Option<[u8; 8]>,
+    /// packets sent by the host, oldest first
+    out: [VecDeque<Vec<u8>>; MAX_ENDPOINTS],
+    /// packets written by the device and not yet read by the host; the data
+    /// of a control transfer is collected for endpoint 0
+    in_: [Option<Vec<u8>>; MAX_ENDPOINTS],
+    /// IN endpoints whose packets have been read by the host
+    in_complete: u16,
+    /// stalled endpoints, OUT endpoints in the low half
+    stalled: u32,
+    event: Option<BusEvent>,
+    suspended: bool,
+}
+
+impl State {
+    fn pending(&self) -> bool {
+        self.setup.is_some()
+            || self.event.is_some()
+            || self.in_complete != 0
+            || self.out.iter().any(|packets| !packets.is_empty())
+    }
+}
+
+fn stall_bit(addr: EndpointAddress) -> u32 {
+    let shift = if addr.is_in() { MAX_ENDPOINTS } else { 0 };
+    1 << (addr.index() + shift)
+}
+
+/// `UsbBus` keeping the packets in memory for a `MockHost`
+pub struct MockBus {
+    state: Arc<Mutex<State>>,
+}
+
+impl MockBus {
+    fn state(&self) -> MutexGuard<'_, State> {
+        lock(&self.state)
+    }
+}
+
+fn lock(state: &Mutex<State>) -> MutexGuard<'_, State> {
+    // a panicking test does not invalidate the state for other threads
+    state.lock().unwrap_or_else(|err| err.into_inner())
+}
+
+impl UsbBus for MockBus {
+    fn alloc_ep(
+        &mut self,
+        ep_dir: UsbDirection,
+        ep_addr: Option<EndpointAddress>,
+        _ep_type: EndpointType,
+        max_packet_size: u16,
+        _interval: u8,
+    ) -> usb_device::Result<EndpointAddress> {
+        let mut state = self.state();
+        if let Some(addr) = ep_addr {
+            if addr.index() == 0 {
+                state.ep0_size = max_packet_size as usize;
+            }
+            return Ok(addr);
+        }
+        let next = match ep_dir {
+            UsbDirection::In => &mut state.next_in,
+            UsbDirection::Out => &mut state.next_out,
+        };
+        if *next + 1 >= MAX_ENDPOINTS {
+            return Err(UsbError::EndpointOverflow);
+        }
+        *next += 1;
+        Ok(EndpointAddress::from_parts(*next, ep_dir))
+    }
+
+    fn enable(&mut self) {}
+
+    fn reset(&self) {
+        let mut state = self.state();
+        state.out.iter_mut().for_each(VecDeque::clear);
+        state.in_ = Default::default();
+        state.in_complete = 0;
+        state.stalled = 0;
+    }
+
+    fn set_device_address(&self, _addr: u8) {}
+
+    fn write(&self, ep_addr: EndpointAddress, buf: &[u8]) -> usb_device::Result<usize> {
+        let mut state = self.state();
+        let index = ep_addr.index();
+        if index == 0 {
+            // the data of a control transfer is collected and each packet is
+            // acknowledged at once
+            state.in_[0]
+                .get_or_insert_with(Vec::new)
+                .extend_from_slice(buf);
+            state.in_complete |= 1;
+        } else if state.in_[index].is_some() {
+            return Err(UsbError::WouldBlock);
+        } else {
+            state.in_[index] = Some(buf.to_vec());
+        }
+        Ok(buf.len())
+    }
+
+    fn read(&self, ep_addr: EndpointAddress, buf: &mut [u8]) -> usb_device::Result<usize> {
+        let mut state = self.state();
+        let index = ep_addr.index();
+        if index == 0 {
+            if let Some(setup) = state.setup.take() {
+                buf.get_mut(..setup.len())
+                    .ok_or(UsbError::BufferOverflow)?
+                    .copy_from_slice(&setup);
+                return Ok(setup.len());
+            }
+        }
+        let packet = state.out[index].pop_front().ok_or(UsbError::WouldBlock)?;
+        buf.get_mut(..packet.len())
+            .ok_or(UsbError::BufferOverflow)?
+            .copy_from_slice(&packet);
+        Ok(packet.len())
+    }
+
+    fn set_stalled(&self, ep_addr: EndpointAddress, stalled: bool) {
+        let mut state = self.state();
+        if stalled {
+            state.stalled |= stall_bit(ep_addr);
+        } else {
+            state.stalled &= !stall_bit(ep_addr);
+        }
+    }
+
+    fn is_stalled(&self, ep_addr: EndpointAddress) -> bool {
+        self.state().stalled & stall_bit(ep_addr) != 0
+    }
+
+    fn suspend(&self) {}
+
+    fn resume(&self) {}
+
+    fn poll(&self) -> PollResult {
+        let mut state = self.state();
+        match state.event.take() {
+            Some(BusEvent::Reset) => {
+                state.suspended = false;
+                return PollResult::Reset;
+            }
+            Some(BusEvent::Suspend) => {
+                state.suspended = true;
+                return PollResult::Suspend;
+            }
+            Some(BusEvent::Resume) => {
+                state.suspended = false;
+                return PollResult::Resume;
+            }
+            None => {}
+        }
+        if state.suspended && !state.pending() {
+            return PollResult::None;
+        }
+        let ep_setup = state.setup.is_some() as u16;
+        let mut ep_out = 0;
+        for (index, packets) in state.out.iter().enumerate() {
+            if !packets.is_empty() {
+                ep_out |= 1 << index;
+            }
+        }
+        let ep_in_complete = core::mem::take(&mut state.in_complete);
+        // the device also polls its classes without any event, which gives
+        // them the chance to write buffered data
+        PollResult::Data {
+            ep_out,
+            ep_in_complete,
+            ep_setup,
+        }
+    }
+}
+
+/// Host side of a `MockBus`
+pub struct MockHost {
+    state: Arc<Mutex<State>>,
+}
+
+impl MockHost {
+    /// Create a bus and the host connected to it
+    pub fn new() -> (MockHost, MockBus) {
+        let state = Arc::new(Mutex::new(State::default()));
+        (
+            MockHost {
+                state: state.clone(),
+            },
+            MockBus { state },
+        )
+    }
+
+    /// Create a bus, the host connected to it and the allocator of the bus.
+    /// The allocator is leaked so that the classes and stream handles built
+    /// on it may be `'static`, like those of an application on the target.
+    pub fn allocator() -> (MockHost, &'static UsbBusAllocator<MockBus>) {
+        let (host, bus) = MockHost::new();
+        (host, Box::leak(Box::new(UsbBusAllocator::new(bus))))
+    }
+
+    /// Start building a device on the allocator of a bus with a test VID/PID,
+    /// e.g. to add strings or `composite_with_iads()`
+    pub fn device_builder(alloc: &UsbBusAllocator<MockBus>) -> UsbDeviceBuilder<'_, MockBus> {
+        UsbDeviceBuilder::new(alloc, UsbVidPid(0x16c0, 0x27dd))
+    }
+
+    /// Build a device on the allocator of a bus with a test VID/PID and the
+    /// default settings of `usb-device`. The classes must have been built
+    /// before.
+    pub fn device(alloc: &UsbBusAllocator<MockBus>) -> UsbDevice<'_, MockBus> {
+        MockHost::device_builder(alloc).build()
+    }
+
+    fn state(&self) -> MutexGuard<'_, State> {
+        lock(&self.state)
+    }
+
+    /// Poll the device until it has handled all packets and events of the host
+    pub fn poll(
+        &self,
+        dev: &mut UsbDevice<'_, MockBus>,
+        classes: &mut [&mut dyn UsbClass<MockBus>],
+    ) {
+        dev.poll(classes);
+        for _ in 0..MAX_POLLS {
+            if !self.state().pending() {
+                break;
+            }
+            dev.poll(classes);
+        }
+    }
+
+    /// Signal a bus reset to the device
+    pub fn reset(
+        &self,
+        dev: &mut UsbDevice<'_, MockBus>,
+        classes: &mut [&mut dyn UsbClass<MockBus>],
+    ) {
+        self.signal(dev, classes, BusEvent::Reset);
+    }
+
+    /// Suspend the bus
+    pub fn suspend(
+        &self,
+        dev: &mut UsbDevice<'_, MockBus>,
+        classes: &mut [&mut dyn UsbClass<MockBus>],
+    ) {
+        self.signal(dev, classes, BusEvent::Suspend);
+    }
+
+    /// Resume the suspended bus
+    pub fn resume(
+        &self,
+        dev: &mut UsbDevice<'_, MockBus>,
+        classes: &mut [&mut dyn UsbClass<MockBus>],
+    ) {
+        self.signal(dev, classes, BusEvent::Resume);
+    }
+
+    fn signal(
+        &self,
+        dev: &mut UsbDevice<'_, MockBus>,
+        classes: &mut [&mut dyn UsbClass<MockBus>],
+        event: BusEvent,
+    ) {
+        self.state().event = Some(event);
+        self.poll(dev, classes);
+    }
+
+    /// Perform a control transfer with a data stage from the device to the
+    /// host and return the data
+    #[allow(clippy::too_many_arguments)]
+    pub fn control_in(
+        &self,
+        dev: &mut UsbDevice<'_, MockBus>,
+        classes: &mut [&mut dyn UsbClass<MockBus>],
+        request_type: RequestType,
+        recipient: Recipient,
+        request: u8,
+        value: u16,
+        index: u16,
+        length: u16,
+    ) -> core::result::Result<Vec<u8>, TransferError> {
+        let setup = setup_packet(
+            UsbDirection::In,
+            request_type,
+            recipient,
+            request,
+            value,
+            index,
+            length,
+        );
+        self.setup(dev, classes, setup)?;
+        let data = self.state().in_[0].take().unwrap_or_default();
+        // status stage
+        self.state().out[0].push_back(Vec::new());
+        self.poll(dev, classes);
+        Ok(data)
+    }
+
+    /// Perform a control transfer with an optional data stage from the host to
+    /// the device
+    #[allow(clippy::too_many_arguments)]
+    pub fn control_out(
+        &self,
+        dev: &mut UsbDevice<'_, MockBus>,
+        classes: &mut [&mut dyn UsbClass<MockBus>],
+        request_type: RequestType,
+        recipient: Recipient,
+        request: u8,
+        value: u16,
+        index: u16,
+        data: &[u8],
+    ) -> core::result::Result<(), TransferError> {
+        let length = u16::try_from(data.len()).map_err(|_| TransferError::Incomplete)?;
+        let setup = setup_packet(
+            UsbDirection::Out,
+            request_type,
+            recipient,
+            request,
+            value,
+            index,
+            length,
+        );
+        self.setup(dev, classes, setup)?;
+        let ep0_size = self.state().ep0_size.max(1);
+        for packet in data.chunks(ep0_size) {
+            self.state().out[0].push_back(packet.to_vec());
+            self.poll(dev, classes);
+            self.check_stall()?;
+        }
+        // status stage
+        self.state().in_[0]
+            .take()
+            .map(drop)
+            .ok_or(TransferError::Incomplete)
+    }
+
+    /// Perform a class-specific control transfer from the device to the host,
+    /// e.g. a GET_CUR request of the audio class
+    #[allow(clippy::too_many_arguments)]
+    pub fn class_in(
+        &self,
+        dev: &mut UsbDevice<'_, MockBus>,
+        classes: &mut [&mut dyn UsbClass<MockBus>],
+        recipient: Recipient,
+        request: u8,
+        value: u16,
+        index: u16,
+        length: u16,
+    ) -> core::result::Result<Vec<u8>, TransferError> {
+        self.control_in(
+            dev,
+            classes,
+            RequestType::Class,
+            recipient,
+            request,
+            value,
+            index,
+            length,
+        )
+    }
+
+    /// Perform a class-specific control transfer from the host to the device,
+    /// e.g. a SET_CUR request of the audio class
+    #[allow(clippy::too_many_arguments)]
+    pub fn class_out(
+        &self,
+        dev: &mut UsbDevice<'_, MockBus>,
+        classes: &mut [&mut dyn UsbClass<MockBus>],
+        recipient: Recipient,
+        request: u8,
+        value: u16,
+        index: u16,
+        data: &[u8],
+    ) -> core::result::Result<(), TransferError> {
+        self.control_out(
+            dev,
+            classes,
+            RequestType::Class,
+            recipient,
+            request,
+            value,
+            index,
+            data,
+        )
+    }
+
+    /// Assign an address to the device and select its configuration
+    pub fn configure(
+        &self,
+        dev: &mut UsbDevice<'_, MockBus>,
+        classes: &mut [&mut dyn UsbClass<MockBus>],
+    ) -> core::result::Result<(), TransferError> {
+        let standard = RequestType::Standard;
+        let device = Recipient::Device;
+        self.control_out(
+            dev,
+            classes,
+            standard,
+            device,
+            Request::SET_ADDRESS,
+            1,
+            0,
+            &[],
+        )?;
+        self.control_out(
+            dev,
+            classes,
+            standard,
+            device,
+            Request::SET_CONFIGURATION,
+            1,
+            0,
+            &[],
+        )
+    }
+
+    /// Select an alternate setting of an interface
+    pub fn set_interface(
+        &self,
+        dev: &mut UsbDevice<'_, MockBus>,
+        classes: &mut [&mut dyn UsbClass<MockBus>],
+        interface: InterfaceNumber,
+        alt_setting: u8,
+    ) -> core::result::Result<(), TransferError> {
+        self.control_out(
+            dev,
+            classes,
+            RequestType::Standard,
+            Recipient::Interface,
+            Request::SET_INTERFACE,
+            alt_setting as u16,
+            u8::from(interface) as u16,
+            &[],
+        )
+    }
+
+    /// Send a packet to an OUT endpoint, e.g. a packet of audio data of an
+    /// isochronous transaction, and let the device handle it
+    pub fn send_packet(
+        &self,
+        dev: &mut UsbDevice<'_, MockBus>,
+        classes: &mut [&mut dyn UsbClass<MockBus>],
+        endpoint: EndpointAddress,
+        data: &[u8],
+    ) -> Result<()> {
+        if endpoint.is_in() || endpoint.index() >= MAX_ENDPOINTS {
+            return Err(UsbError::InvalidEndpoint.into());
+        }
+        self.state().out[endpoint.index()].push_back(data.to_vec());
+        self.poll(dev, classes);
+        Ok(())
+    }
+
+    /// Receive the packet the device has written to an IN endpoint, e.g. a
+    /// packet of audio data or a feedback value of an isochronous transaction,
+    /// and signal its completion to the device. Returns `None` if the device
+    /// has not written a packet.
+    pub fn receive_packet(
+        &self,
+        dev: &mut UsbDevice<'_, MockBus>,
+        classes: &mut [&mut dyn UsbClass<MockBus>],
+        endpoint: EndpointAddress,
+    ) -> Result<Option<Vec<u8>>> {
+        if endpoint.is_out() || endpoint.index() == 0 || endpoint.index() >= MAX_ENDPOINTS {
+            return Err(UsbError::InvalidEndpoint.into());
+        }
+        // give the classes the chance to write buffered data
+        self.poll(dev, classes);
+        let packet = {
+            let mut state = self.state();
+            let packet = state.in_[endpoint.index()].take();
+            if packet.is_some() {
+                state.in_complete |= 1 << endpoint.index();
+            }
+            packet
+        };
+        self.poll(dev, classes);
+        Ok(packet)
+    }
+
+    /// Send a SETUP packet and let the device handle it
+    fn setup(
+        &self,
+        dev: &mut UsbDevice<'_, MockBus>,
+        classes: &mut [&mut dyn UsbClass<MockBus>],
+        setup: [u8; 8],
+    ) -> core::result::Result<(), TransferError> {
+        {
+            let mut state = self.state();
+            state.in_[0] = None;
+            state.out[0].clear();
+            state.stalled &= !(EP0_IN_STALLED | EP0_OUT_STALLED);
+            state.setup = Some(setup);
+        }
+        self.poll(dev, classes);
+        self.check_stall()
+    }
+
+    fn check_stall(&self) -> core::result::Result<(), TransferError> {
+        if self.state().stalled & EP0_IN_STALLED != 0 {
+            return Err(TransferError::Stalled);
+        }
+        Ok(())
+    }
+}
+
+fn setup_packet(
+    direction: UsbDirection,
+    request_type: RequestType,
+    recipient: Recipient,
+    request: u8,
+    value: u16,
+    index: u16,
+    length: u16,
+) -> [u8; 8] {
+    let request_type = (direction as u8) | (request_type as u8) << 5 | recipient as u8;
+    let [value_lo, value_hi] = value.to_le_bytes();
+    let [index_lo, index_hi] = index.to_le_bytes();
+    let [length_lo, length_hi] = length.to_le_bytes();
+    [
+        request_type,
+        request,
+        value_lo,
+        value_hi,
+        index_lo,
+        index_hi,
+        length_lo,
+        length_hi,
+    ]
+}
+
+/// A configured device with a single audio function on a `MockBus`. The
+/// fields are public, so that tests call the class and the host directly
+/// where the methods do not fit. Like a failing assertion, the fixture panics
+/// if the function cannot be set up or has no stream addressed by a method.
+pub struct MockFunction {
+    /// Host of the bus
+    pub host: MockHost,
+    /// Device, configured by `new()`
+    pub dev: UsbDevice<'static, MockBus>,
+    /// Audio function of the device
+    pub audio: AudioClass<'static, MockBus>,
+}
+
+impl MockFunction {
+    /// Build the function on a new bus and let the host configure the device
+    pub fn new(builder: AudioClassBuilder<'static>) -> MockFunction {
+        let (host, alloc) = MockHost::allocator();
+        let mut audio = builder
+            .build(alloc)
+            .unwrap_or_else(|err| panic!("cannot build the function: {err:?}"));
+        let mut dev = MockHost::device(alloc);
+        let classes: &mut [&mut dyn UsbClass<MockBus>] = &mut [&mut audio];
+        if let Err(err) = host.configure(&mut dev, classes) {
+            panic!("cannot configure the device: {err:?}");
+        }
+        MockFunction { host, dev, audio }
+    }
+
+    /// Select the first operational alternate setting of a stream
+    pub fn start(&mut self, stream: StreamId) -> core::result::Result<(), TransferError> {
+        let interface = self
+            .audio
+            .stream_interface(stream)
+            .unwrap_or_else(|_| panic!("no stream {stream:?}"));
+        let classes: &mut [&mut dyn UsbClass<MockBus>] = &mut [&mut self.audio];
+        self.host
+            .set_interface(&mut self.dev, classes, interface, 1)
+    }
+
+    /// Perform a class-specific request with a data stage from the device to
+    /// the host to an entity of the AudioControl interface, e.g. GET_CUR of
+    /// a control of a Feature Unit
+    pub fn entity_in(
+        &mut self,
+        request: u8,
+        value: u16,
+        entity: u8,
+        length: u16,
+    ) -> core::result::Result<Vec<u8>, TransferError> {
+        let index = self.entity_index(entity);
+        let classes: &mut [&mut dyn UsbClass<MockBus>] = &mut [&mut self.audio];
+        self.host.class_in(
+            &mut self.dev,
+            classes,
+            Recipient::Interface,
+            request,
+            value,
+            index,
+            length,
+        )
+    }
+
+    /// Perform a class-specific request with a data stage from the host to
+    /// the device to an entity of the AudioControl interface, e.g. SET_CUR of
+    /// a control of a Feature Unit
+    pub fn entity_out(
+        &mut self,
+        request: u8,
+        value: u16,
+        entity: u8,
+        data: &[u8],
+    ) -> core::result::Result<(), TransferError> {
+        let index = self.entity_index(entity);
+        let classes: &mut [&mut dyn UsbClass<MockBus>] = &mut [&mut self.audio];
+        self.host.class_out(
+            &mut self.dev,
+            classes,
+            Recipient::Interface,
+            request,
+            value,
+            index,
+            data,
+        )
+    }
+
+    /// Perform a class-specific request with a data stage from the device to
+    /// the host to the data endpoint of a stream, e.g. GET_CUR of the
+    /// sampling frequency control of UAC 1.0
+    pub fn endpoint_in(
+        &mut self,
+        request: u8,
+        value: u16,
+        stream: StreamId,
+        length: u16,
+    ) -> core::result::Result<Vec<u8>, TransferError> {
+        let index = u8::from(self.data_endpoint(stream)) as u16;
+        let classes: &mut [&mut dyn UsbClass<MockBus>] = &mut [&mut self.audio];
+        self.host.class_in(
+            &mut self.dev,
+            classes,
+            Recipient::Endpoint,
+            request,
+            value,
+            index,
+            length,
+        )
+    }
+
+    /// Perform a class-specific request with a data stage from the host to
+    /// the device to the data endpoint of a stream, e.g. SET_CUR of the
+    /// sampling frequency control of UAC 1.0
+    pub fn endpoint_out(
+        &mut self,
+        request: u8,
+        value: u16,
+        stream: StreamId,
+        data: &[u8],
+    ) -> core::result::Result<(), TransferError> {
+        let index = u8::from(self.data_endpoint(stream)) as u16;
+        let classes: &mut [&mut dyn UsbClass<MockBus>] = &mut [&mut self.audio];
+        self.host.class_out(
+            &mut self.dev,
+            classes,
+            Recipient::Endpoint,
+            request,
+            value,
+            index,
+            data,
+        )
+    }
+
+    /// Send a packet to the data endpoint of an output stream, see
+    /// `MockHost::send_packet()`
+    pub fn send_packet(&mut self, stream: StreamId, data: &[u8]) -> Result<()> {
+        let endpoint = self.data_endpoint(stream);
+        let classes: &mut [&mut dyn UsbClass<MockBus>] = &mut [&mut self.audio];
+        self.host
+            .send_packet(&mut self.dev, classes, endpoint, data)
+    }
+
+    /// Receive the packet written to the data endpoint of an input stream, see
+    /// `MockHost::receive_packet()`
+    pub fn receive_packet(&mut self, stream: StreamId) -> Result<Option<Vec<u8>>> {
+        let endpoint = self.data_endpoint(stream);
+        let classes: &mut [&mut dyn UsbClass<MockBus>] = &mut [&mut self.audio];
+        self.host.receive_packet(&mut self.dev, classes, endpoint)
+    }
+
+    /// Take the pending events of the function
+    pub fn events(&mut self) -> Vec<AudioEvent> {
+        core::iter::from_fn(|| self.audio.poll_event()).collect()
+    }
+
+    /// wIndex of a request to an entity of the AudioControl interface
+    fn entity_index(&self, entity: u8) -> u16 {
+        (entity as u16) << 8 | u8::from(self.audio.control_interface()) as u16
+    }
+
+    fn data_endpoint(&self, stream: StreamId) -> EndpointAddress {
+        self.audio
+            .data_endpoint(stream)
+            .unwrap_or_else(|_| panic!("no stream {stream:?}"))
+    }
+}
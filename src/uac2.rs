@@ -0,0 +1,1062 @@
+//! Descriptor generation and handling of class-specific requests according to
+//! Universal Serial Bus Device Class Definition for Audio Devices, Release 2.0
+//!
+
+use crate::class_codes::{
+    AS_GENERAL, AUDIO, AUDIOCONTROL, AUDIOSTREAMING, AUTOMATIC_GAIN_CONTROL, BASS_CONTROL,
+    CS_ENDPOINT, CS_INTERFACE, EP_GENERAL, FEATURE_UNIT, FORMAT_TYPE, GRAPHIC_EQUALIZER_CONTROL,
+    HEADER, INPUT_TERMINAL, MID_CONTROL, MIXER_UNIT, MUTE_CONTROL, OUTPUT_TERMINAL, SELECTOR_UNIT,
+    TREBLE_CONTROL, VOLUME_CONTROL,
+};
+use crate::class_codes_v2::*;
+use crate::descriptor::DescriptorSink;
+use crate::split::EndpointDescriptor;
+use crate::{
+    accept_param, AudioCategory, AudioClass, AudioControlHandler, AudioStream, CopyProtectLevel,
+    Entity, Format, Iec61937, LockDelay, Rates, TerminalType, ToneControl, ID_CLOCK_MULTIPLIER,
+    ID_CLOCK_SELECTOR, ID_CLOCK_SOURCE, ID_EXTERNAL_CLOCK, ID_INPUT_TERMINAL, ID_OUTPUT_TERMINAL,
+};
+use core::convert::TryInto;
+use usb_device::class_prelude::*;
+use usb_device::endpoint::EndpointDirection;
+
+/// Size of a Class-Specific AC Interface Header Descriptor
+const AC_HEADER_SIZE: u16 = 9;
+
+/// Convert a bitmap of Feature Unit controls according to UAC 1.0 into the
+/// representation of UAC 2.0, which uses two bits per control. All controls
+/// are indicated as host programmable.
+fn controls_v2(controls: u16) -> u32 {
+    (0..16)
+        .filter(|bit| controls & (1 << bit) != 0)
+        .fold(0, |acc, bit| acc | (0b11 << (2 * bit)))
+}
+
+/// Write a sampling frequency subrange (dMIN, dMAX, dRES) of a layout 3
+/// parameter block
+fn put_subrange(buf: &mut [u8], min: u32, max: u32, res: u32) -> usb_device::Result<()> {
+    let buf = buf.get_mut(..12).ok_or(UsbError::BufferOverflow)?;
+    buf[0..4].copy_from_slice(&min.to_le_bytes());
+    buf[4..8].copy_from_slice(&max.to_le_bytes());
+    buf[8..12].copy_from_slice(&res.to_le_bytes());
+    Ok(())
+}
+
+impl Format {
+    /// Audio Data Format Type I bit allocation (bmFormats) of UAC 2.0
+    fn formats_v2(self) -> u32 {
+        match self {
+            Format::U8 => PCM8,
+            Format::F32le => IEEE_FLOAT,
+            Format::Mpeg { .. } => MPEG_FORMAT,
+            Format::Ac3 { .. } => AC3_FORMAT,
+            Format::Iec61937(Iec61937::Ac3) => IEC61937_AC3,
+            Format::Iec61937(Iec61937::Mpeg1Layer1) => IEC61937_MPEG1_LAYER1,
+            Format::Iec61937(Iec61937::Mpeg1Layer23) => IEC61937_MPEG1_LAYER2_3,
+            Format::Iec61937(Iec61937::Mpeg2Ext) => IEC61937_MPEG2_EXT,
+            Format::Iec61937(Iec61937::Mpeg2Layer1Ls) => IEC61937_MPEG2_LAYER1_LS,
+            Format::Iec61937(Iec61937::Mpeg2Layer23Ls) => IEC61937_MPEG2_LAYER2_3_LS,
+            Format::Raw { .. } => TYPE_I_RAW_DATA,
+            _ => PCM,
+        }
+    }
+}
+
+impl<B: UsbBus, D: EndpointDirection, const P: usize> AudioStream<'_, B, D, P> {
+    /// ID of the internal Clock Source of the stream or of its only Clock
+    /// Source
+    pub(crate) fn clock_id(&self) -> u8 {
+        ID_CLOCK_SOURCE + self.id_offset()
+    }
+
+    /// ID of the Clock Selector if the stream has one
+    fn clock_selector_id(&self) -> Option<u8> {
+        self.stream_config
+            .clock_selector
+            .then(|| ID_CLOCK_SELECTOR + self.id_offset())
+    }
+
+    /// ID of the external Clock Source connected to the Clock Selector if the
+    /// stream has one
+    fn external_clock_id(&self) -> Option<u8> {
+        self.stream_config
+            .clock_selector
+            .then(|| ID_EXTERNAL_CLOCK + self.id_offset())
+    }
+
+    /// ID of the Clock Multiplier if the stream has one
+    fn clock_multiplier_id(&self) -> Option<u8> {
+        self.stream_config
+            .clock_multiplier
+            .map(|_| ID_CLOCK_MULTIPLIER + self.id_offset())
+    }
+
+    /// ID of the Clock Selector or, if there is none, the Clock Source
+    fn selected_clock_id(&self) -> u8 {
+        self.clock_selector_id().unwrap_or(self.clock_id())
+    }
+
+    /// ID of the clock entity the terminals of the stream are connected to
+    fn terminal_clock_id(&self) -> u8 {
+        self.clock_multiplier_id()
+            .unwrap_or(self.selected_clock_id())
+    }
+
+    /// Frequency of the Clock Sources in Hz corresponding to a sampling rate
+    /// of the stream, i.e. before the Clock Multiplier
+    fn source_frequency(&self, rate: u32) -> u32 {
+        match self.stream_config.clock_multiplier {
+            Some((numerator, denominator)) => {
+                let frequency = rate as u64 * denominator as u64 / numerator as u64;
+                frequency.try_into().unwrap_or(u32::MAX)
+            }
+            None => rate,
+        }
+    }
+
+    /// Sampling rate of the stream corresponding to a frequency of the Clock
+    /// Sources or `None` if the Clock Multiplier does not derive an integer
+    /// rate from it
+    fn stream_rate(&self, frequency: u32) -> Option<u32> {
+        match self.stream_config.clock_multiplier {
+            Some((numerator, denominator)) => {
+                let product = frequency as u64 * numerator as u64;
+                if !product.is_multiple_of(denominator as u64) {
+                    return None;
+                }
+                (product / denominator as u64).try_into().ok()
+            }
+            None => Some(frequency),
+        }
+    }
+
+    /// Whether an entity is an external (true) or internal (false) Clock
+    /// Source of the stream, or `None` if it is no Clock Source of the stream
+    fn clock_source(&self, entity: u8) -> Option<bool> {
+        if Some(entity) == self.external_clock_id() {
+            Some(true)
+        } else if entity == self.clock_id() {
+            Some(self.stream_config.external_clock && !self.stream_config.clock_selector)
+        } else {
+            None
+        }
+    }
+
+    /// Clock entity of the stream with the indicated ID
+    pub(crate) fn clock_entity(&self, id: u8) -> Option<Entity> {
+        if let Some(external) = self.clock_source(id) {
+            Some(Entity::ClockSource(external))
+        } else if Some(id) == self.clock_multiplier_id() {
+            Some(Entity::ClockMultiplier)
+        } else if Some(id) == self.clock_selector_id() {
+            Some(Entity::ClockSelector)
+        } else {
+            None
+        }
+    }
+
+    /// Whether the host can select among several sampling rates
+    fn is_programmable(&self) -> bool {
+        match self.stream_config.rates {
+            Rates::Continuous(_, _) => true,
+            Rates::Discrete(rates) => rates.len() > 1,
+        }
+    }
+
+    /// Total length of the AC descriptors written by `write_ac_descriptors_v2()`
+    fn ac_descriptors_length_v2(&self) -> u16 {
+        let fu_length = match self.feature_unit_id() {
+            Some(_) => 6 + 4 * (self.stream_config.channels as u16 + 1),
+            None => 0,
+        };
+        let selector_length = match self.stream_config.selector_pins() {
+            0 => 0,
+            pins => 17 * (pins as u16 - 1) + 7 + pins as u16,
+        };
+        let mixer_length = self.mixer.as_ref().map_or(0, |mixer| {
+            13 + mixer.num_pins() as u16 + mixer.num_nodes().div_ceil(8) as u16
+        });
+        let extension_length = self
+            .stream_config
+            .extension_unit
+            .map_or(0, |unit| 15 + unit.num_pins() as u16);
+        // external Clock Source (8 bytes) and Clock Selector (7 + 2 bytes)
+        let clock_selector_length = if self.stream_config.clock_selector {
+            8 + 9
+        } else {
+            0
+        };
+        // Clock Multiplier (7 bytes)
+        let clock_multiplier_length = self.clock_multiplier_id().map_or(0, |_| 7);
+        // Clock Source (8 bytes), Input Terminal (17 bytes), Output Terminal
+        // (12 bytes)
+        8 + clock_selector_length
+            + clock_multiplier_length
+            + 17
+            + selector_length
+            + mixer_length
+            + extension_length
+            + 12
+            + fu_length
+    }
+
+    fn write_ac_descriptors_v2<W: DescriptorSink>(&self, writer: &mut W) -> usb_device::Result<()> {
+        let is_input = self.is_input();
+        let terminal_type: u16 = self.stream_config.terminal_type.into();
+        let id_offset = self.id_offset();
+        let (it_name, ot_name) = self.terminal_name_indices();
+        let (it_assoc, ot_assoc) = self.assoc_terminals();
+
+        // write Clock Source Descriptor (8 bytes)
+        let external = self.clock_source(self.clock_id()) == Some(true);
+        let (attributes, controls) = if external {
+            (0x00, 0x05) // external clock, frequency control read-only
+        } else if self.is_programmable() {
+            (0x03, 0x07) // internal programmable clock, frequency control writable
+        } else {
+            (0x01, 0x05) // internal fixed clock, frequency control read-only
+        };
+        writer.write(
+            CS_INTERFACE,
+            &[
+                CLOCK_SOURCE,    // bDescriptorSubtype
+                self.clock_id(), // bClockID
+                attributes,      // bmAttributes
+                controls,        // bmControls (clock validity is read-only)
+                0x00,            // bAssocTerminal
+                0x00,            // iClockSource
+            ],
+        )?;
+
+        // write Descriptors of the external Clock Source (8 bytes) and the
+        // Clock Selector (9 bytes)
+        if let (Some(external_id), Some(selector_id)) =
+            (self.external_clock_id(), self.clock_selector_id())
+        {
+            writer.write(
+                CS_INTERFACE,
+                &[
+                    CLOCK_SOURCE, // bDescriptorSubtype
+                    external_id,  // bClockID
+                    0x00,         // bmAttributes: external clock
+                    0x05,         // bmControls: frequency and validity read-only
+                    0x00,         // bAssocTerminal
+                    0x00,         // iClockSource
+                ],
+            )?;
+            writer.write(
+                CS_INTERFACE,
+                &[
+                    CLOCK_SELECTOR,  // bDescriptorSubtype
+                    selector_id,     // bClockID
+                    0x02,            // bNrInPins
+                    self.clock_id(), // baCSourceID(1)
+                    external_id,     // baCSourceID(2)
+                    0x03,            // bmControls: selector control writable
+                    0x00,            // iClockSelector
+                ],
+            )?;
+        }
+
+        // write Clock Multiplier Descriptor (7 bytes)
+        if let Some(multiplier_id) = self.clock_multiplier_id() {
+            writer.write(
+                CS_INTERFACE,
+                &[
+                    CLOCK_MULTIPLIER,         // bDescriptorSubtype
+                    multiplier_id,            // bClockID
+                    self.selected_clock_id(), // bCSourceID
+                    0x05,                     // bmControls: numerator and denominator read-only
+                    0x00,                     // iClockMultiplier
+                ],
+            )?;
+        }
+
+        // write Input Terminal Descriptor (17 bytes)
+        let channel_config = self
+            .stream_config
+            .channel_config(self.stream_config.channels)
+            .to_le_bytes();
+        let tt = if is_input {
+            terminal_type
+        } else {
+            TerminalType::UsbStreaming.into()
+        }
+        .to_le_bytes();
+        let write_input_terminal = |writer: &mut W, id: u8, tt: [u8; 2], assoc, name, controls| {
+            writer.write(
+                CS_INTERFACE,
+                &[
+                    INPUT_TERMINAL, // bDescriptorSubtype
+                    id,             // bTerminalID
+                    tt[0],          // wTerminalType
+                    tt[1],
+                    assoc,                       // bAssocTerminal
+                    self.terminal_clock_id(),    // bCSourceID
+                    self.stream_config.channels, // bNrChannels
+                    channel_config[0],
+                    channel_config[1],
+                    channel_config[2],
+                    channel_config[3],        // bmChannelConfig
+                    self.channel_names_index, // iChannelNames
+                    controls as u8,
+                    (controls >> 8) as u8, // bmControls
+                    name,                  // iTerminal
+                ],
+            )
+        };
+        // controls of the terminal of type `terminal_type`: the Copy Protect
+        // control is read-only for an Input Terminal and write-only for an
+        // Output Terminal, the Connector and Latency controls are read-only
+        let copy_protect = match (self.stream_config.copy_protect_control, is_input) {
+            (false, _) => 0x0000,
+            (true, true) => 0x0001,
+            (true, false) => 0x0003,
+        };
+        let connector = if self.stream_config.connector_control {
+            0x0004
+        } else {
+            0x0000
+        };
+        let latency = if self.stream_config.latency.is_some() {
+            0x1000
+        } else {
+            0x0000
+        };
+        let controls = copy_protect | connector | latency;
+        let (it_controls, ot_controls) = if is_input {
+            (controls, 0x0000)
+        } else {
+            (0x0000, controls)
+        };
+        write_input_terminal(
+            writer,
+            ID_INPUT_TERMINAL + id_offset,
+            tt,
+            it_assoc,
+            it_name,
+            it_controls,
+        )?;
+        let mut source_id = ID_INPUT_TERMINAL + id_offset;
+
+        // write Input Terminal Descriptors of the additional sources and
+        // Selector Unit Descriptor (7 + number of input pins bytes)
+        if let Some(unit_id) = self.selector_unit_id() {
+            for (pin, &tt) in self.stream_config.selector_sources.iter().enumerate() {
+                let tt = u16::from(tt).to_le_bytes();
+                write_input_terminal(writer, unit_id + 1 + pin as u8, tt, 0x00, 0x00, 0x00)?;
+            }
+            writer.write_with(CS_INTERFACE, |buf| {
+                let pins = self.stream_config.selector_pins();
+                let length = 5 + pins as usize;
+                let buf = buf.get_mut(..length).ok_or(UsbError::BufferOverflow)?;
+                buf[0] = SELECTOR_UNIT; // bDescriptorSubtype
+                buf[1] = unit_id; // bUnitID
+                buf[2] = pins; // bNrInPins
+                buf[3] = source_id; // baSourceID(1)
+                for (pin, id) in buf[4..length - 2].iter_mut().enumerate() {
+                    *id = unit_id + 1 + pin as u8; // baSourceID(i)
+                }
+                buf[length - 2] = 0x03; // bmControls: selector control writable
+                buf[length - 1] = 0x00; // iSelector
+                Ok(length)
+            })?;
+            source_id = unit_id;
+        }
+
+        // write Mixer Unit Descriptor (13 + number of input pins + size of
+        // bmMixerControls bytes)
+        if let (Some(unit_id), Some(mixer)) = (self.mixer_unit_id(), &self.mixer) {
+            writer.write_with(CS_INTERFACE, |buf| {
+                let pins = mixer.num_pins() as usize;
+                let controls_length = mixer.num_nodes().div_ceil(8);
+                let length = 11 + pins + controls_length;
+                let buf = buf.get_mut(..length).ok_or(UsbError::BufferOverflow)?;
+                buf[0] = MIXER_UNIT; // bDescriptorSubtype
+                buf[1] = unit_id; // bUnitID
+                buf[2] = mixer.num_pins(); // bNrInPins
+                buf[3] = source_id; // baSourceID(1)
+                buf[4..3 + pins].copy_from_slice(mixer.sources()); // baSourceID(i)
+                buf[3 + pins] = self.stream_config.channels; // bNrChannels
+                buf[4 + pins..8 + pins].copy_from_slice(&channel_config); // bmChannelConfig
+                buf[8 + pins] = self.channel_names_index; // iChannelNames
+                mixer.write_controls(&mut buf[9 + pins..]); // bmMixerControls
+                buf[length - 2] = 0x00; // bmControls
+                buf[length - 1] = 0x00; // iMixer
+                Ok(length)
+            })?;
+            source_id = unit_id;
+        }
+
+        // write Extension Unit Descriptor (15 + number of input pins bytes)
+        if let (Some(unit_id), Some(unit)) =
+            (self.extension_unit_id(), self.stream_config.extension_unit)
+        {
+            writer.write_with(CS_INTERFACE, |buf| {
+                let pins = unit.num_pins() as usize;
+                let length = 13 + pins;
+                let buf = buf.get_mut(..length).ok_or(UsbError::BufferOverflow)?;
+                let code = unit.code.to_le_bytes();
+                buf[0] = EXTENSION_UNIT_V2; // bDescriptorSubtype
+                buf[1] = unit_id; // bUnitID
+                buf[2] = code[0]; // wExtensionCode
+                buf[3] = code[1];
+                buf[4] = unit.num_pins(); // bNrInPins
+                buf[5] = source_id; // baSourceID(1)
+                buf[6..5 + pins].copy_from_slice(&self.extension_sources[..pins - 1]); // baSourceID(i)
+                buf[5 + pins] = self.stream_config.channels; // bNrChannels
+                buf[6 + pins..10 + pins].copy_from_slice(&channel_config); // bmChannelConfig
+                buf[10 + pins] = self.channel_names_index; // iChannelNames
+                buf[11 + pins] = if unit.enable_control { 0x03 } else { 0x00 }; // bmControls
+                buf[12 + pins] = 0x00; // iExtension
+                Ok(length)
+            })?;
+            source_id = unit_id;
+        }
+
+        // write Feature Unit Descriptor (6 + (number of channels + 1) * 4 bytes)
+        if let Some(unit_id) = self.feature_unit_id() {
+            writer.write_with(CS_INTERFACE, |buf| {
+                let length = 4 + 4 * (self.stream_config.channels as usize + 1);
+                let buf = buf.get_mut(..length).ok_or(UsbError::BufferOverflow)?;
+                buf[0] = FEATURE_UNIT; // bDescriptorSubtype
+                buf[1] = unit_id; // bUnitID
+                buf[2] = source_id; // bSourceID
+                for (channel, controls) in buf[3..length - 1].chunks_exact_mut(4).enumerate() {
+                    let bitmap = controls_v2(self.stream_config.controls(channel as u8) as u16);
+                    controls.copy_from_slice(&bitmap.to_le_bytes()); // bmaControls(i)
+                }
+                buf[length - 1] = 0x00; // iFeature
+                Ok(length)
+            })?;
+            source_id = unit_id;
+        }
+
+        // write Output Terminal Descriptor (12 bytes)
+        let tt = if is_input {
+            TerminalType::UsbStreaming.into()
+        } else {
+            terminal_type
+        }
+        .to_le_bytes();
+        writer.write(
+            CS_INTERFACE,
+            &[
+                OUTPUT_TERMINAL,                // bDescriptorSubtype
+                ID_OUTPUT_TERMINAL + id_offset, // bTerminalID
+                tt[0],                          // wTerminalType
+                tt[1],
+                ot_assoc,                 // bAssocTerminal
+                source_id,                // bSourceID
+                self.terminal_clock_id(), // bCSourceID
+                ot_controls as u8,
+                (ot_controls >> 8) as u8, // bmControls
+                ot_name,                  // iTerminal
+            ],
+        )
+    }
+
+    fn write_as_and_ep_descriptors_v2<W: DescriptorSink>(
+        &self,
+        writer: &mut W,
+    ) -> usb_device::Result<()> {
+        // Standard AS Interface Descriptor (Alt. Set. 0)
+        writer.interface_alt(
+            self.interface,
+            0x00,
+            AUDIO,
+            AUDIOSTREAMING,
+            IP_VERSION_02_00,
+            None,
+        )?;
+
+        for alt_setting in 1..=self.stream_config.num_alt_settings() {
+            self.write_alt_setting_descriptors_v2(writer, alt_setting)?;
+        }
+        Ok(())
+    }
+
+    /// Write the AS and EP descriptors of an operational alternate setting
+    fn write_alt_setting_descriptors_v2<W: DescriptorSink>(
+        &self,
+        writer: &mut W,
+        alt_setting: u8,
+    ) -> usb_device::Result<()> {
+        let (format, channels) = self.stream_config.alt_setting(alt_setting);
+
+        // Standard AS Interface Descriptor (Alt. Set. n)
+        self.write_alt_interface_descriptor(writer, alt_setting, IP_VERSION_02_00)?;
+
+        // Class-specific AS General Interface Descriptor (16 bytes)
+        let terminal_link = self.id_offset()
+            + if self.is_input() {
+                ID_OUTPUT_TERMINAL
+            } else {
+                ID_INPUT_TERMINAL
+            };
+        let formats = format.formats_v2().to_le_bytes();
+        let channel_config = self.stream_config.channel_config(channels).to_le_bytes();
+        writer.write(
+            CS_INTERFACE,
+            &[
+                AS_GENERAL,           // bDescriptorSubtype
+                terminal_link,        // bTerminalLink
+                0x00,                 // bmControls
+                format.format_type(), // bFormatType
+                formats[0],           // bmFormats
+                formats[1],
+                formats[2],
+                formats[3],
+                channels, // bNrChannels
+                channel_config[0],
+                channel_config[1],
+                channel_config[2],
+                channel_config[3],        // bmChannelConfig
+                self.channel_names_index, // iChannelNames
+            ],
+        )?;
+
+        if let Some((max_bit_rate, slots_per_frame)) = format.type_ii() {
+            // Type II Format Type Descriptor (8 bytes)
+            let max_bit_rate = max_bit_rate.to_le_bytes();
+            let slots_per_frame = slots_per_frame.to_le_bytes();
+            writer.write(
+                CS_INTERFACE,
+                &[
+                    FORMAT_TYPE,          // bDescriptorSubtype
+                    format.format_type(), // bFormatType
+                    max_bit_rate[0],      // wMaxBitRate
+                    max_bit_rate[1],
+                    slots_per_frame[0], // wSlotsPerFrame
+                    slots_per_frame[1],
+                ],
+            )?;
+        } else {
+            // Type I or Type III Format Type Descriptor (6 bytes)
+            writer.write(
+                CS_INTERFACE,
+                &[
+                    FORMAT_TYPE,             // bDescriptorSubtype
+                    format.format_type(),    // bFormatType
+                    format.subframe_size(),  // bSubslotSize
+                    format.bit_resolution(), // bBitResolution
+                ],
+            )?;
+        }
+
+        // Standard AS Isochronous Audio Data Endpoint Descriptor
+        self.ep_descriptor.write(writer, &[])?;
+
+        // Class-specific AS Isochronous Audio Data Endpoint Descriptor (8 bytes)
+        let lock_delay = LockDelay::fields(self.stream_config.lock_delay);
+        // MaxPacketsOnly
+        let attributes = (self.stream_config.max_packets_only as u8) << 7;
+        // Pitch control host programmable
+        let controls = if self.stream_config.pitch_control {
+            0x03
+        } else {
+            0x00
+        };
+        writer.write(
+            CS_ENDPOINT,
+            &[
+                EP_GENERAL,    // bDescriptorSubtype
+                attributes,    // bmAttributes
+                controls,      // bmControls
+                lock_delay[0], // bLockDelayUnits
+                lock_delay[1],
+                lock_delay[2], // wLockDelay
+            ],
+        )?;
+
+        // Standard AS Isochronous Feedback Endpoint Descriptor
+        if let Some(ref feedback) = self.feedback {
+            EndpointDescriptor::new(feedback).write(writer, &[])?;
+        }
+        Ok(())
+    }
+
+    /// Handle a GET request addressed to the isochronous endpoint of the stream
+    pub(crate) fn endpoint_control_in_v2(&self, xfer: ControlIn<B>) {
+        let req = *xfer.request();
+        let cs = (req.value >> 8) as u8;
+        let channel = req.value as u8;
+        match (req.request, cs, channel) {
+            (CUR, EP_PITCH_CONTROL, 0) if self.stream_config.pitch_control => {
+                accept_param(xfer, &[self.pitch as u8])
+            }
+            _ => xfer.reject(),
+        }
+        .ok();
+    }
+
+    /// Handle a SET request addressed to the isochronous endpoint of the stream
+    pub(crate) fn endpoint_control_out_v2(&mut self, xfer: ControlOut<B>) {
+        let req = *xfer.request();
+        let cs = (req.value >> 8) as u8;
+        let channel = req.value as u8;
+        match (req.request, cs, channel, xfer.data()) {
+            (CUR, EP_PITCH_CONTROL, 0, &[pitch]) if self.stream_config.pitch_control => {
+                self.set_pitch(pitch != 0);
+                xfer.accept()
+            }
+            _ => xfer.reject(),
+        }
+        .ok();
+    }
+
+    /// Write the parameter block of a RANGE request addressed to the sampling
+    /// frequency control of the Clock Source
+    fn write_sampling_freq_range(&self, buf: &mut [u8]) -> usb_device::Result<usize> {
+        let count = match self.stream_config.rates {
+            Rates::Continuous(min, max) => {
+                let (min, max) = (self.source_frequency(min), self.source_frequency(max));
+                put_subrange(&mut buf[2..], min, max, self.source_frequency(1).max(1))?;
+                1
+            }
+            Rates::Discrete(rates) => {
+                for (i, rate) in rates.iter().enumerate() {
+                    let sub = buf.get_mut(2 + 12 * i..).ok_or(UsbError::BufferOverflow)?;
+                    let frequency = self.source_frequency(*rate);
+                    put_subrange(sub, frequency, frequency, 0)?;
+                }
+                rates.len()
+            }
+        };
+        buf[..2].copy_from_slice(&(count as u16).to_le_bytes()); // wNumSubRanges
+        Ok(2 + 12 * count)
+    }
+
+    /// Handle a GET request addressed to an entity of the stream
+    pub(crate) fn entity_in_v2(&self, entity: Entity, xfer: ControlIn<B>) {
+        match entity {
+            Entity::ClockSource(external) => self.clock_source_in(xfer, external),
+            Entity::ClockMultiplier => self.clock_multiplier_in(xfer),
+            Entity::ClockSelector => self.clock_selector_in(xfer),
+            Entity::Terminal => self.terminal_in_v2(xfer),
+            Entity::SelectorUnit => self.selector_unit_in_v2(xfer),
+            Entity::MixerUnit => self.mixer_unit_in_v2(xfer),
+            Entity::FeatureUnit => self.feature_unit_in_v2(xfer),
+            Entity::ProcessingUnit => {
+                xfer.reject().ok();
+            }
+        }
+    }
+
+    /// Handle a SET request addressed to an entity of the stream
+    pub(crate) fn entity_out_v2(
+        &mut self,
+        entity: Entity,
+        xfer: ControlOut<B>,
+        handler: Option<&mut (dyn AudioControlHandler + Send + '_)>,
+    ) {
+        match entity {
+            Entity::ClockSource(external) => self.clock_source_out(xfer, external, handler),
+            Entity::ClockSelector => self.clock_selector_out(xfer),
+            Entity::Terminal => self.terminal_out_v2(xfer),
+            Entity::SelectorUnit => self.selector_unit_out_v2(xfer),
+            Entity::MixerUnit => self.mixer_unit_out_v2(xfer),
+            Entity::FeatureUnit => self.feature_unit_out_v2(xfer, handler),
+            Entity::ProcessingUnit | Entity::ClockMultiplier => {
+                xfer.reject().ok();
+            }
+        }
+    }
+
+    /// Handle a GET request addressed to the Feature Unit of the stream
+    fn feature_unit_in_v2(&self, xfer: ControlIn<B>) {
+        let req = *xfer.request();
+        let cs = (req.value >> 8) as u8;
+        let channel = req.value as u8;
+        let mute_control = self.stream_config.has_mute_control(channel);
+        let agc_control = self.stream_config.has_agc_control(channel);
+        let tone_controls = self.stream_config.has_tone_controls(channel);
+        let ch = channel as usize;
+        match (req.request, cs, self.stream_config.volume_control(channel)) {
+            (CUR, MUTE_CONTROL, _) if mute_control => accept_param(xfer, &[self.mute[ch] as u8]),
+            (CUR, AUTOMATIC_GAIN_CONTROL, _) if agc_control => {
+                accept_param(xfer, &[self.agc as u8])
+            }
+            (CUR, BASS_CONTROL | MID_CONTROL | TREBLE_CONTROL, _) if tone_controls => {
+                let value = ToneControl::from_selector(cs).map_or(0, |c| self.tone[c as usize]);
+                accept_param(xfer, &[value as u8])
+            }
+            (RANGE, BASS_CONTROL | MID_CONTROL | TREBLE_CONTROL, _) if tone_controls => {
+                // wNumSubRanges, bMIN, bMAX, bRES in 0.25 dB
+                xfer.accept_with(&[1, 0, i8::MIN as u8, i8::MAX as u8, 1])
+            }
+            (CUR, GRAPHIC_EQUALIZER_CONTROL, _) => {
+                match self.equalizer.as_ref().filter(|_| channel == 0) {
+                    Some(equalizer) => xfer.accept(|buf| {
+                        equalizer
+                            .write(buf, None)
+                            .filter(|&length| length == req.length as usize)
+                            .ok_or(UsbError::InvalidState)
+                    }),
+                    None => xfer.reject(),
+                }
+            }
+            (RANGE, GRAPHIC_EQUALIZER_CONTROL, _) if self.equalizer.is_some() && channel == 0 => {
+                // one subrange in 0.25 dB applying to all bands
+                let mut buf = [0u8; 5];
+                buf[0..2].copy_from_slice(&1u16.to_le_bytes()); // wNumSubRanges
+                buf[2] = i8::MIN as u8;
+                buf[3] = i8::MAX as u8;
+                buf[4] = 1;
+                xfer.accept_with(&buf)
+            }
+            (CUR, VOLUME_CONTROL, Some(_)) => accept_param(xfer, &self.volume[ch].to_le_bytes()),
+            (RANGE, VOLUME_CONTROL, Some(range)) => {
+                let mut buf = [0u8; 8];
+                buf[0..2].copy_from_slice(&1u16.to_le_bytes()); // wNumSubRanges
+                buf[2..4].copy_from_slice(&range.min.to_le_bytes());
+                buf[4..6].copy_from_slice(&range.max.to_le_bytes());
+                buf[6..8].copy_from_slice(&range.res.to_le_bytes());
+                xfer.accept_with(&buf)
+            }
+            _ => xfer.reject(),
+        }
+        .ok();
+    }
+
+    /// Handle a GET request addressed to the terminal with the Copy Protect
+    /// control, which is read-only for an Input Terminal, or the Connector
+    /// control
+    fn terminal_in_v2(&self, xfer: ControlIn<B>) {
+        let req = *xfer.request();
+        let cs = (req.value >> 8) as u8;
+        let channel = req.value as u8;
+        match (req.request, cs, channel) {
+            (CUR, TE_COPY_PROTECT_CONTROL, 0)
+                if self.is_input() && self.stream_config.copy_protect_control =>
+            {
+                accept_param(xfer, &[self.copy_protect as u8])
+            }
+            (CUR, TE_CONNECTOR_CONTROL, 0) if self.stream_config.connector_control => {
+                // cluster of the connected plug, all zero if none is inserted
+                let mut buf = [0; 6];
+                if self.connected {
+                    let channels = self.stream_config.channels;
+                    buf[0] = channels; // bNrChannels
+                    buf[1..5].copy_from_slice(
+                        &self.stream_config.channel_config(channels).to_le_bytes(),
+                    ); // bmChannelConfig
+                    buf[5] = self.channel_names_index; // iChannelNames
+                }
+                accept_param(xfer, &buf)
+            }
+            (CUR, TE_LATENCY_CONTROL, 0) if self.stream_config.latency.is_some() => {
+                // dCUR in ns
+                let latency = self.latency.saturating_mul(1000);
+                accept_param(xfer, &latency.to_le_bytes())
+            }
+            _ => xfer.reject(),
+        }
+        .ok();
+    }
+
+    /// Handle a GET request addressed to the Selector Unit of the stream
+    fn selector_unit_in_v2(&self, xfer: ControlIn<B>) {
+        let req = *xfer.request();
+        let cs = (req.value >> 8) as u8;
+        let channel = req.value as u8;
+        let pins = self.stream_config.selector_pins();
+        match (req.request, cs, channel) {
+            (CUR, SU_SELECTOR_CONTROL, 0) => accept_param(xfer, &[self.selector]),
+            (RANGE, SU_SELECTOR_CONTROL, 0) => {
+                // wNumSubRanges, bMIN, bMAX, bRES
+                xfer.accept_with(&[0x01, 0x00, 1, pins, 1])
+            }
+            _ => xfer.reject(),
+        }
+        .ok();
+    }
+
+    /// Handle a SET request addressed to the Selector Unit of the stream
+    fn selector_unit_out_v2(&mut self, xfer: ControlOut<B>) {
+        let req = *xfer.request();
+        let cs = (req.value >> 8) as u8;
+        let channel = req.value as u8;
+        let pins = self.stream_config.selector_pins();
+        match (req.request, cs, channel, xfer.data()) {
+            (CUR, SU_SELECTOR_CONTROL, 0, &[selector]) if (1..=pins).contains(&selector) => {
+                self.set_selector(selector);
+                xfer.accept()
+            }
+            _ => xfer.reject(),
+        }
+        .ok();
+    }
+
+    /// Handle a SET request addressed to the terminal with the Copy Protect
+    /// control, which is write-only for an Output Terminal
+    fn terminal_out_v2(&mut self, xfer: ControlOut<B>) {
+        let req = *xfer.request();
+        let cs = (req.value >> 8) as u8;
+        let channel = req.value as u8;
+        match (req.request, cs, channel, xfer.data()) {
+            (CUR, TE_COPY_PROTECT_CONTROL, 0, &[level])
+                if !self.is_input() && self.stream_config.copy_protect_control =>
+            {
+                match CopyProtectLevel::from_u8(level) {
+                    Some(level) => {
+                        self.set_copy_protect(level);
+                        xfer.accept()
+                    }
+                    None => xfer.reject(),
+                }
+            }
+            _ => xfer.reject(),
+        }
+        .ok();
+    }
+
+    /// Handle a SET request addressed to the Feature Unit of the stream
+    fn feature_unit_out_v2(
+        &mut self,
+        xfer: ControlOut<B>,
+        handler: Option<&mut (dyn AudioControlHandler + Send + '_)>,
+    ) {
+        let req = *xfer.request();
+        let cs = (req.value >> 8) as u8;
+        let channel = req.value as u8;
+        let mute_control = self.stream_config.has_mute_control(channel);
+        let agc_control = self.stream_config.has_agc_control(channel);
+        let tone_controls = self.stream_config.has_tone_controls(channel);
+        match (req.request, cs, xfer.data()) {
+            (CUR, MUTE_CONTROL, &[mute]) if mute_control => {
+                if self.set_mute(channel, mute != 0, handler) {
+                    xfer.accept()
+                } else {
+                    xfer.reject()
+                }
+            }
+            (CUR, AUTOMATIC_GAIN_CONTROL, &[agc]) if agc_control => {
+                self.set_agc(agc != 0);
+                xfer.accept()
+            }
+            (CUR, BASS_CONTROL | MID_CONTROL | TREBLE_CONTROL, &[value]) if tone_controls => {
+                if let Some(control) = ToneControl::from_selector(cs) {
+                    self.set_tone(control, value as i8);
+                }
+                xfer.accept()
+            }
+            (CUR, GRAPHIC_EQUALIZER_CONTROL, data) if channel == 0 => {
+                match self.equalizer.as_mut().map(|equalizer| equalizer.set(data)) {
+                    Some(true) => xfer.accept(),
+                    _ => xfer.reject(),
+                }
+            }
+            (CUR, VOLUME_CONTROL, &[lsb, msb]) => {
+                let volume = i16::from_le_bytes([lsb, msb]);
+                match self.stream_config.volume_control(channel) {
+                    Some(range) if self.set_volume(channel, volume, range, handler) => {
+                        xfer.accept()
+                    }
+                    _ => xfer.reject(),
+                }
+            }
+            _ => xfer.reject(),
+        }
+        .ok();
+    }
+
+    /// Handle a GET request addressed to an internal or external Clock Source
+    /// of the stream. The rate of an invalid external clock is reported as
+    /// the current rate of the stream.
+    fn clock_source_in(&self, xfer: ControlIn<B>, external: bool) {
+        let req = *xfer.request();
+        let cs = (req.value >> 8) as u8;
+        let (rate, valid) = if external {
+            (
+                self.external_rate.unwrap_or(self.sampling_rate),
+                self.external_rate.is_some(),
+            )
+        } else {
+            (self.internal_rate, true)
+        };
+        let frequency = self.source_frequency(rate);
+        let channel = req.value as u8;
+        match (req.request, cs, channel) {
+            (CUR, CS_SAM_FREQ_CONTROL, 0) => accept_param(xfer, &frequency.to_le_bytes()),
+            (RANGE, CS_SAM_FREQ_CONTROL, 0) => {
+                xfer.accept(|buf| self.write_sampling_freq_range(buf))
+            }
+            (CUR, CS_CLOCK_VALID_CONTROL, 0) => accept_param(xfer, &[valid as u8]),
+            _ => xfer.reject(),
+        }
+        .ok();
+    }
+
+    /// Handle a SET request addressed to an internal or external Clock Source
+    /// of the stream. Only the rate of the internal clock is programmable.
+    fn clock_source_out(
+        &mut self,
+        xfer: ControlOut<B>,
+        external: bool,
+        handler: Option<&mut (dyn AudioControlHandler + Send + '_)>,
+    ) {
+        let req = *xfer.request();
+        let cs = (req.value >> 8) as u8;
+        let channel = req.value as u8;
+        if req.request == CUR && cs == CS_SAM_FREQ_CONTROL && channel == 0 && !external {
+            if let Ok(data) = xfer.data().try_into() {
+                let rate = self.stream_rate(u32::from_le_bytes(data));
+                if let Some(rate) = rate.filter(|&rate| self.stream_config.rates.contains(rate)) {
+                    if self.set_internal_rate(rate, handler) {
+                        xfer.accept().ok();
+                        return;
+                    }
+                }
+            }
+        }
+        xfer.reject().ok();
+    }
+
+    /// ID of the external Clock Source and the selectors of its controls
+    /// (validity and frequency) that changed since it had the indicated rate
+    pub(crate) fn external_clock_changes(&self, previous: Option<u32>) -> (u8, [Option<u8>; 2]) {
+        let id = self.external_clock_id().unwrap_or(self.clock_id());
+        let rate = self.external_rate;
+        let controls = [
+            (previous.is_some() != rate.is_some()).then_some(CS_CLOCK_VALID_CONTROL),
+            (rate.is_some() && rate != previous).then_some(CS_SAM_FREQ_CONTROL),
+        ];
+        (id, controls)
+    }
+
+    /// Handle a GET request addressed to the Clock Multiplier of the stream
+    fn clock_multiplier_in(&self, xfer: ControlIn<B>) {
+        let req = *xfer.request();
+        let cs = (req.value >> 8) as u8;
+        let channel = req.value as u8;
+        let (numerator, denominator) = self.stream_config.clock_multiplier.unwrap_or((1, 1));
+        match (req.request, cs, channel) {
+            (CUR, CM_NUMERATOR_CONTROL, 0) => accept_param(xfer, &numerator.to_le_bytes()),
+            (CUR, CM_DENOMINATOR_CONTROL, 0) => accept_param(xfer, &denominator.to_le_bytes()),
+            _ => xfer.reject(),
+        }
+        .ok();
+    }
+
+    /// Handle a GET request addressed to the Clock Selector of the stream
+    fn clock_selector_in(&self, xfer: ControlIn<B>) {
+        let req = *xfer.request();
+        let cs = (req.value >> 8) as u8;
+        let channel = req.value as u8;
+        match (req.request, cs, channel) {
+            (CUR, CX_CLOCK_SELECTOR_CONTROL, 0) => accept_param(xfer, &[self.clock_selector]),
+            _ => xfer.reject(),
+        }
+        .ok();
+    }
+
+    /// Handle a SET request addressed to the Clock Selector of the stream
+    fn clock_selector_out(&mut self, xfer: ControlOut<B>) {
+        let req = *xfer.request();
+        let cs = (req.value >> 8) as u8;
+        let channel = req.value as u8;
+        match (req.request, cs, channel, xfer.data()) {
+            (CUR, CX_CLOCK_SELECTOR_CONTROL, 0, &[selector]) if (1..=2).contains(&selector) => {
+                self.set_clock_selector(selector);
+                xfer.accept()
+            }
+            _ => xfer.reject(),
+        }
+        .ok();
+    }
+}
+
+impl AudioCategory {
+    /// Audio Function Category Code
+    fn code(self) -> u8 {
+        match self {
+            AudioCategory::Undefined => CATEGORY_UNDEFINED,
+            AudioCategory::DesktopSpeaker => DESKTOP_SPEAKER,
+            AudioCategory::HomeTheater => HOME_THEATER,
+            AudioCategory::Microphone => MICROPHONE,
+            AudioCategory::Headset => HEADSET,
+            AudioCategory::Telephone => TELEPHONE,
+            AudioCategory::Converter => CONVERTER,
+            AudioCategory::VoiceSoundRecorder => VOICE_SOUND_RECORDER,
+            AudioCategory::IoBox => IO_BOX,
+            AudioCategory::MusicalInstrument => MUSICAL_INSTRUMENT,
+            AudioCategory::ProAudio => PRO_AUDIO,
+            AudioCategory::AudioVideo => AUDIO_VIDEO,
+            AudioCategory::ControlPanel => CONTROL_PANEL,
+            AudioCategory::Other => OTHER,
+        }
+    }
+}
+
+impl<B: UsbBus, const P: usize> AudioClass<'_, B, P> {
+    pub(crate) fn write_descriptors_v2<W: DescriptorSink>(
+        &self,
+        writer: &mut W,
+    ) -> usb_device::Result<()> {
+        let mut total_length = AC_HEADER_SIZE;
+        for a in self.inputs.iter().flatten() {
+            total_length += a.ac_descriptors_length_v2();
+        }
+        for a in self.outputs.iter().flatten() {
+            total_length += a.ac_descriptors_length_v2();
+        }
+
+        // Interface Association Descriptor
+        writer.iad(
+            self.control_iface,
+            self.num_interfaces(),
+            AUDIO_FUNCTION,
+            FUNCTION_SUBCLASS_UNDEFINED,
+            AF_VERSION_02_00,
+            self.function_string(),
+        )?;
+
+        // Standard AC Interface Descriptor
+        writer.interface_alt(
+            self.control_iface,
+            0x00,
+            AUDIO,
+            AUDIOCONTROL,
+            IP_VERSION_02_00,
+            self.function_string(),
+        )?;
+
+        // Class-specific AC Interface Header Descriptor (9 bytes)
+        writer.write(
+            CS_INTERFACE,
+            &[
+                HEADER, // bDescriptorSubtype
+                0x00,
+                0x02,                 // bcdADC
+                self.category.code(), // bCategory
+                total_length as u8,
+                (total_length >> 8) as u8, // wTotalLength
+                0x00,                      // bmControls
+            ],
+        )?;
+        for a in self.inputs.iter().flatten() {
+            a.write_ac_descriptors_v2(writer)?;
+        }
+        for a in self.outputs.iter().flatten() {
+            a.write_ac_descriptors_v2(writer)?;
+        }
+
+        // Standard AC Interrupt Endpoint Descriptor
+        if let Some(ep) = self.interrupt.as_ref() {
+            writer.endpoint(ep)?;
+        }
+
+        // write Audio Streaming (AS) and endpoint (EP) descriptors
+        for a in self.inputs.iter().flatten() {
+            a.write_as_and_ep_descriptors_v2(writer)?;
+        }
+        for a in self.outputs.iter().flatten() {
+            a.write_as_and_ep_descriptors_v2(writer)?;
+        }
+        if let Some(midi) = self.midi.as_ref() {
+            midi.write_descriptors(writer)?;
+        }
+        Ok(())
+    }
+}
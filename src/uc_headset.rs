@@ -0,0 +1,64 @@
+//! Topology preset for unified-communications (UC) headsets
+//!
+//! The preset follows the topology expected by the certification programs of
+//! UC applications: a microphone path with mute, volume and automatic gain
+//! controls and an acoustic echo canceller that receives the signal played by
+//! the host as a reference, and a speaker path with mute and volume controls.
+//! The terminals of both paths form a bi-directional headset.
+//!
+
+use crate::{
+    AudioCategory, AudioClassBuilder, ChannelConfig, ExtensionUnit, Format, Result, StreamConfig,
+    StreamId, TerminalType,
+};
+
+/// Extension code (wExtensionCode) of the acoustic echo canceller of the UC
+/// headset preset
+pub const ECHO_CANCELLER_CODE: u16 = 0x0aec;
+
+/// Sampling rates of the microphone: wideband speech and full band audio
+const MIC_RATES: [u32; 2] = [16000, 48000];
+const SPEAKER_RATES: [u32; 1] = [48000];
+
+/// The echo canceller uses the signal of the speaker as a reference
+const ECHO_REFERENCE: [StreamId; 1] = [StreamId::Output(0)];
+
+impl AudioClassBuilder<'static> {
+    /// Create a builder for a unified-communications headset with a mono
+    /// microphone (`StreamId::Input(0)`, 16 or 48 kHz) and a stereo speaker
+    /// (`StreamId::Output(0)`, 48 kHz), both 16 bit PCM. The microphone path
+    /// contains an acoustic echo canceller, which is an Extension Unit with
+    /// the code `ECHO_CANCELLER_CODE` and an Enable Processing control that
+    /// references the speaker path, followed by a Feature Unit with mute,
+    /// volume (-40 dB to +20 dB) and automatic gain controls. The speaker path
+    /// contains a Feature Unit with mute and volume (-60 dB to 0 dB) controls.
+    /// Requests to the echo canceller are passed to the handler set by
+    /// `extension_unit_handler()`. The category of a USB Audio Class 2.0
+    /// function is `AudioCategory::Headset`. Further streams and settings can
+    /// be added to the returned builder.
+    pub fn uc_headset() -> Result<AudioClassBuilder<'static>> {
+        let echo_canceller = ExtensionUnit::new(ECHO_CANCELLER_CODE)
+            .with_enable_control()
+            .with_sources(&ECHO_REFERENCE);
+        let mic =
+            StreamConfig::new_discrete(Format::S16le, 1, &MIC_RATES, TerminalType::BidirHeadset)?
+                .with_channel_config(ChannelConfig::MONO)?
+                .with_extension_unit(echo_canceller)
+                .with_mute_control()
+                .with_volume_control(-40 * 256, 20 * 256, 256)?
+                .with_agc_control();
+        let speaker = StreamConfig::new_discrete(
+            Format::S16le,
+            2,
+            &SPEAKER_RATES,
+            TerminalType::BidirHeadset,
+        )?
+        .with_mute_control()
+        .with_volume_control(-60 * 256, 0, 256)?;
+        Ok(AudioClassBuilder::new()
+            .input(mic)
+            .output(speaker)
+            .associate_terminals(0, 0)
+            .category(AudioCategory::Headset))
+    }
+}
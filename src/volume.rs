@@ -0,0 +1,249 @@
+//! Volume in the representation of the audio class controls and smooth
+//! application of volume changes in software
+
+use core::fmt;
+
+/// log2(10) / 20 / 256 in 0.32 format, which converts 1/256 dB into powers of
+/// two
+const DB_TO_LOG2: i64 = 2_786_629;
+/// Coefficients of the polynomial approximating 2^x for 0 <= x < 1 in 0.16
+/// format, with a relative error below 2e-4 (0.002 dB)
+const EXP2_C1: i64 = 45_584;
+const EXP2_C2: i64 = 14_823;
+const EXP2_C3: i64 = 5_121;
+
+/// Volume or gain in 1/256 dB as a signed 16 bit value, the representation of
+/// the volume, tone, equalizer and mixer controls. `Volume::SILENCE`
+/// (`i16::MIN`) stands for -∞ dB. The `i16` values reported by e.g.
+/// `AudioClass::volume()` and `AudioEvent::VolumeChanged` convert by
+/// `Volume::from()`.
+///
+/// ```
+/// use usbd_audio::Volume;
+///
+/// let volume = Volume::from_millibels(-650);
+/// assert_eq!(volume, Volume(-6 * 256 - 128));
+/// assert_eq!(volume.to_db(), -6.5);
+/// let gain = Volume::from_db(12.0);
+/// assert_eq!(Volume::from_db(120.0).saturating_add(gain), Volume::MAX);
+/// assert_eq!(Volume::from_db(f32::NEG_INFINITY), Volume::SILENCE);
+/// ```
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, Ord, PartialEq, PartialOrd)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Volume(pub i16);
+
+impl Volume {
+    /// -∞ dB
+    pub const SILENCE: Volume = Volume(i16::MIN);
+    /// Smallest finite volume, -127.9961 dB
+    pub const MIN: Volume = Volume(i16::MIN + 1);
+    /// Largest volume, +127.9961 dB
+    pub const MAX: Volume = Volume(i16::MAX);
+    /// 0 dB
+    pub const UNITY: Volume = Volume(0);
+
+    /// Convert a volume in millibels (1/100 dB), rounded to the nearest
+    /// 1/256 dB and saturated to the representable range, so that volumes of
+    /// -128 dB and below give `Volume::SILENCE`
+    pub fn from_millibels(millibels: i32) -> Volume {
+        let scaled = millibels as i64 * 256;
+        // round half away from zero
+        let rounded = (scaled + scaled.signum() * 50) / 100;
+        Volume(rounded.clamp(i16::MIN as i64, i16::MAX as i64) as i16)
+    }
+
+    /// Convert into millibels, rounded to the nearest integer. Returns
+    /// `i32::MIN` for `Volume::SILENCE`.
+    pub fn to_millibels(self) -> i32 {
+        if self.is_silence() {
+            return i32::MIN;
+        }
+        let scaled = self.0 as i32 * 100;
+        (scaled + scaled.signum() * 128) / 256
+    }
+
+    /// Convert a volume in dB, rounded to the nearest 1/256 dB and saturated
+    /// to the representable range, so that -∞ and volumes of -128 dB and below
+    /// give `Volume::SILENCE`. NaN gives `Volume::UNITY`.
+    pub fn from_db(db: f32) -> Volume {
+        let scaled = db * 256.0;
+        // `as` saturates and maps NaN to 0
+        let rounded = if scaled < 0.0 {
+            scaled - 0.5
+        } else {
+            scaled + 0.5
+        };
+        Volume(rounded as i16)
+    }
+
+    /// Convert into dB. Returns -∞ for `Volume::SILENCE`.
+    pub fn to_db(self) -> f32 {
+        if self.is_silence() {
+            return f32::NEG_INFINITY;
+        }
+        self.0 as f32 / 256.0
+    }
+
+    /// Whether the volume is -∞ dB
+    pub fn is_silence(self) -> bool {
+        self == Volume::SILENCE
+    }
+
+    /// Linear amplitude gain in 16.16 format, e.g. about 0x8000 for -6.02 dB,
+    /// saturating at `u32::MAX` above +96.3 dB. Returns 0 for
+    /// `Volume::SILENCE`.
+    pub fn to_gain(self) -> u32 {
+        if self.is_silence() {
+            return 0;
+        }
+        let exponent = (self.0 as i64 * DB_TO_LOG2) >> 16;
+        let (int, frac) = (exponent >> 16, exponent & 0xffff);
+        let poly = EXP2_C1 + ((frac * (EXP2_C2 + ((frac * EXP2_C3) >> 16))) >> 16);
+        let mantissa = (1 << 16) + ((frac * poly) >> 16);
+        // the exponent of a 16 bit volume lies in -22..=21
+        let gain = if int >= 0 {
+            mantissa << int
+        } else {
+            (mantissa + (1 << (-int - 1))) >> -int
+        };
+        gain.min(u32::MAX as i64) as u32
+    }
+
+    /// Add a gain, saturating at `Volume::MIN` and `Volume::MAX`. Silence
+    /// remains silence.
+    pub fn saturating_add(self, rhs: Volume) -> Volume {
+        if self.is_silence() || rhs.is_silence() {
+            return Volume::SILENCE;
+        }
+        Volume(self.0.saturating_add(rhs.0).max(Volume::MIN.0))
+    }
+
+    /// Subtract a gain, saturating at `Volume::MIN` and `Volume::MAX`. Silence
+    /// remains silence.
+    pub fn saturating_sub(self, rhs: Volume) -> Volume {
+        if self.is_silence() {
+            return Volume::SILENCE;
+        }
+        Volume(self.0.saturating_sub(rhs.0).max(Volume::MIN.0))
+    }
+}
+
+impl From<i16> for Volume {
+    fn from(value: i16) -> Self {
+        Volume(value)
+    }
+}
+
+impl From<Volume> for i16 {
+    fn from(volume: Volume) -> Self {
+        volume.0
+    }
+}
+
+impl fmt::Display for Volume {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_silence() {
+            return write!(f, "-inf dB");
+        }
+        let sign = if self.0 < 0 { "-" } else { "" };
+        let abs = self.0.unsigned_abs() as u32;
+        // two decimals, rounded
+        let hundredths = (abs * 100 + 128) / 256;
+        write!(f, "{}{}.{:02} dB", sign, hundredths / 100, hundredths % 100)
+    }
+}
+
+/// Generator of a linear ramp of the gain from the current to a new volume
+/// over a number of steps, e.g. samples or packets, which avoids the zipper
+/// noise of applying the volume changes of the host at once
+///
+/// ```
+/// use usbd_audio::{Volume, VolumeRamp};
+///
+/// let mut ramp = VolumeRamp::new(Volume::UNITY, 4);
+/// ramp.set_target(Volume::SILENCE);
+/// let gains: [u32; 5] = core::array::from_fn(|_| ramp.next_gain());
+/// assert_eq!(gains, [0xC000, 0x8000, 0x4000, 0, 0]);
+/// assert!(!ramp.is_ramping());
+/// ```
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct VolumeRamp {
+    /// number of steps of a ramp
+    length: u32,
+    /// gains at the start and the end of the ramp in 16.16 format
+    start: u32,
+    target: u32,
+    /// steps taken since the start of the ramp
+    position: u32,
+}
+
+impl VolumeRamp {
+    /// Create a ramp resting at `volume`, which moves to a new target volume
+    /// in `length` steps. A length of 0 applies changes at once.
+    pub fn new(volume: Volume, length: u32) -> Self {
+        let gain = volume.to_gain();
+        VolumeRamp {
+            length,
+            start: gain,
+            target: gain,
+            position: length,
+        }
+    }
+
+    /// Start a ramp from the current gain to `volume`, e.g. on
+    /// `AudioEvent::VolumeChanged` or with `Volume::SILENCE` on
+    /// `AudioEvent::MuteChanged`. A change during a ramp continues from the
+    /// gain reached so far.
+    pub fn set_target(&mut self, volume: Volume) {
+        self.start = self.gain();
+        self.target = volume.to_gain();
+        self.position = 0;
+    }
+
+    /// Current gain in 16.16 format
+    pub fn gain(&self) -> u32 {
+        if self.position >= self.length {
+            return self.target;
+        }
+        let delta = self.target as i64 - self.start as i64;
+        (self.start as i64 + delta * self.position as i64 / self.length as i64) as u32
+    }
+
+    /// Whether the gain has not yet reached the target
+    pub fn is_ramping(&self) -> bool {
+        self.position < self.length
+    }
+
+    /// Advance the ramp by one step and return the gain in 16.16 format
+    pub fn next_gain(&mut self) -> u32 {
+        if self.position < self.length {
+            self.position += 1;
+        }
+        self.gain()
+    }
+
+    /// Apply the ramp to interleaved 16 bit samples of `channels` channels,
+    /// advancing one step per frame, with saturation
+    pub fn apply_i16(&mut self, samples: &mut [i16], channels: usize) {
+        for frame in samples.chunks_mut(channels.max(1)) {
+            let gain = self.next_gain() as i64;
+            for sample in frame {
+                let scaled = (*sample as i64 * gain) >> 16;
+                *sample = scaled.clamp(i16::MIN as i64, i16::MAX as i64) as i16;
+            }
+        }
+    }
+
+    /// Apply the ramp to interleaved 32 bit samples of `channels` channels,
+    /// advancing one step per frame, with saturation
+    pub fn apply_i32(&mut self, samples: &mut [i32], channels: usize) {
+        for frame in samples.chunks_mut(channels.max(1)) {
+            let gain = self.next_gain() as i64;
+            for sample in frame {
+                let scaled = (*sample as i64 * gain) >> 16;
+                *sample = scaled.clamp(i32::MIN as i64, i32::MAX as i64) as i32;
+            }
+        }
+    }
+}
@@ -0,0 +1,77 @@
+//! Tests of the automatic gain control of Feature Units
+#![cfg(all(feature = "test-util", feature = "input"))]
+
+use usbd_audio::test_util::{MockFunction, TransferError};
+use usbd_audio::{
+    AudioClassBuilder, AudioEvent, AudioVersion, Error, Format, StreamConfig, StreamId,
+    TerminalType,
+};
+
+// UAC 1.0 requests
+const SET_CUR: u8 = 0x01;
+const GET_CUR: u8 = 0x81;
+
+// UAC 2.0 request
+const CUR: u8 = 0x01;
+
+// the control selector is the same in UAC 1.0 and 2.0
+const AUTOMATIC_GAIN_CONTROL: u16 = 0x0700;
+const ID_INPUT_FEATURE_UNIT: u8 = 0x04;
+const INPUT: StreamId = StreamId::Input(0);
+
+fn microphone() -> StreamConfig<'static> {
+    StreamConfig::new_discrete(Format::S16le, 1, &[48000], TerminalType::InMicrophone).unwrap()
+}
+
+/// The GET and SET request of the current value of a control
+fn requests(version: AudioVersion) -> (u8, u8) {
+    match version {
+        AudioVersion::Uac2 => (CUR, CUR),
+        _ => (GET_CUR, SET_CUR),
+    }
+}
+
+#[test]
+fn agc_control() {
+    for version in [AudioVersion::Uac1, AudioVersion::Uac2] {
+        let microphone = microphone().with_agc_control();
+        let mut f = MockFunction::new(AudioClassBuilder::new().version(version).input(microphone));
+        let (get, set) = requests(version);
+        let fu = ID_INPUT_FEATURE_UNIT;
+        f.events();
+        // the control is initially enabled
+        assert_eq!(f.audio.agc(INPUT), Ok(true));
+        assert_eq!(f.entity_in(get, AUTOMATIC_GAIN_CONTROL, fu, 1), Ok(vec![1]));
+        assert_eq!(f.entity_out(set, AUTOMATIC_GAIN_CONTROL, fu, &[0]), Ok(()));
+        assert_eq!(f.entity_in(get, AUTOMATIC_GAIN_CONTROL, fu, 1), Ok(vec![0]));
+        assert_eq!(f.audio.agc(INPUT), Ok(false));
+        assert_eq!(f.events(), [AudioEvent::AgcChanged(INPUT, false)]);
+        // setting the same state again is not signalled
+        assert_eq!(f.entity_out(set, AUTOMATIC_GAIN_CONTROL, fu, &[0]), Ok(()));
+        assert_eq!(f.events(), []);
+        // the control is a master control
+        assert_eq!(
+            f.entity_in(get, AUTOMATIC_GAIN_CONTROL | 1, fu, 1),
+            Err(TransferError::Stalled)
+        );
+    }
+}
+
+#[test]
+fn no_agc_control() {
+    for version in [AudioVersion::Uac1, AudioVersion::Uac2] {
+        let microphone = microphone().with_mute_control();
+        let mut f = MockFunction::new(AudioClassBuilder::new().version(version).input(microphone));
+        let (get, set) = requests(version);
+        let fu = ID_INPUT_FEATURE_UNIT;
+        assert_eq!(f.audio.agc(INPUT), Err(Error::InvalidValue));
+        assert_eq!(
+            f.entity_in(get, AUTOMATIC_GAIN_CONTROL, fu, 1),
+            Err(TransferError::Stalled)
+        );
+        assert_eq!(
+            f.entity_out(set, AUTOMATIC_GAIN_CONTROL, fu, &[1]),
+            Err(TransferError::Stalled)
+        );
+    }
+}
@@ -0,0 +1,93 @@
+//! Tests of the fractional resampler of the feature `asrc`
+#![cfg(feature = "asrc")]
+
+use usbd_audio::asrc::Resampler;
+
+#[test]
+fn passthrough() {
+    let mut resampler = Resampler::<2>::new();
+    let input: Vec<[i32; 2]> = (0..8).map(|i| [i, -i]).collect();
+    let mut output = [[0; 2]; 8];
+    assert_eq!(resampler.process(&input, &mut output), (8, 8));
+    // delayed by one frame
+    assert_eq!(output[0], [0, 0]);
+    assert_eq!(output[1..], input[..7]);
+}
+
+#[test]
+fn interpolation() {
+    let mut resampler = Resampler::<1>::new();
+    // three output frames per two input frames
+    resampler.set_ratio((2 << 16) / 3);
+    let input = [[0], [300], [600], [900]];
+    let mut output = [[0; 1]; 6];
+    assert_eq!(resampler.process(&input, &mut output), (4, 6));
+    // the ratio is rounded down, so the positions fall slightly short of the
+    // thirds
+    assert_eq!(output, [[0], [0], [99], [299], [499], [699]]);
+}
+
+#[test]
+fn continuation() {
+    // feeding the frames in pieces gives the same output as at once
+    let input: Vec<[i32; 1]> = (0..1000).map(|i| [i * 1000]).collect();
+    let ratio = 0x1_0123;
+    let mut once = Resampler::<1>::new();
+    once.set_ratio(ratio);
+    let mut expected = vec![[0; 1]; 2000];
+    let (_, produced) = once.process(&input, &mut expected);
+    expected.truncate(produced);
+
+    let mut pieces = Resampler::<1>::new();
+    pieces.set_ratio(ratio);
+    let mut output = Vec::new();
+    let mut buf = [[0; 1]; 7];
+    for chunk in input.chunks(13) {
+        let mut rest = chunk;
+        while !rest.is_empty() {
+            let (consumed, produced) = pieces.process(rest, &mut buf);
+            output.extend_from_slice(&buf[..produced]);
+            rest = &rest[consumed..];
+        }
+    }
+    assert_eq!(output, expected);
+}
+
+#[test]
+fn rate_mismatch() {
+    // the host sends 0.1 % more frames than the codec consumes
+    let mut resampler = Resampler::<1>::new();
+    resampler.set_ratio((65536.0 * 1.001f64).round() as u32);
+    let input = vec![[0; 1]; 20000];
+    let mut output = vec![[0; 1]; 10000];
+    let (consumed, produced) = resampler.process(&input, &mut output);
+    assert_eq!(produced, 10000);
+    assert!((10009..=10011).contains(&consumed), "{consumed}");
+}
+
+#[test]
+fn full_scale() {
+    let mut resampler = Resampler::<1>::new();
+    resampler.set_ratio(0x8000);
+    let input = [[i32::MAX], [i32::MIN], [i32::MAX]];
+    let mut output = [[0; 1]; 6];
+    assert_eq!(resampler.process(&input, &mut output), (3, 6));
+    assert_eq!(output[2..], [[i32::MAX], [-1], [i32::MIN], [-1]]);
+}
+
+#[test]
+fn ratio_limits_and_reset() {
+    let mut resampler = Resampler::<1>::new();
+    resampler.set_ratio(0);
+    assert_eq!(resampler.ratio(), 1 << 15);
+    resampler.set_ratio(u32::MAX);
+    assert_eq!(resampler.ratio(), 2 << 16);
+
+    let mut output = [[0; 1]; 2];
+    resampler.process(&[[1000], [2000], [3000]], &mut output);
+    resampler.reset();
+    assert_eq!(resampler.ratio(), 2 << 16);
+    // the frames before the reset no longer contribute to the output
+    assert_eq!(resampler.process(&[[5000]], &mut output), (1, 1));
+    assert_eq!(output[0], [0]);
+}
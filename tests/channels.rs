@@ -0,0 +1,60 @@
+//! Tests of the mute and volume controls of individual channels
+#![cfg(all(feature = "test-util", feature = "output"))]
+
+use usbd_audio::test_util::MockFunction;
+use usbd_audio::{AudioClassBuilder, Error, Format, StreamConfig, StreamId, TerminalType};
+
+// UAC 1.0 request and control selectors
+const SET_CUR: u8 = 0x01;
+const MUTE_CONTROL: u16 = 0x0100;
+const VOLUME_CONTROL: u16 = 0x0200;
+const ID_OUTPUT_FEATURE_UNIT: u8 = 0x08;
+const OUTPUT: StreamId = StreamId::Output(0);
+
+#[test]
+fn channel_controls() {
+    let speaker = StreamConfig::new_discrete(Format::S16le, 2, &[48000], TerminalType::OutSpeaker)
+        .unwrap()
+        .with_channel_mute_control()
+        .unwrap()
+        .with_channel_volume_control(-60 * 256, 0, 256)
+        .unwrap();
+    let mut f = MockFunction::new(AudioClassBuilder::new().output(speaker));
+    let fu = ID_OUTPUT_FEATURE_UNIT;
+    let volume = (-6i16 * 256).to_le_bytes();
+    assert_eq!(f.entity_out(SET_CUR, MUTE_CONTROL | 2, fu, &[1]), Ok(()));
+    assert_eq!(
+        f.entity_out(SET_CUR, VOLUME_CONTROL | 2, fu, &volume),
+        Ok(())
+    );
+    assert_eq!(f.audio.channel_mute(OUTPUT, 1), Ok(false));
+    assert_eq!(f.audio.channel_mute(OUTPUT, 2), Ok(true));
+    assert_eq!(f.audio.channel_volume(OUTPUT, 1), Ok(0));
+    assert_eq!(f.audio.channel_volume(OUTPUT, 2), Ok(-6 * 256));
+    for channel in [0, 3] {
+        assert_eq!(
+            f.audio.channel_mute(OUTPUT, channel),
+            Err(Error::InvalidValue)
+        );
+        assert_eq!(
+            f.audio.channel_volume(OUTPUT, channel),
+            Err(Error::InvalidValue)
+        );
+    }
+}
+
+#[test]
+fn channels_without_controls() {
+    // per-channel controls are limited to 8 channels, but all channels of the
+    // stream report the defaults
+    let speaker = StreamConfig::new_discrete(Format::S16le, 12, &[16000], TerminalType::OutSpeaker)
+        .unwrap()
+        .with_mute_control();
+    let f = MockFunction::new(AudioClassBuilder::new().output(speaker));
+    for channel in [1, 8, 9, 12] {
+        assert_eq!(f.audio.channel_mute(OUTPUT, channel), Ok(false));
+        assert_eq!(f.audio.channel_volume(OUTPUT, channel), Ok(0));
+    }
+    assert_eq!(f.audio.channel_mute(OUTPUT, 13), Err(Error::InvalidValue));
+    assert_eq!(f.audio.channel_volume(OUTPUT, 13), Err(Error::InvalidValue));
+}
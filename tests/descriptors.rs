@@ -0,0 +1,249 @@
+//! Golden snapshot tests of the descriptors of representative topologies
+//!
+//! Each test builds a function against a mock `UsbBus`, captures its
+//! descriptors by `AudioClass::dump_descriptors()`, which writes the same bytes
+//! as the configuration descriptor sent to the host, and compares them to the
+//! dump checked in under `tests/golden`, one descriptor per line. After an
+//! intended change of the descriptors, the dumps are regenerated by running
+//! the tests with the environment variable `UPDATE_GOLDEN` set and reviewed in
+//! the diff.
+#![cfg(all(feature = "input", feature = "output"))]
+
+use std::fmt::Write;
+use std::path::PathBuf;
+use usb_device::bus::{PollResult, UsbBus, UsbBusAllocator};
+use usb_device::endpoint::{EndpointAddress, EndpointType};
+use usb_device::{UsbDirection, UsbError};
+use usbd_audio::{
+    AudioCategory, AudioClass, AudioClassBuilder, AudioVersion, ChannelConfig, ExtensionUnit,
+    Format, LockDelay, StreamConfig, TerminalType,
+};
+
+/// Bus allocating endpoint numbers in ascending order per direction, which
+/// suffices to build a class and write its descriptors
+#[derive(Default)]
+struct MockBus {
+    next_in: u8,
+    next_out: u8,
+}
+
+impl UsbBus for MockBus {
+    fn alloc_ep(
+        &mut self,
+        ep_dir: UsbDirection,
+        ep_addr: Option<EndpointAddress>,
+        _ep_type: EndpointType,
+        _max_packet_size: u16,
+        _interval: u8,
+    ) -> usb_device::Result<EndpointAddress> {
+        if let Some(addr) = ep_addr {
+            return Ok(addr);
+        }
+        let next = match ep_dir {
+            UsbDirection::In => &mut self.next_in,
+            UsbDirection::Out => &mut self.next_out,
+        };
+        *next += 1;
+        Ok(EndpointAddress::from_parts(*next as usize, ep_dir))
+    }
+
+    fn enable(&mut self) {}
+
+    fn reset(&self) {}
+
+    fn set_device_address(&self, _addr: u8) {}
+
+    fn write(&self, _ep_addr: EndpointAddress, _buf: &[u8]) -> usb_device::Result<usize> {
+        Err(UsbError::WouldBlock)
+    }
+
+    fn read(&self, _ep_addr: EndpointAddress, _buf: &mut [u8]) -> usb_device::Result<usize> {
+        Err(UsbError::WouldBlock)
+    }
+
+    fn set_stalled(&self, _ep_addr: EndpointAddress, _stalled: bool) {}
+
+    fn is_stalled(&self, _ep_addr: EndpointAddress) -> bool {
+        false
+    }
+
+    fn suspend(&self) {}
+
+    fn resume(&self) {}
+
+    fn poll(&self) -> PollResult {
+        PollResult::None
+    }
+}
+
+/// Build the function configured by `builder` and compare its descriptors to
+/// `tests/golden/<name>.txt`
+fn check(name: &str, builder: AudioClassBuilder<'static>) {
+    // the class borrows the allocator for the lifetime of the stream
+    // configurations
+    let alloc = Box::leak(Box::new(UsbBusAllocator::new(MockBus::default())));
+    // the control interface is preceded by an interface of another class as
+    // in a composite device
+    let _other = alloc.interface();
+    let audio: AudioClass<'_, MockBus> = builder.build(alloc).unwrap();
+    let mut buf = [0u8; 4096];
+    let len = audio.dump_descriptors(&mut buf).unwrap();
+
+    let mut dump = String::new();
+    let mut descriptors = &buf[..len];
+    while let Some(&length) = descriptors.first() {
+        assert!(length >= 2, "{name}: invalid descriptor length {length}");
+        let (descriptor, rest) = descriptors.split_at(length as usize);
+        let bytes: Vec<_> = descriptor.iter().map(|b| format!("{b:02x}")).collect();
+        writeln!(dump, "{}", bytes.join(" ")).unwrap();
+        descriptors = rest;
+    }
+
+    let path: PathBuf = [env!("CARGO_MANIFEST_DIR"), "tests", "golden", name]
+        .iter()
+        .collect::<PathBuf>()
+        .with_extension("txt");
+    if std::env::var_os("UPDATE_GOLDEN").is_some() {
+        std::fs::write(&path, &dump).unwrap();
+        return;
+    }
+    let golden = std::fs::read_to_string(&path)
+        .unwrap_or_else(|err| panic!("{}: {err}, run with UPDATE_GOLDEN=1", path.display()));
+    assert!(
+        dump == golden,
+        "descriptors of {name} differ from {}:\n{dump}",
+        path.display()
+    );
+}
+
+fn speaker() -> StreamConfig<'static> {
+    StreamConfig::new_discrete(Format::S16le, 2, &[44100, 48000], TerminalType::OutSpeaker).unwrap()
+}
+
+fn microphone() -> StreamConfig<'static> {
+    StreamConfig::new_discrete(Format::S16le, 1, &[48000], TerminalType::InMicrophone).unwrap()
+}
+
+#[test]
+fn uac1_speaker_microphone() {
+    let builder = AudioClassBuilder::new()
+        .input(microphone())
+        .output(speaker());
+    check("uac1_speaker_microphone", builder);
+}
+
+#[test]
+fn uac1_feature_units() {
+    let mic = microphone()
+        .with_mute_control()
+        .with_volume_control(-40 * 256, 20 * 256, 256)
+        .unwrap()
+        .with_agc_control();
+    let speaker = speaker()
+        .with_channel_mute_control()
+        .unwrap()
+        .with_channel_volume_control(-60 * 256, 0, 256)
+        .unwrap()
+        .with_tone_controls();
+    let builder = AudioClassBuilder::new()
+        .input(mic)
+        .output(speaker)
+        .interrupt_endpoint();
+    check("uac1_feature_units", builder);
+}
+
+#[test]
+fn uac1_feedback_selector_monitor() {
+    let mic = microphone()
+        .with_selector(&[TerminalType::ExtLineConnector])
+        .unwrap();
+    let speaker =
+        StreamConfig::new_continuous(Format::S24le, 2, 8000, 96000, TerminalType::OutSpeaker)
+            .unwrap()
+            .with_feedback_endpoint()
+            .with_lock_delay(LockDelay::Millis(2));
+    let builder = AudioClassBuilder::new()
+        .input(mic)
+        .output(speaker)
+        .monitor(0, 0);
+    check("uac1_feedback_selector_monitor", builder);
+}
+
+#[test]
+fn uac1_extension_unit() {
+    let unit = ExtensionUnit::new(0x1234).with_enable_control();
+    let speaker = speaker().with_extension_unit(unit);
+    let builder = AudioClassBuilder::new().output(speaker);
+    check("uac1_extension_unit", builder);
+}
+
+#[test]
+fn uac2_clock_selector() {
+    let speaker =
+        StreamConfig::new_discrete(Format::S16le, 6, &[44100, 48000], TerminalType::OutSpeaker)
+            .unwrap()
+            .with_channel_config(ChannelConfig::SURROUND_5_1)
+            .unwrap()
+            .with_external_clock()
+            .with_clock_selector()
+            .with_mute_control()
+            .with_latency(3000);
+    let builder = AudioClassBuilder::new()
+        .version(AudioVersion::Uac2)
+        .category(AudioCategory::HomeTheater)
+        .output(speaker);
+    check("uac2_clock_selector", builder);
+}
+
+#[test]
+fn uac2_high_speed_feedback() {
+    let mic = StreamConfig::new_discrete(Format::S32le, 2, &[192000], TerminalType::InMicrophone)
+        .unwrap()
+        .with_connector_control();
+    let speaker =
+        StreamConfig::new_discrete(Format::S32le, 2, &[192000], TerminalType::OutHeadphones)
+            .unwrap()
+            .with_feedback_endpoint();
+    let builder = AudioClassBuilder::new()
+        .version(AudioVersion::Uac2)
+        .high_speed()
+        .input(mic)
+        .output(speaker);
+    check("uac2_high_speed_feedback", builder);
+}
+
+#[test]
+fn badd_headset() {
+    let mic = StreamConfig::new_discrete(Format::S16le, 1, &[48000], TerminalType::BidirHeadset)
+        .unwrap()
+        .with_mute_control();
+    let speaker =
+        StreamConfig::new_discrete(Format::S16le, 2, &[48000], TerminalType::BidirHeadset)
+            .unwrap()
+            .with_mute_control()
+            .with_volume_control(-60 * 256, 0, 256)
+            .unwrap();
+    let builder = AudioClassBuilder::new()
+        .version(AudioVersion::Uac3Badd)
+        .input(mic)
+        .output(speaker);
+    check("badd_headset", builder);
+}
+
+#[test]
+fn uc_headset() {
+    check("uc_headset", AudioClassBuilder::uc_headset().unwrap());
+    let builder = AudioClassBuilder::uc_headset()
+        .unwrap()
+        .version(AudioVersion::Uac2);
+    check("uc_headset_uac2", builder);
+}
+
+#[test]
+fn midi_and_consumer_control() {
+    let builder = AudioClassBuilder::new()
+        .output(speaker())
+        .midi()
+        .consumer_control();
+    check("midi_and_consumer_control", builder);
+}
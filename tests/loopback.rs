@@ -0,0 +1,97 @@
+//! Tests of the loopback self-test mode
+#![cfg(all(feature = "test-util", feature = "input", feature = "output"))]
+
+use usbd_audio::test_util::MockFunction;
+use usbd_audio::{
+    AudioClassBuilder, Error, Format, StreamConfig, StreamId, StreamState, TerminalType,
+};
+
+const INPUT: StreamId = StreamId::Input(0);
+const OUTPUT: StreamId = StreamId::Output(0);
+
+fn loopback(mic: StreamConfig<'static>, speaker: StreamConfig<'static>) -> MockFunction {
+    MockFunction::new(AudioClassBuilder::new().input(mic).output(speaker))
+}
+
+/// Play `data` on the output stream and record the packet of the input stream
+fn play(f: &mut MockFunction, data: &[u8]) -> Option<Vec<u8>> {
+    f.send_packet(OUTPUT, data).unwrap();
+    f.receive_packet(INPUT).unwrap()
+}
+
+fn stream(format: Format, channels: u8, terminal_type: TerminalType) -> StreamConfig<'static> {
+    StreamConfig::new_discrete(format, channels, &[48000], terminal_type).unwrap()
+}
+
+#[test]
+fn same_format() {
+    let mic = stream(Format::S16le, 2, TerminalType::InMicrophone);
+    let speaker = stream(Format::S16le, 2, TerminalType::OutSpeaker);
+    let mut f = loopback(mic, speaker);
+    assert!(!f.audio.loopback());
+    assert_eq!(f.audio.set_loopback(true), Ok(()));
+    assert!(f.audio.loopback());
+    f.start(OUTPUT).unwrap();
+    let samples: Vec<u8> = (0..192).map(|i| i as u8).collect();
+    // the idle input stream drops the packet
+    assert_eq!(play(&mut f, &samples), None);
+    f.start(INPUT).unwrap();
+    assert_eq!(play(&mut f, &samples), Some(samples.clone()));
+    // the class consumed the packet
+    let mut buf = [0; 192];
+    assert_eq!(f.audio.read(&mut buf), Err(Error::WouldBlock));
+
+    assert_eq!(f.audio.set_loopback(false), Ok(()));
+    assert_eq!(play(&mut f, &samples), None);
+    assert_eq!(f.audio.read(&mut buf), Ok(192));
+}
+
+#[test]
+fn format_adaptation() {
+    let mic = stream(Format::S24le, 1, TerminalType::InMicrophone);
+    let speaker = stream(Format::S16le, 2, TerminalType::OutSpeaker);
+    let mut f = loopback(mic, speaker);
+    f.audio.set_loopback(true).unwrap();
+    f.start(OUTPUT).unwrap();
+    f.start(INPUT).unwrap();
+    // the mono input records the left channel, widened to 24 bits
+    let frames = [0x1234i16, -1, -2, 0x5678];
+    let data: Vec<u8> = frames.iter().flat_map(|s| s.to_le_bytes()).collect();
+    assert_eq!(
+        play(&mut f, &data),
+        Some(vec![0x00, 0x34, 0x12, 0x00, 0xfe, 0xff])
+    );
+
+    let mic = stream(Format::S16le, 2, TerminalType::InMicrophone);
+    let speaker = stream(Format::S32le, 1, TerminalType::OutSpeaker);
+    let mut f = loopback(mic, speaker);
+    f.audio.set_loopback(true).unwrap();
+    f.start(OUTPUT).unwrap();
+    f.start(INPUT).unwrap();
+    // the mono output is duplicated to both input channels
+    let data = 0x1234_5678i32.to_le_bytes();
+    assert_eq!(play(&mut f, &data), Some(vec![0x34, 0x12, 0x34, 0x12]));
+}
+
+#[test]
+fn unsupported_format() {
+    let mic = stream(Format::S16le, 2, TerminalType::InMicrophone);
+    let speaker = stream(Format::F32le, 2, TerminalType::OutSpeaker);
+    let mut f = loopback(mic, speaker);
+    f.audio.set_loopback(true).unwrap();
+    f.start(OUTPUT).unwrap();
+    f.start(INPUT).unwrap();
+    assert_eq!(play(&mut f, &[0; 8]), None);
+}
+
+#[test]
+fn requires_endpoints() {
+    static STATE: StreamState = StreamState::new();
+    let mic = stream(Format::S16le, 2, TerminalType::InMicrophone);
+    let speaker = stream(Format::S16le, 2, TerminalType::OutSpeaker);
+    let mut f = loopback(mic, speaker);
+    let _handle = f.audio.take_output_stream(0, &STATE).unwrap();
+    assert_eq!(f.audio.set_loopback(true), Err(Error::StreamNotInitialized));
+    assert_eq!(f.audio.set_loopback(false), Ok(()));
+    assert!(!f.audio.loopback());
+}
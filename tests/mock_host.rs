@@ -0,0 +1,82 @@
+//! Tests of the mock bus and host of the feature `test-util`
+#![cfg(all(feature = "test-util", feature = "input", feature = "output"))]
+
+use usb_device::class::UsbClass;
+use usb_device::prelude::*;
+use usbd_audio::test_util::{MockBus, MockFunction, TransferError};
+use usbd_audio::{AudioClassBuilder, Format, StreamConfig, StreamId, TerminalType};
+
+// UAC 1.0 requests and the entities of the first streams
+const SET_CUR: u8 = 0x01;
+const GET_CUR: u8 = 0x81;
+const MUTE_CONTROL: u16 = 0x0100;
+const ID_INPUT_FEATURE_UNIT: u8 = 0x04;
+const ID_UNKNOWN: u8 = 0x7f;
+
+fn headset() -> MockFunction {
+    MockFunction::new(
+        AudioClassBuilder::new()
+            .input(
+                StreamConfig::new_discrete(Format::S16le, 1, &[48000], TerminalType::InMicrophone)
+                    .unwrap()
+                    .with_mute_control(),
+            )
+            .output(
+                StreamConfig::new_discrete(Format::S16le, 2, &[48000], TerminalType::OutSpeaker)
+                    .unwrap(),
+            ),
+    )
+}
+
+#[test]
+fn control_transfers() {
+    let mut f = headset();
+    assert_eq!(f.dev.state(), UsbDeviceState::Configured);
+
+    let fu = ID_INPUT_FEATURE_UNIT;
+    assert_eq!(f.entity_out(SET_CUR, MUTE_CONTROL, fu, &[1]), Ok(()));
+    assert_eq!(f.entity_in(GET_CUR, MUTE_CONTROL, fu, 1), Ok(vec![1]));
+    // a request to an unknown entity is rejected without affecting the next
+    // transfer
+    assert_eq!(
+        f.entity_in(GET_CUR, MUTE_CONTROL, ID_UNKNOWN, 1),
+        Err(TransferError::Stalled)
+    );
+    assert_eq!(f.entity_in(GET_CUR, MUTE_CONTROL, fu, 1), Ok(vec![1]));
+    assert_eq!(f.audio.input_mute(), Ok(true));
+}
+
+#[test]
+fn isochronous_transfers() {
+    let mut f = headset();
+    let input = StreamId::Input(0);
+    let output = StreamId::Output(0);
+    f.start(input).unwrap();
+    f.start(output).unwrap();
+
+    let samples: Vec<u8> = (0..192).map(|i| i as u8).collect();
+    f.send_packet(output, &samples).unwrap();
+    let mut data = [0; 192];
+    assert_eq!(f.audio.read(&mut data), Ok(192));
+    assert_eq!(data[..], samples[..]);
+
+    assert_eq!(f.receive_packet(input), Ok(None));
+    assert_eq!(f.audio.write(&samples[..96]), Ok(96));
+    // the previous packet has not been read by the host yet
+    assert!(f.audio.write(&samples[96..]).is_err());
+    let packet = f.receive_packet(input).unwrap();
+    assert_eq!(packet.as_deref(), Some(&samples[..96]));
+    assert_eq!(f.audio.write(&samples[96..]), Ok(96));
+}
+
+#[test]
+fn suspend_and_resume() {
+    let mut f = headset();
+    let classes: &mut [&mut dyn UsbClass<MockBus>] = &mut [&mut f.audio];
+    f.host.suspend(&mut f.dev, classes);
+    assert_eq!(f.dev.state(), UsbDeviceState::Suspend);
+    f.host.resume(&mut f.dev, classes);
+    assert_eq!(f.dev.state(), UsbDeviceState::Configured);
+    f.host.reset(&mut f.dev, classes);
+    assert_eq!(f.dev.state(), UsbDeviceState::Default);
+}
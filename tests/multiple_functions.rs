@@ -0,0 +1,184 @@
+//! Tests of two independent audio functions on the same device
+#![cfg(all(feature = "test-util", feature = "output"))]
+
+use usb_device::class::UsbClass;
+use usb_device::control::{Recipient, Request, RequestType};
+use usb_device::prelude::*;
+use usbd_audio::test_util::{MockBus, MockHost};
+use usbd_audio::{AudioClass, AudioClassBuilder, Format, StreamConfig, StreamId, TerminalType};
+
+const SET_CUR: u8 = 0x01;
+const GET_CUR: u8 = 0x81;
+const MUTE_CONTROL: u16 = 0x0100;
+const SAMPLING_FREQ_CONTROL: u16 = 0x0100;
+// Feature Unit of the first output stream of each function
+const ID_OUTPUT_FEATURE_UNIT: u16 = 0x08;
+const OUTPUT: StreamId = StreamId::Output(0);
+
+const IAD: u8 = 0x0b;
+const STRING: u8 = 0x03;
+const EN_US: u16 = 0x0409;
+
+fn speaker(rates: &'static [u32]) -> StreamConfig<'static> {
+    StreamConfig::new_discrete(Format::S16le, 2, rates, TerminalType::OutSpeaker)
+        .unwrap()
+        .with_mute_control()
+}
+
+fn setup() -> (
+    MockHost,
+    UsbDevice<'static, MockBus>,
+    AudioClass<'static, MockBus>,
+    AudioClass<'static, MockBus>,
+) {
+    let (host, alloc) = MockHost::allocator();
+    let speakers = AudioClassBuilder::new()
+        .function_name("Speakers")
+        .output(speaker(&[48000]))
+        .build(alloc)
+        .unwrap();
+    let monitoring = AudioClassBuilder::new()
+        .function_name("Monitoring")
+        .output(speaker(&[44100, 48000]))
+        .build(alloc)
+        .unwrap();
+    let dev = MockHost::device_builder(alloc)
+        .strings(&[StringDescriptors::default().product("Mixer")])
+        .unwrap()
+        .composite_with_iads()
+        .build();
+    (host, dev, speakers, monitoring)
+}
+
+/// Find the Interface Association Descriptor in the descriptors of a function
+/// and return its bFirstInterface, bInterfaceCount and iFunction
+fn iad(audio: &AudioClass<MockBus>) -> (u8, u8, u8) {
+    let mut buf = [0; 512];
+    let len = audio.dump_descriptors(&mut buf).unwrap();
+    let mut descriptors = &buf[..len];
+    while let [length, descriptor_type, ..] = *descriptors {
+        if descriptor_type == IAD {
+            return (descriptors[2], descriptors[3], descriptors[7]);
+        }
+        descriptors = &descriptors[length as usize..];
+    }
+    panic!("no Interface Association Descriptor");
+}
+
+#[test]
+fn separate_interface_associations() {
+    let (host, mut dev, mut speakers, mut monitoring) = setup();
+    let (first, count, speakers_name) = iad(&speakers);
+    assert_eq!(first, speakers.control_interface().into());
+    assert_eq!(count, speakers.num_interfaces());
+    let (first, count, monitoring_name) = iad(&monitoring);
+    assert_eq!(first, monitoring.control_interface().into());
+    assert_eq!(count, monitoring.num_interfaces());
+    assert_eq!(first, u8::from(speakers.control_interface()) + 2);
+    assert_ne!(speakers_name, monitoring_name);
+
+    let classes: &mut [&mut dyn UsbClass<MockBus>] = &mut [&mut speakers, &mut monitoring];
+    host.configure(&mut dev, classes).unwrap();
+    for (index, name) in [(speakers_name, "Speakers"), (monitoring_name, "Monitoring")] {
+        let descriptor = host
+            .control_in(
+                &mut dev,
+                classes,
+                RequestType::Standard,
+                Recipient::Device,
+                Request::GET_DESCRIPTOR,
+                (STRING as u16) << 8 | index as u16,
+                EN_US,
+                255,
+            )
+            .unwrap();
+        let utf16: Vec<u16> = descriptor[2..]
+            .chunks(2)
+            .map(|c| u16::from_le_bytes([c[0], c[1]]))
+            .collect();
+        assert_eq!(String::from_utf16(&utf16).unwrap(), name);
+    }
+}
+
+#[test]
+fn requests_reach_their_function() {
+    let (host, mut dev, mut speakers, mut monitoring) = setup();
+    // both functions use the same entity IDs within their AudioControl
+    // interface
+    let speakers_fu = ID_OUTPUT_FEATURE_UNIT << 8 | u8::from(speakers.control_interface()) as u16;
+    let monitoring_fu =
+        ID_OUTPUT_FEATURE_UNIT << 8 | u8::from(monitoring.control_interface()) as u16;
+    let monitoring_ep = u8::from(monitoring.data_endpoint(OUTPUT).unwrap()) as u16;
+    let classes: &mut [&mut dyn UsbClass<MockBus>] = &mut [&mut speakers, &mut monitoring];
+    host.configure(&mut dev, classes).unwrap();
+
+    host.class_out(
+        &mut dev,
+        classes,
+        Recipient::Interface,
+        SET_CUR,
+        MUTE_CONTROL,
+        monitoring_fu,
+        &[1],
+    )
+    .unwrap();
+    for (index, mute) in [(speakers_fu, 0), (monitoring_fu, 1)] {
+        let cur = host.class_in(
+            &mut dev,
+            classes,
+            Recipient::Interface,
+            GET_CUR,
+            MUTE_CONTROL,
+            index,
+            1,
+        );
+        assert_eq!(cur, Ok(vec![mute]));
+    }
+
+    // the endpoint request is ignored by the function not owning the endpoint
+    host.class_out(
+        &mut dev,
+        classes,
+        Recipient::Endpoint,
+        SET_CUR,
+        SAMPLING_FREQ_CONTROL,
+        monitoring_ep,
+        &44100u32.to_le_bytes()[..3],
+    )
+    .unwrap();
+
+    assert_eq!(speakers.mute(OUTPUT), Ok(false));
+    assert_eq!(monitoring.mute(OUTPUT), Ok(true));
+    assert_eq!(speakers.sampling_rate(OUTPUT), Ok(48000));
+    assert_eq!(monitoring.sampling_rate(OUTPUT), Ok(44100));
+}
+
+#[test]
+fn packets_reach_their_function() {
+    let (host, mut dev, mut speakers, mut monitoring) = setup();
+    let interfaces = [&speakers, &monitoring].map(|audio| audio.stream_interface(OUTPUT).unwrap());
+    let speakers_ep = speakers.data_endpoint(OUTPUT).unwrap();
+    let monitoring_ep = monitoring.data_endpoint(OUTPUT).unwrap();
+    assert_ne!(speakers_ep, monitoring_ep);
+    let classes: &mut [&mut dyn UsbClass<MockBus>] = &mut [&mut speakers, &mut monitoring];
+    host.configure(&mut dev, classes).unwrap();
+    for interface in interfaces {
+        host.set_interface(&mut dev, classes, interface, 1).unwrap();
+    }
+
+    host.send_packet(&mut dev, classes, speakers_ep, &[1; 192])
+        .unwrap();
+    host.send_packet(&mut dev, classes, monitoring_ep, &[2; 188])
+        .unwrap();
+    let mut data = [0; 192];
+    assert_eq!(
+        speakers.read_packet(&mut data).map(|info| info.len),
+        Ok(192)
+    );
+    assert_eq!(data, [1; 192]);
+    assert_eq!(
+        monitoring.read_packet(&mut data).map(|info| info.len),
+        Ok(188)
+    );
+    assert_eq!(data[..188], [2; 188]);
+}
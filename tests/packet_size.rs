@@ -0,0 +1,389 @@
+//! Tests of the packet sizes of isochronous data endpoints
+//!
+//! For arbitrary formats, numbers of channels, sampling rates, speeds and
+//! service intervals, the wMaxPacketSize of the data endpoints must hold the
+//! largest packet of the highest sampling rate, consist of whole audio frames
+//! and stay within the limits of isochronous endpoints, and the packets
+//! written by `AudioClass::write_packet()` must carry the sampling rate. The
+//! packets of all streams must fit into a (micro)frame together. A
+//! wMaxPacketSize set by the application must obey the same limits.
+#![cfg(all(feature = "input", feature = "output"))]
+
+use proptest::prelude::*;
+use usb_device::bus::UsbBusAllocator;
+use usb_device::class::UsbClass;
+use usb_device::endpoint::EndpointAddress;
+use usbd_audio::test_util::{MockBus, MockFunction, MockHost};
+use usbd_audio::{
+    AudioClassBuilder, AudioVersion, BuildError, Error, Format, StreamConfig, StreamId,
+    TerminalType,
+};
+
+/// Number of packets whose frames are counted
+const PACKETS: usize = 16;
+
+fn format() -> impl Strategy<Value = Format> {
+    prop_oneof![
+        Just(Format::S8),
+        Just(Format::U8),
+        Just(Format::S16le),
+        Just(Format::S24le),
+        Just(Format::S32le),
+        Just(Format::S24in32le),
+        Just(Format::F32le),
+    ]
+}
+
+/// Sampling rate, mostly one of the common rates
+fn rate() -> impl Strategy<Value = u32> {
+    prop_oneof![
+        3 => prop::sample::select(&[8000, 16000, 22050, 32000, 44100, 48000, 88200, 96000, 192000][..]),
+        1 => 1000u32..=384_000,
+    ]
+}
+
+fn subframe_size(format: Format) -> u64 {
+    match format {
+        Format::S8 | Format::U8 => 1,
+        Format::S16le => 2,
+        Format::S24le => 3,
+        _ => 4,
+    }
+}
+
+/// wMaxPacketSize of an endpoint in the descriptors
+fn max_packet_size(descriptors: &[u8], endpoint: EndpointAddress) -> Option<u16> {
+    let mut rest = descriptors;
+    while let Some(&length) = rest.first() {
+        let (descriptor, next) = rest.split_at(length as usize);
+        if descriptor[1] == 0x05 && descriptor[2] == u8::from(endpoint) {
+            return Some(u16::from_le_bytes([descriptor[4], descriptor[5]]));
+        }
+        rest = next;
+    }
+    None
+}
+
+proptest! {
+    #[test]
+    fn packet_sizes(
+        format in format(),
+        channels in 1u8..=8,
+        mut rates in prop::collection::vec(rate(), 1..=3),
+        high_speed: bool,
+        uac2: bool,
+        interval in 1u8..=4,
+    ) {
+        rates.sort_unstable();
+        rates.dedup();
+        let max_rate = *rates.last().unwrap() as u64;
+        let (intervals_per_second, limit) = if high_speed { (8000, 1024) } else { (1000, 1023) };
+        let packet_rate = intervals_per_second >> (interval - 1);
+        let frame_size = channels as u64 * subframe_size(format);
+        let max_frames = (max_rate << (interval - 1)).div_ceil(intervals_per_second);
+        let expected = max_frames * frame_size;
+        // the data endpoints of both streams with the protocol overhead of
+        // isochronous transactions in the same (micro)frame, which holds at
+        // most 1350 (full speed) or 6000 (high speed) bytes of periodic
+        // transfers
+        let (overhead, periodic) = if high_speed { (38, 6000) } else { (9, 1350) };
+        let frame_bandwidth = 2 * (expected + overhead);
+
+        let config = |terminal_type| {
+            StreamConfig::new_discrete(format, channels, &rates, terminal_type)
+                .unwrap()
+                .with_service_interval(interval)
+                .unwrap()
+        };
+        let (host, bus) = MockHost::new();
+        let alloc = UsbBusAllocator::new(bus);
+        let mut builder = AudioClassBuilder::new()
+            .input(config(TerminalType::InMicrophone))
+            .output(config(TerminalType::OutSpeaker));
+        if uac2 {
+            builder = builder.version(AudioVersion::Uac2);
+        }
+        if high_speed {
+            builder = builder.high_speed();
+        }
+        let mut audio = match builder.build(&alloc) {
+            Ok(audio) => audio,
+            Err(BuildError::BandwidthExceeded { .. }) => {
+                prop_assert!(expected > limit, "{} bytes rejected", expected);
+                return Ok(());
+            }
+            Err(BuildError::UnsupportedByVersion(_)) => {
+                // UAC 1.0 data endpoints are serviced every frame
+                prop_assert!(!uac2 && interval > 1);
+                return Ok(());
+            }
+            Err(BuildError::FrameBandwidthExceeded { needed, max }) => {
+                prop_assert!(expected <= limit);
+                prop_assert_eq!(needed as u64, frame_bandwidth);
+                prop_assert_eq!(max as u64, periodic);
+                prop_assert!(frame_bandwidth > periodic);
+                return Ok(());
+            }
+            Err(err) => return Err(TestCaseError::fail(format!("unexpected error {err:?}"))),
+        };
+        prop_assert!(expected <= limit);
+        prop_assert!(frame_bandwidth <= periodic);
+
+        let mut descriptors = [0u8; 1024];
+        let len = audio.dump_descriptors(&mut descriptors).unwrap();
+        for stream in [StreamId::Input(0), StreamId::Output(0)] {
+            let endpoint = audio.data_endpoint(stream).unwrap();
+            let size = max_packet_size(&descriptors[..len], endpoint).unwrap() as u64;
+            prop_assert_eq!(size, expected);
+            prop_assert_eq!(size % frame_size, 0);
+        }
+
+        // the frames of the packets of the input stream add up to the sampling
+        // rate, with at most one frame more or less in a packet than the
+        // average
+        let interface = audio.stream_interface(StreamId::Input(0)).unwrap();
+        let endpoint = audio.data_endpoint(StreamId::Input(0)).unwrap();
+        let mut dev = MockHost::device(&alloc);
+        let classes: &mut [&mut dyn UsbClass<MockBus>] = &mut [&mut audio];
+        host.configure(&mut dev, classes).unwrap();
+        host.set_interface(&mut dev, classes, interface, 1).unwrap();
+        let format = audio.stream_format(StreamId::Input(0)).unwrap().unwrap();
+        let rate = format.sampling_rate as u64;
+        let data = vec![0u8; 2 * limit as usize];
+        let mut total = 0;
+        for packet in 1..=PACKETS as u64 {
+            let written = audio.write_packet(&data).unwrap() as u64;
+            let classes: &mut [&mut dyn UsbClass<MockBus>] = &mut [&mut audio];
+            let received = host.receive_packet(&mut dev, classes, endpoint).unwrap();
+            prop_assert_eq!(received.map(|packet| packet.len() as u64), Some(written));
+            prop_assert!(written <= expected);
+            prop_assert_eq!(written % frame_size, 0);
+            let frames = written / frame_size;
+            prop_assert!(frames.abs_diff(rate / packet_rate) <= 1);
+            total += frames;
+            prop_assert_eq!(total, packet * rate / packet_rate);
+        }
+    }
+}
+
+/// bmAttributes of the class-specific descriptor of an endpoint
+fn cs_endpoint_attributes(descriptors: &[u8], endpoint: EndpointAddress) -> Option<u8> {
+    let mut rest = descriptors;
+    let mut found = false;
+    while let Some(&length) = rest.first() {
+        let (descriptor, next) = rest.split_at(length as usize);
+        if found && descriptor[1] == 0x25 {
+            return Some(descriptor[3]);
+        }
+        found = descriptor[1] == 0x05 && descriptor[2] == u8::from(endpoint);
+        rest = next;
+    }
+    None
+}
+
+#[test]
+fn retry_after_would_block() {
+    let mic =
+        StreamConfig::new_discrete(Format::S16le, 1, &[44100], TerminalType::InMicrophone).unwrap();
+    let mut f = MockFunction::new(AudioClassBuilder::new().input(mic));
+    f.start(StreamId::Input(0)).unwrap();
+
+    let data = [0u8; 2 * 45];
+    let mut frames = Vec::new();
+    for _ in 0..20 {
+        let written = f.audio.write_packet(&data).unwrap();
+        // the previous packet is still queued, e.g. when the firmware writes
+        // on each SOF and the host skipped a frame
+        for _ in 0..3 {
+            assert_eq!(f.audio.write_packet(&data), Err(Error::WouldBlock));
+        }
+        let received = f.receive_packet(StreamId::Input(0)).unwrap();
+        assert_eq!(received.map(|packet| packet.len()), Some(written));
+        frames.push(written / 2);
+    }
+    let expected: Vec<usize> = (1..=20)
+        .map(|i| i * 441 / 10 - (i - 1) * 441 / 10)
+        .collect();
+    assert_eq!(frames, expected);
+    assert_eq!(frames[..10], [44, 44, 44, 44, 44, 44, 44, 44, 44, 45]);
+}
+
+#[test]
+fn service_interval() {
+    // a packet of 512 ms holds 512 bytes
+    let speaker = |interval| {
+        StreamConfig::new_discrete(Format::S8, 1, &[1000], TerminalType::OutSpeaker)
+            .unwrap()
+            .with_service_interval(interval)
+            .unwrap()
+    };
+    let build = |version, high_speed, interval| {
+        let (_host, bus) = MockHost::new();
+        let alloc = UsbBusAllocator::new(bus);
+        let mut builder = AudioClassBuilder::new()
+            .version(version)
+            .output(speaker(interval));
+        if high_speed {
+            builder = builder.high_speed();
+        }
+        builder.build(&alloc).map(drop)
+    };
+    let output = StreamId::Output(0);
+    assert_eq!(build(AudioVersion::Uac1, false, 1), Ok(()));
+    assert_eq!(
+        build(AudioVersion::Uac1, false, 2),
+        Err(BuildError::UnsupportedByVersion(Some(output)))
+    );
+    assert_eq!(build(AudioVersion::Uac2, false, 10), Ok(()));
+    assert_eq!(
+        build(AudioVersion::Uac2, false, 11),
+        Err(BuildError::InvalidServiceInterval(output))
+    );
+    assert_eq!(build(AudioVersion::Uac2, true, 13), Ok(()));
+    for interval in [0, 14] {
+        let speaker =
+            StreamConfig::new_discrete(Format::S16le, 2, &[48000], TerminalType::OutSpeaker)
+                .unwrap();
+        assert_eq!(
+            speaker.with_service_interval(interval).err(),
+            Some(Error::InvalidValue)
+        );
+    }
+}
+
+#[test]
+fn max_packet_size_override() {
+    let speaker = |size| {
+        StreamConfig::new_discrete(Format::S16le, 2, &[48000], TerminalType::OutSpeaker)
+            .unwrap()
+            .with_max_packet_size(size)
+    };
+    let (_host, bus) = MockHost::new();
+    let alloc = UsbBusAllocator::new(bus);
+    let audio = AudioClassBuilder::new()
+        .output(speaker(200))
+        .build(&alloc)
+        .unwrap();
+    let mut descriptors = [0u8; 1024];
+    let len = audio.dump_descriptors(&mut descriptors).unwrap();
+    let endpoint = audio.data_endpoint(StreamId::Output(0)).unwrap();
+    assert_eq!(max_packet_size(&descriptors[..len], endpoint), Some(200));
+
+    // 48 frames of 4 bytes do not fit
+    let result = AudioClassBuilder::new().output(speaker(191)).build(&alloc);
+    assert_eq!(
+        result.err(),
+        Some(BuildError::BandwidthExceeded {
+            stream: StreamId::Output(0),
+            needed: 192,
+            max: 191
+        })
+    );
+    let result = AudioClassBuilder::new().output(speaker(1024)).build(&alloc);
+    assert_eq!(
+        result.err(),
+        Some(BuildError::BandwidthExceeded {
+            stream: StreamId::Output(0),
+            needed: 1024,
+            max: 1023
+        })
+    );
+}
+
+#[test]
+fn max_packets_only() {
+    for version in [AudioVersion::Uac1, AudioVersion::Uac2] {
+        let speaker =
+            StreamConfig::new_discrete(Format::S16le, 2, &[48000], TerminalType::OutSpeaker)
+                .unwrap()
+                .with_max_packets_only();
+        let (_host, bus) = MockHost::new();
+        let alloc = UsbBusAllocator::new(bus);
+        let audio = AudioClassBuilder::new()
+            .version(version)
+            .output(speaker)
+            .build(&alloc)
+            .unwrap();
+        let mut descriptors = [0u8; 1024];
+        let len = audio.dump_descriptors(&mut descriptors).unwrap();
+        let endpoint = audio.data_endpoint(StreamId::Output(0)).unwrap();
+        let attributes = cs_endpoint_attributes(&descriptors[..len], endpoint).unwrap();
+        assert_eq!(attributes & 0x80, 0x80, "{version:?}");
+    }
+}
+
+#[test]
+fn frame_bandwidth() {
+    let config = |terminal_type| {
+        StreamConfig::new_discrete(Format::S24le, 2, &[96000], terminal_type).unwrap()
+    };
+    let (_host, bus) = MockHost::new();
+    let alloc = UsbBusAllocator::new(bus);
+    // two streams of 576 byte packets fit into a full-speed frame
+    let builder = AudioClassBuilder::new()
+        .input(config(TerminalType::InMicrophone))
+        .output(config(TerminalType::OutSpeaker));
+    assert!(builder.build(&alloc).is_ok());
+    // three do not, although each fits into an isochronous packet
+    let builder = AudioClassBuilder::new()
+        .input(config(TerminalType::InMicrophone))
+        .output(config(TerminalType::OutSpeaker))
+        .output(config(TerminalType::OutHeadphones));
+    assert_eq!(
+        builder.build(&alloc).err(),
+        Some(BuildError::FrameBandwidthExceeded {
+            needed: 3 * (576 + 9),
+            max: 1350
+        })
+    );
+}
+
+#[test]
+fn high_bandwidth() {
+    // 24 frames of 16 channels of 32 bit samples per microframe
+    let mic = || {
+        StreamConfig::new_discrete(Format::S32le, 16, &[192000], TerminalType::InMicrophone)
+            .unwrap()
+    };
+    let builder = || {
+        AudioClassBuilder::new()
+            .version(AudioVersion::Uac2)
+            .high_speed()
+            .input(mic())
+    };
+    let (host, bus) = MockHost::new();
+    let alloc = UsbBusAllocator::new(bus);
+    let too_large = |max| BuildError::BandwidthExceeded {
+        stream: StreamId::Input(0),
+        needed: 1536,
+        max,
+    };
+    assert_eq!(builder().build(&alloc).err(), Some(too_large(1024)));
+    // the default packet buffers hold a single transaction
+    let result = builder().high_bandwidth().build(&alloc);
+    assert_eq!(result.err(), Some(too_large(1024)));
+
+    let mut audio = builder()
+        .high_bandwidth()
+        .build_sized::<_, 3072>(&alloc)
+        .unwrap();
+    let mut descriptors = [0u8; 1024];
+    let len = audio.dump_descriptors(&mut descriptors).unwrap();
+    let endpoint = audio.data_endpoint(StreamId::Input(0)).unwrap();
+    // two transactions of 768 bytes
+    assert_eq!(
+        max_packet_size(&descriptors[..len], endpoint),
+        Some(1 << 11 | 768)
+    );
+
+    let interface = audio.stream_interface(StreamId::Input(0)).unwrap();
+    let mut dev = MockHost::device(&alloc);
+    let classes: &mut [&mut dyn UsbClass<MockBus>] = &mut [&mut audio];
+    host.configure(&mut dev, classes).unwrap();
+    host.set_interface(&mut dev, classes, interface, 1).unwrap();
+    let data = vec![0u8; 2048];
+    assert_eq!(audio.write_packet(&data), Ok(1536));
+    let classes: &mut [&mut dyn UsbClass<MockBus>] = &mut [&mut audio];
+    let packet = host.receive_packet(&mut dev, classes, endpoint).unwrap();
+    assert_eq!(packet.map(|packet| packet.len()), Some(1536));
+}
@@ -0,0 +1,79 @@
+//! Tests of the Pitch control of adaptive data endpoints
+#![cfg(all(feature = "test-util", feature = "output"))]
+
+use usbd_audio::test_util::{MockFunction, MockHost, TransferError};
+use usbd_audio::{
+    AudioClassBuilder, AudioEvent, AudioVersion, BuildError, Error, Format, StreamConfig, StreamId,
+    Synchronization, TerminalType,
+};
+
+// UAC 1.0 requests and control selector
+const SET_CUR: u8 = 0x01;
+const GET_CUR: u8 = 0x81;
+const PITCH_CONTROL: u16 = 0x0200;
+
+// UAC 2.0 request and control selector
+const CUR: u8 = 0x01;
+const EP_PITCH_CONTROL: u16 = 0x0100;
+
+const OUTPUT: StreamId = StreamId::Output(0);
+
+fn speaker() -> StreamConfig<'static> {
+    StreamConfig::new_discrete(Format::S16le, 2, &[48000], TerminalType::OutSpeaker).unwrap()
+}
+
+/// The GET and SET request and the control selector of the Pitch control
+fn requests(version: AudioVersion) -> (u8, u8, u16) {
+    match version {
+        AudioVersion::Uac2 => (CUR, CUR, EP_PITCH_CONTROL),
+        _ => (GET_CUR, SET_CUR, PITCH_CONTROL),
+    }
+}
+
+#[test]
+fn pitch_control() {
+    for version in [AudioVersion::Uac1, AudioVersion::Uac2] {
+        let speaker = speaker().with_pitch_control();
+        let mut f = MockFunction::new(AudioClassBuilder::new().version(version).output(speaker));
+        let (get, set, pitch) = requests(version);
+        f.events();
+        assert_eq!(f.audio.pitch_enabled(OUTPUT), Ok(false));
+        assert_eq!(f.endpoint_in(get, pitch, OUTPUT, 1), Ok(vec![0]));
+        assert_eq!(f.endpoint_out(set, pitch, OUTPUT, &[1]), Ok(()));
+        assert_eq!(f.endpoint_in(get, pitch, OUTPUT, 1), Ok(vec![1]));
+        assert_eq!(f.audio.pitch_enabled(OUTPUT), Ok(true));
+        assert_eq!(f.events(), [AudioEvent::PitchChanged(OUTPUT, true)]);
+        assert_eq!(f.endpoint_out(set, pitch, OUTPUT, &[1]), Ok(()));
+        assert_eq!(f.events(), []);
+    }
+}
+
+#[test]
+fn no_pitch_control() {
+    for version in [AudioVersion::Uac1, AudioVersion::Uac2] {
+        let mut f = MockFunction::new(AudioClassBuilder::new().version(version).output(speaker()));
+        let (get, set, pitch) = requests(version);
+        assert_eq!(f.audio.pitch_enabled(OUTPUT), Err(Error::InvalidValue));
+        assert_eq!(
+            f.endpoint_in(get, pitch, OUTPUT, 1),
+            Err(TransferError::Stalled)
+        );
+        assert_eq!(
+            f.endpoint_out(set, pitch, OUTPUT, &[1]),
+            Err(TransferError::Stalled)
+        );
+    }
+}
+
+#[test]
+fn pitch_control_requires_adaptive_endpoint() {
+    let (_host, alloc) = MockHost::allocator();
+    let speaker = speaker()
+        .with_pitch_control()
+        .with_synchronization(Synchronization::Synchronous);
+    let result = AudioClassBuilder::new().output(speaker).build(alloc);
+    assert_eq!(
+        result.err(),
+        Some(BuildError::InvalidSynchronization(OUTPUT))
+    );
+}
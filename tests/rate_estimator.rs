@@ -0,0 +1,97 @@
+//! Tests of the estimation of the rate of the host
+
+use usbd_audio::feedback::{rate_to_q10_14, rate_to_q16_16};
+use usbd_audio::rate::RateEstimator;
+
+/// Feed `packets` packets of 1 ms of a host sending at `rate` samples/second
+/// to `estimator`, alternating the packet sizes at fractional rates, with the
+/// arrival times measured by a 12 MHz timer that starts at `ticks` and runs
+/// `ppm` parts per million faster than the clock of the host
+fn feed(estimator: &mut RateEstimator, rate: u32, ppm: i64, packets: u32, ticks: u32) -> u32 {
+    let mut sent = 0u64;
+    let mut now = ticks;
+    for packet in 1..=packets as u64 {
+        let due = packet * rate as u64 / 1000;
+        estimator.packet((due - sent) as usize, now);
+        sent = due;
+        now = ticks.wrapping_add((packet as i64 * (12_000 + 12_000 * ppm / 1_000_000)) as u32);
+    }
+    now
+}
+
+#[test]
+fn nominal_before_measurement() {
+    let mut estimator = RateEstimator::new(48000, 12_000_000);
+    assert!(!estimator.is_measured());
+    assert_eq!(estimator.rate(), 48000);
+    assert_eq!(estimator.ratio(), 1 << 16);
+    assert_eq!(estimator.feedback_q10_14(), rate_to_q10_14(48000));
+    assert_eq!(estimator.feedback_q16_16(), rate_to_q16_16(48000));
+    // the first packet starts the measurement of the following 128 packets
+    let ticks = feed(&mut estimator, 48000, 0, 128, 0);
+    assert!(!estimator.is_measured());
+    estimator.packet(48, ticks);
+    assert!(estimator.is_measured());
+}
+
+#[test]
+fn fractional_rate() {
+    let mut estimator = RateEstimator::new(44100, 12_000_000).with_window(1000);
+    feed(&mut estimator, 44100, 0, 3001, 0);
+    assert_eq!(estimator.rate(), 44100);
+    assert_eq!(estimator.ratio(), 1 << 16);
+    assert_eq!(estimator.feedback_q10_14(), rate_to_q10_14(44100));
+}
+
+#[test]
+fn drift() {
+    // the local timer runs 500 ppm fast, so the host seems 500 ppm slow
+    let mut estimator = RateEstimator::new(48000, 12_000_000).with_window(1000);
+    feed(&mut estimator, 48000, 500, 2001, 0);
+    assert_eq!(estimator.rate(), 47976);
+    assert_eq!(estimator.ratio(), 0xFFDF);
+    // the host shall send 48.024 samples per frame of its clock
+    assert_eq!(estimator.feedback_q10_14(), 0xC0189);
+    assert_eq!(estimator.feedback_q16_16(), 0x600C5);
+}
+
+#[test]
+fn timer_wraparound() {
+    let mut estimator = RateEstimator::new(48000, 12_000_000).with_window(100);
+    feed(&mut estimator, 48000, 0, 201, u32::MAX - 100 * 12_000);
+    assert_eq!(estimator.rate(), 48000);
+}
+
+#[test]
+fn averaging() {
+    let mut estimator = RateEstimator::new(48000, 12_000_000)
+        .with_window(100)
+        .with_averaging(1);
+    let ticks = feed(&mut estimator, 48000, 0, 101, 0);
+    assert_eq!(estimator.rate(), 48000);
+    // the first measurement of the new rate moves the estimate half-way
+    let ticks = feed(&mut estimator, 49000, 0, 100, ticks);
+    assert_eq!(estimator.rate(), 48500);
+    feed(&mut estimator, 49000, 0, 100, ticks);
+    assert_eq!(estimator.rate(), 48750);
+
+    // without averaging, the estimate follows each measurement
+    let mut estimator = RateEstimator::new(48000, 12_000_000)
+        .with_window(100)
+        .with_averaging(0);
+    let ticks = feed(&mut estimator, 48000, 0, 101, 0);
+    feed(&mut estimator, 49000, 0, 100, ticks);
+    assert_eq!(estimator.rate(), 49000);
+}
+
+#[test]
+fn reset() {
+    let mut estimator = RateEstimator::new(48000, 12_000_000).with_window(100);
+    feed(&mut estimator, 48000, 500, 101, 0);
+    assert!(estimator.is_measured());
+    estimator.reset(96000);
+    assert!(!estimator.is_measured());
+    assert_eq!(estimator.rate(), 96000);
+    feed(&mut estimator, 96000, 0, 101, 0);
+    assert_eq!(estimator.rate(), 96000);
+}
@@ -0,0 +1,218 @@
+//! Tests of the conversions of samples from and to subframes
+
+use proptest::prelude::*;
+use usbd_audio::sample::{
+    deinterleave, interleave, pack_channels, pack_i16, pack_i32, pack_samples, unpack_channels,
+    unpack_i16, unpack_i32, unpack_samples,
+};
+use usbd_audio::{Error, Format, I24};
+
+const PCM_FORMATS: [Format; 4] = [
+    Format::S16le,
+    Format::S24le,
+    Format::S32le,
+    Format::S24in32le,
+];
+
+#[test]
+fn pack_i16_subframes() {
+    let samples = [0x1234, -2];
+    let mut buf = [0xaa; 8];
+    assert_eq!(pack_i16(&samples, Format::S16le, &mut buf), Ok(4));
+    assert_eq!(buf[..4], [0x34, 0x12, 0xfe, 0xff]);
+    assert_eq!(pack_i16(&samples, Format::S32le, &mut buf), Ok(8));
+    assert_eq!(buf, [0, 0, 0x34, 0x12, 0, 0, 0xfe, 0xff]);
+}
+
+#[test]
+fn pack_i32_subframes() {
+    let samples = [0x1234_5678, -2];
+    let mut buf = [0xaa; 8];
+    assert_eq!(pack_i32(&samples, Format::S16le, &mut buf), Ok(4));
+    assert_eq!(buf[..4], [0x34, 0x12, 0xff, 0xff]);
+    assert_eq!(pack_i32(&samples, Format::S24le, &mut buf), Ok(6));
+    assert_eq!(buf[..6], [0x56, 0x34, 0x12, 0xff, 0xff, 0xff]);
+    assert_eq!(pack_i32(&samples, Format::S24in32le, &mut buf), Ok(8));
+    assert_eq!(buf, [0, 0x56, 0x34, 0x12, 0, 0xff, 0xff, 0xff]);
+    assert_eq!(pack_i32(&samples, Format::S32le, &mut buf), Ok(8));
+    assert_eq!(buf, [0x78, 0x56, 0x34, 0x12, 0xfe, 0xff, 0xff, 0xff]);
+}
+
+#[test]
+fn unpack_subframes() {
+    let data = [0x78, 0x56, 0x34, 0x12, 0xfe, 0xff, 0xff, 0xff, 0x01];
+    let mut samples = [0i32; 3];
+    // the incomplete subframe at the end is ignored
+    assert_eq!(unpack_i32(&data, Format::S32le, &mut samples), Ok(2));
+    assert_eq!(samples[..2], [0x1234_5678, -2]);
+    assert_eq!(unpack_i32(&data, Format::S24in32le, &mut samples), Ok(2));
+    assert_eq!(samples[..2], [0x1234_5600, -256]);
+    assert_eq!(unpack_i32(&data, Format::S24le, &mut samples), Ok(3));
+    assert_eq!(samples, [0x3456_7800, -0x1_ee00, 0x01ff_ff00]);
+
+    let mut samples = [0i16; 4];
+    assert_eq!(unpack_i16(&data, Format::S16le, &mut samples), Ok(4));
+    assert_eq!(samples, [0x5678, 0x1234, -2, -1]);
+    assert_eq!(unpack_i16(&data, Format::S32le, &mut samples), Ok(2));
+    assert_eq!(samples[..2], [0x1234, -1]);
+}
+
+#[test]
+fn errors() {
+    let mut buf = [0; 5];
+    assert_eq!(
+        pack_i16(&[0; 2], Format::F32le, &mut buf),
+        Err(Error::InvalidValue)
+    );
+    assert_eq!(
+        pack_i32(&[0; 2], Format::S24le, &mut buf),
+        Err(Error::BufferTooSmall)
+    );
+    assert_eq!(
+        pack_samples(&[0i32; 2], &mut buf),
+        Err(Error::BufferTooSmall)
+    );
+    let mut samples = [0i16; 2];
+    assert_eq!(
+        unpack_i16(&[0; 8], Format::U8, &mut samples),
+        Err(Error::InvalidValue)
+    );
+    assert_eq!(
+        unpack_i16(&[0; 6], Format::S16le, &mut samples),
+        Err(Error::BufferTooSmall)
+    );
+    assert_eq!(
+        unpack_samples(&[0; 6], &mut samples),
+        Err(Error::BufferTooSmall)
+    );
+}
+
+#[test]
+fn typed_samples() {
+    let samples = [I24(-1), I24(0x12_3456)];
+    let mut buf = [0; 6];
+    assert_eq!(pack_samples(&samples, &mut buf), Ok(6));
+    assert_eq!(buf, [0xff, 0xff, 0xff, 0x56, 0x34, 0x12]);
+    let mut unpacked = [I24(0); 2];
+    assert_eq!(unpack_samples(&buf, &mut unpacked), Ok(2));
+    assert_eq!(unpacked, samples);
+}
+
+#[test]
+fn planar_bytes() {
+    let left = [1, 2, 3, 4, 5, 6];
+    let right = [11, 12, 13, 14, 15, 16];
+    let mut buf = [0; 13];
+    assert_eq!(interleave(&[&left, &right], 3, &mut buf), Ok(12));
+    assert_eq!(buf[..12], [1, 2, 3, 11, 12, 13, 4, 5, 6, 14, 15, 16]);
+
+    let (mut l, mut r) = ([0; 6], [0; 6]);
+    // the incomplete frame at the end is ignored
+    assert_eq!(deinterleave(&buf[..13], 3, &mut [&mut l, &mut r]), Ok(2));
+    assert_eq!((l, r), (left, right));
+
+    assert_eq!(interleave(&[], 2, &mut buf), Err(Error::InvalidValue));
+    assert_eq!(interleave(&[&left], 0, &mut buf), Err(Error::InvalidValue));
+    assert_eq!(
+        interleave(&[&left, &right[..4]], 2, &mut buf),
+        Err(Error::InvalidLength)
+    );
+    assert_eq!(interleave(&[&left], 4, &mut buf), Err(Error::InvalidLength));
+    assert_eq!(
+        interleave(&[&left, &right], 2, &mut buf[..8]),
+        Err(Error::BufferTooSmall)
+    );
+    assert_eq!(
+        deinterleave(&buf[..12], 2, &mut [&mut l, &mut [0; 4]]),
+        Err(Error::BufferTooSmall)
+    );
+}
+
+#[test]
+fn planar_samples() {
+    let left = [1i16, -2, 3];
+    let right = [-4i16, 5, -6];
+    let mut buf = [0; 12];
+    assert_eq!(pack_channels(&[&left, &right], &mut buf), Ok(12));
+    let mut frames = [0i16; 6];
+    unpack_samples(&buf, &mut frames).unwrap();
+    assert_eq!(frames, [1, -4, -2, 5, 3, -6]);
+
+    let (mut l, mut r) = ([0i16; 3], [0i16; 3]);
+    assert_eq!(unpack_channels(&buf, &mut [&mut l, &mut r]), Ok(3));
+    assert_eq!((l, r), (left, right));
+    assert_eq!(
+        pack_channels(&[&left[..], &right[..2]], &mut buf),
+        Err(Error::InvalidLength)
+    );
+    assert_eq!(
+        pack_channels::<i16>(&[], &mut buf),
+        Err(Error::InvalidValue)
+    );
+}
+
+proptest! {
+    #[test]
+    fn planar_round_trip(
+        channels in 1usize..=8,
+        subframe_size in 1usize..=6,
+        frames in 0usize..16,
+        seed: u8,
+    ) {
+        let planar: Vec<Vec<u8>> = (0..channels)
+            .map(|c| {
+                (0..frames * subframe_size)
+                    .map(|i| seed.wrapping_add((c * 97 + i) as u8))
+                    .collect()
+            })
+            .collect();
+        let slices: Vec<&[u8]> = planar.iter().map(Vec::as_slice).collect();
+        let mut buf = vec![0; channels * frames * subframe_size];
+        prop_assert_eq!(interleave(&slices, subframe_size, &mut buf), Ok(buf.len()));
+        for (i, frame) in buf.chunks_exact(channels * subframe_size).enumerate() {
+            for (c, subframe) in frame.chunks_exact(subframe_size).enumerate() {
+                prop_assert_eq!(
+                    subframe,
+                    &planar[c][i * subframe_size..(i + 1) * subframe_size]
+                );
+            }
+        }
+        let mut unpacked = vec![vec![0; frames * subframe_size]; channels];
+        let mut slices: Vec<&mut [u8]> = unpacked.iter_mut().map(Vec::as_mut_slice).collect();
+        prop_assert_eq!(deinterleave(&buf, subframe_size, &mut slices), Ok(frames));
+        prop_assert_eq!(unpacked, planar);
+    }
+
+    #[test]
+    fn i16_round_trip(samples in prop::collection::vec(any::<i16>(), 0..32)) {
+        for format in PCM_FORMATS {
+            let mut buf = [0; 32 * 4];
+            let len = pack_i16(&samples, format, &mut buf).unwrap();
+            let mut unpacked = vec![0; samples.len()];
+            prop_assert_eq!(unpack_i16(&buf[..len], format, &mut unpacked), Ok(samples.len()));
+            prop_assert_eq!(&unpacked, &samples);
+            let mut wide = vec![0; samples.len()];
+            unpack_i32(&buf[..len], format, &mut wide).unwrap();
+            let expected: Vec<i32> = samples.iter().map(|&s| (s as i32) << 16).collect();
+            prop_assert_eq!(wide, expected);
+        }
+    }
+
+    #[test]
+    fn i32_round_trip(samples in prop::collection::vec(any::<i32>(), 0..32)) {
+        for format in PCM_FORMATS {
+            let mut buf = [0; 32 * 4];
+            let len = pack_i32(&samples, format, &mut buf).unwrap();
+            let mut unpacked = vec![0; samples.len()];
+            prop_assert_eq!(unpack_i32(&buf[..len], format, &mut unpacked), Ok(samples.len()));
+            // the bits below the resolution of the format are dropped
+            let mask = match format {
+                Format::S16le => !0xffff,
+                Format::S32le => !0,
+                _ => !0xff,
+            };
+            let expected: Vec<i32> = samples.iter().map(|&s| s & mask).collect();
+            prop_assert_eq!(unpacked, expected);
+        }
+    }
+}
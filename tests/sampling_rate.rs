@@ -0,0 +1,130 @@
+//! Tests of the requests of the sampling frequency controls of UAC 1.0
+//! isochronous endpoints and UAC 2.0 Clock Sources
+#![cfg(all(feature = "test-util", feature = "input", feature = "output"))]
+
+use usbd_audio::test_util::{MockFunction, TransferError};
+use usbd_audio::{
+    AudioClassBuilder, AudioEvent, AudioVersion, Format, StreamConfig, StreamId, TerminalType,
+};
+
+const SET_CUR: u8 = 0x01;
+const GET_CUR: u8 = 0x81;
+const GET_MIN: u8 = 0x82;
+const GET_MAX: u8 = 0x83;
+const GET_RES: u8 = 0x84;
+const SAMPLING_FREQ_CONTROL: u16 = 0x0100;
+
+// UAC 2.0 request and the Clock Sources of the first streams
+const CUR: u8 = 0x01;
+const ID_INPUT_CLOCK_SOURCE: u8 = 0x03;
+const ID_OUTPUT_CLOCK_SOURCE: u8 = 0x07;
+
+const INPUT: StreamId = StreamId::Input(0);
+const OUTPUT: StreamId = StreamId::Output(0);
+
+/// Perform a request of the sampling frequency control of the data endpoint of
+/// a stream
+fn get(f: &mut MockFunction, stream: StreamId, request: u8) -> Result<u32, TransferError> {
+    let data = f.endpoint_in(request, SAMPLING_FREQ_CONTROL, stream, 3)?;
+    Ok(u32::from_le_bytes([data[0], data[1], data[2], 0]))
+}
+
+fn set(f: &mut MockFunction, stream: StreamId, rate: u32) -> Result<(), TransferError> {
+    let rate = rate.to_le_bytes();
+    f.endpoint_out(SET_CUR, SAMPLING_FREQ_CONTROL, stream, &rate[..3])
+}
+
+/// Set the frequency of a UAC 2.0 Clock Source
+fn set_clock(f: &mut MockFunction, clock_source: u8, rate: u32) -> Result<(), TransferError> {
+    let rate = rate.to_le_bytes();
+    f.entity_out(CUR, SAMPLING_FREQ_CONTROL, clock_source, &rate)
+}
+
+fn headset() -> AudioClassBuilder<'static> {
+    let mic = StreamConfig::new_discrete(
+        Format::S16le,
+        1,
+        &[16000, 48000],
+        TerminalType::InMicrophone,
+    )
+    .unwrap();
+    let speaker =
+        StreamConfig::new_discrete(Format::S16le, 2, &[44100, 48000], TerminalType::OutSpeaker)
+            .unwrap();
+    AudioClassBuilder::new().input(mic).output(speaker)
+}
+
+#[test]
+fn discrete_rates() {
+    let rates = &[48000, 32000, 96000, 44100];
+    let speaker =
+        StreamConfig::new_discrete(Format::S16le, 2, rates, TerminalType::OutSpeaker).unwrap();
+    let mut f = MockFunction::new(AudioClassBuilder::new().output(speaker));
+    assert_eq!(get(&mut f, OUTPUT, GET_MIN), Ok(32000));
+    assert_eq!(get(&mut f, OUTPUT, GET_MAX), Ok(96000));
+    // greatest common divisor of 16000, 64000 and 12100
+    assert_eq!(get(&mut f, OUTPUT, GET_RES), Ok(100));
+    assert_eq!(set(&mut f, OUTPUT, 44100), Ok(()));
+    assert_eq!(get(&mut f, OUTPUT, GET_CUR), Ok(44100));
+    assert_eq!(set(&mut f, OUTPUT, 44200), Err(TransferError::Stalled));
+}
+
+#[test]
+fn continuous_rates() {
+    let speaker =
+        StreamConfig::new_continuous(Format::S16le, 2, 8000, 48000, TerminalType::OutSpeaker)
+            .unwrap();
+    let mut f = MockFunction::new(AudioClassBuilder::new().output(speaker));
+    assert_eq!(get(&mut f, OUTPUT, GET_MIN), Ok(8000));
+    assert_eq!(get(&mut f, OUTPUT, GET_MAX), Ok(48000));
+    assert_eq!(get(&mut f, OUTPUT, GET_RES), Ok(1));
+}
+
+#[test]
+fn single_rate() {
+    let speaker =
+        StreamConfig::new_discrete(Format::S16le, 2, &[48000], TerminalType::OutSpeaker).unwrap();
+    let mut f = MockFunction::new(AudioClassBuilder::new().output(speaker));
+    assert_eq!(get(&mut f, OUTPUT, GET_CUR), Ok(48000));
+    // the rate cannot be changed, so there is no range
+    for request in [GET_MIN, GET_MAX, GET_RES] {
+        assert_eq!(get(&mut f, OUTPUT, request), Err(TransferError::Stalled));
+    }
+}
+
+#[test]
+fn independent_rates() {
+    let mut f = MockFunction::new(headset());
+    f.events();
+    assert_eq!(set(&mut f, INPUT, 16000), Ok(()));
+    assert_eq!(set(&mut f, OUTPUT, 44100), Ok(()));
+    // each stream accepts only its own rates
+    assert_eq!(set(&mut f, OUTPUT, 16000), Err(TransferError::Stalled));
+    assert_eq!(get(&mut f, OUTPUT, GET_CUR), Ok(44100));
+    assert_eq!(get(&mut f, INPUT, GET_CUR), Ok(16000));
+    assert_eq!(get(&mut f, INPUT, GET_MIN), Ok(16000));
+    assert_eq!(get(&mut f, INPUT, GET_MAX), Ok(48000));
+
+    assert_eq!(f.audio.input_sampling_rate(), Ok(16000));
+    assert_eq!(f.audio.output_sampling_rate(), Ok(44100));
+    let events = f.events();
+    assert!(events.contains(&AudioEvent::SampleRateSet(INPUT, 16000)));
+    assert!(events.contains(&AudioEvent::SampleRateSet(OUTPUT, 44100)));
+}
+
+#[test]
+fn independent_clocks() {
+    let mut f = MockFunction::new(headset().version(AudioVersion::Uac2));
+    f.events();
+    assert_eq!(set_clock(&mut f, ID_INPUT_CLOCK_SOURCE, 16000), Ok(()));
+    assert_eq!(set_clock(&mut f, ID_OUTPUT_CLOCK_SOURCE, 44100), Ok(()));
+    assert_eq!(
+        set_clock(&mut f, ID_OUTPUT_CLOCK_SOURCE, 16000),
+        Err(TransferError::Stalled)
+    );
+    assert_eq!(f.audio.input_sampling_rate(), Ok(16000));
+    assert_eq!(f.audio.output_sampling_rate(), Ok(44100));
+    let events = f.events();
+    assert!(events.contains(&AudioEvent::SampleRateSet(INPUT, 16000)));
+    assert!(events.contains(&AudioEvent::SampleRateSet(OUTPUT, 44100)));
+}
@@ -0,0 +1,234 @@
+//! Tests that class-specific requests the function does not support are
+//! STALLed, while the supported requests still succeed
+#![cfg(all(feature = "test-util", feature = "input", feature = "output"))]
+
+use usbd_audio::test_util::{MockFunction, TransferError};
+use usbd_audio::{AudioClassBuilder, AudioVersion, Format, StreamConfig, TerminalType};
+
+// UAC 1.0 requests
+const SET_CUR: u8 = 0x01;
+const GET_CUR: u8 = 0x81;
+const GET_MIN: u8 = 0x82;
+const GET_MEM: u8 = 0x85;
+
+// UAC 2.0 and BADD requests
+const CUR: u8 = 0x01;
+const RANGE: u8 = 0x02;
+const MEM: u8 = 0x03;
+
+// control selectors in the high byte of wValue
+const MUTE_CONTROL: u16 = 0x0100;
+const VOLUME_CONTROL: u16 = 0x0200;
+const COPY_PROTECT_CONTROL: u16 = 0x0100;
+const SAM_FREQ_CONTROL: u16 = 0x0100;
+const UNKNOWN_CONTROL: u16 = 0x7f00;
+
+// entities of the first streams
+const ID_INPUT_TERMINAL: u8 = 0x01;
+const ID_OUTPUT_FEATURE_UNIT: u8 = 0x08;
+const ID_OUTPUT_CLOCK_SOURCE: u8 = 0x07;
+const ID_OUTPUT_OUTPUT_TERMINAL: u8 = 0x06;
+const ID_UNKNOWN: u8 = 0x7f;
+const BADD_FU_ID2: u8 = 0x02;
+const BADD_CS_ID9: u8 = 0x09;
+
+fn function(version: AudioVersion, speaker: StreamConfig<'static>) -> MockFunction {
+    let microphone =
+        StreamConfig::new_discrete(Format::S16le, 1, &[48000], TerminalType::InMicrophone).unwrap();
+    MockFunction::new(
+        AudioClassBuilder::new()
+            .version(version)
+            .input(microphone)
+            .output(speaker),
+    )
+}
+
+fn speaker() -> StreamConfig<'static> {
+    StreamConfig::new_discrete(Format::S16le, 2, &[48000], TerminalType::OutSpeaker)
+        .unwrap()
+        .with_mute_control()
+        .with_channel_volume_control(-60 * 256, 0, 256)
+        .unwrap()
+}
+
+const STALLED: Result<(), TransferError> = Err(TransferError::Stalled);
+
+#[test]
+fn uac1_feature_unit() {
+    let mut f = function(AudioVersion::Uac1, speaker());
+    let fu = ID_OUTPUT_FEATURE_UNIT;
+    assert_eq!(f.entity_out(SET_CUR, MUTE_CONTROL, fu, &[1]), Ok(()));
+    assert_eq!(f.entity_in(GET_CUR, MUTE_CONTROL, fu, 1), Ok(vec![1]));
+    assert_eq!(
+        f.entity_in(GET_CUR, VOLUME_CONTROL | 1, fu, 2)
+            .map(|v| v.len()),
+        Ok(2)
+    );
+
+    // unknown entity and control selector
+    assert_eq!(
+        f.entity_in(GET_CUR, MUTE_CONTROL, ID_UNKNOWN, 1).map(drop),
+        STALLED
+    );
+    assert_eq!(
+        f.entity_out(SET_CUR, MUTE_CONTROL, ID_UNKNOWN, &[1]),
+        STALLED
+    );
+    assert_eq!(
+        f.entity_in(GET_CUR, UNKNOWN_CONTROL, fu, 1).map(drop),
+        STALLED
+    );
+    assert_eq!(f.entity_out(SET_CUR, UNKNOWN_CONTROL, fu, &[1]), STALLED);
+    // the mute control is a master control, the volume controls are per
+    // channel
+    assert_eq!(
+        f.entity_in(GET_CUR, MUTE_CONTROL | 1, fu, 1).map(drop),
+        STALLED
+    );
+    assert_eq!(
+        f.entity_in(GET_CUR, VOLUME_CONTROL | 3, fu, 2).map(drop),
+        STALLED
+    );
+    assert_eq!(
+        f.entity_out(SET_CUR, VOLUME_CONTROL | 3, fu, &[0, 0]),
+        STALLED
+    );
+    // wLength not matching the size of the parameter
+    assert_eq!(f.entity_in(GET_CUR, MUTE_CONTROL, fu, 2).map(drop), STALLED);
+    assert_eq!(
+        f.entity_in(GET_CUR, VOLUME_CONTROL | 1, fu, 1).map(drop),
+        STALLED
+    );
+    assert_eq!(f.entity_out(SET_CUR, MUTE_CONTROL, fu, &[1, 0]), STALLED);
+    assert_eq!(f.entity_out(SET_CUR, VOLUME_CONTROL | 1, fu, &[0]), STALLED);
+    // requests without support for the control
+    assert_eq!(f.entity_in(GET_MIN, MUTE_CONTROL, fu, 1).map(drop), STALLED);
+    assert_eq!(f.entity_in(GET_MEM, 0, fu, 1).map(drop), STALLED);
+
+    // the rejected requests did not change the control
+    assert_eq!(f.entity_in(GET_CUR, MUTE_CONTROL, fu, 1), Ok(vec![1]));
+    assert_eq!(f.audio.output_mute(), Ok(true));
+}
+
+#[test]
+fn uac1_copy_protect() {
+    let mut f = function(AudioVersion::Uac1, speaker());
+    // no terminal has a copy protect control
+    let it = ID_INPUT_TERMINAL;
+    assert_eq!(
+        f.entity_in(GET_CUR, COPY_PROTECT_CONTROL, it, 1).map(drop),
+        STALLED
+    );
+
+    let mut f = function(AudioVersion::Uac1, speaker().with_copy_protect_control());
+    let ot = ID_OUTPUT_OUTPUT_TERMINAL;
+    assert_eq!(
+        f.entity_out(SET_CUR, COPY_PROTECT_CONTROL, ot, &[1]),
+        Ok(())
+    );
+    // the copy protect level of an Output Terminal can only be set
+    assert_eq!(
+        f.entity_in(GET_CUR, COPY_PROTECT_CONTROL, ot, 1).map(drop),
+        STALLED
+    );
+    assert_eq!(
+        f.entity_out(SET_CUR, COPY_PROTECT_CONTROL | 1, ot, &[1]),
+        STALLED
+    );
+    assert_eq!(f.entity_out(SET_CUR, UNKNOWN_CONTROL, ot, &[1]), STALLED);
+}
+
+#[test]
+fn uac2_requests() {
+    let mut f = function(AudioVersion::Uac2, speaker());
+    let fu = ID_OUTPUT_FEATURE_UNIT;
+    let cs = ID_OUTPUT_CLOCK_SOURCE;
+    assert_eq!(f.entity_out(CUR, MUTE_CONTROL, fu, &[1]), Ok(()));
+    assert_eq!(f.entity_in(CUR, MUTE_CONTROL, fu, 1), Ok(vec![1]));
+    assert_eq!(
+        f.entity_in(CUR, SAM_FREQ_CONTROL, cs, 4),
+        Ok(48000u32.to_le_bytes().to_vec())
+    );
+    assert!(f.entity_in(RANGE, SAM_FREQ_CONTROL, cs, 14).is_ok());
+
+    assert_eq!(
+        f.entity_in(CUR, MUTE_CONTROL, ID_UNKNOWN, 1).map(drop),
+        STALLED
+    );
+    assert_eq!(f.entity_in(CUR, UNKNOWN_CONTROL, fu, 1).map(drop), STALLED);
+    assert_eq!(f.entity_in(CUR, UNKNOWN_CONTROL, cs, 4).map(drop), STALLED);
+    assert_eq!(f.entity_in(CUR, MUTE_CONTROL | 1, fu, 1).map(drop), STALLED);
+    assert_eq!(
+        f.entity_in(CUR, SAM_FREQ_CONTROL | 1, cs, 4).map(drop),
+        STALLED
+    );
+    assert_eq!(
+        f.entity_out(CUR, SAM_FREQ_CONTROL | 1, cs, &48000u32.to_le_bytes()),
+        STALLED
+    );
+    assert_eq!(f.entity_in(CUR, MUTE_CONTROL, fu, 2).map(drop), STALLED);
+    assert_eq!(f.entity_in(CUR, SAM_FREQ_CONTROL, cs, 2).map(drop), STALLED);
+    assert_eq!(
+        f.entity_out(CUR, SAM_FREQ_CONTROL, cs, &[0x80, 0xbb]),
+        STALLED
+    );
+    assert_eq!(f.entity_in(MEM, 0, fu, 1).map(drop), STALLED);
+    // the Input Terminal has no copy protect control
+    assert_eq!(
+        f.entity_in(CUR, COPY_PROTECT_CONTROL, ID_INPUT_TERMINAL, 1)
+            .map(drop),
+        STALLED
+    );
+
+    assert_eq!(f.entity_in(CUR, MUTE_CONTROL, fu, 1), Ok(vec![1]));
+}
+
+#[test]
+fn badd_requests() {
+    let speaker =
+        StreamConfig::new_discrete(Format::S16le, 2, &[48000], TerminalType::BidirHeadset)
+            .unwrap()
+            .with_mute_control()
+            .with_volume_control(-60 * 256, 0, 256)
+            .unwrap();
+    let mut f = function(AudioVersion::Uac3Badd, speaker);
+    let fu = BADD_FU_ID2;
+    let cs = BADD_CS_ID9;
+    assert_eq!(f.entity_out(CUR, MUTE_CONTROL, fu, &[1]), Ok(()));
+    assert_eq!(f.entity_in(CUR, MUTE_CONTROL, fu, 1), Ok(vec![1]));
+    assert_eq!(
+        f.entity_in(CUR, VOLUME_CONTROL | 1, fu, 2).map(|v| v.len()),
+        Ok(2)
+    );
+    assert_eq!(
+        f.entity_in(CUR, SAM_FREQ_CONTROL, cs, 4),
+        Ok(48000u32.to_le_bytes().to_vec())
+    );
+
+    assert_eq!(
+        f.entity_in(CUR, MUTE_CONTROL, ID_UNKNOWN, 1).map(drop),
+        STALLED
+    );
+    assert_eq!(f.entity_in(CUR, UNKNOWN_CONTROL, fu, 1).map(drop), STALLED);
+    assert_eq!(f.entity_in(CUR, MUTE_CONTROL | 1, fu, 1).map(drop), STALLED);
+    assert_eq!(
+        f.entity_in(CUR, VOLUME_CONTROL | 3, fu, 2).map(drop),
+        STALLED
+    );
+    assert_eq!(
+        f.entity_in(CUR, SAM_FREQ_CONTROL | 1, cs, 4).map(drop),
+        STALLED
+    );
+    assert_eq!(f.entity_in(CUR, MUTE_CONTROL, fu, 4).map(drop), STALLED);
+    assert_eq!(
+        f.entity_in(CUR, VOLUME_CONTROL | 1, fu, 1).map(drop),
+        STALLED
+    );
+    assert_eq!(
+        f.entity_out(CUR, SAM_FREQ_CONTROL, cs, &44100u32.to_le_bytes()),
+        STALLED
+    );
+    assert_eq!(f.entity_in(MEM, 0, fu, 1).map(drop), STALLED);
+
+    assert_eq!(f.entity_in(CUR, MUTE_CONTROL, fu, 1), Ok(vec![1]));
+}
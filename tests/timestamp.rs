@@ -0,0 +1,67 @@
+//! Tests of the USB frame numbers reported with the packets received from the
+//! host
+#![cfg(all(feature = "test-util", feature = "output"))]
+
+use core::sync::atomic::{AtomicU16, Ordering};
+use usbd_audio::test_util::MockFunction;
+use usbd_audio::{
+    AudioClassBuilder, Error, Format, PacketInfo, StreamConfig, StreamId, StreamState, TerminalType,
+};
+
+const OUTPUT: StreamId = StreamId::Output(0);
+
+// each test counts the frames with its own clock since the tests run in
+// parallel
+static CLASS_FRAME: AtomicU16 = AtomicU16::new(0);
+static HANDLE_FRAME: AtomicU16 = AtomicU16::new(0);
+static STATE: StreamState = StreamState::new();
+
+fn speaker(frame_number: fn() -> u16) -> MockFunction {
+    let speaker =
+        StreamConfig::new_discrete(Format::S16le, 2, &[48000], TerminalType::OutSpeaker).unwrap();
+    MockFunction::new(
+        AudioClassBuilder::new()
+            .output(speaker)
+            .frame_number(frame_number),
+    )
+}
+
+#[test]
+fn frame_number_at_reception() {
+    let mut f = speaker(|| CLASS_FRAME.load(Ordering::Relaxed));
+    f.start(OUTPUT).unwrap();
+    let mut data = [0; 192];
+    assert_eq!(f.audio.read_packet(&mut data), Err(Error::WouldBlock));
+
+    CLASS_FRAME.store(100, Ordering::Relaxed);
+    f.send_packet(OUTPUT, &[0; 192]).unwrap();
+    // the packet is read in a later frame
+    CLASS_FRAME.store(102, Ordering::Relaxed);
+    assert_eq!(
+        f.audio.read_packet(&mut data),
+        Ok(PacketInfo {
+            len: 192,
+            frames: Some(48),
+            frame_number: Some(100),
+        })
+    );
+    assert_eq!(f.audio.frame_number(), Some(102));
+
+    f.send_packet(OUTPUT, &[0; 188]).unwrap();
+    let info = f.audio.read_packet(&mut data).unwrap();
+    assert_eq!((info.frames, info.frame_number), (Some(47), Some(102)));
+}
+
+#[test]
+fn stream_handle() {
+    let mut f = speaker(|| HANDLE_FRAME.load(Ordering::Relaxed));
+    let handle = f.audio.take_output_stream(0, &STATE).unwrap();
+    f.start(OUTPUT).unwrap();
+
+    HANDLE_FRAME.store(7, Ordering::Relaxed);
+    f.send_packet(OUTPUT, &[0; 192]).unwrap();
+    HANDLE_FRAME.store(8, Ordering::Relaxed);
+    let mut data = [0; 192];
+    let info = handle.read_packet(&mut data).unwrap();
+    assert_eq!((info.len, info.frame_number), (192, Some(7)));
+}
@@ -0,0 +1,136 @@
+//! Tests of the conversions and arithmetic of `Volume` and of `VolumeRamp`
+
+use usbd_audio::{Volume, VolumeRamp};
+
+#[test]
+fn millibels() {
+    assert_eq!(Volume::from_millibels(0), Volume::UNITY);
+    assert_eq!(Volume::from_millibels(100), Volume(256));
+    assert_eq!(Volume::from_millibels(-100), Volume(-256));
+    // 1 mB = 2.56/256 dB, rounded half away from zero
+    assert_eq!(Volume::from_millibels(1), Volume(3));
+    assert_eq!(Volume::from_millibels(-1), Volume(-3));
+    assert_eq!(Volume::from_millibels(12800), Volume::MAX);
+    assert_eq!(Volume::from_millibels(-12800), Volume::SILENCE);
+    assert_eq!(Volume::from_millibels(i32::MIN), Volume::SILENCE);
+
+    assert_eq!(Volume(256).to_millibels(), 100);
+    assert_eq!(Volume(-384).to_millibels(), -150);
+    assert_eq!(Volume(1).to_millibels(), 0);
+    assert_eq!(Volume(2).to_millibels(), 1);
+    assert_eq!(Volume::MIN.to_millibels(), -12800);
+    assert_eq!(Volume::SILENCE.to_millibels(), i32::MIN);
+    for millibels in -12700..=12700 {
+        assert_eq!(Volume::from_millibels(millibels).to_millibels(), millibels);
+    }
+}
+
+#[test]
+fn decibels() {
+    assert_eq!(Volume::from_db(-60.0), Volume(-60 * 256));
+    assert_eq!(Volume::from_db(0.5), Volume(128));
+    assert_eq!(Volume::from_db(-0.001), Volume::UNITY);
+    assert_eq!(Volume::from_db(-0.003), Volume(-1));
+    assert_eq!(Volume::from_db(1000.0), Volume::MAX);
+    assert_eq!(Volume::from_db(-1000.0), Volume::SILENCE);
+    assert_eq!(Volume::from_db(f32::NEG_INFINITY), Volume::SILENCE);
+    assert_eq!(Volume::from_db(f32::NAN), Volume::UNITY);
+
+    assert_eq!(Volume(-60 * 256).to_db(), -60.0);
+    assert_eq!(Volume(64).to_db(), 0.25);
+    assert_eq!(Volume::SILENCE.to_db(), f32::NEG_INFINITY);
+    for value in [i16::MIN + 1, -1000, -1, 0, 1, 1000, i16::MAX] {
+        assert_eq!(Volume::from_db(Volume(value).to_db()), Volume(value));
+    }
+}
+
+#[test]
+fn saturating_arithmetic() {
+    let gain = Volume::from_db(6.0);
+    assert_eq!(
+        Volume::from_db(-10.0).saturating_add(gain),
+        Volume(-4 * 256)
+    );
+    assert_eq!(
+        Volume::from_db(-10.0).saturating_sub(gain),
+        Volume(-16 * 256)
+    );
+    assert_eq!(Volume::MAX.saturating_add(gain), Volume::MAX);
+    // the finite range ends at Volume::MIN, only silence is -∞
+    assert_eq!(Volume::MIN.saturating_sub(gain), Volume::MIN);
+    assert_eq!(Volume::SILENCE.saturating_add(gain), Volume::SILENCE);
+    assert_eq!(Volume::SILENCE.saturating_sub(gain), Volume::SILENCE);
+    assert_eq!(gain.saturating_add(Volume::SILENCE), Volume::SILENCE);
+    assert_eq!(gain.saturating_sub(Volume::SILENCE), Volume::MAX);
+}
+
+#[test]
+fn conversions() {
+    assert_eq!(Volume::from(-256i16), Volume(-256));
+    assert_eq!(i16::from(Volume(-256)), -256);
+    assert!(Volume::SILENCE < Volume::MIN);
+    assert!(Volume::MIN < Volume::UNITY);
+    assert_eq!(Volume(-1664).to_string(), "-6.50 dB");
+    assert_eq!(Volume(1).to_string(), "0.00 dB");
+    assert_eq!(Volume(3).to_string(), "0.01 dB");
+    assert_eq!(Volume::MAX.to_string(), "128.00 dB");
+    assert_eq!(Volume::SILENCE.to_string(), "-inf dB");
+}
+
+#[test]
+fn linear_gain() {
+    assert_eq!(Volume::UNITY.to_gain(), 0x1_0000);
+    assert_eq!(Volume::SILENCE.to_gain(), 0);
+    assert_eq!(Volume::MAX.to_gain(), u32::MAX);
+    for value in (i16::MIN + 1..=96 * 256).step_by(7) {
+        let expected = 10f64.powf(value as f64 / 256.0 / 20.0) * 65536.0;
+        let gain = Volume(value).to_gain() as f64;
+        // relative error below 0.002 dB or one LSB
+        assert!(
+            (gain - expected).abs() <= (expected * 2e-4).max(1.0),
+            "{value}: {gain} != {expected}"
+        );
+    }
+}
+
+#[test]
+fn ramp() {
+    let mut ramp = VolumeRamp::new(Volume::SILENCE, 4);
+    assert!(!ramp.is_ramping());
+    assert_eq!(ramp.next_gain(), 0);
+    ramp.set_target(Volume::UNITY);
+    assert!(ramp.is_ramping());
+    assert_eq!(ramp.next_gain(), 0x4000);
+    assert_eq!(ramp.next_gain(), 0x8000);
+    // a new target during the ramp continues from the gain reached so far
+    ramp.set_target(Volume::SILENCE);
+    assert_eq!(ramp.gain(), 0x8000);
+    let gains: Vec<u32> = (0..5).map(|_| ramp.next_gain()).collect();
+    assert_eq!(gains, [0x6000, 0x4000, 0x2000, 0, 0]);
+
+    // without steps, the target applies at once
+    let mut ramp = VolumeRamp::new(Volume::UNITY, 0);
+    ramp.set_target(Volume::from_db(-6.0));
+    assert!(!ramp.is_ramping());
+    assert_eq!(ramp.gain(), Volume::from_db(-6.0).to_gain());
+}
+
+#[test]
+fn apply_to_frames() {
+    let mut ramp = VolumeRamp::new(Volume::SILENCE, 2);
+    ramp.set_target(Volume::UNITY);
+    let mut samples = [1000i16, -1000, 1000, -1000, 1000, -1000];
+    ramp.apply_i16(&mut samples, 2);
+    // one step per stereo frame
+    assert_eq!(samples, [500, -500, 1000, -1000, 1000, -1000]);
+
+    let mut ramp = VolumeRamp::new(Volume::from_db(12.0), 16);
+    let mut samples = [i16::MAX, i16::MIN, 100];
+    ramp.apply_i16(&mut samples, 1);
+    assert_eq!(samples[..2], [i16::MAX, i16::MIN]);
+    assert_eq!(samples[2], 398);
+    let mut samples = [i32::MAX, i32::MIN, 1 << 20];
+    ramp.apply_i32(&mut samples, 3);
+    assert_eq!(samples[..2], [i32::MAX, i32::MIN]);
+    assert_eq!(samples[2] >> 10, 4076);
+}